@@ -1,31 +1,174 @@
 //FIXME: Check unicode support
 
 use log::{Log, Change, LogEntry};
+use layout;
 
 use gapbuffer::GapBuffer;
+use store::{TextStore, Text, MappedFile};
+use regex::Regex;
+use libc;
 
 use std::cmp;
+use std::str;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::iter::{repeat, Extend};
 use std::collections::HashMap;
-use std::io::{File, Reader, BufferedReader};
+use std::io::{fs, File, Reader, IoError, IoResult};
+use std::io::{InvalidInput, OtherIoError, PermissionDenied, FileNotFound, EndOfFile};
+use std::io::PathAlreadyExists;
+use std::io::{MismatchedFileTypeForOperation, FileMode, FileAccess, FileType};
+use std::io::{USER_RWX, GROUP_RWX, OTHER_RWX};
+use std::io::fs::PathExtensions;
 
 #[derive(Copy, PartialEq, Eq, Hash, Show)]
 pub enum Mark {
     Cursor(uint),           //For keeping track of cursors.
     DisplayMark(uint),      //For using in determining some display of characters.
+    Named(char),            //Set by the user to jump back to later.
+    Anchor,                 //Where the selection started, the other end being a cursor.
 }
 
 #[derive(Copy, PartialEq, Eq, Show)]
 pub enum Direction {
     Up(uint), Down(uint), Left(uint), Right(uint),
     LineStart, LineEnd,
+    NextWord(uint), PrevWord(uint),
+    BufferStart, BufferEnd,
+    NextParagraph, PrevParagraph,
+}
+
+///How many bytes are read at a time when loading text.
+const LOAD_CHUNK: uint = 64 * 1024;
+
+///The byte order mark some editors start UTF-8 files with.
+const UTF8_BOM: &'static [u8] = b"\xef\xbb\xbf";
+
+///How much of the start of a file is looked at to tell whether it is binary.
+const BINARY_SAMPLE: uint = 8 * 1024;
+
+///How large a file must be to be mapped into memory and viewed, rather than loaded.
+const MAP_SIZE: u64 = 256 * 1024 * 1024;
+
+///Told how far a load has got, after every chunk read.
+pub trait Progress {
+    ///`loaded` of the `total` bytes have been read so far, where `total` is 0 if the size isn't
+    ///known. The load is cancelled if this returns false.
+    fn progress(&mut self, loaded: u64, total: u64) -> bool;
+}
+
+///Loads without reporting anything, and without being cancelled.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn progress(&mut self, _: u64, _: u64) -> bool { true }
+}
+
+///How a save got the text onto the disk.
+#[derive(Copy, PartialEq, Eq, Show)]
+pub enum SaveMethod {
+    Renamed,                //Written to a temporary file which then replaced the target.
+    InPlace,                //Written straight into the target, as its directory isn't writable.
+}
+
+///The line ending a file uses on disk. The text in memory always uses `\n`.
+#[derive(Copy, PartialEq, Eq, Show)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf   => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr   => "\r",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf   => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr   => "CR",
+        }
+    }
+}
+
+///Where a save keeps a copy of the file it is about to overwrite.
+#[derive(Clone, PartialEq, Show)]
+pub enum Backup {
+    Off,
+    Beside,                 //As `name~` next to the file.
+    InDir(Path),            //As `name~` in the given directory.
+}
+
+///Whether searching matches letters regardless of case.
+#[derive(Copy, PartialEq, Show)]
+pub enum Case {
+    Exact,
+    Ignore,
+    Smart,                  //Ignored unless the query has an upper case letter in it.
+}
+
+impl Case {
+    ///Whether a search for query ignores case.
+    pub fn ignores_case(&self, query: &str) -> bool {
+        match *self {
+            Case::Exact  => false,
+            Case::Ignore => true,
+            Case::Smart  => !query.chars().any(|c| c.is_uppercase()),
+        }
+    }
+}
+
+///What a successful save did.
+pub struct Saved {
+    pub bytes: uint,
+    pub method: SaveMethod,
+    pub backup: Option<IoResult<Path>>,     //The backup made first, or why it couldn't be made.
+    pub added_newline: bool,                //Whether a final line ending was written that the
+                                            // text doesn't have.
 }
 
 pub struct Buffer {
-    text: GapBuffer<u8>,                    //Actual text data being edited.
-    marks: HashMap<Mark, (uint, uint)>,     //Table of marked indices in the text.
-                                            // KEY: mark id => VALUE : (absolute index, line index)
+    text: Text,                             //Actual text data being edited.
+    marks: HashMap<Mark, (uint, Option<uint>)>, //Table of marked indices in the text.
+                                            // KEY: mark id => VALUE : (absolute index, screen
+                                            // column kept to while moving up and down)
     pub log: Log,                           //History of undoable transactions.
     pub file_path: Option<Path>,            //TODO: replace with a general metadata table
+    saved_revision: uint,                   //Log revision that was last written to disk.
+    backed_up: Option<Path>,                //File already backed up, or created, this session.
+    pub line_ending: LineEnding,            //Written in place of each `\n` when saving.
+    pub mixed_line_endings: bool,           //Whether the file was loaded with other endings too.
+    saved_line_ending: LineEnding,          //Line ending of the file as last written to disk.
+    pub bom: bool,                          //Whether the file starts with a UTF-8 byte order
+                                            // mark, which is kept out of the text.
+    saved_bom: bool,                        //Whether the file as last written to disk did.
+    disk_stat: Option<(u64, u64)>,          //Modification time and size of the file when it was
+                                            // last read or written.
+    pub read_only: bool,                    //Whether edits are refused and `save` won't write
+                                            // over the file, as it was not valid UTF-8 or can't
+                                            // be written.
+    pub from_stdin: bool,                   //Whether the text was piped in, rather than read
+                                            // from `file_path`.
+    pub binary: bool,                       //Whether the start of the file looked like binary
+                                            // data rather than text.
+    pub hex_view: bool,                     //Whether the text is a hex dump of the file, which
+                                            // `save` won't write over it.
+    seen_revision: uint,                    //Log revision when edits were last counted.
+    unswapped_edits: uint,                  //Edits made since the swap file was last written.
+    selection: Option<Mark>,                //Cursor the selection runs to from `Mark::Anchor`,
+                                            // while there is a selection.
+    block_selection: bool,                  //Whether the selection is the rectangle between
+                                            // the two, rather than the text.
+    pub tab_width: uint,                    //Columns between tab stops, as the text is drawn,
+                                            // for the column marks keep to moving up and down.
+    line_index: RefCell<LineIndex>,         //Where the lines start, as far as they've been
+                                            // looked for since the text last changed there.
 }
 
 impl Buffer {
@@ -36,28 +179,125 @@ impl Buffer {
     pub fn new() -> Buffer {
         Buffer {
             file_path: None,
-            text: GapBuffer::new(),
+            text: Text::new(),
             marks: HashMap::new(),
             log: Log::new(),
+            saved_revision: 0,
+            backed_up: None,
+            line_ending: LineEnding::Lf,
+            mixed_line_endings: false,
+            saved_line_ending: LineEnding::Lf,
+            bom: false,
+            saved_bom: false,
+            disk_stat: None,
+            read_only: false,
+            from_stdin: false,
+            binary: false,
+            hex_view: false,
+            seen_revision: 0,
+            unswapped_edits: 0,
+            selection: None,
+            block_selection: false,
+            tab_width: 4,
+            line_index: RefCell::new(LineIndex::new()),
         }
     }
 
     /// Constructor for buffer from reader.
-    pub fn new_from_reader<R: Reader>(reader: R) -> Buffer {
-        let mut buff = Buffer::new();
-        if let Ok(contents) = BufferedReader::new(reader).read_to_string() {
-            buff.text.extend(contents.bytes());
-        }
-        buff
+    ///
+    /// The most common line ending in the text is recorded for saving, and every line ending is
+    /// read in as `\n`. Text that isn't valid UTF-8 is read with the invalid parts replaced, and
+    /// the buffer made read-only so that it isn't written back by accident.
+    pub fn new_from_reader<R: Reader>(mut reader: R) -> Buffer {
+        Buffer::load(&mut reader, 0, &mut NoProgress).unwrap_or_else(|_| Buffer::new())
     }
 
     /// Constructor for buffer from file.
-    pub fn new_from_file(path: Path) -> Buffer {
-        if let Ok(file) = File::open(&path) {
-            let mut buff = Buffer::new_from_reader(file);
-            buff.file_path = Some(path);
-            buff
-        } else { Buffer::new() }
+    ///
+    /// A file that doesn't exist yet gives an empty buffer, which is saved to `path`. Any other
+    /// failure to read the file, or a directory at `path`, is an error. A file that can be read
+    /// but not written gives a read-only buffer.
+    pub fn new_from_file(path: Path) -> IoResult<Buffer> {
+        Buffer::new_from_file_with_progress(path, &mut NoProgress)
+    }
+
+    /// As `new_from_file`, telling `progress` how far the load has got as it goes, so that a
+    /// large file can be shown loading and cancelled.
+    ///
+    /// A file too large to load is mapped into memory instead, and is read-only. Its bytes are
+    /// shown as they are on disk, line endings and all, and are read into memory only once
+    /// read-only is turned off and the text is edited.
+    pub fn new_from_file_with_progress(path: Path, progress: &mut Progress) -> IoResult<Buffer> {
+        if path.is_dir() {
+            return Err(IoError {
+                kind: MismatchedFileTypeForOperation,
+                desc: "is a directory",
+                detail: None,
+            })
+        }
+        let mut buff = match File::open(&path) {
+            Ok(mut file) => {
+                let size = try!(file.stat()).size;
+                if size >= MAP_SIZE { try!(Buffer::map(&path, size)) }
+                else { try!(Buffer::load(&mut file, size, progress)) }
+            }
+            Err(ref e) if e.kind == FileNotFound => Buffer::new(),
+            Err(e) => return Err(e),
+        };
+        if !is_writable(&path) {
+            buff.read_only = true;
+        }
+        buff.disk_stat = get_disk_stat(&path);
+        buff.file_path = Some(path);
+        Ok(buff)
+    }
+
+    //Maps the file into memory as its text, rather than reading it, so that only the parts of
+    //it looked at are ever read.
+    fn map(path: &Path, size: u64) -> IoResult<Buffer> {
+        let file = try!(MappedFile::open(path, size as uint));
+        let mut buff = Buffer::new();
+        buff.binary = looks_binary(file.as_slice());
+        buff.text = Text::Mapped(file);
+        buff.read_only = true;
+        Ok(buff)
+    }
+
+    //Reads and decodes the text a chunk at a time, straight into the buffer, which is given
+    //room up front for the `size` bytes expected.
+    fn load<R: Reader>(reader: &mut R, size: u64, progress: &mut Progress) -> IoResult<Buffer> {
+        let mut buff = Buffer::new();
+        let mut text = GapBuffer::with_capacity(size as uint);
+        let mut decoder = Decoder::new();
+        let mut chunk: Vec<u8> = repeat(0u8).take(LOAD_CHUNK).collect();
+        let mut loaded = 0u64;
+        loop {
+            match reader.read(chunk.as_mut_slice()) {
+                Ok(n) => {
+                    if loaded == 0 { buff.binary = looks_binary(chunk[..n]) }
+                    decoder.feed(chunk[..n], &mut text);
+                    loaded += n as u64;
+                }
+                Err(ref e) if e.kind == EndOfFile => break,
+                Err(e) => return Err(e),
+            }
+            if !progress.progress(loaded, size) {
+                return Err(IoError {
+                    kind: OtherIoError,
+                    desc: "loading cancelled",
+                    detail: None,
+                })
+            }
+        }
+        let decoded = decoder.finish(&mut text);
+        buff.text = Text::Loaded(text);
+        buff.line_ending = decoded.line_ending;
+        buff.saved_line_ending = decoded.line_ending;
+        buff.mixed_line_endings = decoded.mixed_line_endings;
+        buff.bom = decoded.bom;
+        buff.saved_bom = decoded.bom;
+        buff.read_only = decoded.lossy;
+        Ok(buff)
     }
 
     //----- ACCESSORS ------------------------------------------------------------------------------
@@ -67,16 +307,69 @@ impl Buffer {
         self.text.len() + 1
     }
 
+    ///Number of characters stored in this buffer.
+    pub fn char_len(&self) -> uint {
+        range(0, self.text.len()).filter(|i| !is_continuation_byte(self.text[*i])).count()
+    }
+
+    ///Number of lines in this buffer.
+    ///
+    ///This is always one more than the number of newlines: an empty buffer has a single empty
+    ///line, and a buffer ending in a newline has an empty last line after it, which is where the
+    ///cursor goes when moving past the final newline.
+    pub fn line_count(&self) -> uint {
+        let mut index = self.line_index.borrow_mut();
+        if let Some(count) = index.count { return count }
+        let count = 1 + newlines(self.text.as_slice().iter());
+        index.count = Some(count);
+        count
+    }
+
+    ///The first line changed since this was last asked, if any was, so that what was worked out
+    ///from the lines after it can be worked out again.
+    pub fn take_edited_line(&mut self) -> Option<uint> {
+        match self.log.take_edited_from() {
+            Some(idx) => Some(self.line_of(cmp::min(idx, self.text.len()))),
+            None      => None,
+        }
+    }
+
     ///The x,y coordinates of a mark within the file. None if not a valid mark.
     pub fn get_mark_coords(&self, mark: Mark) -> Option<(uint, uint)> {
         if let Some(idx) = self.get_mark_idx(mark) {
             if let Some(line) = get_line(idx, &self.text) {
-                Some((idx - line, range(0, idx).filter(|i| -> bool { self.text[*i] == b'\n' })
-                                               .count()))
+                Some((idx - line, self.line_of(idx)))
             } else { None }
         } else { None }
     }
 
+    //The index of the first character of the line with the given (0-based) number.
+    //None iff the text has fewer lines than that.
+    fn line_start(&self, linenum: uint) -> Option<uint> {
+        let mut index = self.line_index.borrow_mut();
+        index.find_past_line(linenum, &self.text);
+        index.starts.get(linenum).map(|&start| start)
+    }
+
+    //The (0-based) number of the line that idx, which is within the text, is on.
+    fn line_of(&self, idx: uint) -> uint {
+        let mut index = self.line_index.borrow_mut();
+        index.find_past(idx, &self.text);
+        index.line_of(idx)
+    }
+
+    ///The (line, column) position of a mark within the file, both 0-based. None if not a valid
+    ///mark.
+    pub fn get_mark_pos(&self, mark: Mark) -> Option<(uint, uint)> {
+        self.get_mark_coords(mark).map(|(col, line)| (line, col))
+    }
+
+    ///The screen column of a mark: the display width of the text before it on its line, with
+    ///tabs expanded to the next multiple of `tab_width`. None if not a valid mark.
+    pub fn get_mark_screen_offset(&self, mark: Mark, tab_width: uint) -> Option<uint> {
+        self.get_mark_idx(mark).map(|idx| get_screen_col(idx, tab_width, &self.text))
+    }
+
     ///The absolute index of a mark within the file. None if not a valid mark.
     pub fn get_mark_idx(&self, mark: Mark) -> Option<uint> {
         if let Some(&(idx, _)) = self.marks.get(&mark) {
@@ -89,7 +382,7 @@ impl Buffer {
     ///Creates an iterator on the text by lines.
     pub fn lines(&self) -> Lines {
         Lines {
-            buffer: self.text[],
+            buffer: self.text.as_slice(),
             tail: 0,
             head: self.len()
         }
@@ -100,7 +393,7 @@ impl Buffer {
         if let Some(&(idx, _)) = self.marks.get(&mark) {
             if idx < self.len() {
                 Some(Lines {
-                    buffer: self.text[idx..],
+                    buffer: self.text.slice_from(idx),
                     tail: 0,
                     head: self.len() - idx,
                 })
@@ -108,268 +401,4719 @@ impl Buffer {
         } else { None }
     }
 
-    ///Returns the status text for this buffer.
-    pub fn status_text(&self) -> String {
-        match self.file_path {
-            Some(ref path)  =>  format!("{} ", path.display()),
-            None            =>  format!("untitled "),
-        }
+    ///Whether the buffer has changed since it was last saved.
+    pub fn is_dirty(&self) -> bool {
+        self.log.revision() != self.saved_revision || self.line_ending != self.saved_line_ending
+            || self.bom != self.saved_bom
     }
 
-    //----- MUTATORS -------------------------------------------------------------------------------
+    ///Creates an iterator on the text by lines that begins at the given (0-based) line number.
+    ///None if the buffer has fewer lines.
+    pub fn lines_at(&self, linenum: uint) -> Option<Lines> {
+        if let Some(idx) = self.line_start(linenum) {
+            Some(Lines {
+                buffer: self.text.slice_from(idx),
+                tail: 0,
+                head: self.len() - idx,
+            })
+        } else { None }
+    }
 
-    ///Sets the mark to a given absolute index. Adds a new mark or overwrites an existing mark.
-    pub fn set_mark(&mut self, mark: Mark, idx: uint) {
-        if let Some(line) = get_line(idx, &self.text) {
-            if let Some(tuple) = self.marks.get_mut(&mark) {
-                *tuple = (idx, idx - line);
-                return;
+    ///Whether something else has changed the file since it was last read or written.
+    pub fn changed_on_disk(&self) -> bool {
+        match self.file_path {
+            Some(ref path) => {
+                let stat = get_disk_stat(path);
+                stat.is_some() && stat != self.disk_stat
             }
-            self.marks.insert(mark, (idx, idx - line));
+            None => false,
         }
     }
 
-    //Shift a mark relative to its position according to the direction given.
-    pub fn shift_mark(&mut self, mark: Mark, direction: Direction) {
-        let last = self.len() - 1;
-        let text = &self.text;
-        if let Some(tuple) = self.marks.get_mut(&mark) {
-            let (idx, line_idx) = *tuple;
-            if let (Some(line), Some(line_end)) = (get_line(idx, text), get_line_end(idx, text)) {
-                *tuple = match direction {
-                    //For every relative motion of a mark, should return this tuple:
-                    //  value 0:    the absolute index of the mark in the file
-                    //  value 1:    the index of the mark in its line (unchanged by direct verticle
-                    //              traversals)
-                    Direction::Left(n)      =>  {
-                        if idx >= n { (idx - n, idx - n - get_line(idx - n, text).unwrap()) }
-                        else { (0, 0) }
-                    }
-                    Direction::Right(n)     =>  {
-                        if idx + n < last { (idx + n, idx + n - get_line(idx + n, text).unwrap()) }
-                        else { (last, last - get_line(last, text).unwrap()) }
-                    }
-                    Direction::Up(n)        =>  {
-                        let nlines = range(0, idx).rev().filter(|i| text[*i] == b'\n')
-                                                        .take(n + 1)
-                                                        .collect::<Vec<uint>>();
-                        if n == nlines.len() { (cmp::min(line_idx, nlines[0]), line_idx) }
-                        else if n > nlines.len() { (0, 0) }
-                        else { (cmp::min(line_idx + nlines[n] + 1, nlines[n-1]), line_idx) }
-                    }
-                    Direction::Down(n)      =>  {
-                        let nlines = range(idx, text.len()).filter(|i| text[*i] == b'\n')
-                                                           .take(n + 1)
-                                                           .collect::<Vec<uint>>();
-                        if n > nlines.len() { (last, last - get_line(last, text).unwrap())
-                        } else if n == nlines.len() {
-                            (cmp::min(line_idx + nlines[n-1] + 1, last), line_idx)
-                        } else { (cmp::min(line_idx + nlines[n-1] + 1, nlines[n]), line_idx) }
-                    }
-                    Direction::LineStart    =>  { (line, 0) }
-                    Direction::LineEnd      =>  { (line_end, line_end - line) }
-                }
-            }
-        }
+    ///Whether the buffer is for a file that hasn't been written yet.
+    pub fn is_new_file(&self) -> bool {
+        self.file_path.is_some() && self.disk_stat.is_none()
     }
 
-    ///Remove the char at the mark.
-    pub fn remove_char(&mut self, mark: Mark) -> Option<u8> {
-        if let Some(&(idx, _)) = self.marks.get(&mark) {
-            if let Some(ch) = self.text.remove(idx) {
-                let mut transaction = self.log.start(idx);
-                transaction.log(Change::Remove(idx, ch), idx);
-                Some(ch)
-            } else { None }
-        } else { None }
+    ///Whether the last character of the text is a newline.
+    pub fn ends_with_newline(&self) -> bool {
+        self.text.len() > 0 && self.text[self.text.len() - 1] == b'\n'
     }
 
-    ///Insert a char at the mark.
-    pub fn insert_char(&mut self, mark: Mark, ch: u8) {
-        if let Some(&(idx, _)) = self.marks.get(&mark) {
-            self.text.insert(idx, ch);
-            let mut transaction = self.log.start(idx);
-            transaction.log(Change::Insert(idx, ch), idx);
+    ///The file's path as the status line shows it, or what the buffer is if it has none.
+    pub fn display_name(&self) -> String {
+        match self.file_path {
+            Some(ref path) => format!("{}", path.display()),
+            None           => String::from_str(if self.from_stdin { "stdin" } else { "untitled" }),
         }
     }
 
-    ///Redo most recently undone action.
-    pub fn redo(&mut self) -> Option<&LogEntry> {
-        if let Some(transaction) = self.log.redo() {
-            commit(transaction, &mut self.text);
-            Some(transaction)
-        } else { None }
+    ///The name of the line ending, saying too if the file had others and if it starts with a
+    ///byte order mark.
+    pub fn ending_text(&self) -> String {
+        let ending = if self.mixed_line_endings { "mixed " } else { "" };
+        let bom = if self.bom { " BOM" } else { "" };
+        format!("{}{}{}", ending, self.line_ending.name(), bom)
     }
 
-    ///Undo most recently performed action.
-    pub fn undo(&mut self) -> Option<&LogEntry> {
-        if let Some(transaction) = self.log.undo() {
-            commit(transaction, &mut self.text);
-            Some(transaction)
-        } else { None }
+    ///"[hex]" or "[binary]" for a buffer that isn't shown as text.
+    pub fn kind_text(&self) -> &'static str {
+        if self.hex_view { "[hex]" } else if self.binary { "[binary]" } else { "" }
     }
 
-}
+    //----- FILE METHODS ---------------------------------------------------------------------------
 
-//Returns the index of the first character of the line the mark is in.
-//Newline prior to mark (EXCLUSIVE) + 1.
-//None iff mark is outside of the len of text.
-fn get_line(mark: uint, text: &GapBuffer<u8>) -> Option<uint> {
-    if mark <= text.len() {
-        range(0, mark + 1).rev().filter(|idx| *idx == 0 || text[*idx - 1] == b'\n')
-                                .take(1)
-                                .next()
-    } else { None }
-}
+    ///Writes the text to `file_path`, with the buffer's line ending. The buffer is no longer
+    ///dirty once this succeeds.
+    ///
+    ///The first save of a file that existed before it was saved first copies it as `backup`
+    ///says. A failed backup is reported in the result rather than stopping the save. With
+    ///`final_newline` a text that doesn't end in a newline gets one on disk, but not in the
+    ///buffer; an empty text is written empty.
+    ///
+    ///A file that something else changed since it was read is not saved over, and neither is
+    ///a read-only one.
+    pub fn save(&mut self, backup: &Backup, final_newline: bool) -> IoResult<Saved> {
+        let path = match self.file_path {
+            Some(ref path) => path.clone(),
+            None => return Err(no_file_name()),
+        };
+        if self.hex_view {
+            return Err(IoError {
+                kind: OtherIoError,
+                desc: "buffer is a hex view of the file",
+                detail: None,
+            })
+        }
+        if self.read_only {
+            return Err(IoError {
+                kind: OtherIoError,
+                desc: "buffer is read-only",
+                detail: None,
+            })
+        }
+        if self.changed_on_disk() {
+            return Err(IoError {
+                kind: OtherIoError,
+                desc: "file changed on disk since it was read",
+                detail: None,
+            })
+        }
+        self.write_file(&path, backup, final_newline)
+    }
 
-//Returns the index of the newline character at the end of the line mark is in.
-//Newline after mark (INCLUSIVE).
-//None iff mark is outside the len of text.
-fn get_line_end(mark: uint, text: &GapBuffer<u8>) -> Option<uint> {
-    if mark <= text.len() {
-        range(mark, text.len() + 1).filter(|idx| *idx == text.len() ||text[*idx] == b'\n')
-                                   .take(1)
-                                   .next()
-    } else { None }
-}
+    ///Add text to the end without it counting as an edit, for text the editor fills the buffer
+    ///with itself, such as search results arriving.
+    pub fn append_output(&mut self, s: &str) {
+        let start = self.text.len();
+        self.log.note_edit(start);
+        for &byte in s.as_bytes().iter() {
+            let end = self.text.len();
+            self.text.edit().insert(end, byte);
+        }
+        self.line_index.borrow_mut().edited(start, 0, newlines(s.as_bytes().iter()));
+    }
 
-//Performs a transaction on the passed in buffer.
-fn commit(transaction: &LogEntry, text: &mut GapBuffer<u8>) {
-    for change in transaction.changes.iter() {
-        match change {
-            &Change::Insert(idx, ch) => { 
-                text.insert(idx, ch);
-            }
-            &Change::Remove(idx, _) => {
-                text.remove(idx);
-            }
+    ///Replaces the text with what the file holds now, as one change that can be undone. Marks
+    ///stay on the same line if it still exists, and go to the end of the text otherwise.
+    ///
+    ///A read-only buffer stays read-only, and one becomes read-only if the file now isn't valid
+    ///UTF-8 or can't be written.
+    ///
+    ///If the file can't be read the buffer is left alone.
+    pub fn revert(&mut self) -> IoResult<()> {
+        let path = match self.file_path {
+            Some(ref path) => path.clone(),
+            None => return Err(no_file_name()),
+        };
+        let contents = try!(File::open(&path).read_to_end());
+        let (text, decoded) = decode_text(contents[]);
+        self.replace_text(text[]);
+        self.line_ending = decoded.line_ending;
+        self.mixed_line_endings = decoded.mixed_line_endings;
+        self.bom = decoded.bom;
+        if decoded.lossy || !is_writable(&path) {
+            self.read_only = true;
         }
+        self.hex_view = false;
+        self.mark_saved();
+        self.disk_stat = get_disk_stat(&path);
+        Ok(())
     }
-}
 
-//----- ITERATE BY LINES ---------------------------------------------------------------------------
+    ///Replaces the text with a hex dump of the file, which is read-only, as there is no
+    ///writing it back.
+    pub fn show_as_hex(&mut self) -> IoResult<()> {
+        let path = match self.file_path {
+            Some(ref path) => path.clone(),
+            None => return Err(no_file_name()),
+        };
+        let contents = try!(File::open(&path).read_to_end());
+        self.replace_text(hex_dump(contents[]).as_bytes());
+        self.line_ending = LineEnding::Lf;
+        self.mixed_line_endings = false;
+        self.bom = false;
+        self.read_only = true;
+        self.hex_view = true;
+        self.mark_saved();
+        Ok(())
+    }
 
-pub struct Lines<'a> {
-    buffer: &'a [u8],
-    tail: uint,
-    head: uint,
-}
+    ///Replaces the text with what the swap file holds, as one change that can be undone. The
+    ///buffer is then dirty, as the file itself doesn't hold the recovered text yet.
+    pub fn recover_from_swap(&mut self) -> IoResult<()> {
+        let path = match self.swap_path() {
+            Some(path) => path,
+            None => return Err(no_file_name()),
+        };
+        let contents = try!(File::open(&path).read_to_end());
+        let (text, decoded) = decode_text(contents[]);
+        self.replace_text(text[]);
+        self.line_ending = decoded.line_ending;
+        self.mixed_line_endings = decoded.mixed_line_endings;
+        self.bom = decoded.bom;
+        Ok(())
+    }
 
-impl<'a> Iterator for Lines<'a> {
-    type Item = &'a [u8];
+    ///The swap file that the text is kept in while `file_path` is open, so that it can be
+    ///recovered if the editor dies. None if the buffer has no file name.
+    pub fn swap_path(&self) -> Option<Path> {
+        self.file_path.as_ref().map(|path| get_swap_path(path))
+    }
 
-    fn next(&mut self) -> Option<&'a [u8]> {
-        if self.tail != self.head {
+    ///Whether the swap file was written after the file itself, or the file is gone.
+    pub fn swap_is_newer(&self) -> bool {
+        let (path, swap) = match (self.file_path.as_ref(), self.swap_path()) {
+            (Some(path), Some(swap)) => (path, swap),
+            _ => return false,
+        };
+        match (path.stat(), swap.stat()) {
+            (Ok(file), Ok(swap)) => swap.modified > file.modified,
+            (Err(_), Ok(_))      => true,
+            _                    => false,
+        }
+    }
 
-            let old_tail = self.tail;
-            //update tail to either the first char after the next \n or to self.head
-            self.tail = range(old_tail, self.head).filter(|i| { *i + 1 == self.head 
-                                                                || self.buffer[*i] == b'\n' })
-                                                  .take(1)
-                                                  .next()
-                                                  .unwrap() + 1;
-            if self.tail == self.head { Some(self.buffer[old_tail..self.tail-1]) }
-            else { Some(self.buffer[old_tail..self.tail]) }
+    ///Writes the text to the swap file, the same way a save would write it to the file.
+    pub fn write_swap(&mut self) -> IoResult<()> {
+        let path = match self.swap_path() {
+            Some(path) => path,
+            None => return Err(no_file_name()),
+        };
+        try!(self.write_to(&path, false));
+        self.seen_revision = self.log.revision();
+        self.unswapped_edits = 0;
+        Ok(())
+    }
 
-        } else { None }
+    ///Counts any edit made since the last call, and writes the swap file once `every` edits
+    ///have been made since it was last written. None if it wasn't due, or there is no file.
+    pub fn update_swap(&mut self, every: uint) -> Option<IoResult<()>> {
+        if self.file_path.is_none() { return None }
+        if self.log.revision() != self.seen_revision {
+            self.seen_revision = self.log.revision();
+            self.unswapped_edits += 1;
+        }
+        if self.unswapped_edits < every { return None }
+        Some(self.write_swap())
     }
 
-    fn size_hint(&self) -> (uint, Option<uint>) {
-        //TODO: this is technically correct but a better estimate could be implemented
-        (1, Some(self.head))
+    ///Removes the swap file, if there is one.
+    pub fn remove_swap(&self) {
+        if let Some(path) = self.swap_path() {
+            if path.exists() {
+                let _ = fs::unlink(&path);
+            }
+        }
     }
 
-}
+    ///Takes the file as it is on disk now to be the one last read, so that a save goes over it.
+    pub fn refresh_disk_stat(&mut self) {
+        self.disk_stat = match self.file_path {
+            Some(ref path) => get_disk_stat(path),
+            None           => None,
+        };
+    }
 
-//----- TESTS --------------------------------------------------------------------------------------
+    ///Writes the text to the given path, which later saves then go to as well. As the file then
+    ///holds exactly the text, the buffer is no longer read-only.
+    pub fn save_as(&mut self, path: Path, backup: &Backup, final_newline: bool) -> IoResult<Saved> {
+        let saved = try!(self.write_file(&path, backup, final_newline));
+        self.remove_autosave();
+        self.remove_swap();
+        self.file_path = Some(path);
+        self.read_only = false;
+        self.hex_view = false;
+        Ok(saved)
+    }
 
-#[cfg(test)]
-mod test {
+    ///As `save`, creating any missing directories leading to `file_path` first.
+    pub fn save_creating_dirs(&mut self, backup: &Backup, final_newline: bool) -> IoResult<Saved> {
+        if let Some(ref path) = self.file_path {
+            try!(fs::mkdir_recursive(&path.dir_path(), USER_RWX | GROUP_RWX | OTHER_RWX));
+        }
+        self.save(backup, final_newline)
+    }
 
-    use buffer::{Buffer, Direction, Mark};
+    ///As `save_as`, creating any missing directories leading to `path` first.
+    pub fn save_as_creating_dirs(&mut self, path: Path, backup: &Backup,
+                                 final_newline: bool) -> IoResult<Saved> {
+        try!(fs::mkdir_recursive(&path.dir_path(), USER_RWX | GROUP_RWX | OTHER_RWX));
+        self.save_as(path, backup, final_newline)
+    }
 
-    fn setup_buffer(testcase: &'static str) -> Buffer {
-        let mut buffer = Buffer::new();
-        buffer.text.extend(testcase.bytes());
-        buffer.set_mark(Mark::Cursor(0), 0);
-        buffer
+    ///Writes the text to the autosave file beside `file_path`, leaving the file itself alone.
+    ///The buffer stays dirty and its history is untouched. None if the buffer has no file name.
+    pub fn autosave(&self) -> Option<IoResult<Path>> {
+        self.file_path.as_ref().map(|path| {
+            let dest = get_autosave_path(path);
+            self.write_to(&dest, false).map(|_| dest)
+        })
     }
 
-    #[test]
-    fn test_insert() {
-        let mut buffer = setup_buffer("");
-        buffer.insert_char(Mark::Cursor(0), b'A');
-        assert_eq!(buffer.len(), 2);
-        assert_eq!(buffer.lines().next().unwrap(), [b'A']);
+    ///Removes the autosave file of `file_path`, if there is one.
+    pub fn remove_autosave(&self) {
+        if let Some(ref path) = self.file_path {
+            remove_autosave_of(path);
+        }
     }
 
-    #[test]
-    fn test_remove() {
-        let mut buffer = setup_buffer("ABCD");
-        buffer.remove_char(Mark::Cursor(0));
+    fn write_file(&mut self, path: &Path, backup: &Backup, final_newline: bool) -> IoResult<Saved> {
+        //A mapped file is read in first, as writing over it would change the text under us.
+        self.text.edit();
+        let existed = path.exists();
+        let backup = if existed && self.backed_up.as_ref() != Some(path) {
+            get_backup_path(path, backup).map(|dest| fs::copy(path, &dest).map(|()| dest))
+        } else { None };
 
-        assert_eq!(buffer.len(), 4);
-        assert_eq!(buffer.lines().next().unwrap(), [b'B', b'C', b'D']);
+        let add_newline = final_newline && self.text.len() > 0 && !self.ends_with_newline();
+        let (bytes, method) = try!(self.write_to(&resolve_links(path), add_newline));
+        if !existed || backup.as_ref().map_or(false, |result| result.is_ok()) {
+            self.backed_up = Some(path.clone());
+        }
+        self.mark_saved();
+        self.disk_stat = get_disk_stat(path);
+        remove_autosave_of(path);
+        Ok(Saved { bytes: bytes, method: method, backup: backup, added_newline: add_newline })
     }
 
-    #[test]
-    fn test_set_mark() {
-        let mut buffer = setup_buffer("Test");
-        buffer.set_mark(Mark::Cursor(0), 2);
+    //The text goes to a temporary file next to the target, so that the rename stays on one
+    //filesystem, and only replaces the target once it is safely on disk. If anything fails the
+    //target is left alone. Only when the directory won't take the temporary file but the
+    //target itself is writable is the target written in place. The temporary file is created
+    //afresh, failing if anything is already at its name, so that writing it can't follow a link
+    //planted in its place.
+    //
+    //The temporary file gets the target's permissions, and its owner too where that is allowed
+    //(as it is for root), so that the rename doesn't drop an executable bit or a shared group.
+    //A new file is created with the default permissions less the umask.
+    fn write_to(&self, path: &Path, add_newline: bool) -> IoResult<(uint, SaveMethod)> {
+        let mut data = self.encoded_text();
+        if add_newline {
+            data.push_all(self.line_ending.as_str().as_bytes());
+        }
+        match create_temp_file(path, data[]) {
+            Ok(tmp_path) => {
+                let mut result = Ok(());
+                if path.exists() {
+                    result = path.stat().and_then(|stat| {
+                        try!(fs::chmod(&tmp_path, stat.perm));
+                        let (uid, gid) = (stat.unstable.uid, stat.unstable.gid);
+                        let tmp_stat = try!(tmp_path.stat());
+                        if (tmp_stat.unstable.uid, tmp_stat.unstable.gid) != (uid, gid) {
+                            let _ = fs::chown(&tmp_path, uid as int, gid as int);
+                        }
+                        Ok(())
+                    });
+                }
+                if let Err(e) = result.and_then(|()| fs::rename(&tmp_path, path)) {
+                    let _ = fs::unlink(&tmp_path);
+                    return Err(e)
+                }
+                Ok((data.len(), SaveMethod::Renamed))
+            }
+            Err(ref e) if e.kind == PermissionDenied && path.exists() => {
+                let mut file = try!(File::create(path));
+                try!(file.write(data[]));
+                try!(file.fsync());
+                Ok((data.len(), SaveMethod::InPlace))
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 2);
+    //Replaces the whole text in one splice. Marks stay on the same line if it still exists,
+    //and go to the end of the text otherwise.
+    fn replace_text(&mut self, text: &[u8]) {
+        let positions: Vec<(Mark, (uint, uint))> = self.marks.keys().filter_map(|&mark| {
+            self.get_mark_pos(mark).map(|pos| (mark, pos))
+        }).collect();
+
+        let len = self.text.len();
+        self.splice(0, len, text);
+        for (mark, (line, col)) in positions.into_iter() {
+            let idx = match self.line_start(line) {
+                Some(start) => cmp::min(start + col, get_line_end(start, &self.text).unwrap()),
+                None        => self.text.len(),
+            };
+            self.set_mark(mark, idx);
+        }
     }
 
-    #[test]
-    fn test_shift_down() {
-        let mut buffer = setup_buffer("Test\nA\nTest");
-        buffer.set_mark(Mark::Cursor(0), 2);
-        buffer.shift_mark(Mark::Cursor(0), Direction::Down(2));
+    //The text as it goes to disk, with every `\n` written as the buffer's line ending, after
+    //the byte order mark if the file has one.
+    fn encoded_text(&self) -> Vec<u8> {
+        let ending = self.line_ending.as_str().as_bytes();
+        let mut data = Vec::with_capacity(self.text.len() + UTF8_BOM.len());
+        if self.bom { data.push_all(UTF8_BOM) }
+        for &ch in self.text.as_slice().iter() {
+            if ch == b'\n' { data.push_all(ending) }
+            else { data.push(ch) }
+        }
+        data
+    }
 
-        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (2, 2));
+    //----- MUTATORS -------------------------------------------------------------------------------
+
+    ///Record that the current contents have been saved, clearing the dirty flag.
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.log.revision();
+        self.saved_line_ending = self.line_ending;
+        self.saved_bom = self.bom;
     }
 
-    #[test]
-    fn test_shift_right() {
-        let mut buffer = setup_buffer("Test");
-        buffer.shift_mark(Mark::Cursor(0), Direction::Right(1));
+    ///Sets the line ending used from the next save on, for the whole file.
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        self.line_ending = ending;
+        self.mixed_line_endings = false;
+    }
 
-        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 1);
+    ///Sets whether the file is saved with a byte order mark at the start.
+    pub fn set_bom(&mut self, bom: bool) {
+        self.bom = bom;
     }
 
-    #[test]
-    fn test_shift_up() {
-        let mut buffer = setup_buffer("Test\nA\nTest");
-        buffer.set_mark(Mark::Cursor(0), 10);
-        buffer.shift_mark(Mark::Cursor(0), Direction::Up(1));
+    ///Sets the mark to a given absolute index. Adds a new mark or overwrites an existing mark.
+    pub fn set_mark(&mut self, mark: Mark, idx: uint) {
+        if get_line(idx, &self.text).is_some() {
+            if let Some(tuple) = self.marks.get_mut(&mark) {
+                *tuple = (idx, None);
+                return;
+            }
+            self.marks.insert(mark, (idx, None));
+        }
+    }
 
-        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 1));
+    ///Forget about a mark, so it is no longer kept up to date with edits.
+    pub fn remove_mark(&mut self, mark: Mark) {
+        self.marks.remove(&mark);
     }
 
-    #[test]
-    fn test_shift_left() {
-        let mut buffer = setup_buffer("Test");
-        buffer.set_mark(Mark::Cursor(0), 2);
-        buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+    ///Start selecting from where the cursor is, so that the selection runs from there to wherever
+    ///the cursor is moved. Any edit ends it.
+    pub fn start_selection(&mut self, cursor: Mark) {
+        if let Some(idx) = self.get_mark_idx(cursor) {
+            self.set_mark(Mark::Anchor, idx);
+            self.selection = Some(cursor);
+            self.block_selection = false;
+        }
+    }
 
-        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 1);
+    ///Stop selecting, leaving the text as it is.
+    pub fn clear_selection(&mut self) {
+        self.marks.remove(&Mark::Anchor);
+        self.selection = None;
+        self.block_selection = false;
     }
 
-    #[test]
-    fn test_shift_linestart() {
-        let mut buffer = setup_buffer("Test");
-        buffer.set_mark(Mark::Cursor(0), 2);
-        buffer.shift_mark(Mark::Cursor(0), Direction::LineStart);
+    ///As `start_selection`, selecting the rectangle between where the cursor is and where it is
+    ///moved to: the text on each line from the left edge's screen column to the right edge's.
+    pub fn start_block_selection(&mut self, cursor: Mark) {
+        self.start_selection(cursor);
+        self.block_selection = self.selection.is_some();
+    }
 
-        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    ///Whether the selection is a block.
+    pub fn is_block_selection(&self) -> bool {
+        self.block_selection
+    }
+
+    ///The screen column of the block selection's left edge, and the start and end of the part of
+    ///each of its lines inside it, top first. A line too short to reach the left edge gives an
+    ///empty range at its end. None if the selection isn't a block.
+    pub fn block_ranges(&self, tab_width: uint) -> Option<(uint, Vec<(uint, uint)>)> {
+        let cursor = match self.selection {
+            Some(cursor) if self.block_selection => cursor,
+            _                                    => return None,
+        };
+        let (anchor, cursor) = match (self.get_mark_idx(Mark::Anchor), self.get_mark_idx(cursor)) {
+            (Some(anchor), Some(cursor)) => (anchor, cursor),
+            _                            => return None,
+        };
+        let (top, bottom) = (cmp::min(anchor, cursor), cmp::max(anchor, cursor));
+        let (first_line, last_line) = (self.line_of(top), self.line_of(bottom));
+        let (a, b) = (get_screen_col(anchor, tab_width, &self.text),
+                      get_screen_col(cursor, tab_width, &self.text));
+        let (left, right) = (cmp::min(a, b), cmp::max(a, b));
+
+        let ranges = range(first_line, last_line + 1).filter_map(|linenum| {
+            self.line_start(linenum).map(|start| {
+                (get_col_idx(start, left, tab_width, &self.text),
+                 get_col_idx(start, right, tab_width, &self.text))
+            })
+        }).collect();
+        Some((left, ranges))
+    }
+
+    ///Delete the part of each line in the block selection, as one step to undo, giving what was
+    ///removed from each line, top first, along with where the block's left edge is on the line
+    ///afterwards. With `pad`, lines too short to reach the left edge are padded with spaces up to
+    ///it, so that every line has a spot there.
+    pub fn delete_block(&mut self, tab_width: uint, pad: bool) -> Vec<(String, uint)> {
+        let (left, ranges) = match self.block_ranges(tab_width) {
+            Some(block) => block,
+            None        => return Vec::new(),
+        };
+        let revision = self.log.revision();
+        let mut deleted = Vec::new();
+        // how far the edits to the lines above have moved each line's range
+        let (mut added, mut removed) = (0, 0);
+        for &(start, end) in ranges.iter() {
+            let (start, end) = (start + added - removed, end + added - removed);
+            let text = self.splice(start, end, &[]);
+            removed += text.len();
+            let width = get_screen_col(start, tab_width, &self.text);
+            let mut edge = start;
+            if pad && width < left {
+                let spaces: Vec<u8> = repeat(b' ').take(left - width).collect();
+                self.splice(start, start, spaces[]);
+                added += spaces.len();
+                edge += spaces.len();
+            }
+            deleted.push((String::from_utf8_lossy(text[]).into_owned(), edge));
+        }
+        self.log.group_since(revision);
+        deleted
+    }
+
+    ///Insert `lines` down successive lines from the mark's, each at the mark's screen column, as
+    ///one step to undo. Lines too short to reach the column are padded with spaces up to it, and
+    ///lines are added to the end of the buffer when there aren't enough. The mark stays put.
+    pub fn insert_block(&mut self, mark: Mark, lines: &[String], tab_width: uint) {
+        let (first_line, col) = match self.get_mark_pos(mark) {
+            Some((line, _)) => (line, self.get_mark_screen_offset(mark, tab_width).unwrap()),
+            None            => return,
+        };
+        let revision = self.log.revision();
+        for (i, line) in lines.iter().enumerate() {
+            let start = match self.line_start(first_line + i) {
+                Some(start) => start,
+                None        => {
+                    let end = self.text.len();
+                    self.splice(end, end, b"\n");
+                    end + 1
+                }
+            };
+            let mut idx = get_col_idx(start, col, tab_width, &self.text);
+            let width = get_screen_col(idx, tab_width, &self.text);
+            if width < col {
+                let spaces: Vec<u8> = repeat(b' ').take(col - width).collect();
+                self.splice(idx, idx, spaces[]);
+                idx += spaces.len();
+            }
+            self.splice(idx, idx, line.as_bytes());
+        }
+        self.log.group_since(revision);
+    }
+
+    ///Whether a selection has been started, even if nothing is in it yet.
+    pub fn is_selecting(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    ///The start and end of the selected text, whichever way round it was selected. None if there
+    ///is no selection, or nothing in it. For a block selection this runs from one corner to the
+    ///other, taking in the whole of the lines between.
+    pub fn selection_range(&self) -> Option<(uint, uint)> {
+        let cursor = match self.selection {
+            Some(cursor) => cursor,
+            None         => return None,
+        };
+        match (self.get_mark_idx(Mark::Anchor), self.get_mark_idx(cursor)) {
+            (Some(a), Some(b)) if a != b => Some((cmp::min(a, b), cmp::max(a, b))),
+            _                            => None,
+        }
+    }
+
+    ///Select [start, end), with the anchor at the start and the cursor at the end.
+    pub fn select_range(&mut self, cursor: Mark, start: uint, end: uint) {
+        self.set_mark(cursor, start);
+        self.start_selection(cursor);
+        self.set_mark(cursor, end);
+    }
+
+    ///The number of the first line the selection takes in, and of the line after its last. A
+    ///selection ending at the start of a line, having taken in the newline before it, doesn't
+    ///take in that line. None if nothing is selected.
+    pub fn selected_lines(&self) -> Option<(uint, uint)> {
+        self.selection_range().map(|(start, end)| (self.line_of(start), self.line_of(end - 1) + 1))
+    }
+
+    ///The next larger of the word, line, paragraph and whole text around [start, end) that takes
+    ///in all of it, for growing a selection a step at a time. Lines and paragraphs are taken with
+    ///their final newline. None once the range is the whole text.
+    pub fn enclosing_range(&self, start: uint, end: uint) -> Option<(uint, uint)> {
+        let text = &self.text;
+        let last = if end > start { end - 1 } else { start };
+        let line = (get_line(start, text).unwrap_or(0),
+                    cmp::min(get_line_end(last, text).unwrap_or(text.len()) + 1, text.len()));
+        let paragraph = (get_paragraph_start(start, text), get_paragraph_end(last, text));
+        let ranges = vec![get_word_range(start, text), Some(line), Some(paragraph),
+                          Some((0, text.len()))];
+        ranges.into_iter().filter_map(|r| r).find(|&(s, e)| {
+            s <= start && e >= end && e - s > end - start
+        })
+    }
+
+    //Shift a mark relative to its position according to the direction given.
+    pub fn shift_mark(&mut self, mark: Mark, direction: Direction) {
+        let last = self.len() - 1;
+        let tab_width = self.tab_width;
+        let text = &self.text;
+        if let Some(tuple) = self.marks.get_mut(&mark) {
+            let (idx, wanted) = *tuple;
+            if let (Some(line), Some(line_end)) = (get_line(idx, text), get_line_end(idx, text)) {
+                //The screen column to move up or down at: the one kept from the last move up or
+                //down, or else the mark's own.
+                let col = || wanted.unwrap_or_else(|| get_drawn_col(line, idx, tab_width, text));
+                *tuple = match direction {
+                    //For every relative motion of a mark, should return this tuple:
+                    //  value 0:    the absolute index of the mark in the file
+                    //  value 1:    the screen column to keep to, set only by direct vertical
+                    //              traversals, which land on the character drawn there
+                    Direction::Left(n)      =>  {
+                        (range(0, n).fold(idx, |i, _| get_grapheme_boundary(i, false, text)), None)
+                    }
+                    Direction::Right(n)     =>  {
+                        (range(0, n).fold(idx, |i, _| get_grapheme_boundary(i, true, text)), None)
+                    }
+                    Direction::Up(0) | Direction::Down(0) => { (idx, wanted) }
+                    Direction::Up(n)        =>  {
+                        let nlines = range(0, line).rev().filter(|i| text[*i] == b'\n')
+                                                         .take(n + 1)
+                                                         .collect::<Vec<uint>>();
+                        if n > nlines.len() { (0, None) }
+                        else {
+                            let start = if n == nlines.len() { 0 } else { nlines[n] + 1 };
+                            let col = col();
+                            (get_drawn_idx(start, col, tab_width, text), Some(col))
+                        }
+                    }
+                    Direction::Down(n)      =>  {
+                        let nlines = range(idx, text.len()).filter(|i| text[*i] == b'\n')
+                                                           .take(n)
+                                                           .collect::<Vec<uint>>();
+                        if n > nlines.len() { (last, None) }
+                        else {
+                            let col = col();
+                            (get_drawn_idx(nlines[n - 1] + 1, col, tab_width, text), Some(col))
+                        }
+                    }
+                    Direction::LineStart    =>  { (line, None) }
+                    Direction::LineEnd      =>  { (line_end, None) }
+                    Direction::BufferStart  =>  { (0, None) }
+                    Direction::BufferEnd    =>  { (last, None) }
+                    Direction::NextParagraph => { (get_next_paragraph(idx, text), None) }
+                    Direction::PrevParagraph => { (get_prev_paragraph(idx, text), None) }
+                    Direction::NextWord(n)  =>  {
+                        (range(0, n).fold(idx, |i, _| get_next_word_end(i, text)), None)
+                    }
+                    Direction::PrevWord(n)  =>  {
+                        (range(0, n).fold(idx, |i, _| get_prev_word_start(i, text)), None)
+                    }
+                }
+            }
+        }
+    }
+
+    ///The character just after the mark. None if the mark is at the end of the buffer.
+    pub fn char_after(&self, mark: Mark) -> Option<u8> {
+        match self.get_mark_idx(mark) {
+            Some(idx) if idx < self.text.len() => Some(self.text[idx]),
+            _                                  => None,
+        }
+    }
+
+    ///The character just before the mark. None if the mark is at the start of the buffer.
+    pub fn char_before(&self, mark: Mark) -> Option<u8> {
+        match self.get_mark_idx(mark) {
+            Some(idx) if idx > 0 => Some(self.text[idx - 1]),
+            _                    => None,
+        }
+    }
+
+    ///The whole character just before the mark, decoded from every byte of it. None if the mark
+    ///is at the start of the buffer, or the bytes before it aren't valid UTF-8.
+    pub fn decoded_char_before(&self, mark: Mark) -> Option<char> {
+        match self.get_mark_idx(mark) {
+            Some(idx) if idx > 0 => {
+                let bytes: Vec<u8> = range(get_char_start(idx, &self.text), idx)
+                                         .map(|i| self.text[i]).collect();
+                str::from_utf8(bytes.as_slice()).ok().and_then(|s| s.chars().next())
+            }
+            _                    => None,
+        }
+    }
+
+    ///The index of the first character on the (0-based) line that isn't a space or tab. None if
+    ///there is no such line, or nothing but whitespace on it.
+    pub fn first_non_whitespace(&self, linenum: uint) -> Option<uint> {
+        let start = match self.line_start(linenum) {
+            Some(start) => start,
+            None        => return None,
+        };
+        range(start, self.text.len()).take_while(|i| self.text[*i] != b'\n')
+                                     .find(|i| self.text[*i] != b' ' && self.text[*i] != b'\t')
+    }
+
+    ///The spaces and tabs at the start of the mark's line, up to the mark.
+    pub fn leading_whitespace(&self, mark: Mark) -> String {
+        let mut indent = String::new();
+        if let Some(idx) = self.get_mark_idx(mark) {
+            for i in range(get_line(idx, &self.text).unwrap(), idx) {
+                match self.text[i] {
+                    b' ' | b'\t' => indent.push(self.text[i] as char),
+                    _           => break,
+                }
+            }
+        }
+        indent
+    }
+
+    ///The start and end of the word the mark is on, by the same rules as moving by words. None if
+    ///the mark is on whitespace or punctuation.
+    pub fn word_at(&self, mark: Mark) -> Option<(uint, uint)> {
+        self.get_mark_idx(mark).and_then(|idx| get_word_around(idx, &self.text))
+    }
+
+    ///Whether [start, end) is a whole word, rather than part of a longer one.
+    pub fn is_whole_word(&self, start: uint, end: uint) -> bool {
+        (start == 0 || !is_word_char(self.text[start - 1]))
+            && (end >= self.text.len() || !is_word_char(self.text[end]))
+    }
+
+    ///The start and end of the line the mark is on, not counting its newline.
+    pub fn line_range(&self, mark: Mark) -> Option<(uint, uint)> {
+        self.get_mark_idx(mark).and_then(|idx| {
+            match (get_line(idx, &self.text), get_line_end(idx, &self.text)) {
+                (Some(start), Some(end)) => Some((start, end)),
+                _                        => None,
+            }
+        })
+    }
+
+    ///The text in [start, end), with any invalid UTF-8 replaced.
+    pub fn text_range(&self, start: uint, end: uint) -> String {
+        let bytes: Vec<u8> = range(start, cmp::min(end, self.text.len())).map(|i| self.text[i]).collect();
+        String::from_utf8_lossy(bytes[]).into_owned()
+    }
+
+    ///The index of the bracket matching the one at offset. If there is no bracket at offset, the
+    ///first one after it on the same line is matched instead. None if there is no bracket to match,
+    ///or it is unbalanced.
+    pub fn find_matching_bracket(&self, offset: uint) -> Option<uint> {
+        let text = &self.text;
+        let line_end = match get_line_end(offset, text) {
+            Some(end) => end,
+            None      => return None,
+        };
+        let start = match range(offset, line_end).find(|i| bracket_pair(text[*i]).is_some()) {
+            Some(start) => start,
+            None        => return None,
+        };
+
+        let (open, close, forward) = bracket_pair(text[start]).unwrap();
+        let mut depth = 0u;
+        if forward {
+            for i in range(start, text.len()) {
+                if text[i] == open { depth += 1; }
+                else if text[i] == close {
+                    depth -= 1;
+                    if depth == 0 { return Some(i) }
+                }
+            }
+        } else {
+            for i in range(0, start + 1).rev() {
+                if text[i] == close { depth += 1; }
+                else if text[i] == open {
+                    depth -= 1;
+                    if depth == 0 { return Some(i) }
+                }
+            }
+        }
+        None
+    }
+
+    ///The indices of the innermost `open` and `close` brackets around idx, which may be one of
+    ///them. None if idx isn't inside such a pair, or the pair is unbalanced.
+    pub fn enclosing_brackets(&self, idx: uint, open: u8, close: u8) -> Option<(uint, uint)> {
+        let text = &self.text;
+        let start = if idx < text.len() && text[idx] == open { Some(idx) } else {
+            let mut depth = 0u;
+            range(0, idx).rev().find(|&i| {
+                if text[i] == close { depth += 1 }
+                else if text[i] == open {
+                    if depth == 0 { return true }
+                    depth -= 1;
+                }
+                false
+            })
+        };
+        start.and_then(|start| self.find_matching_bracket(start).map(|end| (start, end)))
+    }
+
+    ///The indices of the `quote` characters around idx on its line, which may be one of them.
+    ///Quotes are paired off from the start of the line, and those escaped with a backslash are
+    ///passed over. None if idx isn't between a pair.
+    pub fn enclosing_quotes(&self, idx: uint, quote: u8) -> Option<(uint, uint)> {
+        let text = &self.text;
+        let (start, end) = match (get_line(idx, text), get_line_end(idx, text)) {
+            (Some(start), Some(end)) => (start, end),
+            _                        => return None,
+        };
+        // an odd number of backslashes before a quote escapes it
+        let escaped = |&: i: uint| {
+            range(start, i).rev().take_while(|&j| text[j] == b'\\').count() % 2 == 1
+        };
+        let quotes: Vec<uint> = range(start, end).filter(|&i| text[i] == quote && !escaped(i))
+                                                 .collect();
+        quotes.chunks(2).filter(|pair| pair.len() == 2).map(|pair| (pair[0], pair[1]))
+              .find(|&(open, close)| open <= idx && idx <= close)
+    }
+
+    ///The word the mark is on along with the spaces after it, or before it if there are none
+    ///after. None if the mark isn't on a word.
+    pub fn word_with_space(&self, mark: Mark) -> Option<(uint, uint)> {
+        let text = &self.text;
+        let is_space = |&: i: uint| text[i] == b' ' || text[i] == b'\t';
+        self.word_at(mark).map(|(mut start, mut end)| {
+            let word_end = end;
+            while end < text.len() && is_space(end) { end += 1 }
+            if end == word_end {
+                while start > 0 && is_space(start - 1) { start -= 1 }
+            }
+            (start, end)
+        })
+    }
+
+    ///The index of the first occurrence of needle at or after from. None if there is none, or the
+    ///needle is empty.
+    pub fn find(&self, needle: &str, from: uint) -> Option<uint> {
+        let (text, needle) = (&self.text, needle.as_bytes());
+        if needle.is_empty() || needle.len() > text.len() { return None }
+        range(from, text.len() - needle.len() + 1).find(|&i| {
+            range(0, needle.len()).all(|j| text[i + j] == needle[j])
+        })
+    }
+
+    ///The index of the last occurrence of needle starting before from. None if there is none, or
+    ///the needle is empty.
+    pub fn rfind(&self, needle: &str, from: uint) -> Option<uint> {
+        let (text, needle) = (&self.text, needle.as_bytes());
+        if needle.is_empty() || needle.len() > text.len() { return None }
+        let end = cmp::min(from, text.len() - needle.len() + 1);
+        range(0, end).rev().find(|&i| {
+            range(0, needle.len()).all(|j| text[i + j] == needle[j])
+        })
+    }
+
+    ///The start and end of the first occurrence of needle at or after from, ignoring case if
+    ///`case` says to for this needle. Letters are compared by their lower case forms, so É
+    ///matches é. As with `find_regex`, a match ignoring case doesn't span lines.
+    pub fn find_case(&self, needle: &str, from: uint, case: Case) -> Option<(uint, uint)> {
+        if case.ignores_case(needle) {
+            self.find_lines(&LineMatcher::Folded(fold_case(needle)), from)
+        } else {
+            self.find(needle, from).map(|start| (start, start + needle.len()))
+        }
+    }
+
+    ///The start and end of the last occurrence of needle starting before from, ignoring case as
+    ///`find_case` does.
+    pub fn rfind_case(&self, needle: &str, from: uint, case: Case) -> Option<(uint, uint)> {
+        if case.ignores_case(needle) {
+            self.rfind_lines(&LineMatcher::Folded(fold_case(needle)), from)
+        } else {
+            self.rfind(needle, from).map(|start| (start, start + needle.len()))
+        }
+    }
+
+    ///The start and end of the first match of re starting at or after from.
+    ///
+    ///Matches don't span lines: each line is copied out of the gap buffer and matched on its own,
+    ///so a search only copies the lines it looks through rather than the whole text. Lines that
+    ///aren't valid UTF-8 are skipped.
+    pub fn find_regex(&self, re: &Regex, from: uint) -> Option<(uint, uint)> {
+        self.find_lines(&LineMatcher::Regex(re), from)
+    }
+
+    ///The start and end of the last match of re starting before from, found line by line as
+    ///`find_regex` does.
+    pub fn rfind_regex(&self, re: &Regex, from: uint) -> Option<(uint, uint)> {
+        self.rfind_lines(&LineMatcher::Regex(re), from)
+    }
+
+    ///The start and end of every occurrence of needle starting in [from, to), in order, ignoring
+    ///case as `find_case` does. Only the lines the range covers are looked through, and only as
+    ///the iterator gets to them, so going through the matches on the screen stays cheap however
+    ///large the buffer is. Matches don't span lines.
+    pub fn matches_case<'a>(&'a self, needle: &'a str, from: uint, to: uint, case: Case) -> Matches<'a> {
+        let matcher = if case.ignores_case(needle) { LineMatcher::Folded(fold_case(needle)) }
+                      else { LineMatcher::Exact(needle) };
+        Matches::new(self, matcher, from, to)
+    }
+
+    ///The start and end of every match of re starting in [from, to), found line by line as
+    ///`matches_case` does. Lines that aren't valid UTF-8 are skipped.
+    pub fn matches_regex<'a>(&'a self, re: &'a Regex, from: uint, to: uint) -> Matches<'a> {
+        Matches::new(self, LineMatcher::Regex(re), from, to)
+    }
+
+    //The first match in a line starting at or after from.
+    fn find_lines(&self, matcher: &LineMatcher, from: uint) -> Option<(uint, uint)> {
+        let text = &self.text;
+        let mut start = match get_line(from, text) {
+            Some(start) => start,
+            None        => return None,
+        };
+        let mut line = Vec::new();
+        while start <= text.len() {
+            let end = get_line_end(start, text).unwrap_or(text.len());
+            if let Some(s) = copy_line(text, start, end, &mut line) {
+                let found = matcher.matches(s).into_iter().map(|(b, e)| (start + b, start + e))
+                                                          .find(|&(b, _)| b >= from);
+                if found.is_some() { return found }
+            }
+            start = end + 1;
+        }
+        None
+    }
+
+    //The last match in a line starting before from.
+    fn rfind_lines(&self, matcher: &LineMatcher, from: uint) -> Option<(uint, uint)> {
+        let text = &self.text;
+        let mut start = get_line(cmp::min(from, text.len()), text).unwrap_or(0);
+        let mut line = Vec::new();
+        loop {
+            let end = get_line_end(start, text).unwrap_or(text.len());
+            if let Some(s) = copy_line(text, start, end, &mut line) {
+                let found = matcher.matches(s).into_iter().map(|(b, e)| (start + b, start + e))
+                                                          .filter(|&(b, _)| b < from)
+                                                          .last();
+                if found.is_some() { return found }
+            }
+            if start == 0 { return None }
+            start = get_line(start - 1, text).unwrap_or(0);
+        }
+    }
+
+    ///Move the mark to the start of the given (1-based) line, or of the last line if there are not
+    ///that many.
+    pub fn goto_line(&mut self, mark: Mark, linenum: uint) {
+        let last = self.line_count() - 1;
+        let target = cmp::min(if linenum > 0 { linenum - 1 } else { 0 }, last);
+        if let Some(idx) = self.line_start(target) {
+            self.set_mark(mark, idx);
+        }
+    }
+
+    ///Remove the char at the mark, along with any characters combined with it, returning them.
+    ///None if the mark is at the end of the buffer.
+    pub fn remove_char(&mut self, mark: Mark) -> Option<String> {
+        match self.get_mark_idx(mark) {
+            Some(idx) if idx < self.text.len() => {
+                let end = get_grapheme_boundary(idx, true, &self.text);
+                let removed = self.splice(idx, end, &[]);
+                Some(String::from_utf8_lossy(removed.as_slice()).into_owned())
+            }
+            _ => None,
+        }
+    }
+
+    ///Insert a char at the mark.
+    pub fn insert_char(&mut self, mark: Mark, ch: u8) {
+        if let Some(idx) = self.get_mark_idx(mark) {
+            self.splice(idx, idx, &[ch]);
+        }
+    }
+
+    ///Insert a string at the mark, as a single undoable action, leaving the mark after it.
+    pub fn insert_str(&mut self, mark: Mark, s: &str) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+
+        self.splice(idx, idx, s.as_bytes());
+        self.set_mark(mark, idx + s.len());
+    }
+
+    ///Remove the word next to the mark in the given direction, returning the removed text.
+    ///
+    ///Whitespace and punctuation between the mark and the word go with it, but the deletion stops
+    ///at a newline unless the newline is the only thing left to remove in that direction.
+    pub fn delete_word(&mut self, mark: Mark, direction: Direction) -> String {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return String::new(),
+        };
+        let (start, end) = match direction {
+            Direction::Left(_)  => (get_word_start(idx, &self.text), idx),
+            Direction::Right(_) => (idx, get_word_end(idx, &self.text)),
+            _                   => (idx, idx),
+        };
+
+        let removed = self.splice(start, end, &[]);
+        self.set_mark(mark, start);
+        String::from_utf8_lossy(removed.as_slice()).into_owned()
+    }
+
+    ///Remove the line the mark is on, including its newline, returning the removed text.
+    ///
+    ///The mark is left at the start of the following line. When removing the last line of the
+    ///buffer, the newline before it is removed instead and the mark moves to the previous line.
+    pub fn delete_line(&mut self, mark: Mark) -> String {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return String::new(),
+        };
+        let mut start = get_line(idx, &self.text).unwrap();
+        let mut end = get_line_end(idx, &self.text).unwrap();
+        if end < self.text.len() { end += 1; }
+        else if start > 0 { start -= 1; }
+
+        let removed = self.splice(start, end, &[]);
+        let line = get_line(start, &self.text).unwrap();
+        self.set_mark(mark, line);
+        String::from_utf8_lossy(removed.as_slice()).into_owned()
+    }
+
+    ///Insert a copy of the line the mark is on below it, moving the mark onto the copy.
+    pub fn duplicate_line(&mut self, mark: Mark) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let line = get_line(idx, &self.text).unwrap();
+        let line_end = get_line_end(idx, &self.text).unwrap();
+
+        let mut copy = vec![b'\n'];
+        copy.extend(range(line, line_end).map(|i| self.text[i]));
+        self.splice(line_end, line_end, copy.as_slice());
+        self.set_mark(mark, line_end + 1 + idx - line);
+    }
+
+    ///Join the line the mark is on with the next one, moving the mark to the join point.
+    ///
+    ///Any indentation at the start of the next line is collapsed into a single space.
+    pub fn join_lines(&mut self, mark: Mark) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let line_end = get_line_end(idx, &self.text).unwrap();
+        if line_end == self.text.len() { return; }
+
+        let mut end = line_end + 1;
+        while end < self.text.len() && (self.text[end] == b' ' || self.text[end] == b'\t') {
+            end += 1;
+        }
+        if end == self.text.len() || self.text[end] == b'\n' {
+            self.splice(line_end, end, &[]);
+        } else {
+            self.splice(line_end, end, b" ");
+        }
+        self.set_mark(mark, line_end);
+    }
+
+    ///Swap the character before the mark with the one under it, moving the mark past both.
+    ///
+    ///At the end of a line the two characters before the mark are swapped instead. Nothing
+    ///happens if the swap would involve a newline.
+    pub fn transpose_chars(&mut self, mark: Mark) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let line = get_line(idx, &self.text).unwrap();
+        let line_end = get_line_end(idx, &self.text).unwrap();
+
+        let (start, mid, end) = if idx == line_end {
+            if idx < line + 2 { return; }
+            let mid = get_char_start(idx, &self.text);
+            (get_char_start(mid, &self.text), mid, idx)
+        } else {
+            if idx == line { return; }
+            (get_char_start(idx, &self.text), idx, get_char_end(idx, &self.text))
+        };
+        if start < line { return; }
+
+        let mut swapped: Vec<u8> = range(mid, end).map(|i| self.text[i]).collect();
+        swapped.extend(range(start, mid).map(|i| self.text[i]));
+        self.splice(start, end, swapped.as_slice());
+        self.set_mark(mark, end);
+    }
+
+    ///Convert the word after the mark to upper case, moving the mark past it.
+    pub fn upcase_word(&mut self, mark: Mark) {
+        self.change_word_case(mark, true);
+    }
+
+    ///Convert the word after the mark to lower case, moving the mark past it.
+    pub fn downcase_word(&mut self, mark: Mark) {
+        self.change_word_case(mark, false);
+    }
+
+    ///Convert the text in [start, end) to upper case, keeping the selection over it. Nothing
+    ///happens if the range is inverted or beyond the end of the buffer.
+    pub fn upcase_region(&mut self, start: uint, end: uint) {
+        self.change_region_case(start, end, true);
+    }
+
+    ///Convert the text in [start, end) to lower case, as `upcase_region` does to upper case.
+    pub fn downcase_region(&mut self, start: uint, end: uint) {
+        self.change_region_case(start, end, false);
+    }
+
+    ///Sort the lines in [start_line, end_line), keeping the mark on the same line content.
+    pub fn sort_lines(&mut self, mark: Mark, start_line: uint, end_line: uint, reverse: bool) {
+        let end_line = cmp::min(end_line, self.line_count());
+        if start_line + 1 >= end_line { return; }
+
+        let start = self.line_start(start_line).unwrap();
+        let end = self.line_start(end_line).unwrap_or(self.text.len());
+        let mut region: Vec<u8> = range(start, end).map(|i| self.text[i]).collect();
+        let trailing_newline = region.last() == Some(&b'\n');
+        if trailing_newline { region.pop(); }
+
+        // remember which line the mark was on, and where within it
+        let cursor = match self.get_mark_idx(mark) {
+            Some(idx) if idx >= start && (idx < end || (idx == end && !trailing_newline)) => {
+                let line = get_line(idx, &self.text).unwrap();
+                Some((range(line, get_line_end(idx, &self.text).unwrap()).map(|i| self.text[i])
+                                                                       .collect::<Vec<u8>>(),
+                      idx - line))
+            }
+            _ => None,
+        };
+
+        let mut lines: Vec<&[u8]> = region.as_slice().split(|ch| *ch == b'\n').collect();
+        lines.sort();
+        if reverse { lines.reverse(); }
+
+        let mut sorted = Vec::with_capacity(end - start);
+        let mut cursor_idx = None;
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 { sorted.push(b'\n'); }
+            if let Some((ref content, col)) = cursor {
+                if cursor_idx.is_none() && *line == content.as_slice() {
+                    cursor_idx = Some(start + sorted.len() + col);
+                }
+            }
+            sorted.push_all(*line);
+        }
+        if trailing_newline { sorted.push(b'\n'); }
+
+        self.splice(start, end, sorted.as_slice());
+        if let Some(idx) = cursor_idx {
+            self.set_mark(mark, idx);
+        } else if cursor.is_some() {
+            self.set_mark(mark, start);
+        }
+    }
+
+    ///Sort every line in the buffer, keeping the mark on the same line content.
+    pub fn sort_all_lines(&mut self, mark: Mark, reverse: bool) {
+        let end_line = self.line_count();
+        self.sort_lines(mark, 0, end_line, reverse);
+    }
+
+    ///Insert `indent` at the start of every non-empty line in [start_line, end_line), as a single
+    ///undoable action. Marks on those lines move along with their text.
+    pub fn indent_lines(&mut self, start_line: uint, end_line: uint, indent: &str) {
+        let end_line = cmp::min(end_line, self.line_count());
+        let edits = range(start_line, end_line).filter_map(|line| {
+            let start = self.line_start(line).unwrap();
+            if start == self.text.len() || self.text[start] == b'\n' { None }
+            else { Some((start, start, indent.as_bytes().to_vec())) }
+        }).collect();
+        self.splice_lines(edits);
+    }
+
+    ///Comment out every non-blank line in [start_line, end_line) by inserting `leader` after its
+    ///indentation, or uncomment them all if every one of them already has it, as a single
+    ///undoable action. Marks stay on the same character.
+    pub fn toggle_comment(&mut self, start_line: uint, end_line: uint, leader: &str) {
+        let end_line = cmp::min(end_line, self.line_count());
+        let leader = leader.as_bytes();
+
+        // where the text of each non-blank line starts, after its indentation
+        let starts: Vec<uint> = range(start_line, end_line).filter_map(|line| {
+            let mut idx = self.line_start(line).unwrap();
+            while idx < self.text.len() && (self.text[idx] == b' ' || self.text[idx] == b'\t') {
+                idx += 1;
+            }
+            if idx == self.text.len() || self.text[idx] == b'\n' { None } else { Some(idx) }
+        }).collect();
+        if starts.is_empty() { return }
+
+        let commented = starts.iter().all(|&idx| {
+            range(0, leader.len()).all(|i| idx + i < self.text.len() && self.text[idx + i] == leader[i])
+        });
+
+        let edits = starts.into_iter().map(|idx| {
+            if commented { (idx, idx + leader.len(), Vec::new()) }
+            else { (idx, idx, leader.to_vec()) }
+        }).collect();
+        self.splice_lines(edits);
+    }
+
+    ///Remove one level of indentation, up to `width` columns of leading spaces and tabs, from
+    ///every line in [start_line, end_line) as a single undoable action. Nothing but whitespace is
+    ///ever removed, and marks in the removed indentation move to the start of their line.
+    pub fn dedent_lines(&mut self, start_line: uint, end_line: uint, width: uint) {
+        let end_line = cmp::min(end_line, self.line_count());
+        let edits = range(start_line, end_line).filter_map(|line| {
+            let start = self.line_start(line).unwrap();
+            let (mut end, mut col) = (start, 0);
+            while col < width && end < self.text.len() {
+                match self.text[end] {
+                    b' '  => col += 1,
+                    b'\t' => col = width,
+                    _     => break,
+                }
+                end += 1;
+            }
+            if end > start { Some((start, end, Vec::new())) } else { None }
+        }).collect();
+        self.splice_lines(edits);
+    }
+
+    ///Replace the tabs in the indentation of every line with spaces up to the next tab stop, or
+    ///the tabs anywhere in the line if `whole_line`, as a single undoable action. Marks keep their
+    ///screen column. Returns the number of lines that were changed.
+    pub fn expand_tabs(&mut self, tab_width: uint, whole_line: bool) -> uint {
+        self.convert_tabs(tab_width, whole_line, true)
+    }
+
+    ///Replace the runs of spaces in the indentation of every line that reach a tab stop with tabs,
+    ///or such runs anywhere in the line if `whole_line`, as a single undoable action. Marks keep
+    ///their screen column. Returns the number of lines that were changed.
+    pub fn unexpand_tabs(&mut self, tab_width: uint, whole_line: bool) -> uint {
+        self.convert_tabs(tab_width, whole_line, false)
+    }
+
+    fn convert_tabs(&mut self, tab_width: uint, whole_line: bool, expand: bool) -> uint {
+        // remember where every mark is on screen, since the index within its line may change
+        let marks: Vec<Mark> = self.marks.keys().map(|mark| *mark).collect();
+        let positions: Vec<(Mark, uint, uint)> = marks.into_iter().filter_map(|mark| {
+            match (self.get_mark_coords(mark), self.get_mark_screen_offset(mark, tab_width)) {
+                (Some((_, line)), Some(col)) => Some((mark, line, col)),
+                _                            => None,
+            }
+        }).collect();
+
+        let mut edits = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = get_line_end(start, &self.text).unwrap();
+            let line: Vec<u8> = range(start, end).map(|i| self.text[i]).collect();
+            // lines that aren't valid UTF-8 are left alone
+            let converted = match str::from_utf8(line.as_slice()) {
+                Ok(s) if expand => Some(expand_line(s, tab_width, whole_line)),
+                Ok(s)           => Some(unexpand_line(s, tab_width, whole_line)),
+                Err(_)          => None,
+            };
+            match converted {
+                Some(ref converted) if converted.as_bytes() != line.as_slice() => {
+                    edits.push((start, end, converted.as_bytes().to_vec()));
+                }
+                _ => {}
+            }
+
+            if end >= self.text.len() { break }
+            start = end + 1;
+        }
+
+        let changed = edits.len();
+        self.splice_lines(edits);
+        if changed > 0 {
+            for (mark, line, col) in positions.into_iter() {
+                let start = self.line_start(line).unwrap();
+                let idx = get_col_idx(start, col, tab_width, &self.text);
+                self.set_mark(mark, idx);
+            }
+        }
+        changed
+    }
+
+    ///Re-break the paragraph around the mark at word boundaries, so that no line is wider than
+    ///`width` columns unless it holds a single longer word, as a single undoable action.
+    ///
+    ///A paragraph is delimited by empty lines, and the indentation of its first line is repeated on
+    ///every line after it. The mark stays on the same word.
+    pub fn fill_paragraph(&mut self, mark: Mark, width: uint, tab_width: uint) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let line = get_line(idx, &self.text).unwrap();
+        if line == self.text.len() || is_blank_line(line, &self.text) { return }
+
+        let mut start = line;
+        while start > 0 && !is_blank_line(get_prev_line(start, &self.text), &self.text) {
+            start = get_prev_line(start, &self.text);
+        }
+        let mut end = get_line_end(idx, &self.text).unwrap();
+        while end + 1 < self.text.len() && !is_blank_line(end + 1, &self.text) {
+            end = get_line_end(end + 1, &self.text).unwrap();
+        }
+
+        let region: Vec<u8> = range(start, end).map(|i| self.text[i]).collect();
+        let indent_len = region.iter().take_while(|ch| **ch == b' ' || **ch == b'\t').count();
+        let indent = region.slice_to(indent_len);
+        let indent_width = layout::width_of(&*String::from_utf8_lossy(indent), tab_width);
+
+        // the (start, end) of every word in the region
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < region.len() {
+            while i < region.len() && is_fill_space(region[i]) { i += 1; }
+            if i == region.len() { break }
+            let word_start = i;
+            while i < region.len() && !is_fill_space(region[i]) { i += 1; }
+            words.push((word_start, i));
+        }
+        if words.is_empty() { return }
+
+        // the word the mark is on, or the next one after it, and how far into it the mark is
+        let offset = idx - start;
+        let (mark_word, mark_offset) = match words.iter().position(|&(_, e)| offset <= e) {
+            Some(k) => (k, if offset > words[k].0 { offset - words[k].0 } else { 0 }),
+            None    => (words.len() - 1, words[words.len() - 1].1 - words[words.len() - 1].0),
+        };
+
+        let mut filled: Vec<u8> = indent.to_vec();
+        let mut col = indent_width;
+        let mut new_idx = start;
+        for (k, &(s, e)) in words.iter().enumerate() {
+            let word = region.slice(s, e);
+            let w = layout::width_of(&*String::from_utf8_lossy(word), tab_width);
+            if k > 0 {
+                if col + 1 + w > width {
+                    filled.push(b'\n');
+                    filled.push_all(indent);
+                    col = indent_width;
+                } else {
+                    filled.push(b' ');
+                    col += 1;
+                }
+            }
+            if k == mark_word { new_idx = start + filled.len() + mark_offset; }
+            filled.push_all(word);
+            col += w;
+        }
+
+        if filled != region {
+            self.splice(start, end, filled.as_slice());
+        }
+        self.set_mark(mark, new_idx);
+    }
+
+    ///Swap the mark's line with the one above it (`Up`) or below it (`Down`) as a single undoable
+    ///action, keeping the mark at the same column of the moved line. The first line can't move up,
+    ///nor the last line down.
+    pub fn move_line(&mut self, mark: Mark, direction: Direction) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let line = get_line(idx, &self.text).unwrap();
+        let col = idx - line;
+
+        // the starts of the two lines being swapped
+        let (upper, lower) = match direction {
+            Direction::Up(_) if line > 0 => (get_prev_line(line, &self.text), line),
+            Direction::Down(_)           => {
+                let end = get_line_end(idx, &self.text).unwrap();
+                if end == self.text.len() { return }
+                (line, end + 1)
+            }
+            _                            => return,
+        };
+        let lower_end = get_line_end(lower, &self.text).unwrap();
+
+        // the newline after the lower line stays where it is, so a last line without one is fine
+        let upper_text: Vec<u8> = range(upper, lower - 1).map(|i| self.text[i]).collect();
+        let mut swapped: Vec<u8> = range(lower, lower_end).map(|i| self.text[i]).collect();
+        let lower_len = swapped.len();
+        swapped.push(b'\n');
+        swapped.push_all(upper_text.as_slice());
+        self.splice(upper, lower_end, swapped.as_slice());
+
+        let new_idx = match direction {
+            Direction::Up(_) => upper + col,
+            _                => upper + lower_len + 1 + col,
+        };
+        self.set_mark(mark, new_idx);
+    }
+
+    ///Remove spaces and tabs from the end of every line as a single undoable action.
+    ///
+    ///Marks sitting in trimmed whitespace are moved back to the new end of their line. Returns
+    ///the number of lines that were changed.
+    pub fn trim_trailing_whitespace(&mut self) -> uint {
+        let mut edits = Vec::new();
+        let mut line_start = 0;
+        loop {
+            let line_end = get_line_end(line_start, &self.text).unwrap();
+            let mut start = line_end;
+            while start > line_start
+                  && (self.text[start - 1] == b' ' || self.text[start - 1] == b'\t') {
+                start -= 1;
+            }
+            if start < line_end { edits.push((start, line_end, Vec::new())); }
+
+            if line_end == self.text.len() { break }
+            line_start = line_end + 1;
+        }
+
+        let trimmed = edits.len();
+        self.splice_lines(edits);
+        trimmed
+    }
+
+    ///Replace the text in [start, end) with `replacement` as a single undoable action, returning
+    ///the removed text. None if the range is inverted or beyond the end of the buffer.
+    ///
+    pub fn replace_range(&mut self, start: uint, end: uint, replacement: &str) -> Option<String> {
+        if start > end || end > self.text.len() { return None }
+
+        let removed = self.splice(start, end, replacement.as_bytes());
+        Some(String::from_utf8_lossy(removed.as_slice()).into_owned())
+    }
+
+    ///Replace every occurrence of pattern with replacement as a single undoable action, returning
+    ///how many there were. An empty pattern matches nothing, so replaces nothing.
+    pub fn replace_all(&mut self, pattern: &str, replacement: &str) -> uint {
+        let len = self.text.len();
+        self.replace_between(pattern, replacement, 0, len)
+    }
+
+    ///Replace the occurrences of pattern that lie wholly within [start, end) as `replace_all`
+    ///does. One that only starts or ends in the range is left alone.
+    pub fn replace_between(&mut self, pattern: &str, replacement: &str, start: uint, end: uint) -> uint {
+        let mut starts = Vec::new();
+        let mut from = start;
+        loop {
+            match self.find(pattern, from) {
+                Some(start) if start + pattern.len() <= end => {
+                    starts.push(start);
+                    from = start + pattern.len();
+                }
+                _ => break,
+            }
+        }
+
+        let revision = self.log.revision();
+        // back to front, so that each replacement leaves the offsets of the ones before it alone
+        for &start in starts.iter().rev() {
+            self.replace_range(start, start + pattern.len(), replacement);
+        }
+        self.log.group_since(revision);
+        starts.len()
+    }
+
+    ///Redo most recently undone action.
+    pub fn redo(&mut self) -> Option<&LogEntry> {
+        self.clear_selection();
+        let runs = match self.log.redo() {
+            Some(transaction) => runs_of(transaction),
+            None              => return None,
+        };
+        self.replay(runs);
+        self.log.last_done()
+    }
+
+    ///Undo most recently performed action.
+    pub fn undo(&mut self) -> Option<&LogEntry> {
+        self.clear_selection();
+        let runs = match self.log.undo() {
+            Some(transaction) => runs_of(transaction),
+            None              => return None,
+        };
+        self.replay(runs);
+        self.log.last_undone()
+    }
+
+    //Rewrite the word after the mark in upper or lower case, and move the mark past it.
+    fn change_word_case(&mut self, mark: Mark, upper: bool) {
+        let idx = match self.get_mark_idx(mark) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let end = get_word_end(idx, &self.text);
+        let len = self.change_region_case(idx, end, upper);
+        self.set_mark(mark, idx + len);
+    }
+
+    //Convert [start, end) to upper or lower case, giving how long it is now.
+    fn change_region_case(&mut self, start: uint, end: uint, upper: bool) -> uint {
+        if start > end || end > self.text.len() { return 0 }
+
+        let region: Vec<u8> = range(start, end).map(|i| self.text[i]).collect();
+        let changed: String = String::from_utf8_lossy(region.as_slice()).chars().map(|c| {
+            if upper { c.to_uppercase() } else { c.to_lowercase() }
+        }).collect();
+        if changed.as_bytes() != region.as_slice() {
+            self.splice_keeping_selection(start, end, changed.as_bytes());
+        }
+        changed.len()
+    }
+
+    //Move marks to account for the text in [start, end) having been replaced by `len` bytes.
+    fn adjust_marks(&mut self, start: uint, end: uint, len: uint) {
+        let marks: Vec<(Mark, uint)> = self.marks.iter().map(|(mark, &(idx, _))| (*mark, idx))
+                                                        .collect();
+        for (mark, idx) in marks.into_iter() {
+            if idx < start { continue }
+            //a mark right at the edit stays put, but forgets any column it was trying to keep
+            if idx == start { self.set_mark(mark, idx); continue }
+            let new_idx = if idx >= end { idx - (end - start) + len }
+                          else { cmp::min(idx, start + len) };
+            self.set_mark(mark, new_idx);
+        }
+    }
+
+    //Make the changes of an entry replayed from the log, moving the marks for each run of them
+    //just as `splice` moved them for the edit.
+    fn replay(&mut self, runs: Vec<Run>) {
+        for run in runs.into_iter() {
+            match run {
+                Run::Insert(start, bytes) => {
+                    for (offset, &ch) in bytes.iter().enumerate() {
+                        self.text.edit().insert(start + offset, ch);
+                    }
+                    self.line_index.borrow_mut().edited(start, 0, newlines(bytes.iter()));
+                    self.adjust_marks(start, start, bytes.len());
+                }
+                Run::Remove(start, len) => {
+                    let removed: Vec<u8> = {
+                        let text = self.text.edit();
+                        range(0, len).filter_map(|_| text.remove(start)).collect()
+                    };
+                    self.line_index.borrow_mut().edited(start, newlines(removed.iter()), 0);
+                    self.adjust_marks(start, start + len, 0);
+                }
+            }
+        }
+    }
+
+    //Replace the text in [start, end) with `text` as a single undoable action, returning the
+    //removed text.
+    //
+    //Every change to the text goes through here so that marks stay on the same logical spot:
+    //marks after the range move with the text following it, and marks inside the range are
+    //clamped into the replacement. Any selection ends, as the edit is what it was made for.
+    fn splice(&mut self, start: uint, end: uint, text: &[u8]) -> Vec<u8> {
+        let removed = self.splice_keeping_selection(start, end, text);
+        self.clear_selection();
+        removed
+    }
+
+    //As `splice`, for edits that leave the selection as it is.
+    fn splice_keeping_selection(&mut self, start: uint, end: uint, text: &[u8]) -> Vec<u8> {
+        let mut removed = Vec::new();
+        {
+            let mut transaction = self.log.start(start);
+            for _ in range(start, end) {
+                if let Some(ch) = self.text.edit().remove(start) {
+                    transaction.log(Change::Remove(start, ch), start);
+                    removed.push(ch);
+                }
+            }
+            for (offset, &ch) in text.iter().enumerate() {
+                self.text.edit().insert(start + offset, ch);
+                transaction.log(Change::Insert(start + offset, ch), start + offset);
+            }
+        }
+        self.line_index.borrow_mut().edited(start, newlines(removed.iter()), newlines(text.iter()));
+        self.adjust_marks(start, start + removed.len(), text.len());
+        removed
+    }
+
+    //Make each of the edits, a range of the text and what to replace it with, through `splice`
+    //as a single undoable action. They are given in order through the text and made back to
+    //front, so that none moves the text of those before it. Marks where text is only inserted
+    //go along with it, and the selection is kept, as these are edits to the lines it takes in.
+    fn splice_lines(&mut self, edits: Vec<(uint, uint, Vec<u8>)>) {
+        let revision = self.log.revision();
+        for (start, end, text) in edits.into_iter().rev() {
+            let following: Vec<Mark> = if end > start { Vec::new() } else {
+                self.marks.iter().filter(|&(_, &(idx, _))| idx == start)
+                                 .map(|(mark, _)| *mark)
+                                 .collect()
+            };
+            self.splice_keeping_selection(start, end, text.as_slice());
+            for mark in following.into_iter() {
+                self.set_mark(mark, start + text.len());
+            }
+        }
+        self.log.group_since(revision);
+    }
+
+}
+
+//Returns the index of the first character of the line the mark is in.
+//Newline prior to mark (EXCLUSIVE) + 1.
+//None iff mark is outside of the len of text.
+fn get_line<T: TextStore>(mark: uint, text: &T) -> Option<uint> {
+    if mark <= text.len() {
+        range(0, mark + 1).rev().filter(|idx| *idx == 0 || text[*idx - 1] == b'\n')
+                                .take(1)
+                                .next()
+    } else { None }
+}
+
+//Whether the file at path can be written, as it either can be opened for writing or doesn't
+//exist yet.
+fn is_writable(path: &Path) -> bool {
+    !path.exists() || File::open_mode(path, FileMode::Open, FileAccess::Write).is_ok()
+}
+
+//Where the lines of the text start, looked for only as far as they have been asked about, so
+//that moving about the start of a huge file never reads through to its end. An edit forgets
+//the lines after the one it was on, and they are looked for again when next asked about. The
+//number of lines is counted once, and kept up to date by each edit from the newlines it removed
+//and inserted, so that showing it every frame never reads through the file either.
+struct LineIndex {
+    starts: Vec<uint>,              //Start of each line found so far, the first always 0.
+    complete: bool,                 //Whether every line has been found.
+    count: Option<uint>,            //The number of lines, once it has been counted.
+}
+
+impl LineIndex {
+    fn new() -> LineIndex {
+        LineIndex { starts: vec![0], complete: false, count: None }
+    }
+
+    //Look for lines until one starts after idx, or there are no more.
+    fn find_past<T: TextStore>(&mut self, idx: uint, text: &T) {
+        while !self.complete && *self.starts.last().unwrap() <= idx {
+            self.find_next(text);
+        }
+    }
+
+    //Look for lines until the one numbered linenum is found, or there are no more.
+    fn find_past_line<T: TextStore>(&mut self, linenum: uint, text: &T) {
+        while !self.complete && self.starts.len() <= linenum {
+            self.find_next(text);
+        }
+    }
+
+    fn find_next<T: TextStore>(&mut self, text: &T) {
+        let from = *self.starts.last().unwrap();
+        match range(from, text.len()).find(|&i| text[i] == b'\n') {
+            Some(newline) => self.starts.push(newline + 1),
+            None          => self.complete = true,
+        }
+    }
+
+    //The number of the last line found that starts at or before idx, by halving the lines it
+    //could be.
+    fn line_of(&self, idx: uint) -> uint {
+        let (mut low, mut high) = (0, self.starts.len());
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if self.starts[mid] <= idx { low = mid } else { high = mid }
+        }
+        low
+    }
+
+    //Forget the lines after the one idx is on, as the text from idx on has changed. The lines
+    //starting at or before it are just where they were.
+    fn forget_from(&mut self, idx: uint) {
+        let keep = self.line_of(idx) + 1;
+        self.starts.truncate(keep);
+        self.complete = false;
+    }
+
+    //Note an edit at idx that removed and inserted the given numbers of newlines.
+    fn edited(&mut self, idx: uint, removed: uint, inserted: uint) {
+        self.forget_from(idx);
+        if let Some(count) = self.count {
+            self.count = Some(count + inserted - removed);
+        }
+    }
+}
+
+fn newlines<'a, I: Iterator<Item=&'a u8>>(bytes: I) -> uint {
+    bytes.filter(|&&byte| byte == b'\n').count()
+}
+
+//What was found out about text as it was read into memory.
+struct Decoded {
+    line_ending: LineEnding,        //The most common line ending that was read.
+    mixed_line_endings: bool,       //Whether there were others as well.
+    bom: bool,                      //Whether the text started with a byte order mark.
+    lossy: bool,                    //Whether invalid UTF-8 had to be replaced.
+}
+
+//Decodes text given to it a chunk at a time, so that the whole of it is never needed in memory
+//at once. What comes out is valid UTF-8, with every line ending as `\n`, and without a byte
+//order mark at the start.
+struct Decoder {
+    pending: Vec<u8>,               //The start of a UTF-8 sequence cut off by the last chunk.
+    after_cr: bool,                 //Whether the last byte decoded was a `\r`.
+    lf: uint,                       //Line endings of each kind seen so far.
+    crlf: uint,
+    cr: uint,
+    started: bool,                  //Whether any of the text has been decoded yet.
+    bom: bool,
+    lossy: bool,
+}
+
+impl Decoder {
+    fn new() -> Decoder {
+        Decoder {
+            pending: Vec::new(),
+            after_cr: false,
+            lf: 0, crlf: 0, cr: 0,
+            started: false,
+            bom: false,
+            lossy: false,
+        }
+    }
+
+    fn feed<E: Extend<u8>>(&mut self, chunk: &[u8], out: &mut E) {
+        let mut bytes = Vec::with_capacity(self.pending.len() + chunk.len());
+        bytes.push_all(self.pending[]);
+        bytes.push_all(chunk);
+        let split = incomplete_utf8_start(bytes[]);
+        self.pending = bytes[split..].to_vec();
+        self.decode(bytes[..split], out);
+    }
+
+    //A sequence still cut off at the end of the text is invalid, and replaced.
+    fn finish<E: Extend<u8>>(mut self, out: &mut E) -> Decoded {
+        let pending = self.pending.clone();
+        self.decode(pending[], out);
+        if self.after_cr { self.cr += 1 }
+        let (ending, mixed) = pick_line_ending(self.lf, self.crlf, self.cr);
+        Decoded {
+            line_ending: ending,
+            mixed_line_endings: mixed,
+            bom: self.bom,
+            lossy: self.lossy,
+        }
+    }
+
+    fn decode<E: Extend<u8>>(&mut self, mut bytes: &[u8], out: &mut E) {
+        // the mark is a whole sequence, so it is never split between calls
+        if !self.started && bytes.len() > 0 {
+            self.started = true;
+            if bytes.starts_with(UTF8_BOM) {
+                self.bom = true;
+                bytes = bytes[UTF8_BOM.len()..];
+            }
+        }
+        if str::from_utf8(bytes).is_err() { self.lossy = true }
+        let text = String::from_utf8_lossy(bytes);
+        let mut normalized = Vec::with_capacity(text.len());
+        for &ch in text.as_bytes().iter() {
+            if self.after_cr {
+                self.after_cr = false;
+                if ch == b'\n' { self.crlf += 1; continue }
+                self.cr += 1;
+            }
+            match ch {
+                b'\r' => { normalized.push(b'\n'); self.after_cr = true; }
+                b'\n' => { normalized.push(b'\n'); self.lf += 1; }
+                _     => normalized.push(ch),
+            }
+        }
+        out.extend(normalized.into_iter());
+    }
+}
+
+//Decodes the whole of a text at once.
+fn decode_text(bytes: &[u8]) -> (Vec<u8>, Decoded) {
+    let mut text = Vec::with_capacity(bytes.len());
+    let mut decoder = Decoder::new();
+    decoder.feed(bytes, &mut text);
+    let decoded = decoder.finish(&mut text);
+    (text, decoded)
+}
+
+//Whether the start of a file looks like binary data rather than text: it has a NUL byte, or more
+//than one in ten bytes is a control character that text doesn't use. Bytes of multi-byte UTF-8
+//sequences don't count against it.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let sample = bytes[..cmp::min(bytes.len(), BINARY_SAMPLE)];
+    if sample.contains(&0) { return true }
+    let control = sample.iter().filter(|&&byte| {
+        (byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(&byte)) || byte == 0x7f
+    }).count();
+    control * 10 > sample.len()
+}
+
+//Sixteen bytes to a line: the offset, the bytes in hex, then the printable ones as text.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        dump.push_str(format!("{:08x} ", line * 16)[]);
+        for i in range(0, 16) {
+            match chunk.get(i) {
+                Some(byte) => dump.push_str(format!(" {:02x}", *byte)[]),
+                None       => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        for &byte in chunk.iter() {
+            dump.push(if byte >= 0x20 && byte < 0x7f { byte as char } else { '.' });
+        }
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+//The index that a UTF-8 sequence cut off at the end of the bytes starts at, or the length of
+//the bytes if the last sequence is whole.
+fn incomplete_utf8_start(bytes: &[u8]) -> uint {
+    let len = bytes.len();
+    for back in range(1, cmp::min(len, 3) + 1) {
+        let byte = bytes[len - back];
+        if is_continuation_byte(byte) { continue }
+        let width = if byte >= 0xF0 { 4 } else if byte >= 0xE0 { 3 } else if byte >= 0xC0 { 2 } else { 1 };
+        return if width > back { len - back } else { len }
+    }
+    len
+}
+
+//The most common of the line endings counted (LF on a tie, or if there are none), and whether
+//there was more than one kind.
+fn pick_line_ending(lf: uint, crlf: uint, cr: uint) -> (LineEnding, bool) {
+    let ending = if crlf > lf && crlf >= cr { LineEnding::CrLf }
+                 else if cr > lf && cr > crlf { LineEnding::Cr }
+                 else { LineEnding::Lf };
+    let kinds = [lf, crlf, cr].iter().filter(|&&count| count > 0).count();
+    (ending, kinds > 1)
+}
+
+fn no_file_name() -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: "buffer has no file name",
+        detail: None,
+    }
+}
+
+//Modification time and size of the file at `path`, if there is one.
+fn get_disk_stat(path: &Path) -> Option<(u64, u64)> {
+    path.stat().ok().map(|stat| (stat.modified, stat.size))
+}
+
+//The name the backup of the file at `path` is kept under, if backups are made at all.
+fn get_backup_path(path: &Path, backup: &Backup) -> Option<Path> {
+    let name = format!("{}~", path.filename_display());
+    match *backup {
+        Backup::Off            => None,
+        Backup::Beside         => Some(path.dir_path().join(name)),
+        Backup::InDir(ref dir) => Some(dir.join(name)),
+    }
+}
+
+//The file the text of the file at `path` is autosaved to, next to it.
+fn get_autosave_path(path: &Path) -> Path {
+    path.dir_path().join(format!("#{}#", path.filename_display()))
+}
+
+//Writes data to disk in a new temporary file beside `path`, returning its name: the first of
+//.name.iota~, .name.iota1~ and so on that nothing, not even a dangling link, is at.
+fn create_temp_file(path: &Path, data: &[u8]) -> IoResult<Path> {
+    for n in range(0u, 100) {
+        let count = if n == 0 { String::new() } else { n.to_string() };
+        let tmp_path = path.dir_path().join(format!(".{}.iota{}~", path.filename_display(), count));
+        match write_new_file(&tmp_path, data) {
+            Ok(())                                   => return Ok(tmp_path),
+            Err(ref e) if e.kind == PathAlreadyExists => continue,
+            Err(e)                                   => return Err(e),
+        }
+    }
+    Err(IoError {
+        kind: PathAlreadyExists,
+        desc: "no free name for the temporary file",
+        detail: None,
+    })
+}
+
+//Creates the file at path and writes data to it and on to disk. The file is created with
+//O_EXCL, which fails if anything is at path already, as File::create would follow a link there.
+//If the writing fails the file is removed again.
+fn write_new_file(path: &Path, data: &[u8]) -> IoResult<()> {
+    let name = CString::from_slice(path.as_vec());
+    let fd = unsafe {
+        libc::open(name.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o666)
+    };
+    if fd < 0 { return Err(IoError::last_error()) }
+
+    let mut result = Ok(());
+    let mut written = 0;
+    while written < data.len() {
+        let rest = data[written..];
+        let n = unsafe {
+            libc::write(fd, rest.as_ptr() as *const libc::c_void, rest.len() as libc::size_t)
+        };
+        if n < 0 {
+            result = Err(IoError::last_error());
+            break
+        }
+        written += n as uint;
+    }
+    if result.is_ok() && unsafe { libc::fsync(fd) } < 0 {
+        result = Err(IoError::last_error());
+    }
+    unsafe { libc::close(fd); }
+    if result.is_err() {
+        let _ = fs::unlink(path);
+    }
+    result
+}
+
+///Where `path` leads once the symbolic links to it are followed, so that saving replaces the
+///file a link points to rather than the link. A link that can't be read, or the last of too
+///many in a row, is taken as the file itself.
+pub fn resolve_links(path: &Path) -> Path {
+    let mut path = path.clone();
+    for _ in range(0u, 32) {
+        let target = match fs::lstat(&path) {
+            Ok(ref stat) if stat.kind == FileType::Symlink => fs::readlink(&path),
+            _ => break,
+        };
+        match target {
+            Ok(target) => path = path.dir_path().join(target),
+            Err(_)     => break,
+        }
+    }
+    path
+}
+
+//The file the text of the file at `path` is kept in while it is open.
+fn get_swap_path(path: &Path) -> Path {
+    path.dir_path().join(format!(".{}.swp", path.filename_display()))
+}
+
+//Once the file itself is saved the autosave file only holds older text, so it goes.
+fn remove_autosave_of(path: &Path) {
+    let autosave = get_autosave_path(path);
+    if autosave.exists() {
+        let _ = fs::unlink(&autosave);
+    }
+}
+
+//Returns the index of the newline character at the end of the line mark is in.
+//Newline after mark (INCLUSIVE).
+//None iff mark is outside the len of text.
+fn get_line_end<T: TextStore>(mark: uint, text: &T) -> Option<uint> {
+    if mark <= text.len() {
+        range(mark, text.len() + 1).filter(|idx| *idx == text.len() ||text[*idx] == b'\n')
+                                   .take(1)
+                                   .next()
+    } else { None }
+}
+
+//What the line by line searches look for in each line.
+enum LineMatcher<'a> {
+    Regex(&'a Regex),
+    Exact(&'a str),
+    Folded(Vec<char>),      //Text in lower case, matched against the line in lower case.
+}
+
+impl<'a> LineMatcher<'a> {
+    //The start and end of each match in the line, in order and not overlapping.
+    fn matches(&self, line: &str) -> Vec<(uint, uint)> {
+        match *self {
+            LineMatcher::Regex(re) => re.find_iter(line).collect(),
+            LineMatcher::Exact(needle) => {
+                if needle.is_empty() { return Vec::new() }
+                line.match_indices(needle).collect()
+            }
+            LineMatcher::Folded(ref needle) => {
+                let mut found = Vec::new();
+                if needle.is_empty() { return found }
+                let mut next = 0;
+                for (start, _) in line.char_indices() {
+                    if start < next { continue }
+                    let mut chars = line.slice_from(start).chars();
+                    let mut end = start;
+                    let matched = needle.iter().all(|&n| match chars.next() {
+                        Some(c) => {
+                            end += c.len_utf8();
+                            c.to_lowercase() == n
+                        }
+                        None => false,
+                    });
+                    if matched {
+                        found.push((start, end));
+                        next = end;
+                    }
+                }
+                found
+            }
+        }
+    }
+}
+
+fn fold_case(s: &str) -> Vec<char> {
+    s.chars().map(|c| c.to_lowercase()).collect()
+}
+
+//Copy text[start..end] into line, as a str if it is valid UTF-8. The same line is reused for
+//each copy, so that searching doesn't allocate for every line.
+fn copy_line<'a, T: TextStore>(text: &T, start: uint, end: uint,
+                               line: &'a mut Vec<u8>) -> Option<&'a str> {
+    line.clear();
+    line.extend(range(start, end).map(|i| text[i]));
+    str::from_utf8(line[]).ok()
+}
+
+//Whether the byte continues a multibyte character rather than starting a new one.
+fn is_continuation_byte(ch: u8) -> bool {
+    ch & 0xC0 == 0x80
+}
+
+//Returns the index of the first byte of the character before mark, which must be above 0.
+fn get_char_start<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut idx = mark - 1;
+    while idx > 0 && is_continuation_byte(text[idx]) { idx -= 1; }
+    idx
+}
+
+//Returns the index just past the character at mark, which must be within the text.
+fn get_char_end<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut idx = mark + 1;
+    while idx < text.len() && is_continuation_byte(text[idx]) { idx += 1; }
+    idx
+}
+
+//Returns the grapheme cluster boundary next to mark, after it if `forward` or else before it, so
+//that combining characters move and get deleted along with the character they modify. A newline is
+//always a cluster of its own, and text that isn't valid UTF-8 falls back to character boundaries.
+fn get_grapheme_boundary<T: TextStore>(mark: uint, forward: bool, text: &T) -> uint {
+    if forward && mark >= text.len() { return text.len() }
+    if !forward && mark == 0 { return 0 }
+
+    // the character the mark is moving over
+    let probe = if forward { mark } else { mark - 1 };
+    if text[probe] == b'\n' { return if forward { mark + 1 } else { mark - 1 } }
+
+    let line = get_line(probe, text).unwrap();
+    let line_end = get_line_end(probe, text).unwrap();
+    let bytes: Vec<u8> = range(line, line_end).map(|i| text[i]).collect();
+    match str::from_utf8(bytes.as_slice()) {
+        Ok(s) => {
+            let mut boundaries: Vec<uint> = s.grapheme_indices(true).map(|(i, _)| line + i)
+                                                                  .collect();
+            boundaries.push(line_end);
+            if forward { *boundaries.iter().find(|b| **b > mark).unwrap() }
+            else { *boundaries.iter().rev().find(|b| **b < mark).unwrap() }
+        }
+        Err(_) => {
+            if forward { get_char_end(mark, text) } else { get_char_start(mark, text) }
+        }
+    }
+}
+
+//The (opening, closing) pair a bracket belongs to, and whether its match comes after it.
+fn bracket_pair(ch: u8) -> Option<(u8, u8, bool)> {
+    match ch {
+        b'(' => Some((b'(', b')', true)),
+        b')' => Some((b'(', b')', false)),
+        b'[' => Some((b'[', b']', true)),
+        b']' => Some((b'[', b']', false)),
+        b'{' => Some((b'{', b'}', true)),
+        b'}' => Some((b'{', b'}', false)),
+        _    => None,
+    }
+}
+
+//The screen column of idx: the display width of the text before it on its line, as `layout`
+//counts it.
+fn get_screen_col<T: TextStore>(idx: uint, tab_width: uint, text: &T) -> uint {
+    get_drawn_col(get_line(idx, text).unwrap(), idx, tab_width, text)
+}
+
+//Returns the index of the last character boundary on the line starting at `start` whose screen
+//column is no more than `col`.
+fn get_col_idx<T: TextStore>(start: uint, col: uint, tab_width: uint, text: &T) -> uint {
+    let end = get_line_end(start, text).unwrap();
+    let bytes: Vec<u8> = range(start, end).map(|i| text[i]).collect();
+    let line = String::from_utf8_lossy(bytes.as_slice());
+    let mut width = 0;
+    for (i, ch) in line.char_indices() {
+        let w = layout::char_width(ch, tab_width, width);
+        if width + w > col { return start + i }
+        width += w;
+    }
+    end
+}
+
+//The bytes of the line starting at `start`, with its newline if it has one.
+fn get_line_bytes<T: TextStore>(start: uint, text: &T) -> Vec<u8> {
+    let end = get_line_end(start, text).map_or(text.len(), |end| cmp::min(end + 1, text.len()));
+    range(start, end).map(|i| text[i]).collect()
+}
+
+//The screen column idx is drawn at on its line, which starts at `line`, as `layout` counts it.
+fn get_drawn_col<T: TextStore>(line: uint, idx: uint, tab_width: uint, text: &T) -> uint {
+    layout::position(get_line_bytes(line, text).as_slice(), &[0], idx - line, tab_width).1
+}
+
+//The index of the character drawn at screen column `col` of the line starting at `start`, or of
+//the line's end if it is shorter. Zero-width characters belong to the one before them, so this
+//is always the start of a character as it is drawn.
+fn get_drawn_idx<T: TextStore>(start: uint, col: uint, tab_width: uint, text: &T) -> uint {
+    start + layout::offset_at(get_line_bytes(start, text).as_slice(), &[0], 0, col, tab_width)
+}
+
+//Expands the tabs in the line into spaces, stopping at the first character that is neither a
+//space nor a tab unless `whole_line`.
+fn expand_line(line: &str, tab_width: uint, whole_line: bool) -> String {
+    let mut expanded = String::new();
+    let mut col = 0;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '\t'             => {
+                let w = tab_width - col % tab_width;
+                expanded.extend(repeat(' ').take(w));
+                col += w;
+            }
+            ' '              => { expanded.push(' '); col += 1; }
+            _ if !whole_line => { expanded.push_str(line.slice_from(i)); break }
+            _                => {
+                expanded.push(ch);
+                col += layout::char_width(ch, tab_width, col);
+            }
+        }
+    }
+    expanded
+}
+
+//Collapses runs of two or more spaces that end on a tab stop into tabs, stopping at the first
+//character that is neither a space nor a tab unless `whole_line`.
+fn unexpand_line(line: &str, tab_width: uint, whole_line: bool) -> String {
+    let mut unexpanded = String::new();
+    let (mut col, mut spaces) = (0, 0u);
+    for (i, ch) in line.char_indices() {
+        match ch {
+            ' '  => {
+                spaces += 1;
+                col += 1;
+                if col % tab_width == 0 {
+                    unexpanded.push(if spaces > 1 { '\t' } else { ' ' });
+                    spaces = 0;
+                }
+            }
+            '\t' => {
+                // any spaces before the tab are swallowed by it
+                unexpanded.push('\t');
+                spaces = 0;
+                col += tab_width - col % tab_width;
+            }
+            _    => {
+                unexpanded.extend(repeat(' ').take(spaces));
+                spaces = 0;
+                if !whole_line {
+                    unexpanded.push_str(line.slice_from(i));
+                    return unexpanded
+                }
+                unexpanded.push(ch);
+                col += layout::char_width(ch, tab_width, col);
+            }
+        }
+    }
+    unexpanded.extend(repeat(' ').take(spaces));
+    unexpanded
+}
+
+//Whether the character separates words when filling a paragraph.
+fn is_fill_space(ch: u8) -> bool {
+    ch == b' ' || ch == b'\t' || ch == b'\n'
+}
+
+//Whether the character is part of a word. Bytes of multibyte characters count as word characters.
+fn is_word_char(ch: u8) -> bool {
+    ch >= 0x80 || ch == b'_' || (ch as char).is_alphanumeric()
+}
+
+//Returns the index of the start of the word before mark, taking any separators in between with it.
+//Stops after a newline, unless the newline immediately precedes mark.
+fn get_word_start<T: TextStore>(mark: uint, text: &T) -> uint {
+    if mark > 0 && text[mark - 1] == b'\n' { return mark - 1 }
+
+    let mut start = mark;
+    while start > 0 && text[start - 1] != b'\n' && !is_word_char(text[start - 1]) { start -= 1; }
+    while start > 0 && is_word_char(text[start - 1]) { start -= 1; }
+    start
+}
+
+//Returns the index just past the end of the word after mark, taking any separators in between
+//with it. Stops before a newline, unless the newline is at mark.
+fn get_word_end<T: TextStore>(mark: uint, text: &T) -> uint {
+    if mark < text.len() && text[mark] == b'\n' { return mark + 1 }
+
+    let mut end = mark;
+    while end < text.len() && text[end] != b'\n' && !is_word_char(text[end]) { end += 1; }
+    while end < text.len() && is_word_char(text[end]) { end += 1; }
+    end
+}
+
+//Returns the index just past the end of the next word after mark, skipping any separators and
+//newlines on the way.
+fn get_next_word_end<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut end = mark;
+    while end < text.len() && !is_word_char(text[end]) { end += 1; }
+    while end < text.len() && is_word_char(text[end]) { end += 1; }
+    end
+}
+
+//Returns the index of the start of the previous word before mark, skipping any separators and
+//newlines on the way.
+fn get_prev_word_start<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut start = mark;
+    while start > 0 && !is_word_char(text[start - 1]) { start -= 1; }
+    while start > 0 && is_word_char(text[start - 1]) { start -= 1; }
+    start
+}
+
+//Whether the line starting at the given index is empty.
+fn is_blank_line<T: TextStore>(start: uint, text: &T) -> bool {
+    start < text.len() && text[start] == b'\n'
+}
+
+//Returns the start of the next empty line after the paragraph following mark, or the end of the
+//text if there is none. A run of empty lines at mark is skipped first, so that it only counts as
+//one boundary.
+fn get_next_paragraph<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut start = get_line(mark, text).unwrap();
+    while is_blank_line(start, text) { start += 1; }
+    while start < text.len() && !is_blank_line(start, text) {
+        start = get_line_end(start, text).unwrap() + 1;
+    }
+    cmp::min(start, text.len())
+}
+
+//Returns the start of the previous empty line before the paragraph preceding mark, or the start
+//of the text if there is none.
+fn get_prev_paragraph<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut start = get_line(mark, text).unwrap();
+    while start > 0 && is_blank_line(get_prev_line(start, text), text) {
+        start = get_prev_line(start, text);
+    }
+    while start > 0 && !is_blank_line(get_prev_line(start, text), text) {
+        start = get_prev_line(start, text);
+    }
+    if start > 0 { get_prev_line(start, text) } else { 0 }
+}
+
+//Returns the start and end of the word mark is on. None if mark is on whitespace or punctuation.
+fn get_word_around<T: TextStore>(mark: uint, text: &T) -> Option<(uint, uint)> {
+    if mark >= text.len() || !is_word_char(text[mark]) { return None }
+    let mut start = mark;
+    while start > 0 && is_word_char(text[start - 1]) { start -= 1; }
+    let mut end = mark;
+    while end < text.len() && is_word_char(text[end]) { end += 1; }
+    Some((start, end))
+}
+
+//Returns the start and end of the word mark is on or just after. None if it is by neither.
+fn get_word_range<T: TextStore>(mark: uint, text: &T) -> Option<(uint, uint)> {
+    get_word_around(mark, text).or_else(|| {
+        if mark > 0 { get_word_around(mark - 1, text) } else { None }
+    })
+}
+
+//Returns the start of the paragraph mark is in: the start of the line after the empty line before
+//it, or of the text. On an empty line, that line's start.
+fn get_paragraph_start<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut start = get_line(mark, text).unwrap();
+    if is_blank_line(start, text) { return start }
+    while start > 0 && !is_blank_line(get_prev_line(start, text), text) {
+        start = get_prev_line(start, text);
+    }
+    start
+}
+
+//Returns the end of the paragraph mark is in: the start of the empty line after it, or the end of
+//the text. On an empty line, the start of the next line.
+fn get_paragraph_end<T: TextStore>(mark: uint, text: &T) -> uint {
+    let mut start = get_line(mark, text).unwrap();
+    if is_blank_line(start, text) { return start + 1 }
+    while start < text.len() && !is_blank_line(start, text) {
+        start = get_line_end(start, text).unwrap() + 1;
+    }
+    cmp::min(start, text.len())
+}
+
+//Returns the start of the line before the one starting at the given (non-zero) index.
+fn get_prev_line<T: TextStore>(start: uint, text: &T) -> uint {
+    get_line(start - 1, text).unwrap()
+}
+
+//A run of a log entry's changes that together insert bytes at an index, or remove a number of
+//bytes from one.
+enum Run {
+    Insert(uint, Vec<u8>),
+    Remove(uint, uint),
+}
+
+//The changes of a transaction as runs, so that marks are moved once for each run rather than once
+//for each byte. Bytes inserted anywhere in the bytes just inserted join their run, as do bytes
+//removed from either side of where the run's bytes were removed.
+fn runs_of(transaction: &LogEntry) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for change in transaction.changes.iter() {
+        let joined = match (runs.last_mut(), change) {
+            (Some(&mut Run::Insert(start, ref mut bytes)), &Change::Insert(idx, ch)) => {
+                if idx >= start && idx <= start + bytes.len() {
+                    bytes.insert(idx - start, ch);
+                    true
+                } else { false }
+            }
+            (Some(&mut Run::Remove(ref mut start, ref mut len)), &Change::Remove(idx, _)) => {
+                if idx == *start || idx + 1 == *start {
+                    *start = idx;
+                    *len += 1;
+                    true
+                } else { false }
+            }
+            _ => false,
+        };
+        if !joined {
+            runs.push(match *change {
+                Change::Insert(idx, ch) => Run::Insert(idx, vec![ch]),
+                Change::Remove(idx, _)  => Run::Remove(idx, 1),
+            });
+        }
+    }
+    runs
+}
+
+//----- ITERATE BY MATCHES -------------------------------------------------------------------------
+
+///The matches in part of a buffer, from `Buffer::matches_case` or `Buffer::matches_regex`.
+pub struct Matches<'a> {
+    buffer: &'a Buffer,
+    matcher: LineMatcher<'a>,
+    from: uint,
+    to: uint,
+    next_line: Option<uint>,     //start of the next line to look through
+    line: Vec<u8>,              //reused for each line copied out of the gap buffer
+    found: Vec<(uint, uint)>,   //matches in the last line not given yet, the next one last
+    whole_words: bool,
+}
+
+impl<'a> Matches<'a> {
+    fn new(buffer: &'a Buffer, matcher: LineMatcher<'a>, from: uint, to: uint) -> Matches<'a> {
+        Matches {
+            buffer: buffer,
+            matcher: matcher,
+            from: from,
+            to: to,
+            next_line: get_line(from, &buffer.text),
+            line: Vec::new(),
+            found: Vec::new(),
+            whole_words: false,
+        }
+    }
+
+    ///Only give the matches that are whole words, rather than parts of longer ones.
+    pub fn whole_words(mut self) -> Matches<'a> {
+        self.whole_words = true;
+        self
+    }
+}
+
+impl<'a> Iterator for Matches<'a> {
+    type Item = (uint, uint);
+
+    fn next(&mut self) -> Option<(uint, uint)> {
+        let buffer = self.buffer;
+        let text = &buffer.text;
+        loop {
+            if let Some(found) = self.found.pop() { return Some(found) }
+            let start = match self.next_line {
+                Some(start) if start < self.to && start <= text.len() => start,
+                _                                                    => return None,
+            };
+            let end = get_line_end(start, text).unwrap_or(text.len());
+            if let Some(s) = copy_line(text, start, end, &mut self.line) {
+                let (from, to, whole_words) = (self.from, self.to, self.whole_words);
+                self.found = self.matcher.matches(s).into_iter()
+                                 .map(|(b, e)| (start + b, start + e))
+                                 .filter(|&(b, e)| b >= from && b < to
+                                                   && (!whole_words || buffer.is_whole_word(b, e)))
+                                 .collect();
+                self.found.reverse();
+            }
+            self.next_line = Some(end + 1);
+        }
+    }
+}
+
+//----- ITERATE BY LINES ---------------------------------------------------------------------------
+
+pub struct Lines<'a> {
+    buffer: &'a [u8],
+    tail: uint,
+    head: uint,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.tail != self.head {
+
+            let old_tail = self.tail;
+            //update tail to either the first char after the next \n or to self.head
+            self.tail = range(old_tail, self.head).filter(|i| { *i + 1 == self.head 
+                                                                || self.buffer[*i] == b'\n' })
+                                                  .take(1)
+                                                  .next()
+                                                  .unwrap() + 1;
+            if self.tail == self.head { Some(self.buffer[old_tail..self.tail-1]) }
+            else { Some(self.buffer[old_tail..self.tail]) }
+
+        } else { None }
+    }
+
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        //TODO: this is technically correct but a better estimate could be implemented
+        (1, Some(self.head))
+    }
+
+}
+
+//----- TESTS --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+
+    use std::str;
+    use std::iter::repeat;
+    use std::io::{fs, BufReader, File, TempDir, PermissionDenied, FileType};
+    use std::io::{USER_READ, USER_WRITE, USER_EXECUTE, USER_RWX};
+    use std::io::{GROUP_READ, GROUP_WRITE, GROUP_EXECUTE, OTHER_READ, OTHER_EXECUTE};
+    use std::io::fs::PathExtensions;
+
+    use buffer::{Buffer, Backup, Case, Direction, LineEnding, Mark, Matches, SaveMethod, Progress};
+    use buffer::{Decoder, decode_text, get_autosave_path, looks_binary, hex_dump};
+    use gapbuffer::GapBuffer;
+    use regex::Regex;
+    use test::Bencher;
+
+    fn setup_buffer(testcase: &'static str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.text.edit().extend(testcase.bytes());
+        buffer.set_mark(Mark::Cursor(0), 0);
+        buffer
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut buffer = setup_buffer("");
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.lines().next().unwrap(), [b'A']);
+    }
+
+    #[test]
+    fn test_insert_str() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.insert_str(Mark::Cursor(0), "ab\ncd");
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), [b'T', b'e', b'a', b'b', b'\n']);
+        assert_eq!(lines.next().unwrap(), [b'c', b'd', b's', b't']);
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut buffer = setup_buffer("ABCD");
+        buffer.remove_char(Mark::Cursor(0));
+
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.lines().next().unwrap(), [b'B', b'C', b'D']);
+    }
+
+    #[test]
+    fn test_delete_word_forward() {
+        let mut buffer = setup_buffer("foo  bar baz");
+        buffer.set_mark(Mark::Cursor(0), 3);
+
+        assert_eq!(buffer.delete_word(Mark::Cursor(0), Direction::Right(1)).as_slice(), "  bar");
+        assert_eq!(buffer.lines().next().unwrap(), b"foo baz");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_delete_word_backward() {
+        let mut buffer = setup_buffer("foo bar.\nbaz");
+        buffer.set_mark(Mark::Cursor(0), 7);
+
+        assert_eq!(buffer.delete_word(Mark::Cursor(0), Direction::Left(1)).as_slice(), "bar");
+        assert_eq!(buffer.lines().next().unwrap(), b"foo .\n");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_delete_word_stops_at_newline() {
+        let mut buffer = setup_buffer("foo\n  bar");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.delete_word(Mark::Cursor(0), Direction::Left(1));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"foo\n");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+
+        // at the start of the line only the newline goes
+        buffer.delete_word(Mark::Cursor(0), Direction::Left(1));
+        assert_eq!(buffer.lines().next().unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn test_delete_word_at_buffer_edges() {
+        let mut buffer = setup_buffer("foo");
+        assert_eq!(buffer.delete_word(Mark::Cursor(0), Direction::Left(1)).as_slice(), "");
+
+        buffer.set_mark(Mark::Cursor(0), 3);
+        assert_eq!(buffer.delete_word(Mark::Cursor(0), Direction::Right(1)).as_slice(), "");
+        assert_eq!(buffer.lines().next().unwrap(), b"foo");
+    }
+
+    #[test]
+    fn test_delete_line() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Cursor(0), 6);
+
+        assert_eq!(buffer.delete_line(Mark::Cursor(0)).as_slice(), "A\n");
+        let mut lines = buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"Test");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_delete_last_line() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Cursor(0), 9);
+        buffer.delete_line(Mark::Cursor(0));
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"A");
+        assert!(lines.next().is_none());
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_delete_only_line() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.delete_line(Mark::Cursor(0));
+
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_duplicate_first_line() {
+        let mut buffer = setup_buffer("Test\nA");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.duplicate_line(Mark::Cursor(0));
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"A");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn test_duplicate_last_line() {
+        let mut buffer = setup_buffer("Test\nA");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.duplicate_line(Mark::Cursor(0));
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"A\n");
+        assert_eq!(lines.next().unwrap(), b"A");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 2));
+    }
+
+    #[test]
+    fn test_duplicate_empty_line() {
+        let mut buffer = setup_buffer("Test\n\nA");
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.duplicate_line(Mark::Cursor(0));
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.nth(1).unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"A");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 2));
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let mut buffer = setup_buffer("Test\n    A\nTest");
+        buffer.join_lines(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"Test A\n");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+
+        buffer.join_lines(Mark::Cursor(0));
+        assert_eq!(buffer.lines().next().unwrap(), b"Test A Test");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_join_empty_line() {
+        let mut buffer = setup_buffer("Test\n\nA");
+        buffer.join_lines(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"Test\n");
+    }
+
+    #[test]
+    fn test_join_last_line() {
+        let mut buffer = setup_buffer("Test");
+        buffer.join_lines(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"Test");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_transpose_chars() {
+        let mut buffer = setup_buffer("abcd");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.transpose_chars(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"acbd");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_transpose_chars_at_line_end() {
+        let mut buffer = setup_buffer("abc\nd");
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.transpose_chars(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"acb\n");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_transpose_chars_at_start() {
+        let mut buffer = setup_buffer("ab\ncd");
+        buffer.transpose_chars(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.transpose_chars(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"ab\n");
+        assert_eq!(buffer.lines().nth(1).unwrap(), b"cd");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_upcase_word() {
+        let mut buffer = setup_buffer("hello world");
+        buffer.upcase_word(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), b"HELLO world");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+
+        buffer.upcase_word(Mark::Cursor(0));
+        assert_eq!(buffer.lines().next().unwrap(), b"HELLO WORLD");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_upcase_region_keeps_selection() {
+        let mut buffer = setup_buffer("one über two");
+        buffer.set_mark(Mark::Cursor(0), 4);
+        buffer.start_selection(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 9);
+        buffer.upcase_region(4, 9);
+
+        assert_eq!(buffer.lines().next().unwrap(), "one ÜBER two".as_bytes());
+        assert_eq!(buffer.selection_range(), Some((4, 9)));
+
+        buffer.downcase_region(0, 9);
+        assert_eq!(buffer.lines().next().unwrap(), "one über two".as_bytes());
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), "one ÜBER two".as_bytes());
+    }
+
+    #[test]
+    fn test_downcase_word_unicode() {
+        let mut buffer = setup_buffer("ÜBER Alles");
+        buffer.downcase_word(Mark::Cursor(0));
+
+        assert_eq!(buffer.lines().next().unwrap(), "über Alles".as_bytes());
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_sort_lines() {
+        let mut buffer = setup_buffer("d\nc\nb\na\n");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.sort_lines(Mark::Cursor(0), 1, 3, false);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"d\n");
+        assert_eq!(lines.next().unwrap(), b"b\n");
+        assert_eq!(lines.next().unwrap(), b"c\n");
+        assert_eq!(lines.next().unwrap(), b"a\n");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 2));
+    }
+
+    #[test]
+    fn test_sort_all_lines_without_trailing_newline() {
+        let mut buffer = setup_buffer("bb\nc\naa");
+        buffer.set_mark(Mark::Cursor(0), 7);
+        buffer.sort_all_lines(Mark::Cursor(0), false);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"aa\n");
+        assert_eq!(lines.next().unwrap(), b"bb\n");
+        assert_eq!(lines.next().unwrap(), b"c");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (2, 0));
+    }
+
+    #[test]
+    fn test_sort_lines_reverse() {
+        let mut buffer = setup_buffer("a\nc\nb");
+        buffer.sort_all_lines(Mark::Cursor(0), true);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"c\n");
+        assert_eq!(lines.next().unwrap(), b"b\n");
+        assert_eq!(lines.next().unwrap(), b"a");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 2));
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace() {
+        let mut buffer = setup_buffer("Test  \n \t\nA\nTest\t");
+        buffer.set_mark(Mark::Cursor(0), 5);
+
+        assert_eq!(buffer.trim_trailing_whitespace(), 3);
+        let mut lines = buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"A\n");
+        assert_eq!(lines.next().unwrap(), b"Test");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_is_one_undo() {
+        let mut buffer = setup_buffer("A \nB \nC");
+        buffer.set_mark(Mark::Cursor(0), 7);
+        buffer.trim_trailing_whitespace();
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+        buffer.undo();
+        assert_eq!(buffer.len(), 8);
+    }
+
+    #[test]
+    fn test_undo_and_redo_move_marks() {
+        // deleting "two "
+        let mut buffer = setup_buffer("one two three");
+        buffer.set_mark(Mark::Named('a'), 1);
+        buffer.set_mark(Mark::Named('b'), 5);
+        buffer.set_mark(Mark::Named('c'), 10);
+        buffer.replace_range(4, 8, "");
+
+        buffer.undo();
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')), Some(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')), Some(4));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('c')), Some(10));
+        buffer.redo();
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')), Some(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')), Some(4));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('c')), Some(6));
+
+        // inserting "big "
+        let mut buffer = setup_buffer("one two three");
+        buffer.replace_range(4, 4, "big ");
+        buffer.set_mark(Mark::Named('a'), 1);
+        buffer.set_mark(Mark::Named('b'), 6);
+        buffer.set_mark(Mark::Named('c'), 12);
+
+        buffer.undo();
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')), Some(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')), Some(4));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('c')), Some(8));
+        buffer.redo();
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')), Some(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')), Some(4));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('c')), Some(12));
+    }
+
+    #[test]
+    fn test_undo_typing_at_a_mark_leaves_it_in_the_text() {
+        let mut buffer = setup_buffer("ab");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.insert_str(Mark::Cursor(0), "cdef");
+        buffer.set_mark(Mark::Named('z'), 6);
+
+        buffer.undo();
+        assert_eq!(buffer.get_mark_idx(Mark::Named('z')), Some(2));
+        assert_eq!(buffer.lines().next().unwrap(), "ab".as_bytes());
+    }
+
+    #[test]
+    fn test_dirty_after_edit() {
+        let mut buffer = setup_buffer("Test");
+        assert!(!buffer.is_dirty());
+
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(buffer.is_dirty());
+
+        buffer.undo();
+        assert!(!buffer.is_dirty());
+
+        buffer.redo();
+        assert!(buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_clean_after_save() {
+        let mut buffer = setup_buffer("Test");
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.mark_saved();
+        assert!(!buffer.is_dirty());
+
+        // continuing the same typed run still counts as a change
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.insert_char(Mark::Cursor(0), b'B');
+        assert!(buffer.is_dirty());
+
+        buffer.undo();
+        assert!(buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test\nfile").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.set_mark(Mark::Cursor(0), 4);
+        buffer.insert_str(Mark::Cursor(0), " text");
+        let saved = buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!((saved.bytes, saved.method), (14, SaveMethod::Renamed));
+        assert!(saved.backup.is_none());
+        assert!(!buffer.is_dirty());
+
+        // no final newline is added
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"Test text\nfile");
+        let reloaded = Buffer::new_from_file(path).unwrap();
+        assert_eq!(reloaded.lines().collect::<Vec<&[u8]>>(),
+                   buffer.lines().collect::<Vec<&[u8]>>());
+    }
+
+    #[test]
+    fn test_save_as_sets_path() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("new.txt");
+
+        let mut buffer = setup_buffer("Test");
+        let saved = buffer.save_as(path.clone(), &Backup::Off, false).unwrap();
+        assert_eq!((saved.bytes, saved.method), (4, SaveMethod::Renamed));
+        assert_eq!(buffer.file_path, Some(path.clone()));
+        assert!(!buffer.is_dirty());
+
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"ATest");
+    }
+
+    #[test]
+    fn test_save_leaves_no_temporary_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+        fs::chmod(&path, USER_READ | USER_WRITE).unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.save(&Backup::Off, false).unwrap();
+
+        assert_eq!(fs::readdir(dir.path()).unwrap(), vec![path.clone()]);
+        assert_eq!(path.stat().unwrap().perm, USER_READ | USER_WRITE);
+    }
+
+    #[test]
+    fn test_save_through_a_link_keeps_the_link() {
+        let dir = TempDir::new("iota").unwrap();
+        fs::mkdir(&dir.path().join("real"), USER_RWX).unwrap();
+        let target = dir.path().join("real").join("test.txt");
+        let link = dir.path().join("link.txt");
+        File::create(&target).write_str("Test").unwrap();
+        fs::symlink(&Path::new("real/test.txt"), &link).unwrap();
+
+        let mut buffer = Buffer::new_from_file(link.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.save(&Backup::Off, false).unwrap();
+
+        assert_eq!(fs::lstat(&link).unwrap().kind, FileType::Symlink);
+        assert_eq!(File::open(&target).read_to_end().unwrap(), b"ATest");
+        assert_eq!(fs::readdir(&dir.path().join("real")).unwrap(), vec![target.clone()]);
+    }
+
+    #[test]
+    fn test_save_never_writes_through_a_planted_temporary_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        let victim = dir.path().join("victim");
+        File::create(&path).write_str("Test").unwrap();
+        File::create(&victim).write_str("safe").unwrap();
+        fs::symlink(&victim, &dir.path().join(".test.txt.iota~")).unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.save(&Backup::Off, false).unwrap();
+
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"ATest");
+        assert_eq!(File::open(&victim).read_to_end().unwrap(), b"safe");
+    }
+
+    #[test]
+    fn test_failed_save_leaves_no_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("missing").join("test.txt");
+
+        let mut buffer = setup_buffer("Test");
+        assert!(buffer.save_as(path.clone(), &Backup::Off, false).is_err());
+        assert!(buffer.file_path.is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_backup_replaces_stale_backup_once() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        let backup = dir.path().join("test.txt~");
+        File::create(&path).write_str("Test").unwrap();
+        File::create(&backup).write_str("Stale").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        let saved = buffer.save(&Backup::Beside, false).unwrap();
+        assert_eq!(saved.backup.unwrap().unwrap(), backup);
+        assert_eq!(File::open(&backup).read_to_end().unwrap(), b"Test");
+
+        // later saves in the session leave the backup of the original alone
+        buffer.insert_char(Mark::Cursor(0), b'B');
+        assert!(buffer.save(&Backup::Beside, false).unwrap().backup.is_none());
+        assert_eq!(File::open(&backup).read_to_end().unwrap(), b"Test");
+    }
+
+    #[test]
+    fn test_no_backup_of_created_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("new.txt");
+
+        let mut buffer = setup_buffer("Test");
+        assert!(buffer.save_as(path.clone(), &Backup::Beside, false).unwrap().backup.is_none());
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(buffer.save(&Backup::Beside, false).unwrap().backup.is_none());
+        assert!(!dir.path().join("new.txt~").exists());
+    }
+
+    #[test]
+    fn test_unwritable_backup_dir_does_not_stop_save() {
+        let dir = TempDir::new("iota").unwrap();
+        let backups = dir.path().join("backups");
+        fs::mkdir(&backups, USER_READ | USER_EXECUTE).unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        let saved = buffer.save(&Backup::InDir(backups.clone()), false).unwrap();
+        assert!(saved.backup.unwrap().is_err());
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"ATest");
+        fs::chmod(&backups, USER_RWX).unwrap();
+    }
+
+    fn round_trip(contents: &str) -> Buffer {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str(contents).unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert_eq!(buffer.lines().collect::<Vec<&[u8]>>(),
+                   vec![b"a\n"[], b"b\n"[], b"c"[]]);
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), contents.as_bytes());
+        buffer
+    }
+
+    #[test]
+    fn test_lf_round_trip() {
+        assert_eq!(round_trip("a\nb\nc").line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn test_crlf_round_trip() {
+        assert_eq!(round_trip("a\r\nb\r\nc").line_ending, LineEnding::CrLf);
+    }
+
+    #[test]
+    fn test_cr_round_trip() {
+        assert_eq!(round_trip("a\rb\rc").line_ending, LineEnding::Cr);
+    }
+
+    #[test]
+    fn test_mixed_line_endings_use_the_majority() {
+        let buffer = Buffer::new_from_reader(BufReader::new(b"a\r\nb\nc\r\n"));
+        assert_eq!(buffer.line_ending, LineEnding::CrLf);
+        assert!(buffer.mixed_line_endings);
+        assert_eq!(buffer.len(), 7);
+        assert_eq!(buffer.display_name(), "untitled");
+        assert_eq!(buffer.ending_text(), "mixed CRLF");
+    }
+
+    #[test]
+    fn test_display_name_of_piped_text() {
+        let mut buffer = Buffer::new_from_reader(BufReader::new(b"diff\n"));
+        buffer.from_stdin = true;
+        assert_eq!(buffer.display_name(), "stdin");
+        assert_eq!(buffer.ending_text(), "LF");
+        assert!(buffer.file_path.is_none());
+    }
+
+    #[test]
+    fn test_set_line_ending() {
+        let mut buffer = Buffer::new_from_reader(BufReader::new(b"a\r\nb\n"));
+        buffer.set_line_ending(LineEnding::Lf);
+        assert!(!buffer.mixed_line_endings);
+        assert!(buffer.is_dirty());
+        assert_eq!(buffer.encoded_text(), b"a\nb\n");
+
+        buffer.mark_saved();
+        assert!(!buffer.is_dirty());
+        assert_eq!(buffer.ending_text(), "LF");
+    }
+
+    #[test]
+    fn test_save_adds_final_newline() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+
+        let mut buffer = setup_buffer("Test");
+        assert!(!buffer.ends_with_newline());
+        let saved = buffer.save_as(path.clone(), &Backup::Off, true).unwrap();
+        assert!(saved.added_newline);
+        assert_eq!(saved.bytes, 5);
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"Test\n");
+
+        // the buffer itself is left as it was
+        assert_eq!(buffer.len(), 5);
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.ends_with_newline());
+
+        buffer.insert_char(Mark::Cursor(0), b'\n');
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.insert_char(Mark::Cursor(0), b'\n');
+        assert!(buffer.ends_with_newline());
+        assert!(!buffer.save(&Backup::Off, true).unwrap().added_newline);
+    }
+
+    #[test]
+    fn test_save_leaves_empty_buffer_empty() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+
+        let mut buffer = setup_buffer("");
+        let saved = buffer.save_as(path.clone(), &Backup::Off, true).unwrap();
+        assert!(!saved.added_newline);
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"");
+    }
+
+    #[test]
+    fn test_save_refuses_file_changed_on_disk() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(!buffer.changed_on_disk());
+
+        File::create(&path).write_str("Changed elsewhere").unwrap();
+        assert!(buffer.changed_on_disk());
+        assert!(buffer.save(&Backup::Off, false).is_err());
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"Changed elsewhere");
+
+        buffer.refresh_disk_stat();
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"ATest");
+        assert!(!buffer.changed_on_disk());
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        File::create(&path).write_str("New\r\ntext").unwrap();
+
+        buffer.revert().unwrap();
+        assert_eq!(buffer.lines().collect::<Vec<&[u8]>>(), vec![b"New\n"[], b"text"[]]);
+        assert_eq!(buffer.line_ending, LineEnding::CrLf);
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.changed_on_disk());
+
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), b"ATest");
+    }
+
+    #[test]
+    fn test_revert_keeps_buffer_read_only() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.read_only = true;
+        buffer.revert().unwrap();
+        assert!(buffer.read_only);
+
+        //a file that is no longer valid UTF-8 makes it read-only
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        File::create(&path).write(b"Te\xffst").unwrap();
+        buffer.revert().unwrap();
+        assert!(buffer.read_only);
+    }
+
+    #[test]
+    fn test_revert_keeps_cursor_line() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("one\ntwo\nthree").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.insert_str(Mark::Cursor(0), "xxxx");
+        buffer.set_mark(Mark::Named('a'), 17);
+        buffer.revert().unwrap();
+
+        assert_eq!(buffer.lines().nth(1).unwrap(), b"two\n");
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (1, 3));
+        assert_eq!(buffer.get_mark_pos(Mark::Named('a')).unwrap(), (2, 5));
+
+        File::create(&path).write_str("one").unwrap();
+        buffer.revert().unwrap();
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_revert_missing_file_leaves_buffer() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        fs::unlink(&path).unwrap();
+
+        assert!(buffer.revert().is_err());
+        assert_eq!(buffer.lines().next().unwrap(), b"ATest");
+        assert!(buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_decode_in_chunks() {
+        let text = "caf\u{e9}\r\n\u{1f600}\r\nend\r".as_bytes();
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        for byte in text.iter() {
+            decoder.feed(&[*byte], &mut out);
+        }
+        let decoded = decoder.finish(&mut out);
+
+        let (whole, whole_decoded) = decode_text(text);
+        assert_eq!(out, whole);
+        assert_eq!(out[], "caf\u{e9}\n\u{1f600}\nend\n".as_bytes());
+        assert_eq!(decoded.line_ending, LineEnding::CrLf);
+        assert_eq!(decoded.mixed_line_endings, whole_decoded.mixed_line_endings);
+        assert!(!decoded.lossy);
+    }
+
+    #[test]
+    fn test_decode_cut_off_sequence() {
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        decoder.feed(b"ab\xf0\x9f", &mut out);
+        let decoded = decoder.finish(&mut out);
+        assert!(decoded.lossy);
+        assert!(str::from_utf8(out[]).unwrap().starts_with("ab"));
+    }
+
+    #[test]
+    fn test_bom_round_trip() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("windows.txt");
+        let contents = b"\xef\xbb\xbfcaf\xc3\xa9\r\nline two\r\n";
+        File::create(&path).write(contents).unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert!(buffer.bom);
+        assert_eq!(buffer.lines().next().unwrap(), "caf\u{e9}\n".as_bytes());
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (0, 0));
+        assert_eq!(buffer.ending_text(), "CRLF BOM");
+
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), contents);
+    }
+
+    #[test]
+    fn test_toggle_bom() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test\n").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert!(!buffer.bom);
+        buffer.set_bom(true);
+        assert!(buffer.is_dirty());
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"\xef\xbb\xbfTest\n");
+
+        buffer.set_bom(false);
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"Test\n");
+    }
+
+    #[test]
+    fn test_only_leading_bom_is_stripped() {
+        let (text, decoded) = decode_text(b"a\xef\xbb\xbfb");
+        assert!(!decoded.bom);
+        assert_eq!(text[], b"a\xef\xbb\xbfb");
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        let text = b"fn main() {\n\tprintln!(\"hello\");\r\n}\x0c\n\x1b[1mbold\x1b[0m\n";
+        assert!(!looks_binary(text));
+        assert!(!looks_binary("caf\u{e9} \u{1f600}\u{1f680} \u{65e5}\u{672c}\n".as_bytes()));
+        assert!(!looks_binary(b""));
+
+        assert!(looks_binary(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"));
+        // no NUL, but mostly control characters
+        assert!(looks_binary(b"\x01\x02\x03\x04abcdefgh\x05\x06"));
+        // a few control characters in plenty of text is still text
+        let mut mostly_text = repeat(b'a').take(100).collect::<Vec<u8>>();
+        mostly_text.push_all(b"\x01\x02\x03");
+        assert!(!looks_binary(mostly_text[]));
+    }
+
+    #[test]
+    fn test_binary_file_as_hex() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("image.png");
+        File::create(&path).write(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x00\x10").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert!(buffer.binary);
+        assert_eq!(buffer.kind_text(), "[binary]");
+        buffer.show_as_hex().unwrap();
+        assert!(buffer.read_only && buffer.hex_view);
+        assert_eq!(buffer.kind_text(), "[hex]");
+        assert_eq!(buffer.lines().next().unwrap(),
+                   b"00000000  89 50 4e 47 0d 0a 1a 0a 00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n");
+        buffer.read_only = false;
+        assert!(buffer.save(&Backup::Off, false).is_err());
+    }
+
+    #[test]
+    fn test_hex_dump_short_line() {
+        assert_eq!(hex_dump(b"ab")[],
+                   "00000000  61 62                                            |ab|\n");
+    }
+
+    struct CancelAfter(u64);
+
+    impl Progress for CancelAfter {
+        fn progress(&mut self, loaded: u64, total: u64) -> bool {
+            assert!(loaded <= total);
+            loaded < self.0
+        }
+    }
+
+    #[test]
+    fn test_load_large_file_with_progress() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("big.log");
+        let line = "a line of the log \u{2713}\n";
+        let mut data = String::new();
+        for _ in range(0u, 20000) { data.push_str(line) }
+        File::create(&path).write_str(data[]).unwrap();
+
+        let buffer = Buffer::new_from_file_with_progress(path.clone(), &mut CancelAfter(1 << 40));
+        let buffer = buffer.unwrap();
+        assert_eq!(buffer.len(), data.len() + 1);
+        assert_eq!(buffer.lines().count(), 20001);
+        assert!(!buffer.read_only);
+
+        let cancelled = Buffer::new_from_file_with_progress(path, &mut CancelAfter(100000));
+        assert!(cancelled.is_err());
+    }
+
+    fn read_only_fixture(contents: &[u8]) {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.bin");
+        File::create(&path).write(contents).unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert!(buffer.read_only);
+        assert!(buffer.len() > 1);
+        assert!(str::from_utf8(buffer.lines().next().unwrap()).is_ok());
+        assert_eq!(buffer.kind_text(), "");
+
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(buffer.save(&Backup::Off, false).is_err());
+        assert_eq!(File::open(&path).read_to_end().unwrap(), contents);
+
+        // writing somewhere else is fine, as the original is kept
+        buffer.save_as(dir.path().join("copy.txt"), &Backup::Off, false).unwrap();
+        assert!(!buffer.read_only);
+    }
+
+    #[test]
+    fn test_latin1_file_is_read_only() {
+        read_only_fixture(b"caf\xe9 cr\xe8me\n");
+    }
+
+    #[test]
+    fn test_binary_file_is_read_only() {
+        read_only_fixture(b"\x7fELF\x02\x01\x00\x00\xff\xfe\r\x00\n\x80");
+    }
+
+    #[test]
+    fn test_mapped_file_is_read_in_when_edited() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.log");
+        let contents: &[u8] = b"one\r\ntwo\nthree";
+        File::create(&path).write(contents).unwrap();
+
+        let mut buffer = Buffer::map(&path, contents.len() as u64).unwrap();
+        assert!(buffer.read_only && buffer.text.is_mapped());
+        assert_eq!(buffer.line_count(), 3);
+        // the bytes are shown as they are on disk
+        assert_eq!(buffer.lines().next().unwrap(), b"one\r\n");
+        assert_eq!(buffer.lines_at(1).unwrap().next().unwrap(), b"two\n");
+
+        buffer.read_only = false;
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(!buffer.text.is_mapped());
+        assert_eq!(buffer.lines_at(1).unwrap().next().unwrap(), b"Atwo\n");
+        assert_eq!(buffer.line_count(), 3);
+        assert_eq!(File::open(&path).read_to_end().unwrap(), contents);
+
+        buffer.undo();
+        assert_eq!(buffer.lines_at(1).unwrap().next().unwrap(), b"two\n");
+    }
+
+    #[test]
+    fn test_open_missing_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("new.txt");
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.file_path, Some(path.clone()));
+        assert!(buffer.is_new_file());
+
+        buffer.save(&Backup::Off, false).unwrap();
+        assert!(!buffer.is_new_file());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_open_unreadable_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("secret.txt");
+        File::create(&path).write_str("Test").unwrap();
+        fs::chmod(&path, USER_WRITE).unwrap();
+
+        assert!(Buffer::new_from_file(path).is_err());
+    }
+
+    #[test]
+    fn test_open_unwritable_file_read_only() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("locked.txt");
+        File::create(&path).write_str("Test").unwrap();
+        fs::chmod(&path, USER_READ).unwrap();
+
+        let buffer = Buffer::new_from_file(path.clone()).unwrap();
+        assert!(buffer.read_only);
+        assert_eq!(buffer.lines().next().unwrap(), b"Test");
+        fs::chmod(&path, USER_RWX).unwrap();
+        assert!(!Buffer::new_from_file(path).unwrap().read_only);
+    }
+
+    #[test]
+    fn test_open_directory() {
+        let dir = TempDir::new("iota").unwrap();
+        assert!(Buffer::new_from_file(dir.path().clone()).is_err());
+    }
+
+    #[test]
+    fn test_save_creating_dirs() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("notes").join("2015").join("todo.txt");
+
+        let mut buffer = setup_buffer("Test");
+        assert!(buffer.save_as(path.clone(), &Backup::Off, false).is_err());
+        buffer.save_as_creating_dirs(path.clone(), &Backup::Off, false).unwrap();
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"Test");
+
+        let other = dir.path().join("more").join("todo.txt");
+        let mut buffer = Buffer::new_from_file(other.clone()).unwrap();
+        assert!(buffer.save(&Backup::Off, false).is_err());
+        buffer.save_creating_dirs(&Backup::Off, false).unwrap();
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn test_save_creating_dirs_without_permission() {
+        let dir = TempDir::new("iota").unwrap();
+        fs::chmod(dir.path(), USER_READ | USER_EXECUTE).unwrap();
+        let path = dir.path().join("notes").join("todo.txt");
+
+        let mut buffer = setup_buffer("Test");
+        let result = buffer.save_as_creating_dirs(path, &Backup::Off, false);
+        fs::chmod(dir.path(), USER_RWX).unwrap();
+        assert_eq!(result.err().unwrap().kind, PermissionDenied);
+        assert!(buffer.file_path.is_none());
+    }
+
+    #[test]
+    fn test_autosave_path() {
+        let path = Path::new("/home/user/notes.txt");
+        assert_eq!(get_autosave_path(&path), Path::new("/home/user/#notes.txt#"));
+        assert_eq!(get_autosave_path(&Path::new("notes")), Path::new("#notes#"));
+    }
+
+    #[test]
+    fn test_autosave() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("Test").unwrap();
+        let autosave = dir.path().join("#test.txt#");
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert_eq!(buffer.autosave().unwrap().unwrap(), autosave);
+        assert_eq!(File::open(&autosave).read_to_end().unwrap(), b"ATest");
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"Test");
+        assert!(buffer.is_dirty());
+
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), b"Test");
+    }
+
+    #[test]
+    fn test_autosave_removed_on_save() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        let autosave = dir.path().join("#test.txt#");
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.autosave().unwrap().unwrap();
+        assert!(autosave.exists());
+        buffer.save(&Backup::Off, false).unwrap();
+        assert!(!autosave.exists());
+
+        buffer.insert_char(Mark::Cursor(0), b'B');
+        buffer.autosave().unwrap().unwrap();
+        buffer.save_as(dir.path().join("other.txt"), &Backup::Off, false).unwrap();
+        assert!(!autosave.exists());
+
+        buffer.insert_char(Mark::Cursor(0), b'C');
+        buffer.autosave().unwrap().unwrap();
+        assert!(dir.path().join("#other.txt#").exists());
+        buffer.remove_autosave();
+        assert!(!dir.path().join("#other.txt#").exists());
+    }
+
+    #[test]
+    fn test_swap_path() {
+        let mut buffer = setup_buffer("Test");
+        assert!(buffer.swap_path().is_none());
+        buffer.file_path = Some(Path::new("/home/user/notes.txt"));
+        assert_eq!(buffer.swap_path().unwrap(), Path::new("/home/user/.notes.txt.swp"));
+    }
+
+    #[test]
+    fn test_recover_from_swap() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("one\r\ntwo\r\n").unwrap();
+
+        let mut buffer = Buffer::new_from_file(path.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.write_swap().unwrap();
+        assert_eq!(File::open(&dir.path().join(".test.txt.swp")).read_to_end().unwrap(),
+                   b"Aone\r\ntwo\r\n");
+
+        let mut recovered = Buffer::new_from_file(path.clone()).unwrap();
+        recovered.recover_from_swap().unwrap();
+        assert_eq!(recovered.lines().next().unwrap(), b"Aone\n");
+        assert_eq!(recovered.line_ending, LineEnding::CrLf);
+        assert!(recovered.is_dirty());
+        recovered.undo();
+        assert_eq!(recovered.lines().next().unwrap(), b"one\n");
+    }
+
+    #[test]
+    fn test_update_swap_every_few_edits() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        let swap = dir.path().join(".test.txt.swp");
+
+        let mut buffer = Buffer::new_from_file(path).unwrap();
+        assert!(buffer.update_swap(2).is_none());
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(buffer.update_swap(2).is_none());
+        assert!(buffer.update_swap(2).is_none());
+        buffer.insert_char(Mark::Cursor(0), b'B');
+        buffer.update_swap(2).unwrap().unwrap();
+        assert_eq!(File::open(&swap).read_to_end().unwrap(), b"BA");
+
+        buffer.remove_swap();
+        assert!(!swap.exists());
+    }
+
+    #[test]
+    fn test_autosave_without_path() {
+        let mut buffer = setup_buffer("Test");
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(buffer.autosave().is_none());
+    }
+
+    #[test]
+    fn test_save_keeps_permissions() {
+        let dir = TempDir::new("iota").unwrap();
+        let script = dir.path().join("run.sh");
+        File::create(&script).write_str("echo hi\n").unwrap();
+        let mode = USER_RWX | GROUP_READ | GROUP_EXECUTE | OTHER_READ | OTHER_EXECUTE;
+        fs::chmod(&script, mode).unwrap();
+
+        let mut buffer = Buffer::new_from_file(script.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'#');
+        assert_eq!(buffer.save(&Backup::Off, false).unwrap().method, SaveMethod::Renamed);
+        assert_eq!(script.stat().unwrap().perm, mode);
+
+        let shared = dir.path().join("shared.txt");
+        File::create(&shared).write_str("Test").unwrap();
+        fs::chmod(&shared, USER_READ | USER_WRITE | GROUP_READ | GROUP_WRITE).unwrap();
+        let mut buffer = Buffer::new_from_file(shared.clone()).unwrap();
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.save(&Backup::Off, false).unwrap();
+        assert_eq!(shared.stat().unwrap().perm, USER_READ | USER_WRITE | GROUP_READ | GROUP_WRITE);
+    }
+
+    #[test]
+    fn test_save_new_file_permissions() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("new.txt");
+
+        let mut buffer = setup_buffer("Test");
+        buffer.save_as(path.clone(), &Backup::Off, false).unwrap();
+        let perm = path.stat().unwrap().perm;
+        assert!(perm.contains(USER_READ | USER_WRITE));
+        assert!(!perm.intersects(USER_EXECUTE | GROUP_EXECUTE | OTHER_EXECUTE));
+    }
+
+    #[test]
+    fn test_save_without_path() {
+        let mut buffer = setup_buffer("Test");
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        assert!(buffer.save(&Backup::Off, false).is_err());
+        assert!(buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_mark_pos() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (0, 0));
+
+        buffer.set_mark(Mark::Cursor(0), 5);
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (1, 0));
+
+        buffer.set_mark(Mark::Cursor(0), 4);
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (0, 4));
+
+        buffer.set_mark(Mark::Cursor(0), 11);
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (2, 4));
+    }
+
+    #[test]
+    fn test_mark_pos_after_trailing_newline() {
+        let mut buffer = setup_buffer("Test\n");
+        buffer.set_mark(Mark::Cursor(0), 5);
+
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn test_line_count() {
+        assert_eq!(setup_buffer("").line_count(), 1);
+        assert_eq!(setup_buffer("Test").line_count(), 1);
+        assert_eq!(setup_buffer("Test\nA").line_count(), 2);
+        // the empty line after a trailing newline counts
+        assert_eq!(setup_buffer("Test\nA\n").line_count(), 3);
+    }
+
+    #[test]
+    fn test_line_count_is_kept_across_edits() {
+        let mut buffer = setup_buffer("a\nb\nc");
+        assert_eq!(buffer.line_count(), 3);
+
+        buffer.replace_range(0, 4, "x\ny\nz\n");
+        assert_eq!(buffer.line_count(), 4);
+        buffer.replace_range(0, 6, "");
+        assert_eq!(buffer.line_count(), 1);
+
+        buffer.undo();
+        assert_eq!(buffer.line_count(), 4);
+        buffer.undo();
+        assert_eq!(buffer.line_count(), 3);
+        buffer.redo();
+        assert_eq!(buffer.line_count(), 4);
+    }
+
+    #[test]
+    fn test_char_len() {
+        assert_eq!(setup_buffer("").char_len(), 0);
+        assert_eq!(setup_buffer("Test\n").char_len(), 5);
+        assert_eq!(setup_buffer("über").char_len(), 4);
+    }
+
+    #[test]
+    fn test_lines_match_split() {
+        for testcase in ["", "Test", "Test\n", "\n\n", "Test\nA\n\nTest"].iter() {
+            let buffer = setup_buffer(*testcase);
+            let lines: Vec<&[u8]> = buffer.lines().map(|line| {
+                if line.last() == Some(&b'\n') { line[..line.len() - 1] } else { line }
+            }).collect();
+            let expected: Vec<&[u8]> = testcase.split('\n').map(|line| line.as_bytes()).collect();
+
+            assert_eq!(lines, expected);
+        }
+    }
+
+    #[test]
+    fn test_lines_at() {
+        let buffer = setup_buffer("Test\nA\nTest\n");
+        let mut lines = buffer.lines_at(1).unwrap();
+
+        assert_eq!(lines.next().unwrap(), b"A\n");
+        assert_eq!(lines.next().unwrap(), b"Test\n");
+        assert_eq!(lines.next().unwrap(), b"");
+        assert!(lines.next().is_none());
+        assert!(buffer.lines_at(4).is_none());
+    }
+
+    #[test]
+    fn test_lines_are_found_again_after_edits() {
+        let mut buffer = setup_buffer("a\nb\nc");
+        assert_eq!(buffer.lines_at(2).unwrap().next().unwrap(), b"c");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.insert_char(Mark::Cursor(0), b'\n');
+
+        assert_eq!(buffer.line_count(), 4);
+        assert_eq!(buffer.lines_at(2).unwrap().next().unwrap(), b"b\n");
+        assert_eq!(buffer.get_mark_pos(Mark::Cursor(0)).unwrap(), (1, 0));
+
+        buffer.undo();
+        assert_eq!(buffer.line_count(), 3);
+        assert_eq!(buffer.lines_at(2).unwrap().next().unwrap(), b"c");
+    }
+
+    #[test]
+    fn test_replace_range() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Cursor(0), 9);
+
+        assert_eq!(buffer.replace_range(5, 6, "Longer").unwrap().as_slice(), "A");
+        assert_eq!(buffer.lines().nth(1).unwrap(), b"Longer\n");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 14);
+    }
+
+    #[test]
+    fn test_replace_range_clamps_marks_inside() {
+        let mut buffer = setup_buffer("Test\nLonger\nTest");
+        buffer.set_mark(Mark::Cursor(0), 9);
+        buffer.set_mark(Mark::DisplayMark(0), 5);
+        buffer.replace_range(5, 11, "A");
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 6);
+        assert_eq!(buffer.get_mark_idx(Mark::DisplayMark(0)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_replace_range_rejects_bad_ranges() {
+        let mut buffer = setup_buffer("Test");
+
+        assert!(buffer.replace_range(3, 2, "A").is_none());
+        assert!(buffer.replace_range(2, 5, "A").is_none());
+        assert_eq!(buffer.replace_range(4, 4, "A").unwrap().as_slice(), "");
+        assert_eq!(buffer.lines().next().unwrap(), b"TestA");
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut buffer = setup_buffer("foo bar foo\nfoofoo");
+        assert_eq!(buffer.replace_all("foo", "x"), 4);
+        let lines: Vec<&[u8]> = buffer.lines().collect();
+        assert_eq!(lines[0], b"x bar x\n"[]);
+        assert_eq!(lines[1], b"xx"[]);
+    }
+
+    #[test]
+    fn test_replace_all_keeps_marks_in_place() {
+        let mut buffer = setup_buffer("aa b aa b");
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.set_mark(Mark::Named('a'), 8);
+        buffer.set_mark(Mark::Named('b'), 6);
+        assert_eq!(buffer.replace_all("aa", "c"), 2);
+
+        assert_eq!(buffer.lines().next().unwrap(), b"c b c b");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 2);
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')).unwrap(), 6);
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_replace_all_with_text_containing_the_pattern() {
+        let mut buffer = setup_buffer("a-a");
+        assert_eq!(buffer.replace_all("a", "aa"), 2);
+        assert_eq!(buffer.lines().next().unwrap(), b"aa-aa");
+    }
+
+    #[test]
+    fn test_replace_all_is_one_undo() {
+        let mut buffer = setup_buffer("x y x y x");
+        buffer.insert_char(Mark::Cursor(0), b'z');
+        assert_eq!(buffer.replace_all("x", "w"), 3);
+        assert_eq!(buffer.lines().next().unwrap(), b"zw y w y w");
+
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), b"zx y x y x");
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), b"x y x y x");
+    }
+
+    #[test]
+    fn test_replace_between() {
+        let mut buffer = setup_buffer("ab ab ab ab");
+        assert_eq!(buffer.replace_between("ab", "xyz", 3, 8), 2);
+        assert_eq!(buffer.lines().next().unwrap(), b"ab xyz xyz ab");
+
+        let mut buffer = setup_buffer("ab ab ab ab");
+        assert_eq!(buffer.replace_between("ab", "", 0, 9), 3);
+        assert_eq!(buffer.lines().next().unwrap(), b"   ab");
+    }
+
+    #[test]
+    fn test_replace_between_skips_matches_across_the_edges() {
+        let mut buffer = setup_buffer("abab");
+        assert_eq!(buffer.replace_between("ab", "x", 1, 4), 1);
+        assert_eq!(buffer.lines().next().unwrap(), b"abx");
+
+        let mut buffer = setup_buffer("abab");
+        assert_eq!(buffer.replace_between("ab", "x", 0, 3), 1);
+        assert_eq!(buffer.lines().next().unwrap(), b"xab");
+    }
+
+    #[test]
+    fn test_replace_all_empty_pattern_does_nothing() {
+        let mut buffer = setup_buffer("abc");
+        assert_eq!(buffer.replace_all("", "x"), 0);
+        assert_eq!(buffer.replace_all("d", "x"), 0);
+        assert_eq!(buffer.lines().next().unwrap(), b"abc");
+        assert!(!buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_mark_moves_with_insert_before() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Named('a'), 2);
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.insert_str(Mark::Cursor(0), "BC");
+
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_mark_stays_with_insert_after() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Named('a'), 2);
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.remove_char(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.remove_char(Mark::Cursor(0));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_mark_clamped_by_overlapping_delete() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Named('a'), 9);
+        buffer.set_mark(Mark::Named('b'), 11);
+        buffer.set_mark(Mark::Cursor(0), 9);
+        buffer.delete_line(Mark::Cursor(0));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')).unwrap(), 6);
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')).unwrap(), 6);
+        buffer.remove_char(Mark::Cursor(0));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('b')).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_screen_offset() {
+        let mut buffer = setup_buffer("Test\nA");
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 4).unwrap(), 0);
+
+        buffer.set_mark(Mark::Cursor(0), 3);
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 4).unwrap(), 3);
+
+        buffer.set_mark(Mark::Cursor(0), 5);
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 4).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_screen_offset_with_tabs() {
+        let mut buffer = setup_buffer("\t\t\t\nA\tB");
+        buffer.set_mark(Mark::Cursor(0), 3);
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 4).unwrap(), 12);
+
+        buffer.set_mark(Mark::Cursor(0), 6);
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 4).unwrap(), 4);
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 8).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_set_mark() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 2);
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_shift_down() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(2));
+
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (2, 2));
+    }
+
+    #[test]
+    fn test_shift_right() {
+        let mut buffer = setup_buffer("Test");
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(1));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_shift_up() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Cursor(0), 10);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Up(1));
+
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 1));
+    }
+
+    #[test]
+    fn test_shift_left() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_shift_linestart() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineStart);
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shift_lineend() {
+        let mut buffer = setup_buffer("Test");
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineEnd);
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_shift_linestart_and_lineend_on_first_line() {
+        let mut buffer = setup_buffer("Test\nA");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineEnd);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineStart);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shift_linestart_and_lineend_on_last_line() {
+        let mut buffer = setup_buffer("Test\nAbc");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineEnd);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 8);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineStart);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_shift_linestart_and_lineend_on_empty_line() {
+        let mut buffer = setup_buffer("Test\n\nA");
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineEnd);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::LineStart);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_shift_to_buffer_start_and_end() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.shift_mark(Mark::Cursor(0), Direction::BufferEnd);
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (4, 2));
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::BufferStart);
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_shift_to_buffer_end_with_trailing_newline() {
+        let mut buffer = setup_buffer("Test\nA\n");
+        buffer.shift_mark(Mark::Cursor(0), Direction::BufferEnd);
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 2));
+    }
+
+    #[test]
+    fn test_shift_by_paragraph_forward() {
+        let mut buffer = setup_buffer("\n\na\nb\n\n\nc\n\n");
+        let mut lines = Vec::new();
+        for _ in range(0, 4) {
+            buffer.shift_mark(Mark::Cursor(0), Direction::NextParagraph);
+            lines.push(buffer.get_mark_coords(Mark::Cursor(0)).unwrap().1);
+        }
+        assert_eq!(lines, vec![4, 7, 8, 8]);
+    }
+
+    #[test]
+    fn test_shift_by_paragraph_backward() {
+        let mut buffer = setup_buffer("\n\na\nb\n\n\nc\n\n");
+        buffer.shift_mark(Mark::Cursor(0), Direction::BufferEnd);
+        let mut lines = Vec::new();
+        for _ in range(0, 4) {
+            buffer.shift_mark(Mark::Cursor(0), Direction::PrevParagraph);
+            lines.push(buffer.get_mark_coords(Mark::Cursor(0)).unwrap().1);
+        }
+        assert_eq!(lines, vec![5, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_shift_by_paragraph_without_blank_lines() {
+        let mut buffer = setup_buffer("a\nb");
+        buffer.shift_mark(Mark::Cursor(0), Direction::NextParagraph);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+        buffer.shift_mark(Mark::Cursor(0), Direction::PrevParagraph);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_goto_line() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.goto_line(Mark::Cursor(0), 2);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+
+        buffer.goto_line(Mark::Cursor(0), 1);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_goto_line_past_the_end() {
+        let mut buffer = setup_buffer("Test\nA\nTest");
+        buffer.goto_line(Mark::Cursor(0), 347);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_vertical_movement_keeps_desired_column() {
+        let mut buffer = setup_buffer("Test line\n\nTest line");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 1));
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (6, 2));
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::Up(2));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (6, 0));
+    }
+
+    #[test]
+    fn test_horizontal_movement_resets_desired_column() {
+        let mut buffer = setup_buffer("Test line\nAb\nTest line");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 2));
+    }
+
+    #[test]
+    fn test_editing_resets_desired_column() {
+        let mut buffer = setup_buffer("Test line\nAb\nTest line");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        buffer.insert_char(Mark::Cursor(0), b'c');
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (2, 2));
+    }
+
+    #[test]
+    fn test_vertical_movement_lands_on_characters() {
+        let mut buffer = setup_buffer("ab\n\u{e9}\u{e9}\nabc");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 5);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 9);
+
+        //wide characters are landed on at their start, and combining marks are passed over
+        let mut buffer = setup_buffer("\u{4e2d}\u{6587}a\nabcd\ne\u{301}x");
+        buffer.set_mark(Mark::Cursor(0), 2 + 8);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Up(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+        buffer.set_mark(Mark::Cursor(0), 1 + 8);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3 + 13);
+    }
+
+    #[test]
+    fn test_vertical_movement_counts_tabs_to_their_stop() {
+        let mut buffer = setup_buffer("\tx\nabcdefg\n\t\ty");
+        buffer.tab_width = 4;
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (4, 1));
+
+        //a column inside a tab lands on the tab
+        buffer.set_mark(Mark::Cursor(0), 3 + 6);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 2));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Up(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (6, 1));
+    }
+
+    #[test]
+    fn test_find_matching_bracket_nested() {
+        let buffer = setup_buffer("f(a[0], {b})\n");
+        assert_eq!(buffer.find_matching_bracket(1), Some(11));
+        assert_eq!(buffer.find_matching_bracket(11), Some(1));
+        assert_eq!(buffer.find_matching_bracket(3), Some(5));
+        assert_eq!(buffer.find_matching_bracket(10), Some(8));
+    }
+
+    #[test]
+    fn test_find_matching_bracket_across_lines() {
+        let buffer = setup_buffer("fn main() {\n    x\n}");
+        assert_eq!(buffer.find_matching_bracket(10), Some(18));
+        assert_eq!(buffer.find_matching_bracket(18), Some(10));
+    }
+
+    #[test]
+    fn test_find_matching_bracket_from_before_bracket() {
+        let buffer = setup_buffer("call (x)\n(y)");
+        assert_eq!(buffer.find_matching_bracket(0), Some(7));
+        assert_eq!(buffer.find_matching_bracket(8), None);
+    }
+
+    #[test]
+    fn test_find_matching_bracket_unbalanced() {
+        let buffer = setup_buffer("((a)\nb)]");
+        assert_eq!(buffer.find_matching_bracket(0), Some(6));
+        assert_eq!(buffer.find_matching_bracket(1), Some(3));
+        assert_eq!(buffer.find_matching_bracket(7), None);
+
+        let buffer = setup_buffer("(a");
+        assert_eq!(buffer.find_matching_bracket(0), None);
+    }
+
+    #[test]
+    fn test_enclosing_brackets_nested() {
+        let buffer = setup_buffer("f(a, (b), [c])");
+        assert_eq!(buffer.enclosing_brackets(2, b'(', b')'), Some((1, 13)));
+        assert_eq!(buffer.enclosing_brackets(6, b'(', b')'), Some((5, 7)));
+        assert_eq!(buffer.enclosing_brackets(5, b'(', b')'), Some((5, 7)));
+        assert_eq!(buffer.enclosing_brackets(7, b'(', b')'), Some((5, 7)));
+        assert_eq!(buffer.enclosing_brackets(8, b'(', b')'), Some((1, 13)));
+        assert_eq!(buffer.enclosing_brackets(11, b'(', b')'), Some((1, 13)));
+        assert_eq!(buffer.enclosing_brackets(11, b'[', b']'), Some((10, 12)));
+        assert_eq!(buffer.enclosing_brackets(0, b'(', b')'), None);
+        assert_eq!(buffer.enclosing_brackets(2, b'{', b'}'), None);
+    }
+
+    #[test]
+    fn test_enclosing_brackets_unbalanced() {
+        let buffer = setup_buffer("(a (b");
+        assert_eq!(buffer.enclosing_brackets(4, b'(', b')'), None);
+        let buffer = setup_buffer("a) (b)");
+        assert_eq!(buffer.enclosing_brackets(0, b'(', b')'), None);
+        assert_eq!(buffer.enclosing_brackets(4, b'(', b')'), Some((3, 5)));
+    }
+
+    #[test]
+    fn test_enclosing_quotes() {
+        let buffer = setup_buffer(r#"a "b \"c\\" d "e""#);
+        assert_eq!(buffer.enclosing_quotes(4, b'"'), Some((2, 10)));
+        assert_eq!(buffer.enclosing_quotes(2, b'"'), Some((2, 10)));
+        assert_eq!(buffer.enclosing_quotes(13, b'"'), None);
+        assert_eq!(buffer.enclosing_quotes(15, b'"'), Some((14, 16)));
+        assert_eq!(buffer.enclosing_quotes(0, b'"'), None);
+        assert_eq!(buffer.enclosing_quotes(4, b'\''), None);
+
+        let buffer = setup_buffer("\"a\nb\"");
+        assert_eq!(buffer.enclosing_quotes(1, b'"'), None);
+    }
+
+    #[test]
+    fn test_word_with_space() {
+        let mut buffer = setup_buffer("one  two");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        assert_eq!(buffer.word_with_space(Mark::Cursor(0)), Some((0, 5)));
+        buffer.set_mark(Mark::Cursor(0), 6);
+        assert_eq!(buffer.word_with_space(Mark::Cursor(0)), Some((3, 8)));
+        buffer.set_mark(Mark::Cursor(0), 3);
+        assert_eq!(buffer.word_with_space(Mark::Cursor(0)), None);
+    }
+
+    #[test]
+    fn test_find() {
+        let buffer = setup_buffer("one two\none two");
+        assert_eq!(buffer.find("two", 0), Some(4));
+        assert_eq!(buffer.find("two", 4), Some(4));
+        assert_eq!(buffer.find("two", 5), Some(12));
+        assert_eq!(buffer.find("two", 13), None);
+        assert_eq!(buffer.find("two\none", 0), Some(4));
+        assert_eq!(buffer.find("three", 0), None);
+    }
+
+    #[test]
+    fn test_find_at_the_edges() {
+        let buffer = setup_buffer("abc");
+        assert_eq!(buffer.find("abc", 0), Some(0));
+        assert_eq!(buffer.find("c", 2), Some(2));
+        assert_eq!(buffer.find("abcd", 0), None);
+        assert_eq!(buffer.find("", 0), None);
+        assert_eq!(buffer.find("a", 10), None);
+    }
+
+    #[test]
+    fn test_find_multibyte() {
+        let buffer = setup_buffer("naïve café");
+        assert_eq!(buffer.find("é", 0), Some(10));
+        assert_eq!(buffer.find("ïv", 0), Some(2));
+    }
+
+    #[test]
+    fn test_find_regex() {
+        let buffer = setup_buffer("let x = 10;\nlet yy = 200;\n");
+        let re = Regex::new(r"\d+").unwrap();
+        assert_eq!(buffer.find_regex(&re, 0), Some((8, 10)));
+        assert_eq!(buffer.find_regex(&re, 9), Some((21, 24)));
+        assert_eq!(buffer.find_regex(&re, 22), None);
+
+        let re = Regex::new(r"^let (\w+)").unwrap();
+        assert_eq!(buffer.find_regex(&re, 1), Some((12, 18)));
+    }
+
+    #[test]
+    fn test_find_regex_does_not_span_lines() {
+        let buffer = setup_buffer("ab\ncd");
+        assert_eq!(buffer.find_regex(&Regex::new(r"b\s*c").unwrap(), 0), None);
+        assert_eq!(buffer.find_regex(&Regex::new(r"d$").unwrap(), 0), Some((4, 5)));
+        assert_eq!(buffer.find_regex(&Regex::new(r"b$").unwrap(), 0), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_rfind_regex() {
+        let buffer = setup_buffer("a1 a22\na333");
+        let re = Regex::new(r"a\d+").unwrap();
+        assert_eq!(buffer.rfind_regex(&re, 100), Some((7, 11)));
+        assert_eq!(buffer.rfind_regex(&re, 7), Some((3, 6)));
+        assert_eq!(buffer.rfind_regex(&re, 3), Some((0, 2)));
+        assert_eq!(buffer.rfind_regex(&re, 0), None);
+    }
+
+    #[test]
+    fn test_find_exact_case() {
+        let buffer = setup_buffer("Word word WORD");
+        assert_eq!(buffer.find_case("word", 0, Case::Exact), Some((5, 9)));
+        assert_eq!(buffer.find_case("WORD", 0, Case::Exact), Some((10, 14)));
+        assert_eq!(buffer.find_case("wORD", 0, Case::Exact), None);
+        assert_eq!(buffer.rfind_case("Word", 14, Case::Exact), Some((0, 4)));
+    }
+
+    #[test]
+    fn test_find_ignoring_case() {
+        let buffer = setup_buffer("Word word\nWORD");
+        assert_eq!(buffer.find_case("word", 0, Case::Ignore), Some((0, 4)));
+        assert_eq!(buffer.find_case("WORD", 1, Case::Ignore), Some((5, 9)));
+        assert_eq!(buffer.find_case("wOrD", 6, Case::Ignore), Some((10, 14)));
+        assert_eq!(buffer.rfind_case("word", 10, Case::Ignore), Some((5, 9)));
+        assert_eq!(buffer.find_case("d w", 0, Case::Ignore), Some((3, 6)));
+        assert_eq!(buffer.find_case("d\nw", 0, Case::Ignore), None);
+    }
+
+    #[test]
+    fn test_find_with_smart_case() {
+        let buffer = setup_buffer("Word word WORD");
+        assert_eq!(buffer.find_case("word", 0, Case::Smart), Some((0, 4)));
+        assert_eq!(buffer.rfind_case("word", 14, Case::Smart), Some((10, 14)));
+        assert_eq!(buffer.find_case("Word", 1, Case::Smart), None);
+        assert_eq!(buffer.find_case("WORD", 0, Case::Smart), Some((10, 14)));
+    }
+
+    #[test]
+    fn test_find_ignoring_case_folds_unicode() {
+        let buffer = setup_buffer("CAFÉ café");
+        assert_eq!(buffer.find_case("é", 0, Case::Ignore), Some((3, 5)));
+        assert_eq!(buffer.find_case("café", 1, Case::Smart), Some((6, 11)));
+        assert_eq!(buffer.find_case("CAFÉ", 1, Case::Smart), None);
+        assert!(Case::Smart.ignores_case("café"));
+        assert!(!Case::Smart.ignores_case("Café"));
+    }
+
+    #[test]
+    fn test_selection_range_either_way_round() {
+        let mut buffer = setup_buffer("one\ntwo\nthree");
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.start_selection(Mark::Cursor(0));
+        assert!(buffer.is_selecting());
+        assert_eq!(buffer.selection_range(), None);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.selection_range(), Some((5, 9)));
+        buffer.shift_mark(Mark::Cursor(0), Direction::BufferStart);
+        assert_eq!(buffer.selection_range(), Some((0, 5)));
+
+        buffer.clear_selection();
+        assert!(!buffer.is_selecting());
+        assert_eq!(buffer.selection_range(), None);
+    }
+
+    #[test]
+    fn test_enclosing_ranges_grow_a_step_at_a_time() {
+        let buffer = setup_buffer("one two\nthree\n\nfour");
+        assert_eq!(buffer.enclosing_range(5, 5), Some((4, 7)));
+        assert_eq!(buffer.enclosing_range(4, 7), Some((0, 8)));
+        assert_eq!(buffer.enclosing_range(0, 8), Some((0, 14)));
+        assert_eq!(buffer.enclosing_range(0, 14), Some((0, 19)));
+        assert_eq!(buffer.enclosing_range(0, 19), None);
+
+        // just after a word, and between words
+        assert_eq!(buffer.enclosing_range(3, 3), Some((0, 3)));
+        assert_eq!(buffer.enclosing_range(2, 5), Some((0, 8)));
+        // across lines, and a word that is the whole of the last paragraph
+        assert_eq!(buffer.enclosing_range(5, 10), Some((0, 14)));
+        assert_eq!(buffer.enclosing_range(16, 16), Some((15, 19)));
+        assert_eq!(buffer.enclosing_range(15, 19), Some((0, 19)));
+    }
+
+    #[test]
+    fn test_selected_lines() {
+        let mut buffer = setup_buffer("one\ntwo\nthree");
+        assert_eq!(buffer.selected_lines(), None);
+        buffer.select_range(Mark::Cursor(0), 1, 5);
+        assert_eq!(buffer.selected_lines(), Some((0, 2)));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)), Some(5));
+        buffer.select_range(Mark::Cursor(0), 4, 8);
+        assert_eq!(buffer.selected_lines(), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_edits_end_the_selection() {
+        let mut buffer = setup_buffer("abc");
+        buffer.start_selection(Mark::Cursor(0));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(2));
+        buffer.insert_char(Mark::Cursor(0), b'x');
+        assert!(!buffer.is_selecting());
+
+        buffer.start_selection(Mark::Cursor(0));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+        buffer.undo();
+        assert!(!buffer.is_selecting());
+    }
+
+    #[test]
+    fn test_block_ranges() {
+        let mut buffer = setup_buffer("abcd\n\nefgh");
+        buffer.set_mark(Mark::Cursor(0), 9);
+        buffer.start_block_selection(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 1);
+        assert!(buffer.is_block_selection());
+        assert_eq!(buffer.block_ranges(4), Some((1, vec![(1, 3), (5, 5), (7, 9)])));
+
+        buffer.start_selection(Mark::Cursor(0));
+        assert_eq!(buffer.block_ranges(4), None);
+    }
+
+    #[test]
+    fn test_block_ranges_by_screen_column() {
+        let mut buffer = setup_buffer("\tab\nabcdefg");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.start_block_selection(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 10);
+        assert_eq!(buffer.block_ranges(4), Some((4, vec![(1, 3), (8, 10)])));
     }
 
     #[test]
-    fn test_shift_lineend() {
-        let mut buffer = setup_buffer("Test");
-        buffer.shift_mark(Mark::Cursor(0), Direction::LineEnd);
+    fn test_block_ranges_count_control_chars_as_drawn() {
+        // the escape is drawn in a cell of its own, so the `b` after it is in column 2
+        let mut buffer = setup_buffer("a\x1bbc\nabcd");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        assert_eq!(buffer.get_mark_screen_offset(Mark::Cursor(0), 4).unwrap(), 2);
+        buffer.start_block_selection(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 8);
+        assert_eq!(buffer.block_ranges(4), Some((2, vec![(2, 3), (7, 8)])));
+    }
 
-        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+    #[test]
+    fn test_delete_block_pads_short_lines() {
+        let mut buffer = setup_buffer("abcd\n\nefgh");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.start_block_selection(Mark::Cursor(0));
+        buffer.set_mark(Mark::Cursor(0), 9);
+        let deleted = buffer.delete_block(4, true);
+        assert_eq!(deleted, vec![(String::from_str("bc"), 1), (String::new(), 4),
+                                 (String::from_str("fg"), 6)]);
+        assert_eq!(buffer.text_range(0, buffer.len()), String::from_str("ad\n \neh"));
+        assert!(!buffer.is_selecting());
+
+        buffer.undo();
+        assert_eq!(buffer.text_range(0, buffer.len()), String::from_str("abcd\n\nefgh"));
+    }
+
+    #[test]
+    fn test_insert_block() {
+        let mut buffer = setup_buffer("ab\n\ncd");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        let lines = vec![String::from_str("X"), String::from_str("Y"), String::from_str("Z"),
+                         String::from_str("W")];
+        buffer.insert_block(Mark::Cursor(0), lines[], 4);
+        assert_eq!(buffer.text_range(0, buffer.len()), String::from_str("aXb\n Y\ncZd\n W"));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)), Some(1));
+
+        buffer.undo();
+        assert_eq!(buffer.text_range(0, buffer.len()), String::from_str("ab\n\ncd"));
+    }
+
+    #[test]
+    fn test_word_at() {
+        let mut buffer = setup_buffer("let foo_bar = x;");
+        buffer.set_mark(Mark::Cursor(0), 6);
+        assert_eq!(buffer.word_at(Mark::Cursor(0)), Some((4, 11)));
+        assert_eq!(buffer.text_range(4, 11), String::from_str("foo_bar"));
+        buffer.set_mark(Mark::Cursor(0), 11);
+        assert_eq!(buffer.word_at(Mark::Cursor(0)), None);
+        buffer.set_mark(Mark::Cursor(0), 14);
+        assert_eq!(buffer.word_at(Mark::Cursor(0)), Some((14, 15)));
+        buffer.set_mark(Mark::Cursor(0), 16);
+        assert_eq!(buffer.word_at(Mark::Cursor(0)), None);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let mut buffer = setup_buffer("one\n\nthree");
+        assert_eq!(buffer.line_range(Mark::Cursor(0)), Some((0, 3)));
+        buffer.set_mark(Mark::Cursor(0), 4);
+        assert_eq!(buffer.line_range(Mark::Cursor(0)), Some((4, 4)));
+        buffer.set_mark(Mark::Cursor(0), 10);
+        assert_eq!(buffer.line_range(Mark::Cursor(0)), Some((5, 10)));
+        assert_eq!(buffer.line_range(Mark::Named('x')), None);
+    }
+
+    #[test]
+    fn test_is_whole_word() {
+        let buffer = setup_buffer("foo foobar _foo foo");
+        assert!(buffer.is_whole_word(0, 3));
+        assert!(!buffer.is_whole_word(4, 7));
+        assert!(!buffer.is_whole_word(12, 15));
+        assert!(buffer.is_whole_word(16, 19));
+    }
+
+    #[test]
+    fn test_find_all_in_a_range() {
+        let buffer = setup_buffer("ab Ab\nab ab\nab");
+        fn all(matches: Matches) -> Vec<(uint, uint)> { matches.collect() }
+        assert_eq!(all(buffer.matches_case("ab", 0, 15, Case::Exact)), vec![(0, 2), (6, 8), (9, 11), (12, 14)]);
+        assert_eq!(all(buffer.matches_case("ab", 0, 6, Case::Smart)), vec![(0, 2), (3, 5)]);
+        assert_eq!(all(buffer.matches_case("ab", 1, 9, Case::Ignore)), vec![(3, 5), (6, 8)]);
+        assert_eq!(all(buffer.matches_case("", 0, 15, Case::Exact)), vec![]);
+        assert_eq!(all(buffer.matches_regex(&Regex::new(r"b\s").unwrap(), 0, 15)), vec![(1, 3), (7, 9)]);
+    }
+
+    #[test]
+    fn test_matches_of_whole_words() {
+        let buffer = setup_buffer("ab abc ab_ xab ab");
+        let matches: Vec<(uint, uint)> = buffer.matches_case("ab", 0, 17, Case::Exact).whole_words().collect();
+        assert_eq!(matches, vec![(0, 2), (15, 17)]);
+    }
+
+    #[test]
+    fn test_zero_length_matches_are_each_counted_once() {
+        let buffer = setup_buffer("ab\n\nc");
+        let re = Regex::new("^").unwrap();
+        assert_eq!(buffer.matches_regex(&re, 0, buffer.len()).count(), 3);
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(buffer.matches_regex(&re, 0, buffer.len()).count(), 6);
+    }
+
+    #[test]
+    fn test_rfind() {
+        let buffer = setup_buffer("one two\none two");
+        assert_eq!(buffer.rfind("two", 16), Some(12));
+        assert_eq!(buffer.rfind("two", 12), Some(4));
+        assert_eq!(buffer.rfind("two", 5), Some(4));
+        assert_eq!(buffer.rfind("two", 4), None);
+        assert_eq!(buffer.rfind("two\none", 100), Some(4));
+        assert_eq!(buffer.rfind("three", 16), None);
+    }
+
+    #[test]
+    fn test_rfind_at_the_edges() {
+        let buffer = setup_buffer("abc");
+        assert_eq!(buffer.rfind("abc", 3), Some(0));
+        assert_eq!(buffer.rfind("abc", 1), Some(0));
+        assert_eq!(buffer.rfind("abc", 0), None);
+        assert_eq!(buffer.rfind("abcd", 3), None);
+        assert_eq!(buffer.rfind("", 3), None);
+    }
+
+    #[test]
+    fn test_first_non_whitespace() {
+        let buffer = setup_buffer("\t  foo\n   \nbar");
+        assert_eq!(buffer.first_non_whitespace(0), Some(3));
+        assert_eq!(buffer.first_non_whitespace(1), None);
+        assert_eq!(buffer.first_non_whitespace(2), Some(11));
+        assert_eq!(buffer.first_non_whitespace(3), None);
+    }
+
+    #[test]
+    fn test_leading_whitespace() {
+        let mut buffer = setup_buffer("\t  if x {\n");
+        buffer.set_mark(Mark::Cursor(0), 8);
+        assert_eq!(buffer.leading_whitespace(Mark::Cursor(0)).as_slice(), "\t  ");
+
+        buffer.set_mark(Mark::Cursor(0), 1);
+        assert_eq!(buffer.leading_whitespace(Mark::Cursor(0)).as_slice(), "\t");
+    }
+
+    #[test]
+    fn test_indent_lines() {
+        let mut buffer = setup_buffer("a\n\n  b\nc");
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.indent_lines(0, 3, "    ");
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"    a\n");
+        assert_eq!(lines.next().unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"      b\n");
+        assert_eq!(lines.next().unwrap(), b"c");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (6, 2));
+    }
+
+    #[test]
+    fn test_indent_lines_is_one_undo_step() {
+        let mut buffer = setup_buffer("a\nb");
+        buffer.indent_lines(0, 2, "\t");
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), b"a\n");
+        assert_eq!(buffer.lines().nth(1).unwrap(), b"b");
+    }
+
+    #[test]
+    fn test_indenting_moves_marks_and_keeps_the_selection() {
+        let mut buffer = setup_buffer("a\nb\nc");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.start_selection(Mark::Cursor(0));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Down(1));
+        buffer.set_mark(Mark::Named('a'), 5);
+        buffer.indent_lines(0, 2, "\t");
+
+        assert_eq!(buffer.selection_range(), Some((4, 6)));
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')).unwrap(), 7);
+        buffer.undo();
+        assert_eq!(buffer.get_mark_idx(Mark::Named('a')).unwrap(), 5);
+        assert_eq!(buffer.lines().next().unwrap(), b"a\n");
+    }
+
+    #[test]
+    fn test_toggle_comment_on_mixed_block() {
+        let mut buffer = setup_buffer("a\n    // b\n\n  c");
+        buffer.set_mark(Mark::Cursor(0), 14);
+        buffer.toggle_comment(0, 4, "// ");
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"// a\n");
+        assert_eq!(lines.next().unwrap(), b"    // // b\n");
+        assert_eq!(lines.next().unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"  // c");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (5, 3));
+    }
+
+    #[test]
+    fn test_toggle_comment_twice_is_a_no_op() {
+        let mut buffer = setup_buffer("fn main() {\n    x\n}");
+        buffer.set_mark(Mark::Cursor(0), 16);
+        buffer.toggle_comment(0, 3, "// ");
+        buffer.toggle_comment(0, 3, "// ");
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"fn main() {\n");
+        assert_eq!(lines.next().unwrap(), b"    x\n");
+        assert_eq!(lines.next().unwrap(), b"}");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_fill_paragraph() {
+        let mut buffer = setup_buffer("aaa bbb ccc\nddd eee\n\nfff ggg");
+        buffer.set_mark(Mark::Cursor(0), 17);
+        buffer.fill_paragraph(Mark::Cursor(0), 10, 4);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"aaa bbb\n");
+        assert_eq!(lines.next().unwrap(), b"ccc ddd\n");
+        assert_eq!(lines.next().unwrap(), b"eee\n");
+        assert_eq!(lines.next().unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"fff ggg");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 2));
+    }
+
+    #[test]
+    fn test_fill_paragraph_joins_short_lines() {
+        let mut buffer = setup_buffer("\none\ntwo\nthree\n");
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.fill_paragraph(Mark::Cursor(0), 79, 4);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"\n");
+        assert_eq!(lines.next().unwrap(), b"one two three\n");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (4, 1));
+    }
+
+    #[test]
+    fn test_fill_paragraph_with_long_words() {
+        let mut buffer = setup_buffer("a verylongword b");
+        buffer.fill_paragraph(Mark::Cursor(0), 5, 4);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"a\n");
+        assert_eq!(lines.next().unwrap(), b"verylongword\n");
+        assert_eq!(lines.next().unwrap(), b"b");
+    }
+
+    #[test]
+    fn test_fill_paragraph_keeps_indentation() {
+        let mut buffer = setup_buffer("  one two three\n  four");
+        buffer.fill_paragraph(Mark::Cursor(0), 12, 4);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"  one two\n");
+        assert_eq!(lines.next().unwrap(), b"  three four");
+    }
+
+    #[test]
+    fn test_move_line_up_and_down() {
+        let mut buffer = setup_buffer("one\ntwo\nthree");
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.move_line(Mark::Cursor(0), Direction::Up(1));
+        assert_eq!(buffer.lines().next().unwrap(), b"two\n");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 0));
+
+        buffer.move_line(Mark::Cursor(0), Direction::Down(1));
+        assert_eq!(buffer.lines().next().unwrap(), b"one\n");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 1));
+    }
+
+    #[test]
+    fn test_move_line_at_the_edges_does_nothing() {
+        let mut buffer = setup_buffer("one\ntwo");
+        buffer.move_line(Mark::Cursor(0), Direction::Up(1));
+        buffer.set_mark(Mark::Cursor(0), 5);
+        buffer.move_line(Mark::Cursor(0), Direction::Down(1));
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"one\n");
+        assert_eq!(lines.next().unwrap(), b"two");
+        assert!(!buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_move_line_through_the_whole_file() {
+        let mut buffer = setup_buffer("a\nb\nc\nd");
+        for _ in range(0, 3) { buffer.move_line(Mark::Cursor(0), Direction::Down(1)); }
+        {
+            let mut lines = buffer.lines();
+            assert_eq!(lines.next().unwrap(), b"b\n");
+            assert_eq!(lines.next().unwrap(), b"c\n");
+            assert_eq!(lines.next().unwrap(), b"d\n");
+            assert_eq!(lines.next().unwrap(), b"a");
+        }
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 3));
+
+        for _ in range(0, 3) { buffer.move_line(Mark::Cursor(0), Direction::Up(1)); }
+        let mut lines = buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"a\n");
+        assert_eq!(lines.next().unwrap(), b"b\n");
+        assert_eq!(lines.next().unwrap(), b"c\n");
+        assert_eq!(lines.next().unwrap(), b"d");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_dedent_lines() {
+        let mut buffer = setup_buffer("      a\n  b\nc\n    d");
+        buffer.set_mark(Mark::Cursor(0), 7);
+        buffer.dedent_lines(0, 4, 4);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"  a\n");
+        assert_eq!(lines.next().unwrap(), b"b\n");
+        assert_eq!(lines.next().unwrap(), b"c\n");
+        assert_eq!(lines.next().unwrap(), b"d");
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (3, 0));
+    }
+
+    #[test]
+    fn test_dedent_mixed_indent() {
+        let mut buffer = setup_buffer("\t\ta\n  \tb\n \t c");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.dedent_lines(0, 3, 4);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"\ta\n");
+        assert_eq!(lines.next().unwrap(), b"b\n");
+        assert_eq!(lines.next().unwrap(), b" c");
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_expand_tabs_in_indentation() {
+        let mut buffer = setup_buffer("\tif x {\n\t\ta\tb\n  \tc");
+        assert_eq!(buffer.expand_tabs(4, false), 3);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"    if x {\n");
+        assert_eq!(lines.next().unwrap(), b"        a\tb\n");
+        assert_eq!(lines.next().unwrap(), b"    c");
+    }
+
+    #[test]
+    fn test_expand_tabs_in_whole_line() {
+        let mut buffer = setup_buffer("\ta\tb\nab\tc");
+        buffer.expand_tabs(4, true);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"    a   b\n");
+        assert_eq!(lines.next().unwrap(), b"ab  c");
+    }
+
+    #[test]
+    fn test_unexpand_tabs_in_indentation() {
+        let mut buffer = setup_buffer("      a    b\n   c\n  \td");
+        assert_eq!(buffer.unexpand_tabs(4, false), 2);
+        let mut lines = buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"\t  a    b\n");
+        assert_eq!(lines.next().unwrap(), b"   c\n");
+        assert_eq!(lines.next().unwrap(), b"\td");
+    }
+
+    #[test]
+    fn test_unexpand_tabs_in_whole_line() {
+        let mut buffer = setup_buffer("ab      c d");
+        buffer.unexpand_tabs(4, true);
+        assert_eq!(buffer.lines().next().unwrap(), b"ab\t\tc d");
+    }
+
+    #[test]
+    fn test_converting_tabs_keeps_screen_column() {
+        let mut buffer = setup_buffer("\t\tab");
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.expand_tabs(4, false);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 9);
+
+        buffer.unexpand_tabs(4, false);
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_converting_tabs_without_changes_leaves_buffer_clean() {
+        let mut buffer = setup_buffer("a\tb\n  c");
+        assert_eq!(buffer.expand_tabs(4, false), 0);
+        assert_eq!(buffer.unexpand_tabs(4, false), 0);
+        assert!(!buffer.is_dirty());
     }
 
     #[test]
@@ -392,6 +5136,171 @@ mod test {
         assert_eq!(lines.next().unwrap(), [b'T',b'e',b's',b't']);
     }
 
+    #[test]
+    fn test_undo_typed_run() {
+        let mut buffer = setup_buffer("");
+        for (idx, ch) in "abc".bytes().enumerate() {
+            buffer.set_mark(Mark::Cursor(0), idx);
+            buffer.insert_char(Mark::Cursor(0), ch);
+        }
+        buffer.undo();
+
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_undo_stops_at_newline() {
+        let mut buffer = setup_buffer("");
+        for (idx, ch) in "a\nb".bytes().enumerate() {
+            buffer.set_mark(Mark::Cursor(0), idx);
+            buffer.insert_char(Mark::Cursor(0), ch);
+        }
+        buffer.undo();
+
+        assert_eq!(buffer.lines().next().unwrap(), [b'a', b'\n']);
+    }
+
+    #[test]
+    fn test_redo_cleared_by_edit() {
+        let mut buffer = setup_buffer("");
+        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.undo();
+        buffer.insert_char(Mark::Cursor(0), b'B');
+
+        assert!(buffer.redo().is_none());
+        assert_eq!(buffer.lines().next().unwrap(), [b'B']);
+    }
+
+    #[test]
+    fn test_shift_left_past_start_clamps() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(10));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shift_right_past_end_clamps() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(10));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_shift_by_zero() {
+        let mut buffer = setup_buffer("Test");
+        buffer.set_mark(Mark::Cursor(0), 2);
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(0));
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(0));
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_shift_within_bounds() {
+        let mut buffer = setup_buffer("Test\nA");
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(6));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 6);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(3));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_walk_buffer_right_and_left() {
+        let mut buffer = setup_buffer("ab\nc");
+        for idx in range(1, 5) {
+            buffer.shift_mark(Mark::Cursor(0), Direction::Right(1));
+            assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), idx);
+        }
+        // already at the end of the buffer
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+
+        for idx in range(0, 4).rev() {
+            buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+            assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), idx);
+        }
+    }
+
+    #[test]
+    fn test_shift_right_in_one_char_buffer() {
+        let mut buffer = setup_buffer("A");
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(1));
+
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn test_shift_over_combining_character() {
+        let mut buffer = setup_buffer("e\u{301}x");
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shift_over_flag() {
+        let mut buffer = setup_buffer("a\u{1F1EC}\u{1F1E7}b");
+        buffer.shift_mark(Mark::Cursor(0), Direction::Right(2));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 9);
+
+        buffer.shift_mark(Mark::Cursor(0), Direction::Left(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_remove_combined_character() {
+        let mut buffer = setup_buffer("e\u{301}x");
+
+        assert_eq!(buffer.remove_char(Mark::Cursor(0)).unwrap().as_slice(), "e\u{301}");
+        assert_eq!(buffer.lines().next().unwrap(), b"x");
+    }
+
+    #[test]
+    fn test_move_by_word_over_punctuation() {
+        let mut buffer = setup_buffer("foo.bar_baz(qux)");
+        buffer.shift_mark(Mark::Cursor(0), Direction::NextWord(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
+        buffer.shift_mark(Mark::Cursor(0), Direction::NextWord(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 11);
+        buffer.shift_mark(Mark::Cursor(0), Direction::PrevWord(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_move_by_word_over_spaces() {
+        let mut buffer = setup_buffer("one   two");
+        buffer.shift_mark(Mark::Cursor(0), Direction::NextWord(2));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 9);
+        buffer.shift_mark(Mark::Cursor(0), Direction::PrevWord(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_move_by_word_across_lines() {
+        let mut buffer = setup_buffer("one\n\ntwo");
+        buffer.set_mark(Mark::Cursor(0), 3);
+        buffer.shift_mark(Mark::Cursor(0), Direction::NextWord(1));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (3, 2));
+        buffer.shift_mark(Mark::Cursor(0), Direction::PrevWord(2));
+        assert_eq!(buffer.get_mark_coords(Mark::Cursor(0)).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_move_by_word_clamps_at_buffer_edges() {
+        let mut buffer = setup_buffer("  word  ");
+        buffer.shift_mark(Mark::Cursor(0), Direction::PrevWord(1));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 0);
+        buffer.shift_mark(Mark::Cursor(0), Direction::NextWord(3));
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 8);
+    }
+
     #[test]
     fn move_from_final_position() {
         let mut buffer = setup_buffer("Test");
@@ -401,4 +5310,112 @@ mod test {
         assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 3);
     }
 
+    fn large_text() -> Vec<u8> {
+        let line = "2015-01-20 12:00:00 request served in 12ms \u{2713}\r\n";
+        range(0u, 100000).flat_map(|_| line.bytes()).collect()
+    }
+
+    #[bench]
+    fn bench_load_all_at_once(b: &mut Bencher) {
+        let data = large_text();
+        b.iter(|| {
+            let contents = BufReader::new(data[]).read_to_end().unwrap();
+            let (text, _) = decode_text(contents[]);
+            let mut gap = GapBuffer::new();
+            gap.extend(text.into_iter());
+            gap
+        })
+    }
+
+    #[bench]
+    fn bench_load_streamed(b: &mut Bencher) {
+        let data = large_text();
+        b.iter(|| Buffer::new_from_reader(BufReader::new(data[])))
+    }
+
+    #[bench]
+    fn bench_find_regex_through_large_buffer(b: &mut Bencher) {
+        let buffer = Buffer::new_from_reader(BufReader::new(large_text()[]));
+        // never matches, so every line is looked through
+        let re = Regex::new(r"served in \d+s\b").unwrap();
+        b.iter(|| buffer.find_regex(&re, 0))
+    }
+
+    #[bench]
+    fn bench_count_matches_in_large_buffer(b: &mut Bencher) {
+        let buffer = Buffer::new_from_reader(BufReader::new(large_text()[]));
+        let len = buffer.len();
+        b.iter(|| buffer.matches_case("served", 0, len, Case::Exact).count())
+    }
+
+    #[bench]
+    fn bench_count_regex_matches_in_large_buffer(b: &mut Bencher) {
+        let buffer = Buffer::new_from_reader(BufReader::new(large_text()[]));
+        let re = Regex::new(r"\d+ms").unwrap();
+        let len = buffer.len();
+        b.iter(|| buffer.matches_regex(&re, 0, len).count())
+    }
+
+    #[bench]
+    fn bench_find_through_large_buffer(b: &mut Bencher) {
+        let buffer = Buffer::new_from_reader(BufReader::new(large_text()[]));
+        b.iter(|| buffer.find("served in 12s", 0))
+    }
+
+    #[bench]
+    fn bench_insert_large_paste(b: &mut Bencher) {
+        let text = String::from_utf8(large_text()).unwrap();
+        b.iter(|| {
+            let mut buffer = Buffer::new();
+            buffer.set_mark(Mark::Cursor(0), 0);
+            buffer.insert_str(Mark::Cursor(0), text[]);
+            buffer
+        })
+    }
+
+    #[bench]
+    fn bench_scroll_to_end_of_large_buffer(b: &mut Bencher) {
+        let buffer = Buffer::new_from_reader(BufReader::new(large_text()[]));
+        b.iter(|| {
+            let last = buffer.line_count() - 1;
+            buffer.lines_at(last).unwrap().next().map(|line| line.len())
+        })
+    }
+
+    //`large_text` written to a file, for mapping.
+    fn large_file() -> (TempDir, Path, u64) {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("large.log");
+        let data = large_text();
+        File::create(&path).write(data[]).unwrap();
+        (dir, path, data.len() as u64)
+    }
+
+    #[bench]
+    fn bench_open_mapped(b: &mut Bencher) {
+        let (_dir, path, size) = large_file();
+        b.iter(|| Buffer::map(&path, size).unwrap())
+    }
+
+    #[bench]
+    fn bench_open_mapped_and_scroll_to_end(b: &mut Bencher) {
+        let (_dir, path, size) = large_file();
+        b.iter(|| {
+            let buffer = Buffer::map(&path, size).unwrap();
+            let last = buffer.line_count() - 1;
+            buffer.lines_at(last).unwrap().next().map(|line| line.len())
+        })
+    }
+
+    #[bench]
+    fn bench_break_line_at_end_of_large_buffer(b: &mut Bencher) {
+        let mut buffer = Buffer::new_from_reader(BufReader::new(large_text()[]));
+        let end = buffer.len() - 1;
+        buffer.set_mark(Mark::Cursor(0), end);
+        b.iter(|| {
+            buffer.insert_char(Mark::Cursor(0), b'\n');
+            buffer.get_mark_pos(Mark::Cursor(0))
+        })
+    }
+
 }
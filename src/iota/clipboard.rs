@@ -0,0 +1,175 @@
+use std::os;
+use std::io::process::{Command, ProcessOutput, StdioContainer};
+
+/// Somewhere outside the editor to put text for other programs to take, and to take theirs from.
+pub trait Clipboard {
+    /// Put `text` on the clipboard in place of what was there, or say why it couldn't be.
+    fn copy(&mut self, text: &str) -> Result<(), String>;
+
+    /// The text on the clipboard, or why it couldn't be had.
+    fn paste(&mut self) -> Result<String, String>;
+}
+
+/// A pair of programs for the system clipboard: one that takes the text to copy on its input,
+/// and one that writes out the text to paste.
+pub struct Tool {
+    copy: Vec<&'static str>,
+    paste: Vec<&'static str>,
+}
+
+impl Tool {
+    pub fn new(copy: &[&'static str], paste: &[&'static str]) -> Tool {
+        Tool { copy: copy.to_vec(), paste: paste.to_vec() }
+    }
+}
+
+/// The system clipboard, reached through whichever of pbcopy, wl-copy, xclip or xsel suits the
+/// display and is installed.
+///
+/// The tools are tried in turn until one works, and the one that did is tried first after that.
+pub struct SystemClipboard {
+    tools: Vec<Tool>,
+    working: uint,      // index of the tool that last worked
+}
+
+impl SystemClipboard {
+    pub fn new() -> SystemClipboard {
+        SystemClipboard::with_tools(tools_for(cfg!(target_os = "macos"),
+                                              os::getenv("WAYLAND_DISPLAY").is_some(),
+                                              os::getenv("DISPLAY").is_some()))
+    }
+
+    pub fn with_tools(tools: Vec<Tool>) -> SystemClipboard {
+        SystemClipboard { tools: tools, working: 0 }
+    }
+
+    // The tools, the one that last worked first.
+    fn order(&self) -> Vec<uint> {
+        let mut order = vec![self.working];
+        order.extend(range(0, self.tools.len()).filter(|i| *i != self.working));
+        order.retain(|i| *i < self.tools.len());
+        order
+    }
+}
+
+impl Clipboard for SystemClipboard {
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        let mut error = String::from_str("no clipboard program for this display");
+        for i in self.order().into_iter() {
+            match run_copy(self.tools[i].copy[], text) {
+                Ok(())   => {
+                    self.working = i;
+                    return Ok(())
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    fn paste(&mut self) -> Result<String, String> {
+        let mut error = String::from_str("no clipboard program for this display");
+        for i in self.order().into_iter() {
+            match run_paste(self.tools[i].paste[]) {
+                Ok(text) => {
+                    self.working = i;
+                    return Ok(text)
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+}
+
+/// The tools worth trying: pbcopy on a Mac, wl-copy under Wayland, and xclip then xsel under X.
+pub fn tools_for(mac: bool, wayland: bool, x: bool) -> Vec<Tool> {
+    let mut tools = Vec::new();
+    if mac {
+        tools.push(Tool::new(&["pbcopy"], &["pbpaste"]));
+    }
+    if wayland {
+        tools.push(Tool::new(&["wl-copy"], &["wl-paste", "--no-newline"]));
+    }
+    if x {
+        tools.push(Tool::new(&["xclip", "-selection", "clipboard"],
+                             &["xclip", "-selection", "clipboard", "-o"]));
+        tools.push(Tool::new(&["xsel", "--clipboard", "--input"], &["xsel", "--clipboard", "--output"]));
+    }
+    tools
+}
+
+// Run the program in `args` with `text` as its input. xclip stays behind to hand the text out,
+// so its output goes nowhere rather than to a pipe that would be left open.
+fn run_copy(args: &[&str], text: &str) -> Result<(), String> {
+    let mut process = match Command::new(args[0]).args(args.slice_from(1))
+                                                 .stdout(StdioContainer::Ignored)
+                                                 .stderr(StdioContainer::Ignored)
+                                                 .spawn() {
+        Ok(process) => process,
+        Err(e)      => return Err(format!("{}: {}", args[0], e)),
+    };
+    let written = match process.stdin.take() {
+        // the input is closed when it is dropped, so that the program sees the end of the text
+        Some(mut input) => input.write_str(text),
+        None            => Ok(()),
+    };
+    match (written, process.wait()) {
+        (Ok(()), Ok(status)) if status.success() => Ok(()),
+        (Ok(()), Ok(status))                     => Err(format!("{} failed: {}", args[0], status)),
+        (Err(e), _) | (_, Err(e))                => Err(format!("{}: {}", args[0], e)),
+    }
+}
+
+// Run the program in `args`, giving what it writes out, with Windows line endings made `\n`.
+fn run_paste(args: &[&str]) -> Result<String, String> {
+    match Command::new(args[0]).args(args.slice_from(1)).output() {
+        Ok(ProcessOutput { status, output, .. }) => {
+            if status.success() {
+                Ok(String::from_utf8_lossy(output[]).replace("\r\n", "\n"))
+            } else {
+                Err(format!("{} failed: {}", args[0], status))
+            }
+        }
+        Err(e) => Err(format!("{}: {}", args[0], e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use clipboard::{Clipboard, SystemClipboard, Tool, tools_for};
+
+    #[test]
+    fn test_tools_for_each_display() {
+        assert_eq!(tools_for(false, false, false).len(), 0);
+        assert_eq!(tools_for(true, false, false)[0].copy, vec!["pbcopy"]);
+        let tools = tools_for(false, true, true);
+        let names: Vec<&str> = tools.iter().map(|tool| tool.copy[0]).collect();
+        assert_eq!(names, vec!["wl-copy", "xclip", "xsel"]);
+    }
+
+    #[test]
+    fn test_missing_programs_are_an_error() {
+        let mut clipboard = SystemClipboard::with_tools(vec![]);
+        assert!(clipboard.copy("text").is_err());
+        assert!(clipboard.paste().is_err());
+
+        let mut clipboard = SystemClipboard::with_tools(vec![
+            Tool::new(&["/nonexistent/iota-copy"], &["/nonexistent/iota-paste"]),
+        ]);
+        assert!(clipboard.copy("text").is_err());
+        assert!(clipboard.paste().is_err());
+    }
+
+    #[test]
+    fn test_falls_back_to_a_tool_that_works() {
+        let mut clipboard = SystemClipboard::with_tools(vec![
+            Tool::new(&["/nonexistent/iota-copy"], &["/nonexistent/iota-paste"]),
+            Tool::new(&["cat"], &["printf", "a\\r\\nb"]),
+        ]);
+        assert_eq!(clipboard.copy("text"), Ok(()));
+        assert_eq!(clipboard.working, 1);
+        assert_eq!(clipboard.paste(), Ok(String::from_str("a\nb")));
+    }
+}
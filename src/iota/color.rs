@@ -0,0 +1,151 @@
+//! Bringing colors down to what a terminal can show: 24-bit colors to the 256-color palette,
+//! and either of them to the basic eight.
+
+use std::cmp;
+
+use uibuf::CharColor;
+
+/// How many colors a terminal with only the basic palette has.
+pub const BASIC: uint = 8;
+/// How many colors a terminal with the 256-color palette has.
+pub const INDEXED: uint = 256;
+/// How many colors a terminal that takes 24-bit colors as they are has.
+pub const TRUE_COLOR: uint = 1 << 24;
+
+// The levels of red, green and blue in the palette's 6x6x6 cube of colors, at indexes 16-231.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+// The first 16 entries of the palette, as xterm draws them.
+const SYSTEM: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+/// `color` as near as a terminal with `colors` colors can show it: a 24-bit color is brought
+/// down to the nearest entry of the 256-color palette, and on a terminal with only the basic
+/// eight, either is brought down to the nearest of them. The basic colors are left as they are.
+pub fn reduce(color: CharColor, colors: uint) -> CharColor {
+    match color {
+        CharColor::Rgb(r, g, b) if colors < TRUE_COLOR => {
+            if colors < INDEXED { nearest_basic(r, g, b) }
+            else { CharColor::Indexed(nearest_indexed(r, g, b)) }
+        }
+        CharColor::Indexed(index) if colors < INDEXED => {
+            let (r, g, b) = indexed_rgb(index);
+            nearest_basic(r, g, b)
+        }
+        color => color,
+    }
+}
+
+/// The entry of the 256-color palette nearest to the 24-bit color (`r`, `g`, `b`), out of its
+/// cube of colors and its ramp of greys. The first 16 are left out, as terminals differ on them.
+pub fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    // the greys go from 8 to 238 in steps of 10
+    let average = (r as uint + g as uint + b as uint) / 3;
+    let grey_index = if average < 8 { 0 } else { cmp::min((average - 8 + 5) / 10, 23) };
+    let level = (8 + grey_index * 10) as u8;
+
+    if distance((r, g, b), (level, level, level)) < distance((r, g, b), cube) {
+        (232 + grey_index) as u8
+    } else {
+        (16 + 36 * ri + 6 * gi + bi) as u8
+    }
+}
+
+/// The 24-bit color that entry `index` of the 256-color palette stands for.
+pub fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    let index = index as uint;
+    if index < 16 {
+        SYSTEM[index]
+    } else if index < 232 {
+        let cube = index - 16;
+        (CUBE_LEVELS[cube / 36], CUBE_LEVELS[cube / 6 % 6], CUBE_LEVELS[cube % 6])
+    } else {
+        let level = (8 + (index - 232) * 10) as u8;
+        (level, level, level)
+    }
+}
+
+/// The basic color nearest to the 24-bit color (`r`, `g`, `b`).
+pub fn nearest_basic(r: u8, g: u8, b: u8) -> CharColor {
+    let basic = [CharColor::Black, CharColor::Red, CharColor::Green, CharColor::Yellow,
+                 CharColor::Blue, CharColor::Magenta, CharColor::Cyan, CharColor::Grey];
+    let mut nearest = (CharColor::Black, distance((r, g, b), SYSTEM[0]));
+    for (i, &color) in basic.iter().enumerate().skip(1) {
+        let d = distance((r, g, b), SYSTEM[i]);
+        if d < nearest.1 { nearest = (color, d) }
+    }
+    nearest.0
+}
+
+// Which of the cube's levels `value` is nearest to.
+fn cube_index(value: u8) -> uint {
+    match value {
+        0...47   => 0,
+        48...114 => 1,
+        _        => (value as uint - 35) / 40,
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> uint {
+    let d = |x: u8, y: u8| { let d = x as int - y as int; (d * d) as uint };
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use color::{reduce, nearest_indexed, indexed_rgb, nearest_basic, BASIC, INDEXED, TRUE_COLOR};
+    use uibuf::CharColor;
+
+    #[test]
+    fn test_nearest_indexed_colors() {
+        // exactly on the cube
+        assert_eq!(nearest_indexed(0, 0, 0), 16);
+        assert_eq!(nearest_indexed(255, 255, 255), 231);
+        assert_eq!(nearest_indexed(255, 135, 0), 208);
+        assert_eq!(nearest_indexed(95, 135, 175), 67);
+        // near it
+        assert_eq!(nearest_indexed(250, 130, 10), 208);
+        // greys that fall between the cube's are taken from the ramp
+        assert_eq!(nearest_indexed(128, 128, 128), 244);
+        assert_eq!(nearest_indexed(30, 30, 30), 234);
+        assert_eq!(nearest_indexed(240, 240, 240), 255);
+    }
+
+    #[test]
+    fn test_indexed_colors_stand_for_themselves() {
+        for index in range(16u, 256) {
+            let (r, g, b) = indexed_rgb(index as u8);
+            assert_eq!(indexed_rgb(nearest_indexed(r, g, b)), (r, g, b));
+        }
+        assert_eq!(indexed_rgb(1), (205, 0, 0));
+    }
+
+    #[test]
+    fn test_nearest_basic_colors() {
+        assert!(nearest_basic(0, 0, 0) == CharColor::Black);
+        assert!(nearest_basic(255, 20, 20) == CharColor::Red);
+        assert!(nearest_basic(255, 135, 0) == CharColor::Yellow);
+        assert!(nearest_basic(200, 200, 200) == CharColor::Grey);
+        assert!(nearest_basic(30, 30, 200) == CharColor::Blue);
+    }
+
+    #[test]
+    fn test_reduce_to_what_the_terminal_has() {
+        let orange = CharColor::Rgb(255, 135, 0);
+        assert!(reduce(orange, TRUE_COLOR) == orange);
+        assert!(reduce(orange, INDEXED) == CharColor::Indexed(208));
+        assert!(reduce(orange, BASIC) == CharColor::Yellow);
+        assert!(reduce(CharColor::Indexed(67), BASIC) == CharColor::Cyan);
+        assert!(reduce(CharColor::Indexed(67), INDEXED) == CharColor::Indexed(67));
+        assert!(reduce(CharColor::Magenta, BASIC) == CharColor::Magenta);
+        assert!(reduce(CharColor::Default, BASIC) == CharColor::Default);
+    }
+}
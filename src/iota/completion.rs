@@ -0,0 +1,126 @@
+use std::os;
+use std::cmp;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+
+/// Turn a path typed at a prompt into the path of the file: a leading `~` stands for the home
+/// directory, and a relative path is taken from the current directory.
+pub fn expand_path(input: &str) -> Path {
+    expand_path_from(input, &current_dir(), os::homedir().as_ref())
+}
+
+/// The ways to complete a path typed at a prompt, from the current and home directories.
+pub fn complete_path(input: &str) -> Vec<String> {
+    complete_path_from(input, &current_dir(), os::homedir().as_ref())
+}
+
+/// The ways to complete a path typed at a prompt, each as the whole text to type: the names in
+/// its directory that start with its last component, sorted, with a `/` after directories.
+///
+/// Hidden names only complete once the last component starts with a `.` itself.
+pub fn complete_path_from(input: &str, cwd: &Path, home: Option<&Path>) -> Vec<String> {
+    let (dir_part, name_part) = match input.rfind('/') {
+        Some(i) => (input.slice_to(i + 1), input.slice_from(i + 1)),
+        None    => ("", input),
+    };
+    let dir = expand_path_from(dir_part, cwd, home);
+    let entries = match fs::readdir(&dir) {
+        Ok(entries) => entries,
+        Err(_)      => return Vec::new(),
+    };
+
+    let mut matches: Vec<String> = entries.iter().filter_map(|entry| {
+        let name = match entry.filename_str() {
+            Some(name) => name,
+            None       => return None,
+        };
+        if !name.starts_with(name_part) { return None }
+        if name.starts_with(".") && !name_part.starts_with(".") { return None }
+        let slash = if entry.is_dir() { "/" } else { "" };
+        Some(format!("{}{}{}", dir_part, name, slash))
+    }).collect();
+    matches.sort();
+    matches
+}
+
+/// As `expand_path`, from the given current and home directories.
+pub fn expand_path_from(input: &str, cwd: &Path, home: Option<&Path>) -> Path {
+    if input == "~" || input.starts_with("~/") {
+        if let Some(home) = home {
+            return home.join(input.slice_from(cmp::min(2, input.len())))
+        }
+    }
+    // joining an absolute path gives that path
+    cwd.join(input)
+}
+
+fn current_dir() -> Path {
+    os::getcwd().unwrap_or_else(|_| Path::new("."))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{fs, File, TempDir, USER_RWX};
+
+    use completion::{complete_path_from, expand_path_from};
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| String::from_str(*name)).collect()
+    }
+
+    fn setup_tree() -> TempDir {
+        let dir = TempDir::new("iota").unwrap();
+        fs::mkdir(&dir.path().join("src"), USER_RWX).unwrap();
+        fs::mkdir(&dir.path().join("scripts"), USER_RWX).unwrap();
+        for name in ["src/main.rs", "src/lib.rs", "setup.py", ".secret", "README"].iter() {
+            File::create(&dir.path().join(*name)).write_str("").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_complete_in_current_dir() {
+        let dir = setup_tree();
+        assert_eq!(complete_path_from("s", dir.path(), None),
+                   strings(&["scripts/", "setup.py", "src/"]));
+        assert_eq!(complete_path_from("src", dir.path(), None), strings(&["src/"]));
+        assert_eq!(complete_path_from("x", dir.path(), None), strings(&[]));
+    }
+
+    #[test]
+    fn test_complete_in_sub_dir() {
+        let dir = setup_tree();
+        assert_eq!(complete_path_from("src/", dir.path(), None), strings(&["src/lib.rs", "src/main.rs"]));
+        assert_eq!(complete_path_from("src/m", dir.path(), None), strings(&["src/main.rs"]));
+        assert_eq!(complete_path_from("nowhere/m", dir.path(), None), strings(&[]));
+    }
+
+    #[test]
+    fn test_complete_hidden_names() {
+        let dir = setup_tree();
+        assert!(complete_path_from("", dir.path(), None).iter().all(|m| !m.starts_with(".")));
+        assert_eq!(complete_path_from(".s", dir.path(), None), strings(&[".secret"]));
+    }
+
+    #[test]
+    fn test_complete_absolute_and_home() {
+        let dir = setup_tree();
+        let absolute = format!("{}/R", dir.path().display());
+        assert_eq!(complete_path_from(absolute[], &Path::new("/"), None),
+                   vec![format!("{}/README", dir.path().display())]);
+        assert_eq!(complete_path_from("~/src/l", &Path::new("/"), Some(dir.path())),
+                   strings(&["~/src/lib.rs"]));
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let (cwd, home) = (Path::new("/work"), Path::new("/home/user"));
+        assert_eq!(expand_path_from("notes.txt", &cwd, Some(&home)), Path::new("/work/notes.txt"));
+        assert_eq!(expand_path_from("/etc/hosts", &cwd, Some(&home)), Path::new("/etc/hosts"));
+        assert_eq!(expand_path_from("~/notes.txt", &cwd, Some(&home)),
+                   Path::new("/home/user/notes.txt"));
+        assert_eq!(expand_path_from("~", &cwd, Some(&home)), Path::new("/home/user"));
+        assert_eq!(expand_path_from("~/x", &cwd, None), Path::new("/work/~/x"));
+    }
+}
@@ -1,25 +1,89 @@
+use std::cmp;
+use std::ptr;
+use std::time::Duration;
+
+use libc;
+
 use super::Response;
 use input::Input;
-use buffer::Direction;
+use buffer::{Direction, LineEnding, Progress};
 use keyboard::Key;
 use view::View;
 use frontends::{Frontend, EditorEvent};
+use uibuf::Face;
+use theme::Theme;
 use modes::Mode;
 use overlay::{OverlayType};
+use session::Session;
+use recent::RecentFiles;
+use history::Histories;
+use clipboard::Clipboard;
 
 
 #[derive(Copy, Show)]
 pub enum Command {
     SaveBuffer,
+    RevertBuffer,
+    ToggleReadOnly,
     ExitEditor,
 
     MoveCursor(Direction),
+    SetMark(char),
+    AddCursorBelow,
+    CollapseCursors,
+    ToggleSelection,
+    ToggleBlockSelection,
+    SelectAll,
+    SelectLine,
+    SelectInside(char),
+    SelectAround(char),
+    ExpandSelection,
+    ShrinkSelection,
+    Copy,
+    Cut,
+    Paste,
+    PasteClipboard,
+    PasteReindented,
+    YankPop,
+    GotoMark(char),
     LineEnd,
     LineStart,
+    PageUp,
+    PageDown,
+    GotoLine(uint),
+    MatchBracket,
+    Search,
+    ReverseSearch,
+    SearchWord(bool),
+    CountMatches,
+    ClearHighlight,
+    CycleLineNumbers,
+    ToggleWrap,
+    CycleTheme,
+    ToggleInvisibles,
+    ToggleCurrentLine,
 
     Delete(Direction),
+    DeleteWord(Direction),
+    DeleteLine,
+    DuplicateLine,
+    JoinLines,
+    TransposeChars,
+    UpcaseWord,
+    DowncaseWord,
+    SortLines(bool),
     InsertTab,
+    IndentLine,
+    DedentLine,
+    ToggleComment,
+    FillParagraph,
+    MoveLine(Direction),
+    ExpandTabs(bool),
+    UnexpandTabs(bool),
+    SetLineEnding(LineEnding),
+    ToggleBom,
     InsertChar(char),
+    InsertNewline,
 
     SetOverlay(OverlayType),
 
@@ -34,8 +98,69 @@ impl Command {
     pub fn from_str(string: &str) -> Command {
         match string {
             "q" | "quit" => Command::ExitEditor,
+            "w" | "write" => Command::SaveBuffer,
+            "e!" | "revert" => Command::RevertBuffer,
+            "ro" | "readonly" => Command::ToggleReadOnly,
+            "saveas"     => Command::SetOverlay(OverlayType::SaveAs),
+            "e" | "open" => Command::SetOverlay(OverlayType::OpenFile),
+            "recent"     => Command::SetOverlay(OverlayType::OpenRecent),
+            "replace"    => Command::SetOverlay(OverlayType::ReplaceFrom),
+            "replaceall" => Command::SetOverlay(OverlayType::ReplaceAll),
+            "noh" | "nohighlight" => Command::ClearHighlight,
+            "grep"       => Command::SetOverlay(OverlayType::Grep),
+            "count"      => Command::CountMatches,
+            "selectall"  => Command::SelectAll,
+            "nu" | "number" => Command::CycleLineNumbers,
+            "wrap"       => Command::ToggleWrap,
+            "theme"      => Command::CycleTheme,
+            "list" | "invisibles" => Command::ToggleInvisibles,
+            "cursorline" => Command::ToggleCurrentLine,
+            "sort"       => Command::SortLines(false),
+            "sort!"      => Command::SortLines(true),
+            "expand"     => Command::ExpandTabs(false),
+            "expand!"    => Command::ExpandTabs(true),
+            "unexpand"   => Command::UnexpandTabs(false),
+            "unexpand!"  => Command::UnexpandTabs(true),
+            "lf"         => Command::SetLineEnding(LineEnding::Lf),
+            "crlf"       => Command::SetLineEnding(LineEnding::CrLf),
+            "cr"         => Command::SetLineEnding(LineEnding::Cr),
+            "bom"        => Command::ToggleBom,
+
+            _            => match string.parse() {
+                Some(n) => Command::GotoLine(n),
+                None    => Command::Unknown,
+            },
+        }
+    }
 
-            _            => Command::Unknown,
+    /// Interpret the text entered into an overlay of the given type.
+    pub fn from_overlay(overlay: OverlayType, input: &str) -> Command {
+        match overlay {
+            OverlayType::Prompt   => Command::from_str(input),
+            OverlayType::GotoLine => match input.trim().parse() {
+                Some(n) => Command::GotoLine(n),
+                None    => Command::Unknown,
+            },
+            // answered with a path or a keypress, which a Command can't carry
+            OverlayType::SaveAs |
+            OverlayType::OpenFile |
+            OverlayType::OpenRecent |
+            OverlayType::ReplaceFrom |
+            OverlayType::ReplaceWith |
+            OverlayType::ReplaceAll |
+            OverlayType::ReplaceAllWith |
+            OverlayType::Grep |
+            OverlayType::GrepIn |
+            OverlayType::CountMatches |
+            OverlayType::ConfirmOverwrite |
+            OverlayType::FileChanged |
+            OverlayType::ConfirmRevert |
+            OverlayType::ConfirmReadOnly |
+            OverlayType::ConfirmCreateDir |
+            OverlayType::ConfirmRecover |
+            OverlayType::ConfirmBinary |
+            OverlayType::ConfirmQuit |
+            OverlayType::ConfirmReplace => Command::Unknown,
         }
     }
 }
@@ -46,6 +171,34 @@ pub enum EventStatus {
 }
 
 
+// Shows how far the file has been loaded on the bottom line, and cancels the load on Ctrl-C.
+struct LoadingIndicator<'a, T: 'a> {
+    frontend: &'a mut T,
+    shown: Option<u64>,
+    face: Face,
+}
+
+impl<'a, T: Frontend> Progress for LoadingIndicator<'a, T> {
+    fn progress(&mut self, loaded: u64, total: u64) -> bool {
+        let percent = if total > 0 { loaded * 100 / total } else { 0 };
+        if self.shown != Some(percent) {
+            self.shown = Some(percent);
+            let text = format!("Loading... {}% (Ctrl-C to cancel)", percent);
+            let line = self.frontend.get_window_height() - 1;
+            for (index, ch) in text.chars().enumerate() {
+                let face = self.face;
+                self.frontend.draw_char(index, line, ch, face.fg, face.bg, face.style);
+            }
+            self.frontend.present();
+        }
+        match self.frontend.peek_event(Duration::zero()) {
+            EditorEvent::KeyEvent(Some(Key::Ctrl('c'))) => false,
+            _                                          => true,
+        }
+    }
+}
+
+
 pub struct Editor<'e, T: Frontend> {
     view: View<'e>,
     running: bool,
@@ -54,10 +207,19 @@ pub struct Editor<'e, T: Frontend> {
 }
 
 impl<'e, T: Frontend> Editor<'e, T> {
-    pub fn new(source: Input, mode: Box<Mode + 'e>, frontend: T) -> Editor<'e, T> {
+    pub fn new(source: Input, mode: Box<Mode + 'e>, mut frontend: T) -> Editor<'e, T> {
         let height = frontend.get_window_height();
         let width = frontend.get_window_width();
-        let view = View::new(source, width, height);
+        let mut view = {
+            // drawn before the view and its options are made, so in the default theme
+            let mut indicator = LoadingIndicator {
+                frontend: &mut frontend,
+                shown: None,
+                face: Theme::dark().message,
+            };
+            View::new_with_progress(source, width, height, &mut indicator)
+        };
+        view.set_colors(frontend.colors());
 
         Editor {
             view: view,
@@ -67,34 +229,214 @@ impl<'e, T: Frontend> Editor<'e, T> {
         }
     }
 
+    /// Make the buffer read-only, as if its file couldn't be written.
+    pub fn set_read_only(&mut self) {
+        self.view.buffer.read_only = true;
+    }
+
+    /// Put the cursor on a (1-based) line and column, such as one given on the command line.
+    pub fn goto_position(&mut self, linenum: uint, col: Option<uint>) {
+        self.view.goto_position(linenum, col.unwrap_or(1));
+    }
+
+    /// Go back to where the session says the editor was in the file.
+    pub fn restore_position(&mut self, session: &Session) {
+        self.view.restore_position(session);
+    }
+
+    /// Record where the editor is in the file, for the next session.
+    pub fn remember_position(&self, session: &mut Session) {
+        self.view.remember_position(session);
+    }
+
+    /// Use `recent` as the list of files opened recently, adding the file being edited.
+    pub fn set_recent_files(&mut self, recent: RecentFiles) {
+        self.view.set_recent_files(recent);
+    }
+
+    /// The files opened recently, to keep for next time.
+    pub fn recent_files(&self) -> &RecentFiles {
+        self.view.recent_files()
+    }
+
+    /// Use `histories` as what was entered at the prompts before.
+    pub fn set_histories(&mut self, histories: Histories) {
+        self.view.set_histories(histories);
+    }
+
+    /// What was entered at the prompts, to keep for next time.
+    pub fn histories(&self) -> &Histories {
+        self.view.histories()
+    }
+
+    /// Copy to `clipboard` as well as the editor's own register, and paste from it on request.
+    pub fn set_clipboard(&mut self, clipboard: Box<Clipboard>) {
+        self.view.set_clipboard(clipboard);
+    }
+
     pub fn handle_key_event(&mut self, key: Option<Key>) {
         let Editor {ref mut view, .. } = *self;
+        view.clear_message();
+
+        if let Some(key) = key {
+            if view.is_searching() && handle_search_key(key, view) { return }
+            // Enter on a line of search results goes to it, rather than to the mode
+            if key == Key::Enter && view.is_showing_results() && view.overlay.overlay_type().is_none() {
+                return view.open_result()
+            }
+        }
 
         let response = match self.mode.handle_key_event(key, view) {
             EventStatus::Handled(response) => { response }
             EventStatus::NotHandled        => { Response::Continue }
         };
+        view.check_file_changed();
+        view.update_swap();
 
         if let Response::Quit = response {
             self.running = false
         }
     }
 
+    /// Insert text pasted into the terminal as it is. While a prompt or search is waiting for
+    /// input, it is typed in there instead, leaving out any newlines and tabs.
+    pub fn handle_paste(&mut self, text: &str) {
+        if self.view.is_searching() || self.view.overlay.overlay_type().is_some() {
+            for ch in text.chars().filter(|ch| !ch.is_control()) {
+                self.handle_key_event(Some(Key::Char(ch)));
+            }
+            return
+        }
+        self.view.clear_message();
+        self.view.paste_text(text);
+        self.view.check_file_changed();
+        self.view.update_swap();
+    }
+
     pub fn draw(&mut self) {
         self.view.draw(&mut self.frontend);
         self.view.draw_status(&mut self.frontend);
     }
 
     pub fn start(&mut self) {
+        // when the message went up, and when the last key was pressed or autosave made
+        let (mut message_since, mut idle_since) = (now(), now());
         while self.running {
-            self.view.clear(&mut self.frontend);
+            self.view.clear();
             self.draw();
             self.frontend.present();
-            let event = self.frontend.poll_event();
+            // waiting out the autosave interval between keys means an autosave never lands
+            // in the middle of a key sequence
+            let time = now();
+            let message_wait = match self.view.options.message_timeout {
+                Some(timeout) if self.view.has_message() => {
+                    Some(timeout - cmp::min(seconds_between(message_since, time), timeout))
+                }
+                _ => None,
+            };
+            let autosave_wait = self.view.options.autosave_interval.map(|interval| {
+                interval - cmp::min(seconds_between(idle_since, time), interval)
+            });
+            let wait = match (message_wait, autosave_wait) {
+                (Some(message), Some(autosave)) => Some(cmp::min(message, autosave)),
+                (message, autosave)             => message.or(autosave),
+            };
+            let event = if self.view.is_grepping() {
+                // a search through files carries on whenever no key is waiting
+                self.frontend.peek_event(Duration::zero())
+            } else {
+                match wait {
+                    Some(secs) => self.frontend.peek_event(Duration::seconds(secs as i64)),
+                    None       => self.frontend.poll_event(),
+                }
+            };
 
-            if let EditorEvent::KeyEvent(key) = event {
-                self.handle_key_event(key)
+            match event {
+                EditorEvent::KeyEvent(key) => {
+                    self.handle_key_event(key);
+                    message_since = now();
+                    idle_since = now();
+                }
+                EditorEvent::Paste(text)   => {
+                    self.handle_paste(text[]);
+                    message_since = now();
+                    idle_since = now();
+                }
+                EditorEvent::Resize(width, height) => self.view.resize(width, height),
+                EditorEvent::NoEvent if self.view.is_grepping() => {
+                    self.view.grep_step();
+                    // the count of what was found goes up as the grep finishes
+                    if !self.view.is_grepping() { message_since = now() }
+                }
+                EditorEvent::NoEvent       => {}
+                EditorEvent::UnSupported   => {}
+            }
+
+            // whichever of the two waits has run out by the clock is due, whatever ended the wait
+            let time = now();
+            let due = |since: i64, secs: Option<uint>| {
+                secs.map_or(false, |secs| seconds_between(since, time) >= secs)
+            };
+            if self.view.has_message() && due(message_since, self.view.options.message_timeout) {
+                self.view.clear_message();
+                message_since = time;
+            }
+            if due(idle_since, self.view.options.autosave_interval) {
+                self.view.autosave();
+                message_since = time;
+                idle_since = time;
             }
         }
+
+        // quitting with unsaved changes keeps the autosave file, as the only copy of them
+        if !self.view.buffer.is_dirty() {
+            self.view.buffer.remove_autosave();
+        }
+    }
+}
+
+// The clock, in seconds.
+fn now() -> i64 {
+    unsafe { libc::time(ptr::null_mut()) as i64 }
+}
+
+// The whole seconds from `since` to `time`, none if the clock went back.
+fn seconds_between(since: i64, time: i64) -> uint {
+    cmp::max(time - since, 0) as uint
+}
+
+// While searching, keys edit the query and step through the matches instead of going to
+// the mode. Any other key ends the search with the cursor where it is, and is handled as
+// usual.
+fn handle_search_key(key: Key, view: &mut View) -> bool {
+    match key {
+        Key::Char(c)                    => view.extend_search(c),
+        Key::Backspace                  => view.shorten_search(),
+        Key::Ctrl('s') | Key::Ctrl('f') => view.search_again(true),
+        Key::Ctrl('r')                  => view.search_again(false),
+        Key::Ctrl('t')                  => view.toggle_search_regex(),
+        Key::Ctrl('u')                  => view.cycle_search_case(),
+        Key::Up                         => view.recall_search(true),
+        Key::Down                       => view.recall_search(false),
+        Key::Enter                      => view.end_search(),
+        Key::Esc | Key::Ctrl('g')       => view.cancel_search(),
+        _                               => {
+            view.end_search();
+            return false
+        }
+    }
+    true
+}
+
+#[unsafe_destructor]
+impl<'e, T: Frontend> Drop for Editor<'e, T> {
+    fn drop(&mut self) {
+        // still running means the editor is being unwound by a panic rather than quit, so the
+        // swap file is kept with the latest text for the next session to recover
+        if self.running && self.view.buffer.is_dirty() {
+            let _ = self.view.buffer.write_swap();
+        } else {
+            self.view.buffer.remove_swap();
+        }
     }
 }
@@ -0,0 +1,54 @@
+//! Settings that depend on the type of file being edited, going by its extension.
+
+use syntax::{Syntax, RUST, C_LIKE};
+
+/// The string that starts a line comment in the file at `path`, with the space that usually
+/// follows it. Files of an unknown type, or without a path, are assumed to use `# `.
+pub fn comment_leader(path: Option<&Path>) -> &'static str {
+    match path.and_then(|p| p.extension_str()) {
+        Some("rs") | Some("c") | Some("h") | Some("cpp") | Some("hpp") | Some("cc") |
+        Some("js") | Some("java") | Some("go") | Some("swift") | Some("scala")      => "// ",
+        Some("hs") | Some("lua") | Some("sql") | Some("elm")                         => "-- ",
+        Some("el") | Some("lisp") | Some("clj") | Some("scm")                        => ";; ",
+        Some("vim")                                                                  => "\" ",
+        Some("tex") | Some("erl")                                                    => "% ",
+        _                                                                            => "# ",
+    }
+}
+
+/// The syntax to color the file at `path` by, if it is of a type that has one.
+pub fn syntax(path: Option<&Path>) -> Option<&'static Syntax> {
+    match path.and_then(|p| p.extension_str()) {
+        Some("rs")                                                                   => Some(&RUST),
+        Some("c") | Some("h") | Some("cpp") | Some("hpp") | Some("cc") | Some("cs") |
+        Some("js") | Some("java") | Some("go") | Some("swift") | Some("scala")      => Some(&C_LIKE),
+        _                                                                            => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use filetype::{comment_leader, syntax};
+    use syntax::{RUST, C_LIKE};
+
+    #[test]
+    fn comment_leader_from_extension() {
+        assert_eq!(comment_leader(Some(&Path::new("src/main.rs"))), "// ");
+        assert_eq!(comment_leader(Some(&Path::new("init.lua"))), "-- ");
+    }
+
+    #[test]
+    fn comment_leader_defaults_to_hash() {
+        assert_eq!(comment_leader(Some(&Path::new("Makefile"))), "# ");
+        assert_eq!(comment_leader(None), "# ");
+    }
+
+    #[test]
+    fn syntax_from_extension() {
+        assert_eq!(syntax(Some(&Path::new("src/main.rs"))), Some(&RUST));
+        assert_eq!(syntax(Some(&Path::new("main.c"))), Some(&C_LIKE));
+        assert_eq!(syntax(Some(&Path::new("notes.txt"))), None);
+        assert_eq!(syntax(None), None);
+    }
+}
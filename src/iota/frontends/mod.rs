@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 pub use super::keyboard::Key;
 pub use super::uibuf::{CharStyle, CharColor};
 
@@ -5,16 +7,28 @@ pub use self::rb::RustboxFrontend;
 
 pub enum EditorEvent {
     KeyEvent(Option<Key>),
+    /// Text pasted into the terminal, all at once. Frontends that can't tell a paste from typing
+    /// never give this, and the text comes as keys instead.
+    Paste(String),
+    /// The window changed to this width and height.
+    Resize(uint, uint),
+    NoEvent,
     UnSupported
 }
 
 pub trait Frontend {
     fn poll_event(&self) -> EditorEvent;
+    /// As `poll_event`, but giving up with `NoEvent` once `timeout` passes without one.
+    fn peek_event(&self, timeout: Duration) -> EditorEvent;
     fn present(&self);
     fn get_window_height(&self) -> uint;
     fn get_window_width(&self) -> uint;
     fn draw_cursor(&mut self, offset: int, linenum: int);
     fn draw_char(&mut self, offset: uint, linenum: uint, ch: char, fg: CharColor, bg: CharColor, style: CharStyle);
+    /// How many colors the terminal can show: `color::BASIC`, `color::INDEXED` or
+    /// `color::TRUE_COLOR`. Any color it is given to draw beyond them is shown as the nearest
+    /// it has.
+    fn colors(&self) -> uint;
 }
 
 mod rb;
@@ -1,38 +1,110 @@
 use std::char;
+use std::cell::RefCell;
+use std::collections::RingBuf;
+use std::io::{File, FileMode, FileAccess};
+use std::time::Duration;
 
 use rustbox::{RustBox, Event};
 use rustbox::{Style, Color};
+use rustbox::RB_REVERSE;
 
 use super::Frontend;
 use super::{CharStyle, CharColor};
 use super::Key;
 use super::EditorEvent;
+use color;
 
+/// What the terminal sends, after an Esc, at the start of a bracketed paste.
+const PASTE_START: &'static str = "[200~";
+/// What it sends at the end, Esc and all.
+const PASTE_END: &'static str = "\x1b[201~";
+/// What the terminal sends, after an Esc, for Shift-Tab.
+const BACK_TAB: &'static str = "[Z";
+/// How long to wait for the rest of the start of a paste after an Esc, in milliseconds, before
+/// taking the Esc as a key of its own. The terminal sends it all at once.
+const PASTE_WAIT: i64 = 10;
 
 pub struct RustboxFrontend<'f> {
     rb: &'f RustBox,
+    pending: RefCell<RingBuf<EditorEvent>>, // Events read looking for the start of a paste that
+                                            // turned out not to be one.
 }
 
 impl<'f> RustboxFrontend<'f> {
     pub fn new(rb: &'f RustBox) -> RustboxFrontend<'f> {
+        // have the terminal mark the start and end of anything pasted into it
+        set_bracketed_paste(true);
         RustboxFrontend {
             rb: rb,
+            pending: RefCell::new(RingBuf::new()),
         }
     }
+
+    // After an Esc, the rest of a paste or a Shift-Tab if it is the start of one, or else the
+    // Esc, keeping the events read to find out for after it.
+    fn escape_sequence(&self) -> EditorEvent {
+        let mut read = Vec::new();
+        let mut sequence = String::new();
+        loop {
+            if sequence.as_slice() == PASTE_START { return self.bracketed_paste() }
+            if sequence.as_slice() == BACK_TAB { return EditorEvent::KeyEvent(Some(Key::BackTab)) }
+
+            let event = get_event(self.rb.peek_event(Duration::milliseconds(PASTE_WAIT)).unwrap());
+            let ch = match event {
+                EditorEvent::KeyEvent(Some(key)) => pasted_char(key),
+                _                                => None,
+            };
+            match event {
+                EditorEvent::NoEvent => {}
+                event                => read.push(event),
+            }
+            if let Some(ch) = ch { sequence.push(ch) }
+            let expected = ch.is_some() && (PASTE_START.starts_with(sequence.as_slice())
+                                            || BACK_TAB.starts_with(sequence.as_slice()));
+            if !expected {
+                self.pending.borrow_mut().extend(read.into_iter());
+                return EditorEvent::KeyEvent(Some(Key::Esc))
+            }
+        }
+    }
+
+    // The text of a paste, up to the end the terminal marks it with.
+    fn bracketed_paste(&self) -> EditorEvent {
+        let mut text = String::new();
+        while !text.ends_with(PASTE_END) {
+            if let EditorEvent::KeyEvent(Some(key)) = get_event(self.rb.poll_event().unwrap()) {
+                if let Some(ch) = pasted_char(key) { text.push(ch) }
+            }
+        }
+        let len = text.len() - PASTE_END.len();
+        text.truncate(len);
+        EditorEvent::Paste(text)
+    }
+
+    fn next_event(&self, event: EditorEvent) -> EditorEvent {
+        match event {
+            EditorEvent::KeyEvent(Some(Key::Esc)) => self.escape_sequence(),
+            event                                 => event,
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<'f> Drop for RustboxFrontend<'f> {
+    fn drop(&mut self) {
+        set_bracketed_paste(false);
+    }
 }
 
 impl<'f> Frontend for RustboxFrontend<'f> {
     fn poll_event(&self) -> EditorEvent {
-        match self.rb.poll_event().unwrap() {
-            Event::KeyEvent(_, key, ch) => {
-                let k = match key {
-                    0 => char::from_u32(ch).map(|c| Key::Char(c)),
-                    a => Key::from_special_code(a),
-                };
-                EditorEvent::KeyEvent(k)
-            }
-            _ => EditorEvent::UnSupported
-        }
+        if let Some(event) = self.pending.borrow_mut().pop_front() { return event }
+        self.next_event(get_event(self.rb.poll_event().unwrap()))
+    }
+
+    fn peek_event(&self, timeout: Duration) -> EditorEvent {
+        if let Some(event) = self.pending.borrow_mut().pop_front() { return event }
+        self.next_event(get_event(self.rb.peek_event(timeout).unwrap()))
     }
 
     fn draw_cursor(&mut self, offset: int, linenum: int) {
@@ -58,18 +130,70 @@ impl<'f> Frontend for RustboxFrontend<'f> {
     fn get_window_width(&self) -> uint {
         self.rb.width()
     }
+
+    fn colors(&self) -> uint {
+        color::BASIC
+    }
+}
+
+fn get_event(e: Event) -> EditorEvent {
+    match e {
+        Event::KeyEvent(_, key, ch) => {
+            let k = match key {
+                0 if ch == 0 => Some(Key::Ctrl(' ')),
+                0 => char::from_u32(ch).map(|c| Key::Char(c)),
+                a => Key::from_special_code(a),
+            };
+            EditorEvent::KeyEvent(k)
+        }
+        Event::ResizeEvent(width, height) => EditorEvent::Resize(width as uint, height as uint),
+        Event::NoEvent => EditorEvent::NoEvent,
+        _ => EditorEvent::UnSupported
+    }
+}
+
+// The character a key stands for in pasted text. The terminal sends a newline as Enter, and
+// termbox gives an Esc, a space or a tab as a key of its own.
+fn pasted_char(key: Key) -> Option<char> {
+    match key {
+        Key::Char(c)                 => Some(c),
+        Key::Enter | Key::Ctrl('j')  => Some('\n'),
+        Key::Tab                     => Some('\t'),
+        Key::Esc                     => Some('\x1b'),
+        _                            => None,
+    }
+}
+
+// Turn the terminal's bracketed paste mode on or off. Terminals without it ignore the request.
+// termbox writes to the terminal itself rather than to stdout, so the request goes there too.
+fn set_bracketed_paste(on: bool) {
+    let request = if on { "\x1b[?2004h" } else { "\x1b[?2004l" };
+    if let Ok(mut tty) = File::open_mode(&Path::new("/dev/tty"), FileMode::Open, FileAccess::Write) {
+        let _ = tty.write_str(request);
+    }
 }
 
+// rustbox only has the basic colors, so anything finer is drawn as the nearest of them.
 fn get_color(c: CharColor) -> Color {
-    match c {
+    match color::reduce(c, color::BASIC) {
         CharColor::Default => Color::Default,
         CharColor::Blue    => Color::Blue,
         CharColor::Black   => Color::Black,
+        CharColor::Yellow  => Color::Yellow,
+        // the terminal's plain white is greyer than the bright white most use for text
+        CharColor::Grey    => Color::White,
+        CharColor::Red     => Color::Red,
+        CharColor::Green   => Color::Green,
+        CharColor::Magenta => Color::Magenta,
+        CharColor::Cyan    => Color::Cyan,
+        // reduced to the basic colors above
+        CharColor::Indexed(_) | CharColor::Rgb(..) => Color::Default,
     }
 }
 
 fn get_style(s: CharStyle) -> Style {
     match s {
-        CharStyle::Normal => Style::empty(),
+        CharStyle::Normal  => Style::empty(),
+        CharStyle::Reverse => RB_REVERSE,
     }
 }
@@ -0,0 +1,184 @@
+use std::ascii::AsciiExt;
+use std::io::{fs, File};
+use std::io::fs::PathExtensions;
+
+use regex::Regex;
+
+use buffer::looks_binary;
+
+/// Directories that are never looked in, as what they hold isn't the project's own text.
+const SKIPPED_DIRS: [&'static str; 3] = [".git", ".hg", ".svn"];
+
+/// Extensions of files that are binary whatever their first bytes look like.
+const BINARY_EXTENSIONS: [&'static str; 16] = [
+    "o", "a", "so", "rlib", "dylib", "dll", "exe", "class",
+    "png", "jpg", "jpeg", "gif", "pdf", "zip", "gz", "tar",
+];
+
+/// A search for a pattern through the files under a directory, done a few files at a time so
+/// that the editor can carry on handling keys in between.
+///
+/// Each matching line gives a result line of `path:line: text`, with the path relative to the
+/// directory searched.
+pub struct Grep {
+    re: Regex,
+    root: Path,
+    pending: Vec<Path>,     // files and directories still to look through, the next one last
+    matches: uint,
+    files: uint,            // files with a match in them
+}
+
+impl Grep {
+    pub fn new(re: Regex, dir: Path) -> Grep {
+        Grep { re: re, root: dir.clone(), pending: vec![dir], matches: 0, files: 0 }
+    }
+
+    /// Look through up to `budget` more files or directories, giving the result lines found.
+    pub fn step(&mut self, budget: uint) -> Vec<String> {
+        let mut results = Vec::new();
+        for _ in range(0, budget) {
+            let path = match self.pending.pop() {
+                Some(path) => path,
+                None       => break,
+            };
+            if path.is_dir() {
+                self.enter_dir(&path);
+            } else if !skips_file(&path) {
+                self.search_file(&path, &mut results);
+            }
+        }
+        results
+    }
+
+    /// Whether every file has been looked through.
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// How many lines have matched, and in how many files.
+    pub fn counts(&self) -> (uint, uint) {
+        (self.matches, self.files)
+    }
+
+    /// The directory being searched, which the result paths are relative to.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn enter_dir(&mut self, dir: &Path) {
+        let mut entries = match fs::readdir(dir) {
+            Ok(entries) => entries,
+            Err(_)      => return,
+        };
+        entries.retain(|entry| !(entry.is_dir() && skips_dir(entry)));
+        entries.sort_by(|a, b| a.as_vec().cmp(b.as_vec()));
+        // reversed, so that they are popped in order
+        self.pending.extend(entries.into_iter().rev());
+    }
+
+    fn search_file(&mut self, path: &Path, results: &mut Vec<String>) {
+        let contents = match File::open(path).read_to_end() {
+            Ok(contents) => contents,
+            Err(_)       => return,
+        };
+        if looks_binary(contents[]) { return }
+        let text = String::from_utf8_lossy(contents[]);
+        let name = path.path_relative_from(&self.root).unwrap_or_else(|| path.clone());
+        let before = self.matches;
+        for (i, line) in text.lines_any().enumerate() {
+            if self.re.is_match(line) {
+                results.push(format!("{}:{}: {}", name.display(), i + 1, line));
+                self.matches += 1;
+            }
+        }
+        if self.matches > before { self.files += 1 }
+    }
+}
+
+/// Whether a directory is left out of the search.
+pub fn skips_dir(dir: &Path) -> bool {
+    dir.filename_str().map_or(false, |name| SKIPPED_DIRS.iter().any(|skipped| *skipped == name))
+}
+
+/// Whether a file is left out of the search by its name, before its contents are looked at.
+pub fn skips_file(file: &Path) -> bool {
+    file.extension_str().map_or(false, |ext| {
+        let ext = ext.to_ascii_lowercase();
+        BINARY_EXTENSIONS.iter().any(|binary| *binary == ext[])
+    })
+}
+
+/// The path and (1-based) line number of a `path:line: text` result line. The path is taken up
+/// to the first `:` followed by a number and another `:`, so it may hold colons itself.
+pub fn parse_result_line(line: &str) -> Option<(&str, uint)> {
+    for (i, _) in line.match_indices(":") {
+        let rest = line.slice_from(i + 1);
+        let digits = rest.find(|&: c: char| !c.is_digit(10)).unwrap_or(rest.len());
+        if i > 0 && digits > 0 && rest.slice_from(digits).starts_with(":") {
+            if let Some(n) = rest.slice_to(digits).parse::<uint>() {
+                return Some((line.slice_to(i), n))
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{fs, File, TempDir, USER_RWX};
+
+    use regex::Regex;
+
+    use grep::{Grep, parse_result_line, skips_dir, skips_file};
+
+    #[test]
+    fn test_parse_result_line() {
+        assert_eq!(parse_result_line("src/main.rs:12: fn main() {"), Some(("src/main.rs", 12)));
+        assert_eq!(parse_result_line("a.txt:3: x:4: y"), Some(("a.txt", 3)));
+        assert_eq!(parse_result_line("odd:name.txt:7:"), Some(("odd:name.txt", 7)));
+    }
+
+    #[test]
+    fn test_parse_bad_result_lines() {
+        assert_eq!(parse_result_line(""), None);
+        assert_eq!(parse_result_line("no line number here"), None);
+        assert_eq!(parse_result_line("file.rs:: text"), None);
+        assert_eq!(parse_result_line("file.rs:12 text"), None);
+        assert_eq!(parse_result_line(":12: text"), None);
+    }
+
+    #[test]
+    fn test_skipped_dirs_and_files() {
+        assert!(skips_dir(&Path::new("project/.git")));
+        assert!(skips_dir(&Path::new(".hg")));
+        assert!(!skips_dir(&Path::new("src")));
+        assert!(!skips_dir(&Path::new("git")));
+
+        assert!(skips_file(&Path::new("target/libiota.rlib")));
+        assert!(skips_file(&Path::new("logo.PNG")));
+        assert!(!skips_file(&Path::new("src/lib.rs")));
+        assert!(!skips_file(&Path::new("Makefile")));
+    }
+
+    #[test]
+    fn test_search_a_tree() {
+        let dir = TempDir::new("iota").unwrap();
+        fs::mkdir(&dir.path().join("src"), USER_RWX).unwrap();
+        fs::mkdir(&dir.path().join(".git"), USER_RWX).unwrap();
+        File::create(&dir.path().join("src/a.rs")).write_str("let x = 1;\nlet needle = 2;\n").unwrap();
+        File::create(&dir.path().join("b.txt")).write_str("needle\r\nhay\r\nneedles\r\n").unwrap();
+        File::create(&dir.path().join(".git/config")).write_str("needle\n").unwrap();
+        File::create(&dir.path().join("c.bin")).write(b"needle\x00\x01\x02").unwrap();
+
+        let mut grep = Grep::new(Regex::new("needle").unwrap(), dir.path().clone());
+        let mut results = Vec::new();
+        while !grep.is_done() {
+            results.extend(grep.step(1).into_iter());
+        }
+        assert_eq!(results, vec![String::from_str("b.txt:1: needle"),
+                                 String::from_str("b.txt:3: needles"),
+                                 String::from_str("src/a.rs:2: let needle = 2;")]);
+        assert_eq!(grep.counts(), (3, 2));
+    }
+}
@@ -0,0 +1,242 @@
+use std::os;
+use std::io::{fs, File, IoResult, USER_RWX};
+
+/// How many entries each history keeps.
+const MAX_ENTRIES: uint = 100;
+
+/// What has been entered at one kind of prompt, most recent first, with the place of the entry
+/// being recalled with Up and Down.
+pub struct History {
+    entries: Vec<String>,
+    recalled: Option<uint>,     // index of the entry in the prompt, if one was recalled
+    draft: String,              // what was typed before recalling, to come back to
+}
+
+impl History {
+    pub fn new() -> History {
+        History { entries: Vec::new(), recalled: None, draft: String::new() }
+    }
+
+    /// Remember an entry as the most recent, unless it is empty or the same as the last one.
+    /// Recalling starts again from the most recent entry.
+    pub fn add(&mut self, entry: &str) {
+        self.recalled = None;
+        if entry.is_empty() || self.entries.first().map_or(false, |last| last[] == entry) {
+            return
+        }
+        self.entries.insert(0, String::from_str(entry));
+        self.entries.truncate(MAX_ENTRIES);
+    }
+
+    /// The entry before the one being recalled, or the most recent one if none is yet, in which
+    /// case `typed` is kept to come back to. None once the oldest entry is reached.
+    pub fn previous(&mut self, typed: &str) -> Option<&str> {
+        let i = match self.recalled {
+            Some(i) if i + 1 < self.entries.len() => i + 1,
+            Some(_)                               => return None,
+            None if self.entries.is_empty()       => return None,
+            None                                  => {
+                self.draft = String::from_str(typed);
+                0
+            }
+        };
+        self.recalled = Some(i);
+        Some(self.entries[i][])
+    }
+
+    /// The entry after the one being recalled, or what was typed before recalling once past the
+    /// most recent entry. None when nothing is being recalled.
+    pub fn next(&mut self) -> Option<&str> {
+        match self.recalled {
+            Some(0) => {
+                self.recalled = None;
+                Some(self.draft[])
+            }
+            Some(i) => {
+                self.recalled = Some(i - 1);
+                Some(self.entries[i - 1][])
+            }
+            None => None,
+        }
+    }
+
+    /// Stop recalling, for a new prompt.
+    pub fn reset(&mut self) {
+        self.recalled = None;
+    }
+
+    /// The entries, most recent first.
+    pub fn entries(&self) -> &[String] {
+        self.entries[]
+    }
+}
+
+/// The histories of the search, command and go to line prompts.
+///
+/// They are kept together in a file of one entry per line, oldest first, each starting with the
+/// name of its history and a tab. Lines that don't start with a known name are skipped.
+pub struct Histories {
+    pub search: History,
+    pub command: History,
+    pub goto_line: History,
+}
+
+impl Histories {
+    pub fn new() -> Histories {
+        Histories { search: History::new(), command: History::new(), goto_line: History::new() }
+    }
+
+    /// Read the histories kept at `path`. A missing or unreadable file gives empty histories.
+    pub fn load(path: &Path) -> Histories {
+        let mut histories = Histories::new();
+        let contents = match File::open(path).read_to_string() {
+            Ok(contents) => contents,
+            Err(_)       => return histories,
+        };
+        for line in contents.lines() {
+            let (name, entry) = match line.find('\t') {
+                Some(i) => (line.slice_to(i), line.slice_from(i + 1)),
+                None    => continue,
+            };
+            match histories.named(name) {
+                Some(history) => history.add(entry),
+                None          => {}
+            }
+        }
+        histories
+    }
+
+    /// Write the histories to `path`, creating the directory it is in if need be.
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        try!(fs::mkdir_recursive(&path.dir_path(), USER_RWX));
+        let mut file = try!(File::create(path));
+        for &(name, history) in [("search", &self.search), ("command", &self.command),
+                                 ("line", &self.goto_line)].iter() {
+            for entry in history.entries.iter().rev() {
+                try!(file.write_line(format!("{}\t{}", name, entry)[]));
+            }
+        }
+        Ok(())
+    }
+
+    fn named(&mut self, name: &str) -> Option<&mut History> {
+        match name {
+            "search"  => Some(&mut self.search),
+            "command" => Some(&mut self.command),
+            "line"    => Some(&mut self.goto_line),
+            _         => None,
+        }
+    }
+}
+
+/// `~/.iota/history`, where the histories are kept. None if there is no home directory.
+pub fn history_path() -> Option<Path> {
+    os::homedir().map(|home| home.join(".iota").join("history"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{File, TempDir};
+
+    use history::{History, Histories, MAX_ENTRIES};
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::new();
+        for entry in entries.iter() { history.add(*entry) }
+        history
+    }
+
+    #[test]
+    fn test_recall_most_recent_first() {
+        let mut history = history(&["one", "two", "three"]);
+        assert_eq!(history.previous("typed"), Some("three"));
+        assert_eq!(history.previous("typed"), Some("two"));
+        assert_eq!(history.previous("typed"), Some("one"));
+        assert_eq!(history.next(), Some("two"));
+    }
+
+    #[test]
+    fn test_recall_stops_at_the_oldest() {
+        let mut history = history(&["one", "two"]);
+        history.previous("");
+        history.previous("");
+        assert_eq!(history.previous(""), None);
+        assert_eq!(history.previous(""), None);
+        assert_eq!(history.next(), Some("two"));
+    }
+
+    #[test]
+    fn test_recall_comes_back_to_what_was_typed() {
+        let mut history = history(&["one", "two"]);
+        assert_eq!(history.next(), None);
+        history.previous("draft");
+        history.previous("ignored");
+        assert_eq!(history.next(), Some("two"));
+        assert_eq!(history.next(), Some("draft"));
+        assert_eq!(history.next(), None);
+    }
+
+    #[test]
+    fn test_empty_history() {
+        let mut history = History::new();
+        assert_eq!(history.previous("x"), None);
+        assert_eq!(history.next(), None);
+    }
+
+    #[test]
+    fn test_consecutive_duplicates_are_kept_once() {
+        let history = history(&["one", "one", "two", "two", "one", ""]);
+        assert_eq!(history.entries(), [String::from_str("one"), String::from_str("two"),
+                                       String::from_str("one")][]);
+    }
+
+    #[test]
+    fn test_edited_entry_is_added_as_a_new_one() {
+        let mut history = history(&["cat"]);
+        let edited = format!("{}s", history.previous("").unwrap());
+        history.add(edited[]);
+        assert_eq!(history.entries(), [String::from_str("cats"), String::from_str("cat")][]);
+        assert_eq!(history.previous(""), Some("cats"));
+    }
+
+    #[test]
+    fn test_keeps_a_limited_number_of_entries() {
+        let mut history = History::new();
+        for i in range(0, MAX_ENTRIES + 5) { history.add(format!("{}", i)[]) }
+        assert_eq!(history.entries().len(), MAX_ENTRIES);
+        assert_eq!(history.entries()[0], format!("{}", MAX_ENTRIES + 4));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join(".iota").join("history");
+        let mut histories = Histories::new();
+        histories.search.add("fn main");
+        histories.search.add("a\tb");
+        histories.command.add("w");
+        histories.goto_line.add("12");
+        histories.save(&path).unwrap();
+
+        let loaded = Histories::load(&path);
+        assert_eq!(loaded.search.entries(), [String::from_str("a\tb"), String::from_str("fn main")][]);
+        assert_eq!(loaded.command.entries(), [String::from_str("w")][]);
+        assert_eq!(loaded.goto_line.entries(), [String::from_str("12")][]);
+    }
+
+    #[test]
+    fn test_corrupt_history_is_ignored() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("history");
+        File::create(&path).write(b"\xff\xfe garbage\n").unwrap();
+        assert_eq!(Histories::load(&path).search.entries().len(), 0);
+
+        File::create(&path).write_str("search\tok\n\
+                                       no tab here\n\
+                                       other\tskipped\n").unwrap();
+        let histories = Histories::load(&path);
+        assert_eq!(histories.search.entries(), [String::from_str("ok")][]);
+        assert_eq!(histories.command.entries().len(), 0);
+    }
+}
@@ -1,6 +1,98 @@
 use std::io::stdio;
+use std::io::fs::PathExtensions;
 
 pub enum Input {
     Filename(Option<String>),
     Stdin(stdio::StdinReader),
 }
+
+/// Split a `:line` or `:line:column` suffix, as printed by compilers and grep, off a file name
+/// given on the command line.
+///
+/// The suffix is only split off when there is no file with the full name but there is one
+/// without the suffix, so that names which really contain colons still open.
+pub fn parse_file_position(arg: &str) -> (String, Option<uint>, Option<uint>) {
+    split_position(arg, file_exists)
+}
+
+/// The line number of a `+line` argument.
+pub fn parse_line_arg(arg: &str) -> Option<uint> {
+    if arg.starts_with("+") { arg.slice_from(1).parse() } else { None }
+}
+
+fn file_exists(name: &str) -> bool {
+    Path::new(name).exists()
+}
+
+fn split_position(arg: &str, exists: fn(&str) -> bool) -> (String, Option<uint>, Option<uint>) {
+    if exists(arg) { return (String::from_str(arg), None, None) }
+
+    let mut name = arg;
+    let mut numbers: Vec<uint> = Vec::new();
+    while numbers.len() < 2 {
+        let number = match name.rfind(':') {
+            Some(i) => name.slice_from(i + 1).parse().map(|n| (i, n)),
+            None    => None,
+        };
+        match number {
+            Some((i, n)) => {
+                numbers.insert(0, n);
+                name = name.slice_to(i);
+            }
+            None => break,
+        }
+        if exists(name) {
+            return (String::from_str(name), numbers.get(0).map(|&n| n), numbers.get(1).map(|&n| n))
+        }
+    }
+    (String::from_str(arg), None, None)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use input::{split_position, parse_line_arg};
+
+    fn only_main_rs(name: &str) -> bool {
+        name == "src/main.rs"
+    }
+
+    fn colon_file(name: &str) -> bool {
+        name == "notes:12" || name == "notes"
+    }
+
+    #[test]
+    fn splits_line_number() {
+        assert_eq!(split_position("src/main.rs:143", only_main_rs),
+                   (String::from_str("src/main.rs"), Some(143), None));
+    }
+
+    #[test]
+    fn splits_line_and_column() {
+        assert_eq!(split_position("src/main.rs:143:7", only_main_rs),
+                   (String::from_str("src/main.rs"), Some(143), Some(7)));
+    }
+
+    #[test]
+    fn keeps_names_that_exist_with_colons() {
+        assert_eq!(split_position("notes:12", colon_file),
+                   (String::from_str("notes:12"), None, None));
+        assert_eq!(split_position("notes:12:3", colon_file),
+                   (String::from_str("notes:12"), Some(3), None));
+    }
+
+    #[test]
+    fn keeps_suffix_without_matching_file() {
+        assert_eq!(split_position("other.rs:143", only_main_rs),
+                   (String::from_str("other.rs:143"), None, None));
+        assert_eq!(split_position("src/main.rs:x", only_main_rs),
+                   (String::from_str("src/main.rs:x"), None, None));
+    }
+
+    #[test]
+    fn parses_plus_line() {
+        assert_eq!(parse_line_arg("+143"), Some(143));
+        assert_eq!(parse_line_arg("143"), None);
+        assert_eq!(parse_line_arg("+x"), None);
+    }
+}
@@ -1,6 +1,7 @@
 #[derive(Copy, PartialEq, Eq, Hash)]
 pub enum Key {
     Tab,
+    BackTab,        // Shift-Tab, which the terminal sends as Esc [ Z.
     Enter,
     Esc,
     Backspace,
@@ -9,6 +10,10 @@ pub enum Key {
     Down,
     Up,
     Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
 
     Char(char),
     Ctrl(char),
@@ -26,12 +31,19 @@ impl Key {
             7     => Some(Key::Ctrl('g')),
             8     => Some(Key::Ctrl('h')),
             9     => Some(Key::Tab),
+            10    => Some(Key::Ctrl('j')),
+            11    => Some(Key::Ctrl('k')),
+            12    => Some(Key::Ctrl('l')),
             13    => Some(Key::Enter),
             14    => Some(Key::Ctrl('n')),
             16    => Some(Key::Ctrl('p')),
             17    => Some(Key::Ctrl('q')),
             18    => Some(Key::Ctrl('r')),
             19    => Some(Key::Ctrl('s')),
+            20    => Some(Key::Ctrl('t')),
+            21    => Some(Key::Ctrl('u')),
+            22    => Some(Key::Ctrl('v')),
+            23    => Some(Key::Ctrl('w')),
             24    => Some(Key::Ctrl('x')),
             25    => Some(Key::Ctrl('y')),
             26    => Some(Key::Ctrl('z')),
@@ -42,6 +54,10 @@ impl Key {
             65515 => Some(Key::Left),
             65516 => Some(Key::Down),
             65517 => Some(Key::Up),
+            65518 => Some(Key::PageDown),
+            65519 => Some(Key::PageUp),
+            65520 => Some(Key::End),
+            65521 => Some(Key::Home),
             65522 => Some(Key::Delete),
             _     => None,
         }
@@ -0,0 +1,209 @@
+use std::iter::repeat;
+
+use utils;
+
+/// How many kills the ring keeps.
+const MAX_KILLS: uint = 30;
+
+/// Something copied, cut or deleted, for pasting back.
+#[derive(Clone, PartialEq, Show)]
+pub enum Register {
+    Text(String),           // Pasted at the cursor.
+    Line(String),           // A whole line, pasted as a line of its own below the cursor's.
+    Block(Vec<String>),     // The lines of a block, pasted down the lines from the cursor's.
+}
+
+impl Register {
+    /// The text as other programs should see it, with a block's lines on lines of their own.
+    pub fn text(&self) -> String {
+        match *self {
+            Register::Text(ref text) | Register::Line(ref text) => text.clone(),
+            Register::Block(ref lines)                         => lines.connect("\n"),
+        }
+    }
+
+    /// The text moved to the indentation `indent`: the indentation its lines have in common is
+    /// taken off, and `indent` put on in its place, so that nested lines keep their extra
+    /// indentation. That extra is made tabs and spaces `with_tabs`, or only spaces otherwise.
+    ///
+    /// The first line of plain text goes in at the cursor, after whatever indentation is already
+    /// there, so it just loses its own. Blank lines are left empty, and blocks as they are.
+    pub fn reindented(&self, indent: &str, with_tabs: bool, tab_width: uint) -> Register {
+        let (text, skip_first) = match *self {
+            Register::Text(ref text) => (text, true),
+            Register::Line(ref text) => (text, false),
+            Register::Block(_)       => return self.clone(),
+        };
+        let lines: Vec<(&str, &str)> = text.split('\n').map(|line| {
+            let text = line.trim_left_matches(|&: c: char| c == ' ' || c == '\t');
+            (line.slice_to(line.len() - text.len()), text)
+        }).collect();
+        let widths: Vec<uint> = lines.iter().map(|&(leading, _)| {
+            utils::str_width(leading, false, tab_width)
+        }).collect();
+        let common = range(0, lines.len()).filter(|&i| {
+            !lines[i].1.is_empty() && !(skip_first && i == 0)
+        }).map(|i| widths[i]).min().unwrap_or(0);
+
+        let reindented: Vec<String> = lines.iter().enumerate().map(|(i, &(_, text))| {
+            if text.is_empty() { return String::new() }
+            if skip_first && i == 0 { return String::from_str(text) }
+            let extra = widths[i] - common;
+            let (tabs, spaces) = if with_tabs { (extra / tab_width, extra % tab_width) }
+                                 else { (0, extra) };
+            let extra: String = repeat('\t').take(tabs).chain(repeat(' ').take(spaces)).collect();
+            format!("{}{}{}", indent, extra, text)
+        }).collect();
+        let reindented = reindented.connect("\n");
+        match *self {
+            Register::Text(_) => Register::Text(reindented),
+            _                 => Register::Line(reindented),
+        }
+    }
+}
+
+/// The last few things copied, cut or deleted, most recent first, as Emacs keeps them.
+///
+/// Pasting takes the most recent, and each paste straight after steps on to an older one, going
+/// round to the most recent again after the oldest.
+pub struct KillRing {
+    entries: Vec<Register>,
+    yanked: uint,       // index of the entry last pasted
+}
+
+impl KillRing {
+    pub fn new() -> KillRing {
+        KillRing { entries: Vec::new(), yanked: 0 }
+    }
+
+    /// Keep `register` as the most recent kill.
+    pub fn push(&mut self, register: Register) {
+        self.entries.insert(0, register);
+        self.entries.truncate(MAX_KILLS);
+        self.yanked = 0;
+    }
+
+    /// Add `text` to the most recent kill, in front of it if it was deleted going backward, as
+    /// a run of kills makes one entry. It is kept as a kill of its own if the most recent isn't
+    /// plain text.
+    pub fn add_to_newest(&mut self, text: &str, before: bool) {
+        match self.entries.first_mut() {
+            Some(&mut Register::Text(ref mut newest)) => {
+                if before { *newest = format!("{}{}", text, newest) } else { newest.push_str(text) }
+                self.yanked = 0;
+                return
+            }
+            _ => {}
+        }
+        self.push(Register::Text(String::from_str(text)));
+    }
+
+    /// The most recent kill, to paste.
+    pub fn newest(&mut self) -> Option<&Register> {
+        self.yanked = 0;
+        self.entries.first()
+    }
+
+    /// The kill before the one last pasted, or the most recent after the oldest.
+    pub fn older(&mut self) -> Option<&Register> {
+        if self.entries.is_empty() { return None }
+        self.yanked = (self.yanked + 1) % self.entries.len();
+        Some(&self.entries[self.yanked])
+    }
+
+    pub fn len(&self) -> uint {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use killring::{KillRing, Register, MAX_KILLS};
+
+    fn text(s: &str) -> Register {
+        Register::Text(String::from_str(s))
+    }
+
+    #[test]
+    fn test_older_kills_cycle() {
+        let mut ring = KillRing::new();
+        ring.push(text("one"));
+        ring.push(text("two"));
+        ring.push(text("three"));
+        assert_eq!(ring.newest(), Some(&text("three")));
+        assert_eq!(ring.older(), Some(&text("two")));
+        assert_eq!(ring.older(), Some(&text("one")));
+        assert_eq!(ring.older(), Some(&text("three")));
+        assert_eq!(ring.newest(), Some(&text("three")));
+        assert_eq!(ring.older(), Some(&text("two")));
+    }
+
+    #[test]
+    fn test_empty_ring() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.newest(), None);
+        assert_eq!(ring.older(), None);
+    }
+
+    #[test]
+    fn test_a_push_starts_from_the_newest() {
+        let mut ring = KillRing::new();
+        ring.push(text("one"));
+        ring.push(text("two"));
+        ring.older();
+        ring.push(text("three"));
+        assert_eq!(ring.older(), Some(&text("two")));
+    }
+
+    #[test]
+    fn test_runs_of_kills_make_one_entry() {
+        let mut ring = KillRing::new();
+        ring.push(text("two"));
+        ring.add_to_newest(" three", false);
+        ring.add_to_newest("one ", true);
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.newest(), Some(&text("one two three")));
+
+        ring.push(Register::Line(String::from_str("line")));
+        ring.add_to_newest("word", false);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.newest(), Some(&text("word")));
+
+        let mut ring = KillRing::new();
+        ring.add_to_newest("first", true);
+        assert_eq!(ring.newest(), Some(&text("first")));
+    }
+
+    #[test]
+    fn test_keeps_a_limited_number_of_kills() {
+        let mut ring = KillRing::new();
+        for i in range(0, MAX_KILLS + 5) { ring.push(text(format!("{}", i)[])) }
+        assert_eq!(ring.len(), MAX_KILLS);
+        assert_eq!(ring.newest(), Some(&text(format!("{}", MAX_KILLS + 4)[])));
+    }
+
+    #[test]
+    fn test_reindent_a_line_deeper() {
+        let line = Register::Line(String::from_str("  if a {\n      b();\n\n  }"));
+        assert_eq!(line.reindented("\t\t", true, 4),
+                   Register::Line(String::from_str("\t\tif a {\n\t\t\tb();\n\n\t\t}")));
+        assert_eq!(line.reindented("        ", false, 4),
+                   Register::Line(String::from_str("        if a {\n            b();\n\n        }")));
+    }
+
+    #[test]
+    fn test_reindent_text_after_its_first_line() {
+        let text = Register::Text(String::from_str("if a {\n\t\tb();\n\t}\n"));
+        assert_eq!(text.reindented("  ", false, 4),
+                   Register::Text(String::from_str("if a {\n      b();\n  }\n")));
+        let word = Register::Text(String::from_str("  word"));
+        assert_eq!(word.reindented("    ", false, 4), text("word"));
+    }
+
+    #[test]
+    fn test_block_text_is_its_lines() {
+        let block = Register::Block(vec![String::from_str("ab"), String::from_str("cd")]);
+        assert_eq!(block.text(), String::from_str("ab\ncd"));
+    }
+}
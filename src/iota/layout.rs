@@ -0,0 +1,150 @@
+use std::str;
+
+use utils;
+
+/// The byte offsets that the screen rows of `line` start at when it is wrapped to `width`
+/// columns, the first always 0. A row breaks after the last space that fits on it, or, if the
+/// row has none, before the character that goes past the edge.
+///
+/// The columns of each row are counted from its start, tab stops and all, as `draw_line` counts
+/// them for the row's bytes. Lines that aren't valid UTF-8 aren't wrapped, as their bytes don't
+/// line up with what is drawn.
+pub fn wrap(line: &[u8], width: uint, tab_width: uint) -> Vec<uint> {
+    let mut rows = vec![0];
+    let text = match str::from_utf8(line) {
+        Ok(text) => text,
+        Err(_)   => return rows,
+    };
+    let mut col = 0;            // column of the next character on its row
+    let mut space = None;       // offset just after the last space on the row
+    for (i, ch) in text.char_indices() {
+        if ch == '\n' { break }
+        if col + char_width(ch, tab_width, col) > width && col > 0 {
+            let start = space.unwrap_or(i);
+            rows.push(start);
+            col = width_of(text[start..i], tab_width);
+            space = None;
+            // what came after the space may still leave this character no room
+            if col + char_width(ch, tab_width, col) > width && col > 0 {
+                rows.push(i);
+                col = 0;
+            }
+        }
+        col += char_width(ch, tab_width, col);
+        if ch == ' ' { space = Some(i + 1) }
+    }
+    rows
+}
+
+/// The row of `rows` that the byte at `offset` in `line` is on, and its column there.
+pub fn position(line: &[u8], rows: &[uint], offset: uint, tab_width: uint) -> (uint, uint) {
+    let row = rows.iter().rposition(|&start| start <= offset).unwrap_or(0);
+    let before = String::from_utf8_lossy(line[rows[row]..offset]);
+    (row, width_of(&*before, tab_width))
+}
+
+/// The offset in `line` of the character at column `col` of row `row`, as `position` gives it.
+/// A column past the end of the row gives its last character, or the end of the line on its
+/// last row.
+pub fn offset_at(line: &[u8], rows: &[uint], row: uint, col: uint, tab_width: uint) -> uint {
+    let start = rows[row];
+    let end = if row + 1 < rows.len() { rows[row + 1] } else { line.len() };
+    let text = match str::from_utf8(line[start..end]) {
+        Ok(text) => text,
+        Err(_)   => return start,
+    };
+    let mut width = 0;
+    let mut last = start;       // the offset of the last character that starts a cell
+    for (i, ch) in text.char_indices() {
+        if ch == '\n' { return start + i }
+        let w = char_width(ch, tab_width, width);
+        if w == 0 { continue }
+        if width + w > col { return start + i }
+        last = start + i;
+        width += w;
+    }
+    if row + 1 < rows.len() { last } else { end }
+}
+
+/// The columns `ch` takes at column `col`, as `draw_line` draws it: a tab to the next tab stop,
+/// and anything without a width of its own, such as a control character, as one cell.
+pub fn char_width(ch: char, tab_width: uint, col: uint) -> uint {
+    utils::char_width(ch, false, tab_width, col).unwrap_or(1)
+}
+
+/// The columns `text` takes from the start of a line, each character counted as `char_width`
+/// counts it.
+pub fn width_of(text: &str, tab_width: uint) -> uint {
+    text.chars().fold(0, |col, ch| col + char_width(ch, tab_width, col))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use layout::{wrap, position, offset_at};
+
+    #[test]
+    fn test_short_lines_have_one_row() {
+        assert_eq!(wrap(b"short\n", 10, 4), vec![0]);
+        assert_eq!(wrap(b"", 10, 4), vec![0]);
+        assert_eq!(wrap(b"exactly10!\n", 10, 4), vec![0]);
+    }
+
+    #[test]
+    fn test_rows_break_after_the_last_space() {
+        // "one two " | "three four"
+        assert_eq!(wrap(b"one two three four", 10, 4), vec![0, 8]);
+        // a word too long for a row is broken at the edge
+        assert_eq!(wrap(b"abcdefghijklmnopqrstuvwxy", 10, 4), vec![0, 10, 20]);
+        assert_eq!(wrap(b"ab abcdefghijklmno", 10, 4), vec![0, 3, 13]);
+    }
+
+    #[test]
+    fn test_tabs_and_wide_characters_take_their_width() {
+        // a tab at the start of a row takes a whole tab stop
+        let line = b"abcdefgh\tx";
+        let rows = wrap(line, 8, 4);
+        assert_eq!(rows, vec![0, 8]);
+        assert_eq!(position(line, rows[], 9, 4), (1, 4));
+        // the wide character doesn't fit in the last column, so it starts the next row
+        let line = "abcdefg\u{4E2D}".as_bytes();
+        assert_eq!(wrap(line, 8, 4), vec![0, 7]);
+    }
+
+    #[test]
+    fn test_positions_on_wrapped_rows() {
+        let line = b"one two three four\n";
+        let rows = wrap(line, 10, 4);
+        assert_eq!(position(line, rows[], 0, 4), (0, 0));
+        assert_eq!(position(line, rows[], 7, 4), (0, 7));
+        assert_eq!(position(line, rows[], 8, 4), (1, 0));
+        assert_eq!(position(line, rows[], 18, 4), (1, 10));
+    }
+
+    #[test]
+    fn test_offsets_at_screen_positions() {
+        let line = b"one two three four\n";
+        let rows = wrap(line, 10, 4);
+        assert_eq!(offset_at(line, rows[], 0, 2, 4), 2);
+        assert_eq!(offset_at(line, rows[], 1, 2, 4), 10);
+        // past the end of the first row is its last character, past the end of the line its end
+        assert_eq!(offset_at(line, rows[], 0, 9, 4), 7);
+        assert_eq!(offset_at(line, rows[], 1, 20, 4), 18);
+
+        let line = "a\u{4E2D}b\te\u{301}x".as_bytes();
+        let rows = vec![0];
+        assert_eq!(offset_at(line, rows[], 0, 2, 4), 1);
+        assert_eq!(offset_at(line, rows[], 0, 3, 4), 4);
+        assert_eq!(offset_at(line, rows[], 0, 9, 4), 9);
+    }
+
+    #[test]
+    fn test_positions_and_offsets_agree() {
+        let line = b"\tone two\tthree four five six seven\n";
+        let rows = wrap(line, 12, 4);
+        for offset in range(0, line.len() - 1) {
+            let (row, col) = position(line, rows[], offset, 4);
+            assert_eq!(offset_at(line, rows[], row, col, 4), offset);
+        }
+    }
+}
@@ -5,15 +5,24 @@
 
 extern crate rustbox;
 extern crate gapbuffer;
+extern crate regex;
+extern crate libc;
+#[cfg(test)] extern crate test;
 
 pub use editor::Editor;
-pub use input::Input;
+pub use options::Options;
+pub use input::{Input, parse_file_position, parse_line_arg};
 pub use frontends::RustboxFrontend;
 pub use modes::{StandardMode, NormalMode, Mode};
+pub use session::{Session, session_path};
+pub use recent::{RecentFiles, recent_path};
+pub use history::{Histories, history_path};
+pub use clipboard::{Clipboard, SystemClipboard};
 
 mod input;
 mod utils;
 mod buffer;
+mod store;
 mod editor;
 mod keyboard;
 mod keymap;
@@ -23,6 +32,22 @@ mod log;
 mod frontends;
 mod modes;
 mod overlay;
+mod options;
+mod filetype;
+mod session;
+mod completion;
+mod recent;
+mod replace;
+mod history;
+mod grep;
+mod clipboard;
+mod killring;
+mod layout;
+mod status;
+mod message;
+mod syntax;
+mod theme;
+mod color;
 
 #[derive(Copy)]
 pub enum Response {
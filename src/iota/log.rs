@@ -2,6 +2,7 @@
 /// This is a deliberately unoptimized representation, for simplicity.  It is by no means final.
 
 
+use std::cmp;
 use std::mem;
 
 /// Represents a modification of data.
@@ -13,6 +14,13 @@ pub enum Change {
 }
 
 impl Change {
+    /// The index the change was made at.
+    pub fn index(&self) -> uint {
+        match *self {
+            Change::Insert(idx, _) | Change::Remove(idx, _) => idx,
+        }
+    }
+
     /// Reverses a change, consuming it in the process
     pub fn reverse(self) -> Change {
         match self {
@@ -25,6 +33,8 @@ impl Change {
 /// Log entry
 /// Entries may only be played linearly--they don't make sense out of order.
 pub struct LogEntry {
+    /// Identifies the state of the data after this entry has been applied.
+    id: uint,
     /// The initial point position associated with this log entry.
     ///
     /// The OLD point position.
@@ -41,11 +51,26 @@ impl LogEntry {
     pub fn reverse(mut self) -> LogEntry {
         self.changes.reverse();
         LogEntry {
+            id: self.id,
             init_point: self.end_point,
             end_point: self.init_point,
             changes: self.changes.map_in_place( |change| change.reverse() ),
         }
     }
+
+    /// Whether `next` just continues the run of characters typed in this entry.
+    ///
+    /// Such entries are merged so that undoing a typed word doesn't take a keypress per letter.
+    /// A run is broken by a newline or by the insertion point moving elsewhere.
+    fn continued_by(&self, next: &LogEntry) -> bool {
+        if next.changes.len() != 1 { return false }
+        match (self.changes.last(), &next.changes[0]) {
+            (Some(&Change::Insert(idx, ch)), &Change::Insert(next_idx, next_ch)) => {
+                ch != b'\n' && next_ch != b'\n' && next_idx == idx + 1
+            }
+            _ => false,
+        }
+    }
 }
 
 /// A set of `Change`s that should be treated atomically.
@@ -66,6 +91,7 @@ impl<'a> Transaction<'a> {
     /// The logging should occur after the change has been executed.  This may eventually allow
     /// rollback in case of failure.
     pub fn log(&mut self, change: Change, idx: uint) {
+        self.entries.note_edit(change.index());
         self.entry.changes.push(change);
         self.entry.end_point = idx;
     }
@@ -81,8 +107,21 @@ impl<'a> Drop for Transaction<'a> {
             changes: mem::replace(&mut self.entry.changes, Vec::new()),
             .. self.entry
         };
-        // Commit the transaction.
-        self.entries.undo.push(entry);
+        // Commit the transaction, folding it into the previous entry if it continues a typed run.
+        let coalesce = match self.entries.undo.last() {
+            Some(last) => last.continued_by(&entry),
+            None => false,
+        };
+        let id = self.entries.next_id;
+        self.entries.next_id += 1;
+        if coalesce {
+            let last = self.entries.undo.last_mut().unwrap();
+            last.id = id;
+            last.changes.extend(entry.changes.into_iter());
+            last.end_point = entry.end_point;
+        } else {
+            self.entries.undo.push(LogEntry { id: id, .. entry });
+        }
         // Clear the redo entries now that the transaction has been committed.
         self.entries.redo.clear();
     }
@@ -95,6 +134,11 @@ pub struct Log {
     /// Redo log entries--LIFO stack.  Cleared after a new change (other than an undo or redo)
     /// is committed.
     redo: Vec<LogEntry>,
+    /// Id given to the next committed entry.  Id 0 is the state before any entries.
+    next_id: uint,
+    /// The lowest index changed since `take_edited_from` was last called, by new entries, undo
+    /// or redo.
+    edited_from: Option<uint>,
 }
 
 impl Log {
@@ -103,9 +147,55 @@ impl Log {
         Log {
             undo: Vec::new(),
             redo: Vec::new(),
+            next_id: 1,
+            edited_from: None,
         }
     }
 
+    /// Note a change at `idx`, whether or not it is logged.
+    pub fn note_edit(&mut self, idx: uint) {
+        self.edited_from = Some(self.edited_from.map_or(idx, |from| cmp::min(from, idx)));
+    }
+
+    /// The lowest index changed since this was last called, if any was.
+    pub fn take_edited_from(&mut self) -> Option<uint> {
+        self.edited_from.take()
+    }
+
+    /// Identifies the current state of the data, as reached by the entries on the undo stack.
+    ///
+    /// Undoing back to an earlier state gives back its revision, while any new change gives a
+    /// revision that has never been seen before.
+    pub fn revision(&self) -> uint {
+        match self.undo.last() {
+            Some(entry) => entry.id,
+            None        => 0,
+        }
+    }
+
+    /// Fold the entries committed since `revision` into one, so that they are undone and redone
+    /// together, as a single step.
+    pub fn group_since(&mut self, revision: uint) {
+        let start = match self.undo.iter().position(|entry| entry.id > revision) {
+            Some(start) => start,
+            None        => return,
+        };
+        let mut entries = Vec::new();
+        while self.undo.len() > start {
+            entries.push(self.undo.pop().unwrap());
+        }
+        entries.reverse();
+
+        let mut entries = entries.into_iter();
+        let mut group = entries.next().unwrap();
+        for entry in entries {
+            group.id = entry.id;
+            group.end_point = entry.end_point;
+            group.changes.extend(entry.changes.into_iter());
+        }
+        self.undo.push(group);
+    }
+
     /// Start a new transaction.
     ///
     /// This returns a RAII guard that can be used to record edits during the transaction.
@@ -113,6 +203,7 @@ impl Log {
         Transaction {
             entries: self,
             entry: LogEntry {
+                id: 0,
                 init_point: idx,
                 end_point: idx,
                 changes: Vec::new(),
@@ -126,6 +217,7 @@ impl Log {
     pub fn undo(&mut self) -> Option<&LogEntry> {
         match self.undo.pop() {
             Some(change) => {
+                for c in change.changes.iter() { self.note_edit(c.index()) }
                 let last = self.redo.len();
                 self.redo.push(change.reverse());
                 Some(&self.redo[last])
@@ -133,12 +225,24 @@ impl Log {
             None => None
         }
     }
+
+    /// The entry last undone, as `undo` gave it.
+    pub fn last_undone(&self) -> Option<&LogEntry> {
+        self.redo.last()
+    }
+
+    /// The entry last done or redone.
+    pub fn last_done(&self) -> Option<&LogEntry> {
+        self.undo.last()
+    }
+
     /// This reverses the most recent change on the redo stack, places the new change on the undo
     /// stack, and then returns a reference to it.  It is the caller's responsibility to actually
     /// perform the change.
     pub fn redo(&mut self) -> Option<&LogEntry> {
         match self.redo.pop() {
             Some(change) => {
+                for c in change.changes.iter() { self.note_edit(c.index()) }
                 let last = self.undo.len();
                 self.undo.push(change.reverse());
                 Some(&self.undo[last])
@@ -0,0 +1,138 @@
+use std::collections::RingBuf;
+
+use utils;
+
+/// How a message is shown.
+#[derive(Copy, PartialEq, Show)]
+pub enum Level {
+    Info,       // Something done, or worth knowing.
+    Progress,   // How far something has got, only worth knowing until there is more to say.
+    Error,      // Something that went wrong, or needs care.
+}
+
+#[derive(Clone, PartialEq, Show)]
+pub struct Message {
+    pub text: String,
+    pub level: Level,
+}
+
+/// The messages waiting for the message area, oldest first.
+///
+/// Only the oldest is shown. It stays until it is dismissed, by the next key or by going
+/// unanswered for a while, and then the next one is shown, so that messages given together
+/// are each seen in turn.
+pub struct Messages {
+    queue: RingBuf<Message>,
+}
+
+impl Messages {
+    pub fn new() -> Messages {
+        Messages { queue: RingBuf::new() }
+    }
+
+    /// Queue `text` to be shown after the messages already waiting. It takes the place of the
+    /// newest if that is only progress, and the same message twice in a row is only shown once.
+    pub fn push(&mut self, text: &str, level: Level) {
+        if self.queue.back().map_or(false, |newest| newest.level == Level::Progress) {
+            self.queue.pop_back();
+        }
+        if let Some(newest) = self.queue.back() {
+            if newest.text[] == text && newest.level == level { return }
+        }
+        self.queue.push_back(Message { text: String::from_str(text), level: level });
+    }
+
+    /// The message to show.
+    pub fn current(&self) -> Option<&Message> {
+        self.queue.front()
+    }
+
+    /// The message given last.
+    #[cfg(test)]
+    pub fn newest(&self) -> Option<&Message> {
+        self.queue.back()
+    }
+
+    /// Take away the message being shown, so that the next can be.
+    pub fn dismiss(&mut self) {
+        self.queue.pop_front();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// `text` cut short with an ellipsis if it is wider than `width` columns.
+pub fn truncate(text: &str, width: uint) -> String {
+    if utils::str_width(text, false, 1) <= width { return String::from_str(text) }
+    if width == 0 { return String::new() }
+    let mut fitted = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let w = utils::char_width(ch, false, 1, used).unwrap_or(1);
+        // leave room for the ellipsis
+        if used + w >= width { break }
+        fitted.push(ch);
+        used += w;
+    }
+    fitted.push('…');
+    fitted
+}
+
+#[cfg(test)]
+mod tests {
+
+    use message::{Messages, Level, truncate};
+
+    #[test]
+    fn test_messages_are_shown_in_turn() {
+        let mut messages = Messages::new();
+        messages.push("wrote 532 bytes", Level::Info);
+        messages.push("Could not write swap file", Level::Error);
+        assert_eq!(messages.current().unwrap().text, "wrote 532 bytes");
+        messages.dismiss();
+        assert_eq!(messages.current().unwrap().text, "Could not write swap file");
+        assert_eq!(messages.current().unwrap().level, Level::Error);
+        messages.dismiss();
+        assert!(messages.is_empty());
+        assert!(messages.current().is_none());
+    }
+
+    #[test]
+    fn test_repeated_messages_are_shown_once() {
+        let mut messages = Messages::new();
+        messages.push("File changed on disk", Level::Error);
+        messages.push("File changed on disk", Level::Error);
+        messages.dismiss();
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn test_progress_gives_way_to_the_next_message() {
+        let mut messages = Messages::new();
+        messages.push("autosaved", Level::Info);
+        messages.push("Search (smart case): a", Level::Progress);
+        messages.push("Search (smart case): ab", Level::Progress);
+        assert_eq!(messages.current().unwrap().text, "autosaved");
+        assert_eq!(messages.newest().unwrap().text, "Search (smart case): ab");
+
+        let mut messages = Messages::new();
+        messages.push("grep: searching... 3 matching lines", Level::Progress);
+        messages.push("grep: 5 matching lines in 2 files", Level::Info);
+        messages.push("File changed on disk", Level::Error);
+        assert_eq!(messages.current().unwrap().text, "grep: 5 matching lines in 2 files");
+        messages.dismiss();
+        assert_eq!(messages.current().unwrap().level, Level::Error);
+    }
+
+    #[test]
+    fn test_long_messages_are_cut_short() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly 10", 10), "exactly 10");
+        assert_eq!(truncate("a bit too long", 10), "a bit too…");
+        assert_eq!(truncate("anything", 0), "");
+        // a wide character that would leave no room for the ellipsis is left out
+        assert_eq!(truncate("abcdefgh\u{4E2D}xyz", 10), "abcdefgh…");
+    }
+}
@@ -7,7 +7,6 @@ use super::KeyMapState;
 use super::EventStatus;
 use super::Direction;
 use super::Response;
-use super::utils;
 use super::{Overlay, OverlayType, OverlayEvent};
 
 
@@ -37,9 +36,64 @@ impl NormalMode {
         keymap.bind_key(Key::Char('l'), Command::MoveCursor(Direction::Right(1)));
         keymap.bind_key(Key::Char('^'), Command::LineStart);
         keymap.bind_key(Key::Char('$'), Command::LineEnd);
+        keymap.bind_key(Key::Home, Command::LineStart);
+        keymap.bind_key(Key::End, Command::LineEnd);
+        keymap.bind_key(Key::PageUp, Command::PageUp);
+        keymap.bind_key(Key::PageDown, Command::PageDown);
+        keymap.bind_key(Key::Ctrl('b'), Command::PageUp);
+        keymap.bind_key(Key::Ctrl('f'), Command::PageDown);
+        keymap.bind_key(Key::Char('{'), Command::MoveCursor(Direction::PrevParagraph));
+        keymap.bind_key(Key::Char('}'), Command::MoveCursor(Direction::NextParagraph));
+        keymap.bind_key(Key::Char('%'), Command::MatchBracket);
+        keymap.bind_key(Key::Char('/'), Command::Search);
+        keymap.bind_key(Key::Char('?'), Command::ReverseSearch);
+        keymap.bind_key(Key::Char('*'), Command::SearchWord(true));
+        keymap.bind_key(Key::Char('#'), Command::SearchWord(false));
+        keymap.bind_keys(vec![Key::Char('g'), Key::Char('g')].as_slice(), Command::MoveCursor(Direction::BufferStart));
+        keymap.bind_key(Key::Char('G'), Command::MoveCursor(Direction::BufferEnd));
+        keymap.bind_key(Key::Char('w'), Command::MoveCursor(Direction::NextWord(1)));
+        keymap.bind_key(Key::Char('b'), Command::MoveCursor(Direction::PrevWord(1)));
+
+        // selection
+        keymap.bind_key(Key::Char('v'), Command::ToggleSelection);
+        keymap.bind_key(Key::Ctrl('v'), Command::ToggleBlockSelection);
+        keymap.bind_key(Key::Char('V'), Command::SelectLine);
+        for c in "w\"'`b()[]{}".chars() {
+            keymap.bind_keys(vec![Key::Char('g'), Key::Char('i'), Key::Char(c)].as_slice(),
+                             Command::SelectInside(c));
+            keymap.bind_keys(vec![Key::Char('g'), Key::Char('a'), Key::Char(c)].as_slice(),
+                             Command::SelectAround(c));
+        }
+        keymap.bind_key(Key::Char('='), Command::ExpandSelection);
+        keymap.bind_key(Key::Char('-'), Command::ShrinkSelection);
+        keymap.bind_key(Key::Esc, Command::CollapseCursors);
+        keymap.bind_key(Key::Char('y'), Command::Copy);
+        keymap.bind_key(Key::Char('X'), Command::Cut);
+        keymap.bind_key(Key::Char('p'), Command::Paste);
+        keymap.bind_keys(vec![Key::Char('"'), Key::Char('+'), Key::Char('p')].as_slice(), Command::PasteClipboard);
+        keymap.bind_keys(vec![Key::Char(']'), Key::Char('p')].as_slice(), Command::PasteReindented);
+        keymap.bind_key(Key::Ctrl('p'), Command::YankPop);
+
+        // marks
+        for c in "abcdefghijklmnopqrstuvwxyz".chars() {
+            keymap.bind_keys(vec![Key::Char('m'), Key::Char(c)].as_slice(), Command::SetMark(c));
+            keymap.bind_keys(vec![Key::Char('`'), Key::Char(c)].as_slice(), Command::GotoMark(c));
+        }
 
         // editing
         keymap.bind_key(Key::Char('x'), Command::Delete(Direction::Right(1)));
+        keymap.bind_keys(vec![Key::Char('d'), Key::Char('w')].as_slice(), Command::DeleteWord(Direction::Right(1)));
+        keymap.bind_keys(vec![Key::Char('d'), Key::Char('b')].as_slice(), Command::DeleteWord(Direction::Left(1)));
+        keymap.bind_keys(vec![Key::Char('d'), Key::Char('d')].as_slice(), Command::DeleteLine);
+        keymap.bind_key(Key::Char('J'), Command::JoinLines);
+        keymap.bind_keys(vec![Key::Char('>'), Key::Char('>')].as_slice(), Command::IndentLine);
+        keymap.bind_keys(vec![Key::Char('<'), Key::Char('<')].as_slice(), Command::DedentLine);
+        keymap.bind_keys(vec![Key::Char('g'), Key::Char('c'), Key::Char('c')].as_slice(), Command::ToggleComment);
+        keymap.bind_keys(vec![Key::Char('g'), Key::Char('q')].as_slice(), Command::FillParagraph);
+        keymap.bind_keys(vec![Key::Char('['), Key::Char('e')].as_slice(), Command::MoveLine(Direction::Up(1)));
+        keymap.bind_keys(vec![Key::Char(']'), Key::Char('e')].as_slice(), Command::MoveLine(Direction::Down(1)));
+        keymap.bind_keys(vec![Key::Char('g'), Key::Char('U')].as_slice(), Command::UpcaseWord);
+        keymap.bind_keys(vec![Key::Char('g'), Key::Char('u')].as_slice(), Command::DowncaseWord);
         keymap.bind_key(Key::Char('u'), Command::Undo);
         keymap.bind_key(Key::Ctrl('r'), Command::Redo);
 
@@ -52,16 +106,64 @@ impl NormalMode {
     fn handle_command(&mut self, c: Command, view: &mut View) -> Response {
         match c {
             // Editor Commands
-            Command::ExitEditor      => return Response::Quit,
-            Command::SaveBuffer      => utils::save_buffer(&view.buffer),
+            Command::ExitEditor      => return view.quit(),
+            Command::SaveBuffer      => view.save_buffer(),
+            Command::RevertBuffer    => view.revert_buffer(),
+            Command::ToggleReadOnly  => view.toggle_read_only(),
 
             // Navigation
             Command::MoveCursor(dir) => view.move_cursor(dir),
             Command::LineEnd         => view.move_cursor_to_line_end(),
             Command::LineStart       => view.move_cursor_to_line_start(),
+            Command::PageUp          => view.page_up(),
+            Command::PageDown        => view.page_down(),
+            Command::SetMark(c)      => view.set_mark(c),
+            Command::GotoMark(c)     => view.goto_mark(c),
+            Command::ToggleSelection => view.toggle_selection(),
+            Command::ToggleBlockSelection => view.toggle_block_selection(),
+            Command::SelectAll       => view.select_all(),
+            Command::SelectLine      => view.select_line(),
+            Command::SelectInside(c) => view.select_object(c, false),
+            Command::SelectAround(c) => view.select_object(c, true),
+            Command::ExpandSelection => view.expand_selection(),
+            Command::ShrinkSelection => view.shrink_selection(),
+            Command::CollapseCursors => view.collapse_cursors(),
+            Command::Copy            => view.copy(),
+            Command::Cut             => view.cut(),
+            Command::Paste           => view.paste(),
+            Command::PasteClipboard  => view.paste_from_clipboard(),
+            Command::PasteReindented => view.paste_reindented(),
+            Command::YankPop         => view.yank_pop(),
+            Command::GotoLine(n)     => view.goto_line(n),
+            Command::MatchBracket    => view.goto_matching_bracket(),
+            Command::Search          => view.start_search(true),
+            Command::ReverseSearch   => view.start_search(false),
+            Command::SearchWord(fwd) => view.search_word(fwd),
+            Command::ClearHighlight  => view.clear_highlight(),
+            Command::CountMatches    => view.count_matches(),
+            Command::CycleLineNumbers => view.cycle_line_numbers(),
+            Command::ToggleWrap      => view.toggle_wrap(),
+            Command::CycleTheme      => view.cycle_theme(),
+            Command::ToggleInvisibles => view.toggle_invisibles(),
+            Command::ToggleCurrentLine => view.toggle_current_line(),
 
             // Editing
-            Command::Delete(dir)     => view.delete_char(dir),
+            Command::Delete(dir)     => { view.delete_char(dir); }
+            Command::DeleteWord(dir) => view.delete_word(dir),
+            Command::DeleteLine      => view.delete_line(),
+            Command::JoinLines       => view.join_lines(),
+            Command::IndentLine      => view.indent_line(),
+            Command::DedentLine      => view.dedent_line(),
+            Command::ToggleComment   => view.toggle_comment(),
+            Command::FillParagraph   => view.fill_paragraph(),
+            Command::MoveLine(dir)   => view.move_line(dir),
+            Command::UpcaseWord      => view.upcase_word(),
+            Command::DowncaseWord    => view.downcase_word(),
+            Command::SortLines(rev)  => view.sort_lines(rev),
+            Command::ExpandTabs(all) => view.expand_tabs(all),
+            Command::UnexpandTabs(all) => view.unexpand_tabs(all),
+            Command::SetLineEnding(e) => view.set_line_ending(e),
+            Command::ToggleBom       => view.toggle_bom(),
             Command::Redo            => view.redo(),
             Command::Undo            => view.undo(),
 
@@ -82,18 +184,16 @@ impl Mode for NormalMode {
 
         // if there is an overlay on the view, send the key there
         // and don't allow the mode to handle it.
-        match view.overlay {
-            Overlay::None => {},
-            _             => {
-                let event = view.overlay.handle_key_event(key);
-                if let OverlayEvent::Finished(response) = event {
-                    view.overlay = Overlay::None;
-                    if let Some(data) = response {
-                        return EventStatus::Handled(self.interpret_input(data, view))
-                    }
+        if let Some(overlay) = view.overlay.overlay_type() {
+            let event = view.handle_overlay_key(key);
+            if let OverlayEvent::Finished(response) = event {
+                view.overlay = Overlay::None;
+                match response {
+                    Some(data) => return EventStatus::Handled(self.interpret_input(overlay, data, view)),
+                    None       => view.cancel_overlay(overlay),
                 }
-                return EventStatus::NotHandled
             }
+            return EventStatus::NotHandled
         }
 
         // send key to the keymap
@@ -110,8 +210,32 @@ impl Mode for NormalMode {
 
     }
 
-    fn interpret_input(&mut self, input: String, view: &mut View) -> Response {
-        let command = Command::from_str(&*input);
-        self.handle_command(command, view)
+    fn interpret_input(&mut self, overlay: OverlayType, input: String, view: &mut View) -> Response {
+        match overlay {
+            OverlayType::SaveAs           => view.save_buffer_as(input.trim()),
+            OverlayType::OpenFile |
+            OverlayType::OpenRecent       => view.open_file(input.trim()),
+            OverlayType::ConfirmOverwrite => view.confirm_overwrite(input == "y"),
+            OverlayType::FileChanged      => view.answer_file_changed(&*input),
+            OverlayType::ConfirmRevert    => view.confirm_revert(input == "y"),
+            OverlayType::ConfirmReadOnly  => view.confirm_read_only(input == "y"),
+            OverlayType::ConfirmCreateDir => view.confirm_create_dir(input == "y"),
+            OverlayType::ConfirmRecover   => view.confirm_recover(input == "y"),
+            OverlayType::ConfirmBinary    => view.answer_binary(&*input),
+            OverlayType::ConfirmQuit      => return view.answer_quit(&*input),
+            OverlayType::ReplaceFrom      => view.ask_replacement(&*input),
+            OverlayType::ReplaceWith      => view.start_replace(&*input),
+            OverlayType::ConfirmReplace   => view.answer_replace(&*input),
+            OverlayType::ReplaceAll       => view.ask_replace_all_with(&*input),
+            OverlayType::ReplaceAllWith   => view.replace_all(&*input),
+            OverlayType::Grep             => view.ask_grep_dir(&*input),
+            OverlayType::GrepIn           => view.start_grep(input.trim()),
+            OverlayType::CountMatches     => view.count_matches_of(&*input),
+            _ => {
+                let command = Command::from_overlay(overlay, &*input);
+                return self.handle_command(command, view)
+            }
+        }
+        Response::Continue
     }
 }
@@ -7,7 +7,7 @@ use super::KeyMapState;
 use super::EventStatus;
 use super::Direction;
 use super::Response;
-use super::utils;
+use super::{Overlay, OverlayType, OverlayEvent};
 
 
 /// Standard mode is Iota's default mode.
@@ -38,6 +38,16 @@ impl StandardMode {
         keymap.bind_key(Key::Ctrl('s'), Command::SaveBuffer);
         keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('c')].as_slice(), Command::ExitEditor);
         keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('s')].as_slice(), Command::SaveBuffer);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('w')].as_slice(), Command::SetOverlay(OverlayType::SaveAs));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('f')].as_slice(), Command::SetOverlay(OverlayType::OpenFile));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('r')].as_slice(), Command::SetOverlay(OverlayType::OpenRecent));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('r')].as_slice(), Command::RevertBuffer);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('q')].as_slice(), Command::ToggleReadOnly);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('n')].as_slice(), Command::CycleLineNumbers);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('w')].as_slice(), Command::ToggleWrap);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('t')].as_slice(), Command::CycleTheme);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('$')].as_slice(), Command::ToggleInvisibles);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('L')].as_slice(), Command::ToggleCurrentLine);
 
         // Navigation
         keymap.bind_key(Key::Up, Command::MoveCursor(Direction::Up(1)));
@@ -48,17 +58,75 @@ impl StandardMode {
         keymap.bind_key(Key::Ctrl('p'), Command::MoveCursor(Direction::Up(1)));
         keymap.bind_key(Key::Ctrl('n'), Command::MoveCursor(Direction::Down(1)));
         keymap.bind_key(Key::Ctrl('b'), Command::MoveCursor(Direction::Left(1)));
-        keymap.bind_key(Key::Ctrl('f'), Command::MoveCursor(Direction::Right(1)));
+        keymap.bind_key(Key::Ctrl('f'), Command::Search);
+        keymap.bind_key(Key::Ctrl('r'), Command::ReverseSearch);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('*')].as_slice(), Command::SearchWord(true));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('#')].as_slice(), Command::SearchWord(false));
         keymap.bind_key(Key::Ctrl('e'), Command::LineEnd);
         keymap.bind_key(Key::Ctrl('a'), Command::LineStart);
+        keymap.bind_key(Key::Ctrl('g'), Command::SetOverlay(OverlayType::GotoLine));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('%')].as_slice(), Command::MatchBracket);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('s')].as_slice(), Command::SetOverlay(OverlayType::ReplaceFrom));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('S')].as_slice(), Command::SetOverlay(OverlayType::ReplaceAll));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('g')].as_slice(), Command::SetOverlay(OverlayType::Grep));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('c')].as_slice(), Command::CountMatches);
+        keymap.bind_key(Key::Home, Command::LineStart);
+        keymap.bind_key(Key::End, Command::LineEnd);
+        keymap.bind_key(Key::PageUp, Command::PageUp);
+        keymap.bind_key(Key::PageDown, Command::PageDown);
+        keymap.bind_key(Key::Ctrl('v'), Command::PageDown);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('{')].as_slice(), Command::MoveCursor(Direction::PrevParagraph));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('}')].as_slice(), Command::MoveCursor(Direction::NextParagraph));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('<')].as_slice(), Command::MoveCursor(Direction::BufferStart));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('>')].as_slice(), Command::MoveCursor(Direction::BufferEnd));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('f')].as_slice(), Command::MoveCursor(Direction::NextWord(1)));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('b')].as_slice(), Command::MoveCursor(Direction::PrevWord(1)));
+
+        // Cursors
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('n')].as_slice(), Command::AddCursorBelow);
+        keymap.bind_key(Key::Esc, Command::CollapseCursors);
+
+        // Selection
+        keymap.bind_key(Key::Ctrl(' '), Command::ToggleSelection);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char(' ')].as_slice(), Command::ToggleBlockSelection);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('h')].as_slice(), Command::SelectAll);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('l')].as_slice(), Command::SelectLine);
+        for c in "w\"'`b()[]{}".chars() {
+            keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('i'), Key::Char(c)].as_slice(),
+                             Command::SelectInside(c));
+            keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('a'), Key::Char(c)].as_slice(),
+                             Command::SelectAround(c));
+        }
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('=')].as_slice(), Command::ExpandSelection);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('-')].as_slice(), Command::ShrinkSelection);
+        keymap.bind_key(Key::Ctrl('c'), Command::Copy);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('x')].as_slice(), Command::Cut);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('v')].as_slice(), Command::Paste);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('v')].as_slice(), Command::PasteClipboard);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('V')].as_slice(), Command::PasteReindented);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('y')].as_slice(), Command::YankPop);
 
         // Editing
         keymap.bind_key(Key::Tab, Command::InsertTab);
-        keymap.bind_key(Key::Enter, Command::InsertChar('\n'));
+        keymap.bind_key(Key::BackTab, Command::DedentLine);
+        keymap.bind_key(Key::Enter, Command::InsertNewline);
         keymap.bind_key(Key::Backspace, Command::Delete(Direction::Left(1)));
         keymap.bind_key(Key::Ctrl('h'), Command::Delete(Direction::Left(1)));
         keymap.bind_key(Key::Delete, Command::Delete(Direction::Right(1)));
         keymap.bind_key(Key::Ctrl('d'), Command::Delete(Direction::Right(1)));
+        keymap.bind_key(Key::Ctrl('w'), Command::DeleteWord(Direction::Left(1)));
+        keymap.bind_key(Key::Ctrl('k'), Command::DeleteLine);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('d')].as_slice(), Command::DuplicateLine);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('j')].as_slice(), Command::JoinLines);
+        keymap.bind_key(Key::Ctrl('t'), Command::TransposeChars);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char(']')].as_slice(), Command::IndentLine);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('[')].as_slice(), Command::DedentLine);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char(';')].as_slice(), Command::ToggleComment);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Char('q')].as_slice(), Command::FillParagraph);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Up].as_slice(), Command::MoveLine(Direction::Up(1)));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Down].as_slice(), Command::MoveLine(Direction::Down(1)));
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('u')].as_slice(), Command::UpcaseWord);
+        keymap.bind_keys(vec![Key::Ctrl('x'), Key::Ctrl('l')].as_slice(), Command::DowncaseWord);
 
         // History
         keymap.bind_key(Key::Ctrl('y'), Command::Redo);
@@ -70,21 +138,73 @@ impl StandardMode {
     fn handle_command(&mut self, c: Command, view: &mut View) -> Response {
         match c {
             // Editor Commands
-            Command::ExitEditor      => return Response::Quit,
-            Command::SaveBuffer      => utils::save_buffer(&view.buffer),
+            Command::ExitEditor      => return view.quit(),
+            Command::SaveBuffer      => view.save_buffer(),
+            Command::RevertBuffer    => view.revert_buffer(),
+            Command::ToggleReadOnly  => view.toggle_read_only(),
 
             // Navigation
             Command::MoveCursor(dir) => view.move_cursor(dir),
             Command::LineEnd         => view.move_cursor_to_line_end(),
             Command::LineStart       => view.move_cursor_to_line_start(),
+            Command::PageUp          => view.page_up(),
+            Command::PageDown        => view.page_down(),
+            Command::AddCursorBelow  => view.add_cursor_below(),
+            Command::CollapseCursors => view.collapse_cursors(),
+            Command::ToggleSelection => view.toggle_selection(),
+            Command::ToggleBlockSelection => view.toggle_block_selection(),
+            Command::SelectAll       => view.select_all(),
+            Command::SelectLine      => view.select_line(),
+            Command::SelectInside(c) => view.select_object(c, false),
+            Command::SelectAround(c) => view.select_object(c, true),
+            Command::ExpandSelection => view.expand_selection(),
+            Command::ShrinkSelection => view.shrink_selection(),
+            Command::Copy            => view.copy(),
+            Command::Cut             => view.cut(),
+            Command::Paste           => view.paste(),
+            Command::PasteClipboard  => view.paste_from_clipboard(),
+            Command::PasteReindented => view.paste_reindented(),
+            Command::YankPop         => view.yank_pop(),
+            Command::GotoLine(n)     => view.goto_line(n),
+            Command::MatchBracket    => view.goto_matching_bracket(),
+            Command::Search          => view.start_search(true),
+            Command::ReverseSearch   => view.start_search(false),
+            Command::SearchWord(fwd) => view.search_word(fwd),
+            Command::ClearHighlight  => view.clear_highlight(),
+            Command::CountMatches    => view.count_matches(),
+            Command::CycleLineNumbers => view.cycle_line_numbers(),
+            Command::ToggleWrap      => view.toggle_wrap(),
+            Command::CycleTheme      => view.cycle_theme(),
+            Command::ToggleInvisibles => view.toggle_invisibles(),
+            Command::ToggleCurrentLine => view.toggle_current_line(),
 
             // Editing
-            Command::Delete(dir)     => view.delete_char(dir),
+            Command::Delete(dir)     => { view.delete_char(dir); }
+            Command::DeleteWord(dir) => view.delete_word(dir),
+            Command::DeleteLine      => view.delete_line(),
+            Command::DuplicateLine   => view.duplicate_line(),
+            Command::JoinLines       => view.join_lines(),
+            Command::TransposeChars  => view.transpose_chars(),
+            Command::UpcaseWord      => view.upcase_word(),
+            Command::DowncaseWord    => view.downcase_word(),
             Command::InsertTab       => view.insert_tab(),
+            Command::IndentLine      => view.indent_line(),
+            Command::DedentLine      => view.dedent_line(),
+            Command::ToggleComment   => view.toggle_comment(),
+            Command::FillParagraph   => view.fill_paragraph(),
+            Command::MoveLine(dir)   => view.move_line(dir),
+            Command::ExpandTabs(all) => view.expand_tabs(all),
+            Command::UnexpandTabs(all) => view.unexpand_tabs(all),
+            Command::SetLineEnding(e) => view.set_line_ending(e),
+            Command::ToggleBom       => view.toggle_bom(),
             Command::InsertChar(c)   => view.insert_char(c),
+            Command::InsertNewline   => view.insert_newline(),
             Command::Redo            => view.redo(),
             Command::Undo            => view.undo(),
 
+            // Prompt
+            Command::SetOverlay(o)   => view.set_overlay(o),
+
             _ => {},
         }
         Response::Continue
@@ -98,6 +218,20 @@ impl Mode for StandardMode {
             None => return EventStatus::NotHandled
         };
 
+        // if there is an overlay on the view, send the key there
+        // and don't allow the mode to handle it.
+        if let Some(overlay) = view.overlay.overlay_type() {
+            let event = view.handle_overlay_key(key);
+            if let OverlayEvent::Finished(response) = event {
+                view.overlay = Overlay::None;
+                match response {
+                    Some(data) => return EventStatus::Handled(self.interpret_input(overlay, data, view)),
+                    None       => view.cancel_overlay(overlay),
+                }
+            }
+            return EventStatus::NotHandled
+        }
+
         // send key to the keymap
         match self.keymap.check_key(key) {
             KeyMapState::Match(command) => {
@@ -121,7 +255,379 @@ impl Mode for StandardMode {
 
     }
 
-    fn interpret_input(&mut self, _input: String, _view: &mut View) -> Response {
+    fn interpret_input(&mut self, overlay: OverlayType, input: String, view: &mut View) -> Response {
+        match overlay {
+            OverlayType::SaveAs           => view.save_buffer_as(input.trim()),
+            OverlayType::OpenFile |
+            OverlayType::OpenRecent       => view.open_file(input.trim()),
+            OverlayType::ConfirmOverwrite => view.confirm_overwrite(input == "y"),
+            OverlayType::FileChanged      => view.answer_file_changed(&*input),
+            OverlayType::ConfirmRevert    => view.confirm_revert(input == "y"),
+            OverlayType::ConfirmReadOnly  => view.confirm_read_only(input == "y"),
+            OverlayType::ConfirmCreateDir => view.confirm_create_dir(input == "y"),
+            OverlayType::ConfirmRecover   => view.confirm_recover(input == "y"),
+            OverlayType::ConfirmBinary    => view.answer_binary(&*input),
+            OverlayType::ConfirmQuit      => return view.answer_quit(&*input),
+            OverlayType::ReplaceFrom      => view.ask_replacement(&*input),
+            OverlayType::ReplaceWith      => view.start_replace(&*input),
+            OverlayType::ConfirmReplace   => view.answer_replace(&*input),
+            OverlayType::ReplaceAll       => view.ask_replace_all_with(&*input),
+            OverlayType::ReplaceAllWith   => view.replace_all(&*input),
+            OverlayType::Grep             => view.ask_grep_dir(&*input),
+            OverlayType::GrepIn           => view.start_grep(input.trim()),
+            OverlayType::CountMatches     => view.count_matches_of(&*input),
+            _ => {
+                let command = Command::from_overlay(overlay, &*input);
+                return self.handle_command(command, view)
+            }
+        }
         Response::Continue
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{File, TempDir};
+
+    use input::Input;
+    use keyboard::Key;
+    use view::View;
+    use overlay::OverlayType;
+    use editor::EventStatus;
+    use recent::RecentFiles;
+    use super::super::Mode;
+    use super::StandardMode;
+    use Response;
+
+    fn type_keys(mode: &mut StandardMode, view: &mut View, keys: &[Key]) {
+        for &key in keys.iter() {
+            mode.handle_key_event(Some(key), view);
+        }
+    }
+
+    fn type_str(mode: &mut StandardMode, view: &mut View, s: &str) {
+        for ch in s.chars() {
+            mode.handle_key_event(Some(Key::Char(ch)), view);
+        }
+    }
+
+    fn quits(mode: &mut StandardMode, view: &mut View, key: Key) -> bool {
+        match mode.handle_key_event(Some(key), view) {
+            EventStatus::Handled(Response::Quit) => true,
+            _                                    => false,
+        }
+    }
+
+    #[test]
+    fn quitting_a_clean_buffer_needs_no_answer() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        assert!(quits(&mut mode, &mut view, Key::Ctrl('q')));
+    }
+
+    #[test]
+    fn cancelling_quit_goes_back_to_editing() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "abc");
+
+        assert!(!quits(&mut mode, &mut view, Key::Ctrl('q')));
+        assert!(view.overlay.overlay_type().is_some());
+        assert!(!quits(&mut mode, &mut view, Key::Char('c')));
+        assert!(view.overlay.overlay_type().is_none());
+
+        type_str(&mut mode, &mut view, "d");
+        assert_eq!(view.buffer.lines().next().unwrap(), b"abcd");
+
+        assert!(!quits(&mut mode, &mut view, Key::Ctrl('q')));
+        assert!(quits(&mut mode, &mut view, Key::Char('q')));
+    }
+
+    #[test]
+    fn save_and_quit() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("quit.txt");
+        File::create(&path).write_str("old").unwrap();
+        let name = String::from_str(path.as_str().unwrap());
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(Some(name)), 50, 50);
+        type_str(&mut mode, &mut view, "x");
+
+        assert!(!quits(&mut mode, &mut view, Key::Ctrl('q')));
+        assert!(quits(&mut mode, &mut view, Key::Char('s')));
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"xold");
+    }
+
+    #[test]
+    fn save_and_quit_without_a_name_asks_for_one() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "x");
+
+        assert!(!quits(&mut mode, &mut view, Key::Ctrl('q')));
+        assert!(!quits(&mut mode, &mut view, Key::Char('s')));
+        match view.overlay.overlay_type() {
+            Some(OverlayType::SaveAs) => {}
+            other                     => panic!("expected the save as prompt, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn saving_untitled_buffer_prompts_for_a_name() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("new.txt");
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+
+        type_str(&mut mode, &mut view, "abc");
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('s')]);
+        type_str(&mut mode, &mut view, "zz");
+        type_keys(&mut mode, &mut view, &[Key::Backspace, Key::Backspace]);
+        type_str(&mut mode, &mut view, path.as_str().unwrap());
+        type_keys(&mut mode, &mut view, &[Key::Enter]);
+
+        assert!(view.overlay.overlay_type().is_none());
+        assert_eq!(view.buffer.file_path, Some(path.clone()));
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"abc");
+    }
+
+    #[test]
+    fn cancelling_save_as_writes_nothing() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Ctrl('w')]);
+        type_str(&mut mode, &mut view, "somewhere.txt");
+        type_keys(&mut mode, &mut view, &[Key::Esc]);
+
+        assert!(view.overlay.overlay_type().is_none());
+        assert!(view.buffer.file_path.is_none());
+    }
+
+    #[test]
+    fn overwriting_a_file_needs_confirmation() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("existing.txt");
+        File::create(&path).write_str("old").unwrap();
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "new");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Ctrl('w')]);
+        type_str(&mut mode, &mut view, path.as_str().unwrap());
+        type_keys(&mut mode, &mut view, &[Key::Enter, Key::Char('n')]);
+        assert!(view.buffer.file_path.is_none());
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"old");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Ctrl('w')]);
+        type_str(&mut mode, &mut view, path.as_str().unwrap());
+        type_keys(&mut mode, &mut view, &[Key::Enter, Key::Char('y')]);
+        assert_eq!(view.buffer.file_path, Some(path.clone()));
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"new");
+    }
+
+    #[test]
+    fn saving_over_a_changed_file_asks_first() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("changed.txt");
+        File::create(&path).write_str("old").unwrap();
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(Some(String::from_str(path.as_str().unwrap()))),
+                                 50, 50);
+        type_str(&mut mode, &mut view, "x");
+        File::create(&path).write_str("changed").unwrap();
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('s'), Key::Char('c')]);
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"changed");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('s'), Key::Char('r')]);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"changed");
+
+        type_str(&mut mode, &mut view, "x");
+        File::create(&path).write_str("changed again").unwrap();
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('s'), Key::Char('o')]);
+        assert_eq!(File::open(&path).read_to_end().unwrap(), b"cxhanged");
+    }
+
+    #[test]
+    fn opening_a_binary_file_asks_what_to_do() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("a.out");
+        File::create(&path).write(b"\x7fELF\x02\x01\x01\x00\x00\x00").unwrap();
+        let name = String::from_str(path.as_str().unwrap());
+        let mut mode = StandardMode::new();
+
+        let mut view = View::new(Input::Filename(Some(name.clone())), 50, 50);
+        type_keys(&mut mode, &mut view, &[Key::Char('h')]);
+        assert!(view.buffer.hex_view && view.buffer.read_only);
+        type_str(&mut mode, &mut view, "x");
+        assert!(view.buffer.lines().next().unwrap().starts_with(b"00000000  7f 45 4c 46"));
+
+        let mut view = View::new(Input::Filename(Some(name.clone())), 50, 50);
+        type_keys(&mut mode, &mut view, &[Key::Char('o')]);
+        assert!(!view.buffer.hex_view);
+        assert_eq!(view.buffer.file_path, Some(path.clone()));
+
+        let mut view = View::new(Input::Filename(Some(name)), 50, 50);
+        type_keys(&mut mode, &mut view, &[Key::Char('c')]);
+        assert!(view.buffer.file_path.is_none());
+        assert_eq!(view.buffer.len(), 1);
+    }
+
+    #[test]
+    fn opening_a_file_with_a_swap_file_offers_recovery() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("notes.txt");
+        File::create(&path).write_str("old").unwrap();
+        File::create(&dir.path().join(".notes.txt.swp")).write_str("unsaved").unwrap();
+        let name = String::from_str(path.as_str().unwrap());
+        let mut mode = StandardMode::new();
+
+        let mut view = View::new(Input::Filename(Some(name.clone())), 50, 50);
+        type_keys(&mut mode, &mut view, &[Key::Char('y')]);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"unsaved");
+        assert!(view.buffer.is_dirty());
+
+        File::create(&dir.path().join(".notes.txt.swp")).write_str("unsaved").unwrap();
+        let mut view = View::new(Input::Filename(Some(name)), 50, 50);
+        type_keys(&mut mode, &mut view, &[Key::Char('n')]);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"old");
+        assert_eq!(File::open(&dir.path().join(".notes.txt.swp")).read_to_end().unwrap(), b"old");
+    }
+
+    #[test]
+    fn opening_a_file_completes_its_path() {
+        let dir = TempDir::new("iota").unwrap();
+        File::create(&dir.path().join("notes.txt")).write_str("notes").unwrap();
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Ctrl('f')]);
+        type_str(&mut mode, &mut view, format!("{}/no", dir.path().display())[]);
+        type_keys(&mut mode, &mut view, &[Key::Tab, Key::Enter]);
+
+        assert!(view.overlay.overlay_type().is_none());
+        assert_eq!(view.buffer.file_path, Some(dir.path().join("notes.txt")));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"notes");
+    }
+
+    #[test]
+    fn opening_a_file_keeps_unsaved_changes() {
+        let dir = TempDir::new("iota").unwrap();
+        File::create(&dir.path().join("notes.txt")).write_str("notes").unwrap();
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "abc");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Ctrl('f')]);
+        type_str(&mut mode, &mut view, dir.path().join("notes.txt").as_str().unwrap());
+        type_keys(&mut mode, &mut view, &[Key::Enter]);
+
+        assert!(view.buffer.file_path.is_none());
+        assert_eq!(view.buffer.lines().next().unwrap(), b"abc");
+    }
+
+    #[test]
+    fn open_recent_offers_the_previous_file() {
+        let dir = TempDir::new("iota").unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        File::create(&a).write_str("a").unwrap();
+        File::create(&b).write_str("b").unwrap();
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(Some(String::from_str(a.as_str().unwrap()))), 50, 50);
+        view.set_recent_files(RecentFiles::new());
+
+        view.open_file(b.as_str().unwrap());
+        assert_eq!(view.buffer.file_path, Some(b.clone()));
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Ctrl('r'), Key::Enter]);
+        assert_eq!(view.buffer.file_path, Some(a.clone()));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"a");
+    }
+
+    #[test]
+    fn query_replace_asks_about_each_match() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "a b a b a");
+        type_keys(&mut mode, &mut view, &[Key::Home]);
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Char('s')]);
+        type_str(&mut mode, &mut view, "a");
+        type_keys(&mut mode, &mut view, &[Key::Enter]);
+        type_str(&mut mode, &mut view, "aa");
+        type_keys(&mut mode, &mut view, &[Key::Enter, Key::Char('y'), Key::Char('n'), Key::Char('y')]);
+
+        assert!(view.overlay.overlay_type().is_none());
+        assert_eq!(view.buffer.lines().next().unwrap(), b"aa b a b aa");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('z')]);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"a b a b a");
+    }
+
+    #[test]
+    fn query_replace_can_stop_or_replace_the_rest() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "x x x");
+        type_keys(&mut mode, &mut view, &[Key::Home]);
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Char('s'), Key::Char('x'), Key::Enter,
+                                          Key::Char('y'), Key::Enter, Key::Char('y'), Key::Esc]);
+        assert!(view.overlay.overlay_type().is_none());
+        assert_eq!(view.buffer.lines().next().unwrap(), b"y x x");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Char('s'), Key::Char('x'), Key::Enter,
+                                          Key::Char('z'), Key::Enter, Key::Char('a')]);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"y z z");
+    }
+
+    #[test]
+    fn replace_all_asks_for_the_pattern_and_replacement() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "a b a b a");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Char('S'), Key::Char('b'), Key::Enter,
+                                          Key::Char('c'), Key::Enter]);
+        assert!(view.overlay.overlay_type().is_none());
+        assert_eq!(view.buffer.lines().next().unwrap(), b"a c a c a");
+    }
+
+    #[test]
+    fn prompts_recall_earlier_entries() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "abc");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('g'), Key::Char('2'), Key::Enter]);
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('g'), Key::Char('3'), Key::Enter]);
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('g'), Key::Up, Key::Up]);
+        assert_eq!(view.overlay.input(), "2");
+        type_keys(&mut mode, &mut view, &[Key::Up, Key::Down]);
+        assert_eq!(view.overlay.input(), "3");
+        type_keys(&mut mode, &mut view, &[Key::Down]);
+        assert_eq!(view.overlay.input(), "");
+        type_keys(&mut mode, &mut view, &[Key::Up, Key::Backspace, Key::Char('4'), Key::Enter]);
+        assert_eq!(view.histories().goto_line.entries(),
+                   [String::from_str("4"), String::from_str("3"), String::from_str("2")][]);
+    }
+
+    #[test]
+    fn tab_indents_the_selected_lines_and_back_tab_dedents_them() {
+        let mut mode = StandardMode::new();
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        type_str(&mut mode, &mut view, "a");
+        type_keys(&mut mode, &mut view, &[Key::Enter]);
+        type_str(&mut mode, &mut view, "b");
+
+        type_keys(&mut mode, &mut view, &[Key::Ctrl('x'), Key::Char('h'), Key::Tab]);
+        let lines: Vec<&[u8]> = view.buffer.lines().collect();
+        assert_eq!(lines, vec![b"    a\n"[], b"    b"[]]);
+
+        type_keys(&mut mode, &mut view, &[Key::BackTab]);
+        let lines: Vec<&[u8]> = view.buffer.lines().collect();
+        assert_eq!(lines, vec![b"a\n"[], b"b"[]]);
+    }
+}
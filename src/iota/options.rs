@@ -0,0 +1,96 @@
+use buffer::{Backup, Case};
+use view::LineNumbers;
+use status::{StatusLine, DEFAULT_FORMAT};
+use theme::Theme;
+
+/// User configurable editor behaviour.
+///
+/// Every option has a default chosen in `Options::new`, which is what the
+/// editor starts with.
+pub struct Options {
+    /// Remove trailing whitespace from every line when the buffer is saved.
+    pub trim_trailing_whitespace: bool,
+    /// Write a newline at the end of a file that doesn't end with one, when saving it.
+    pub final_newline: bool,
+    /// Where to copy a file to before the first save of the session overwrites it.
+    pub backup: Backup,
+    /// Write a buffer with unsaved changes to its `#name#` autosave file after this many seconds
+    /// without a keypress. Off if None.
+    pub autosave_interval: Option<uint>,
+    /// Keep the text of an open file in a `.name.swp` file beside it, to recover it from if the
+    /// editor dies.
+    pub swap_file: bool,
+    /// The number of edits after which the swap file is written again.
+    pub swap_edits: uint,
+    /// The number of columns between tab stops.
+    pub tab_width: uint,
+    /// Indent with a tab rather than `tab_width` spaces.
+    pub indent_with_tabs: bool,
+    /// Make Tab indent the current line, rather than insert at the cursor.
+    pub tab_indents_line: bool,
+    /// Make line start go to the first non-whitespace character, then toggle between it and
+    /// column 0.
+    pub smart_home: bool,
+    /// The width that paragraphs are filled to.
+    pub text_width: uint,
+    /// Start new lines with the indentation of the line they were split from.
+    pub auto_indent: bool,
+    /// Insert the closing bracket or quote along with an opening one.
+    pub auto_pair: bool,
+    /// How searches start out matching case, which can be changed while searching.
+    pub search_case: Case,
+    /// Leave the view where it is when selecting the whole buffer, rather than scrolling to the
+    /// cursor at its end.
+    pub select_all_keeps_view: bool,
+    /// Which numbers to show beside the lines, in a column to the left of the text.
+    pub line_numbers: LineNumbers,
+    /// Show lines too long for the screen on as many rows as they take, rather than scrolling
+    /// sideways to the rest of them.
+    pub wrap: bool,
+    /// What the status line shows, and in what order.
+    pub status_line: StatusLine,
+    /// Take a message away after this many seconds without a key, if an autosave isn't due
+    /// first. Messages stay until the next key if None.
+    pub message_timeout: Option<uint>,
+    /// The colors everything is drawn in.
+    pub theme: Theme,
+    /// Mark tabs and non-breaking spaces, and the whitespace at the ends of lines, in the faces
+    /// the theme has for them.
+    pub show_invisibles: bool,
+    /// Drawn after the end of each line while invisible characters are shown, such as `¶` or
+    /// `$`. Nothing is if None.
+    pub line_end_mark: Option<char>,
+    /// Draw the screen row the cursor is on, across the whole window, in the face the theme has
+    /// for it.
+    pub highlight_current_line: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            trim_trailing_whitespace: false,
+            backup: Backup::Off,
+            final_newline: true,
+            autosave_interval: None,
+            swap_file: true,
+            swap_edits: 20,
+            tab_width: 4,
+            auto_indent: true,
+            auto_pair: true,
+            indent_with_tabs: false,
+            tab_indents_line: false,
+            smart_home: true,
+            text_width: 79,
+            search_case: Case::Smart,
+            select_all_keeps_view: true,
+            line_numbers: LineNumbers::Absolute,
+            wrap: false,
+            status_line: StatusLine::parse(DEFAULT_FORMAT),
+            message_timeout: Some(5),
+            theme: Theme::dark(),
+            show_invisibles: false,
+            line_end_mark: None,
+            highlight_current_line: false,
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use uibuf::UIBuffer;
 use keyboard::Key;
 use frontends::Frontend;
+use completion;
 
 
 /// State for the overlay
@@ -12,6 +13,61 @@ pub enum OverlayEvent {
 #[derive(Copy, Show)]
 pub enum OverlayType {
     Prompt,
+    GotoLine,
+    SaveAs,
+    /// Answered by a single keypress rather than a line of input.
+    ConfirmOverwrite,
+    /// Answered by a single keypress too, like the ones after it.
+    FileChanged,
+    ConfirmRevert,
+    ConfirmReadOnly,
+    ConfirmCreateDir,
+    ConfirmRecover,
+    ConfirmBinary,
+    ConfirmQuit,
+    ConfirmReplace,
+    /// Answered with a path, which Tab completes.
+    OpenFile,
+    /// Answered with a path too, which Tab first picks from the files opened recently.
+    OpenRecent,
+    /// Answered with the text to replace, and then what to replace it with.
+    ReplaceFrom,
+    ReplaceWith,
+    ReplaceAll,
+    ReplaceAllWith,
+    /// Answered with the pattern to search files for, and then the directory to search.
+    Grep,
+    GrepIn,
+    /// Answered with the text to count the occurrences of.
+    CountMatches,
+}
+
+impl OverlayType {
+    /// Whether the overlay finishes on the first character typed.
+    pub fn is_confirmation(&self) -> bool {
+        match *self {
+            OverlayType::ConfirmOverwrite |
+            OverlayType::FileChanged |
+            OverlayType::ConfirmRevert |
+            OverlayType::ConfirmReadOnly |
+            OverlayType::ConfirmCreateDir |
+            OverlayType::ConfirmRecover |
+            OverlayType::ConfirmBinary |
+            OverlayType::ConfirmQuit |
+            OverlayType::ConfirmReplace   => true,
+            _                             => false,
+        }
+    }
+
+    /// Whether Tab completes the input as a path.
+    pub fn completes_paths(&self) -> bool {
+        match *self {
+            OverlayType::OpenFile |
+            OverlayType::OpenRecent |
+            OverlayType::GrepIn     => true,
+            _                       => false,
+        }
+    }
 }
 
 
@@ -19,20 +75,85 @@ pub enum OverlayType {
 ///
 /// This can be a prompt, autocompletion list, anything thatn requires input
 /// from the user.
+///
+/// A prompt reads a line of input on the bottom row, after its prefix. Keys edit the input at
+/// the prompt's own cursor, which Left, Right, Home and End move within it, and Ctrl-U clears
+/// it. Enter finishes with the input, and Esc finishes with nothing.
 pub enum Overlay {
     Prompt {
-        cursor_x: uint,
+        cursor: uint,                               // byte offset of the cursor in the input
         data: String,
         prefix: &'static str,
+        kind: OverlayType,
+        completion: Option<(Vec<String>, uint)>,    // matches being cycled through with Tab,
+                                                    // and which is in the input now
     },
 
     None,
 }
 
+// The width of the input as the prompt draws it, counting what it can't draw as nothing.
+fn str_width(s: &str) -> uint {
+    s.chars().filter_map(|c| c.width(false)).fold(0, |width, w| width + w)
+}
+
 impl Overlay {
+    /// A prompt of the given type, showing `prefix` before its empty input.
+    pub fn prompt(kind: OverlayType, prefix: &'static str) -> Overlay {
+        Overlay::Prompt {
+            cursor: 0,
+            data: String::new(),
+            prefix: prefix,
+            kind: kind,
+            completion: None,
+        }
+    }
+
+    /// The type of the overlay being shown, if any.
+    pub fn overlay_type(&self) -> Option<OverlayType> {
+        match self {
+            &Overlay::Prompt {kind, ..} => Some(kind),
+            _                           => None,
+        }
+    }
+
+    /// Fill the prompt's input with the first of `choices`, which Tab then cycles through.
+    pub fn offer(&mut self, choices: Vec<String>) {
+        if let Some(choice) = choices.first() {
+            self.set_input(choice[]);
+        }
+        match self {
+            &Overlay::Prompt {ref mut completion, ..} => {
+                if choices.len() > 1 { *completion = Some((choices, 0)) }
+            }
+
+            _ => {}
+        }
+    }
+
+    /// What has been typed into the prompt.
+    pub fn input(&self) -> &str {
+        match self {
+            &Overlay::Prompt {ref data, ..} => data[],
+            _                               => "",
+        }
+    }
+
+    /// Replace what has been typed into the prompt, with the cursor after it.
+    pub fn set_input(&mut self, input: &str) {
+        match self {
+            &Overlay::Prompt {ref mut cursor, ref mut data, ..} => {
+                *data = String::from_str(input);
+                *cursor = data.len();
+            }
+
+            _ => {}
+        }
+    }
+
     pub fn draw<F: Frontend>(&self, frontend: &mut F, uibuf: &mut UIBuffer) {
         match self {
-            &Overlay::Prompt {prefix, ref data, ..} => {
+            &Overlay::Prompt {prefix, ref data, ref completion, ..} => {
                 let height = frontend.get_window_height() - 1;
                 let offset = prefix.len();
 
@@ -46,6 +167,19 @@ impl Overlay {
                     uibuf.update_cell_content(index + offset, height, ch);
                 }
 
+                // and the other matches it could be completed to, by name
+                if let &Some((ref matches, _)) = completion {
+                    let names: Vec<&str> = matches.iter().map(|m| {
+                        let m = m.trim_right_matches('/');
+                        m.slice_from(m.rfind('/').map_or(0, |i| i + 1))
+                    }).collect();
+                    let list = format!("  [{}]", names.connect(" "));
+                    let start = offset + data.chars().count();
+                    for (index, ch) in list.chars().enumerate() {
+                        uibuf.update_cell_content(start + index, height, ch);
+                    }
+                }
+
                 uibuf.draw_range(frontend, height, height+1);
             }
 
@@ -55,11 +189,12 @@ impl Overlay {
 
     pub fn draw_cursor<F: Frontend>(&mut self, frontend: &mut F) {
         match self {
-            &Overlay::Prompt {cursor_x, ..} => {
+            &Overlay::Prompt {cursor, prefix, ref data, ..} => {
                 // Prompt is always on the bottom, so we can use the
                 // height given by the frontend here
                 let height = frontend.get_window_height() - 1;
-                frontend.draw_cursor(cursor_x as int, height as int)
+                let x = str_width(prefix) + str_width(data[..cursor]);
+                frontend.draw_cursor(x as int, height as int)
             },
 
             _ => {}
@@ -68,25 +203,60 @@ impl Overlay {
 
     pub fn handle_key_event(&mut self, key: Key) -> OverlayEvent {
         match self {
-            &Overlay::Prompt {ref mut cursor_x, ref mut data, ..} => {
+            &Overlay::Prompt {ref mut cursor, ref mut data, kind, completion: ref mut cycle, ..} => {
+                if key != Key::Tab { *cycle = None }
                 match key {
+                    Key::Tab if kind.completes_paths() => {
+                        let (i, matches) = match cycle.take() {
+                            Some((matches, i)) => ((i + 1) % matches.len(), matches),
+                            None               => (0, completion::complete_path(data[])),
+                        };
+                        if let Some(choice) = matches.get(i).map(|m| m.clone()) {
+                            *data = choice;
+                            *cursor = data.len();
+                        }
+                        // a single match is done with, so the next Tab completes from it
+                        if matches.len() > 1 { *cycle = Some((matches, i)) }
+                    }
+                    Key::Char(c) if kind.is_confirmation() => {
+                        return OverlayEvent::Finished(Some(c.to_string()))
+                    }
                     Key::Esc => return OverlayEvent::Finished(None),
                     Key::Backspace => {
-                        if let Some(c) = data.pop() {
-                            if let Some(width) = c.width(false) {
-                                *cursor_x -= width;
-                            }
+                        let before = data[..*cursor].char_indices().next_back().map(|(i, _)| i);
+                        if let Some(start) = before {
+                            data.remove(start);
+                            *cursor = start;
+                        }
+                    }
+                    Key::Delete => {
+                        if *cursor < data.len() { data.remove(*cursor); }
+                    }
+                    Key::Left => {
+                        if let Some((i, _)) = data[..*cursor].char_indices().next_back() {
+                            *cursor = i;
+                        }
+                    }
+                    Key::Right => {
+                        if let Some(ch) = data[*cursor..].chars().next() {
+                            *cursor += ch.len_utf8();
                         }
                     }
+                    Key::Home => *cursor = 0,
+                    Key::End => *cursor = data.len(),
+                    Key::Ctrl('u') => {
+                        data.clear();
+                        *cursor = 0;
+                    }
                     Key::Enter => {
                         // FIXME: dont clone
                         let data = data.clone();
                         return OverlayEvent::Finished(Some(data))
                     }
                     Key::Char(c) => {
-                        if let Some(width) = c.width(false) {
-                            data.push(c);
-                            *cursor_x += width;
+                        if c.width(false).is_some() {
+                            data.insert(*cursor, c);
+                            *cursor += c.len_utf8();
                         }
                     }
                     _ => {}
@@ -98,3 +268,76 @@ impl Overlay {
         OverlayEvent::Ok
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use overlay::{Overlay, OverlayType, OverlayEvent};
+    use keyboard::Key;
+
+    // What the prompt finishes with after `keys`, or the input so far if it hasn't finished.
+    fn type_keys(overlay: &mut Overlay, keys: &[Key]) -> Result<Option<String>, String> {
+        for &key in keys.iter() {
+            if let OverlayEvent::Finished(result) = overlay.handle_key_event(key) {
+                return Ok(result)
+            }
+        }
+        Err(String::from_str(overlay.input()))
+    }
+
+    fn chars(text: &str) -> Vec<Key> {
+        text.chars().map(|c| Key::Char(c)).collect()
+    }
+
+    #[test]
+    fn test_enter_accepts_what_was_typed() {
+        let mut prompt = Overlay::prompt(OverlayType::SaveAs, "Save as: ");
+        let mut keys = chars("notes.txt");
+        keys.push(Key::Enter);
+        assert_eq!(type_keys(&mut prompt, keys[]), Ok(Some(String::from_str("notes.txt"))));
+    }
+
+    #[test]
+    fn test_esc_cancels() {
+        let mut prompt = Overlay::prompt(OverlayType::GotoLine, "Go to line: ");
+        let mut keys = chars("12");
+        keys.push(Key::Esc);
+        assert_eq!(type_keys(&mut prompt, keys[]), Ok(None));
+    }
+
+    #[test]
+    fn test_editing_at_the_cursor() {
+        let mut prompt = Overlay::prompt(OverlayType::OpenFile, "Open: ");
+        let mut keys = chars("mainrs");
+        keys.extend([Key::Left, Key::Left, Key::Char('.'), Key::Home, Key::Char('/'),
+                     Key::Delete, Key::End, Key::Backspace, Key::Char('s')].iter().map(|&k| k));
+        assert_eq!(type_keys(&mut prompt, keys[]), Err(String::from_str("/ain.rs")));
+
+        assert_eq!(type_keys(&mut prompt, &[Key::Ctrl('u')]), Err(String::new()));
+        let keys = [Key::Backspace, Key::Left, Key::Delete, Key::Char('a'), Key::Enter];
+        assert_eq!(type_keys(&mut prompt, &keys), Ok(Some(String::from_str("a"))));
+    }
+
+    #[test]
+    fn test_cursor_moves_over_whole_characters() {
+        let mut prompt = Overlay::prompt(OverlayType::Prompt, ":");
+        let mut keys = chars("caf\u{e9}");
+        keys.extend([Key::Left, Key::Char('x'), Key::Right, Key::Right, Key::Char('!'),
+                     Key::Left, Key::Left, Key::Backspace, Key::Enter].iter().map(|&k| k));
+        assert_eq!(type_keys(&mut prompt, keys[]), Ok(Some(String::from_str("caf\u{e9}!"))));
+    }
+
+    #[test]
+    fn test_set_input_puts_the_cursor_after_it() {
+        let mut prompt = Overlay::prompt(OverlayType::Prompt, ":");
+        prompt.set_input("wq");
+        assert_eq!(type_keys(&mut prompt, &[Key::Char('!'), Key::Enter]),
+                   Ok(Some(String::from_str("wq!"))));
+    }
+
+    #[test]
+    fn test_confirmations_finish_on_a_key() {
+        let mut prompt = Overlay::prompt(OverlayType::ConfirmRevert, "Discard? ");
+        assert_eq!(type_keys(&mut prompt, &[Key::Char('y')]), Ok(Some(String::from_str("y"))));
+    }
+}
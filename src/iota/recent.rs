@@ -0,0 +1,166 @@
+use std::os;
+use std::io::{fs, File, IoResult, USER_RWX};
+use std::io::fs::PathExtensions;
+
+/// How many files the list keeps.
+const MAX_FILES: uint = 20;
+
+/// The files most recently opened or saved, most recent first.
+///
+/// It is kept in a file of one absolute path per line. Lines that aren't absolute paths are
+/// skipped, and files that no longer exist are only dropped once the list is looked at.
+pub struct RecentFiles {
+    files: Vec<Path>,
+}
+
+impl RecentFiles {
+    pub fn new() -> RecentFiles {
+        RecentFiles { files: Vec::new() }
+    }
+
+    /// Read the list kept at `path`. A missing or unreadable file gives an empty list.
+    pub fn load(path: &Path) -> RecentFiles {
+        let contents = match File::open(path).read_to_string() {
+            Ok(contents) => contents,
+            Err(_)       => return RecentFiles::new(),
+        };
+        let mut recent = RecentFiles::new();
+        for line in contents.lines() {
+            if !line.starts_with("/") { continue }
+            let path = Path::new(line);
+            if recent.files.len() < MAX_FILES && !recent.files.contains(&path) {
+                recent.files.push(path);
+            }
+        }
+        recent
+    }
+
+    /// Write the list to `path`, creating the directory it is in if need be.
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        try!(fs::mkdir_recursive(&path.dir_path(), USER_RWX));
+        let mut file = try!(File::create(path));
+        for file_path in self.files.iter() {
+            try!(file.write_line(format!("{}", file_path.display())[]));
+        }
+        Ok(())
+    }
+
+    /// Put the file at `path` at the top of the list, taking it out of wherever it was before.
+    pub fn add(&mut self, path: &Path) {
+        let path = os::make_absolute(path).unwrap_or_else(|_| path.clone());
+        self.files.retain(|p| *p != path);
+        self.files.insert(0, path);
+        self.files.truncate(MAX_FILES);
+    }
+
+    /// The files in the list that still exist, most recent first.
+    pub fn files(&mut self) -> &[Path] {
+        self.files.retain(|p| p.exists());
+        self.files[]
+    }
+
+    /// The most recent file in the list that still exists.
+    pub fn most_recent(&mut self) -> Option<&Path> {
+        self.files().first()
+    }
+}
+
+/// `~/.iota/recent`, where the list is kept. None if there is no home directory.
+pub fn recent_path() -> Option<Path> {
+    os::homedir().map(|home| home.join(".iota").join("recent"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{File, TempDir};
+
+    use recent::{RecentFiles, MAX_FILES};
+
+    fn touch(dir: &TempDir, name: &str) -> Path {
+        let path = dir.path().join(name);
+        File::create(&path).write_str(name).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_most_recent_first() {
+        let dir = TempDir::new("iota").unwrap();
+        let (a, b, c) = (touch(&dir, "a"), touch(&dir, "b"), touch(&dir, "c"));
+        let mut recent = RecentFiles::new();
+        recent.add(&a);
+        recent.add(&b);
+        recent.add(&c);
+        assert_eq!(recent.files(), [c.clone(), b.clone(), a.clone()][]);
+        assert_eq!(recent.most_recent(), Some(&c));
+    }
+
+    #[test]
+    fn test_duplicates_move_to_the_top() {
+        let dir = TempDir::new("iota").unwrap();
+        let (a, b) = (touch(&dir, "a"), touch(&dir, "b"));
+        let mut recent = RecentFiles::new();
+        recent.add(&a);
+        recent.add(&b);
+        recent.add(&a);
+        assert_eq!(recent.files(), [a.clone(), b.clone()][]);
+    }
+
+    #[test]
+    fn test_missing_files_are_pruned() {
+        let dir = TempDir::new("iota").unwrap();
+        let kept = touch(&dir, "kept");
+        let mut recent = RecentFiles::new();
+        recent.add(&kept);
+        recent.add(&dir.path().join("deleted"));
+        assert_eq!(recent.files.len(), 2);
+        assert_eq!(recent.most_recent(), Some(&kept));
+        assert_eq!(recent.files.len(), 1);
+    }
+
+    #[test]
+    fn test_keeps_a_limited_number_of_files() {
+        let mut recent = RecentFiles::new();
+        for i in range(0, MAX_FILES + 5) {
+            recent.add(&Path::new(format!("/tmp/file{}", i)));
+        }
+        assert_eq!(recent.files.len(), MAX_FILES);
+        assert_eq!(recent.files[0], Path::new(format!("/tmp/file{}", MAX_FILES + 4)));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join(".iota").join("recent");
+        let (a, b) = (touch(&dir, "a"), touch(&dir, "b"));
+        let mut recent = RecentFiles::new();
+        recent.add(&a);
+        recent.add(&b);
+        recent.save(&path).unwrap();
+
+        let mut loaded = RecentFiles::load(&path);
+        assert_eq!(loaded.files(), [b.clone(), a.clone()][]);
+    }
+
+    #[test]
+    fn test_corrupt_list_is_ignored() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("recent");
+        File::create(&path).write(b"\xff\xfe garbage\n").unwrap();
+        assert_eq!(RecentFiles::load(&path).files.len(), 0);
+
+        File::create(&path).write_str("/home/user/a.txt\n\
+                                       relative.txt\n\
+                                       \n\
+                                       /home/user/a.txt\n\
+                                       /home/user/b.txt\n").unwrap();
+        let recent = RecentFiles::load(&path);
+        assert_eq!(recent.files, vec![Path::new("/home/user/a.txt"), Path::new("/home/user/b.txt")]);
+    }
+
+    #[test]
+    fn test_missing_list_is_empty() {
+        let dir = TempDir::new("iota").unwrap();
+        assert_eq!(RecentFiles::load(&dir.path().join("nothing")).files.len(), 0);
+    }
+}
@@ -0,0 +1,199 @@
+use buffer::Buffer;
+
+/// Steps through the matches of a pattern in a buffer, from some point to its end or to the end
+/// of a range, replacing the ones it is told to.
+///
+/// The search for the next match carries on after the last replacement, so text that a
+/// replacement put in is never matched again, even when it contains the pattern.
+pub struct QueryReplace {
+    pattern: String,
+    replacement: String,
+    next: uint,                     // where the next match is looked for
+    end: uint,                      // where matches must end by, moved as replacements change
+                                    // the length of the text before it
+    current: Option<(uint, uint)>,  // start and end of the match waiting to be decided on
+    last_replaced: Option<uint>,    // end of the last replacement made
+    replaced: uint,
+    revision: uint,                 // log revision before the first replacement
+}
+
+impl QueryReplace {
+    pub fn new(buffer: &Buffer, pattern: &str, replacement: &str, from: uint) -> QueryReplace {
+        QueryReplace::within(buffer, pattern, replacement, from, buffer.len())
+    }
+
+    /// Step through only the matches lying wholly within [start, end).
+    pub fn within(buffer: &Buffer, pattern: &str, replacement: &str, start: uint, end: uint) -> QueryReplace {
+        QueryReplace {
+            pattern: String::from_str(pattern),
+            replacement: String::from_str(replacement),
+            next: start,
+            end: end,
+            current: None,
+            last_replaced: None,
+            replaced: 0,
+            revision: buffer.log.revision(),
+        }
+    }
+
+    /// Find the next match to decide on. None once there are no more.
+    pub fn next_match(&mut self, buffer: &Buffer) -> Option<(uint, uint)> {
+        let (len, end) = (self.pattern.len(), self.end);
+        self.current = buffer.find(self.pattern[], self.next).and_then(|start| {
+            if start + len <= end { Some((start, start + len)) } else { None }
+        });
+        self.current
+    }
+
+    /// The match waiting to be decided on.
+    pub fn current(&self) -> Option<(uint, uint)> {
+        self.current
+    }
+
+    /// Replace the current match, and carry on from the end of the replacement.
+    pub fn replace(&mut self, buffer: &mut Buffer) {
+        if let Some((start, end)) = self.current.take() {
+            if buffer.replace_range(start, end, self.replacement[]).is_some() {
+                self.end = self.end - (end - start) + self.replacement.len();
+                self.next = start + self.replacement.len();
+                self.last_replaced = Some(self.next);
+                self.replaced += 1;
+            }
+        }
+    }
+
+    /// Leave the current match as it is, and carry on from its end.
+    pub fn skip(&mut self) {
+        if let Some((_, end)) = self.current.take() {
+            self.next = end;
+        }
+    }
+
+    /// Replace the current match and every one after it.
+    pub fn replace_remaining(&mut self, buffer: &mut Buffer) {
+        while self.current.is_some() || self.next_match(buffer).is_some() {
+            self.replace(buffer);
+        }
+    }
+
+    /// Stop replacing, making all the replacements a single step to undo. Gives the number of
+    /// replacements, and the end of the last one.
+    pub fn finish(self, buffer: &mut Buffer) -> (uint, Option<uint>) {
+        buffer.log.group_since(self.revision);
+        (self.replaced, self.last_replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::BufReader;
+
+    use buffer::Buffer;
+    use replace::QueryReplace;
+
+    fn setup_buffer(text: &str) -> Buffer {
+        Buffer::new_from_reader(BufReader::new(text.as_bytes()))
+    }
+
+    fn text(buffer: &Buffer) -> String {
+        let lines: Vec<String> = buffer.lines().map(|line| String::from_utf8_lossy(line).into_owned())
+                                       .collect();
+        lines.concat()
+    }
+
+    #[test]
+    fn test_replace_and_skip() {
+        let mut buffer = setup_buffer("cat cat cat");
+        let mut replace = QueryReplace::new(&buffer, "cat", "dog", 0);
+        assert_eq!(replace.next_match(&buffer), Some((0, 3)));
+        replace.replace(&mut buffer);
+        assert_eq!(replace.next_match(&buffer), Some((4, 7)));
+        replace.skip();
+        assert_eq!(replace.next_match(&buffer), Some((8, 11)));
+        replace.replace(&mut buffer);
+        assert_eq!(replace.next_match(&buffer), None);
+
+        assert_eq!(replace.finish(&mut buffer), (2, Some(11)));
+        assert_eq!(text(&buffer), "dog cat dog");
+    }
+
+    #[test]
+    fn test_starts_from_the_given_point() {
+        let mut buffer = setup_buffer("a a a");
+        let mut replace = QueryReplace::new(&buffer, "a", "b", 1);
+        replace.next_match(&buffer);
+        replace.replace_remaining(&mut buffer);
+        assert_eq!(replace.finish(&mut buffer), (2, Some(5)));
+        assert_eq!(text(&buffer), "a b b");
+    }
+
+    #[test]
+    fn test_replacement_containing_the_pattern_is_not_rescanned() {
+        let mut buffer = setup_buffer("x x");
+        let mut replace = QueryReplace::new(&buffer, "x", "xx", 0);
+        replace.next_match(&buffer);
+        replace.replace_remaining(&mut buffer);
+        assert_eq!(replace.finish(&mut buffer), (2, Some(5)));
+        assert_eq!(text(&buffer), "xx xx");
+    }
+
+    #[test]
+    fn test_replacements_undo_together() {
+        let mut buffer = setup_buffer("one two one two one");
+        let mut replace = QueryReplace::new(&buffer, "one", "1", 0);
+        replace.next_match(&buffer);
+        replace.replace_remaining(&mut buffer);
+        replace.finish(&mut buffer);
+        assert_eq!(text(&buffer), "1 two 1 two 1");
+
+        buffer.undo();
+        assert_eq!(text(&buffer), "one two one two one");
+        buffer.redo();
+        assert_eq!(text(&buffer), "1 two 1 two 1");
+    }
+
+    #[test]
+    fn test_within_a_range_that_grows() {
+        let mut buffer = setup_buffer("a a a a");
+        let mut replace = QueryReplace::within(&buffer, "a", "bbb", 2, 5);
+        replace.next_match(&buffer);
+        replace.replace_remaining(&mut buffer);
+        assert_eq!(replace.finish(&mut buffer), (2, Some(9)));
+        assert_eq!(text(&buffer), "a bbb bbb a");
+    }
+
+    #[test]
+    fn test_within_a_range_that_shrinks() {
+        let mut buffer = setup_buffer("xx xx xx xx");
+        let mut replace = QueryReplace::within(&buffer, "xx", "", 0, 8);
+        assert_eq!(replace.next_match(&buffer), Some((0, 2)));
+        replace.replace(&mut buffer);
+        assert_eq!(replace.next_match(&buffer), Some((1, 3)));
+        replace.skip();
+        assert_eq!(replace.next_match(&buffer), Some((4, 6)));
+        replace.replace(&mut buffer);
+        assert_eq!(replace.next_match(&buffer), None);
+        assert_eq!(replace.finish(&mut buffer), (2, Some(4)));
+        assert_eq!(text(&buffer), " xx  xx");
+    }
+
+    #[test]
+    fn test_match_across_the_end_of_the_range_is_skipped() {
+        let mut buffer = setup_buffer("ab ab ab");
+        let mut replace = QueryReplace::within(&buffer, "ab", "x", 0, 4);
+        replace.next_match(&buffer);
+        replace.replace_remaining(&mut buffer);
+        assert_eq!(replace.finish(&mut buffer), (1, Some(1)));
+        assert_eq!(text(&buffer), "x ab ab");
+    }
+
+    #[test]
+    fn test_no_matches() {
+        let mut buffer = setup_buffer("abc");
+        let mut replace = QueryReplace::new(&buffer, "x", "y", 0);
+        assert_eq!(replace.next_match(&buffer), None);
+        assert_eq!(replace.finish(&mut buffer), (0, None));
+        assert!(!buffer.is_dirty());
+    }
+}
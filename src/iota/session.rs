@@ -0,0 +1,210 @@
+use std::os;
+use std::io::{fs, File, IoResult, USER_RWX};
+use std::io::fs::PathExtensions;
+
+use buffer::resolve_links;
+
+/// How many files a session remembers positions in.
+const MAX_FILES: uint = 100;
+
+/// Where the cursor and the top of the screen were in a file, as 0-based lines and the byte
+/// column of the cursor in its line.
+#[derive(Copy, PartialEq, Show)]
+pub struct Position {
+    pub line: uint,
+    pub col: uint,
+    pub top_line: uint,
+}
+
+/// The files the editor has had open, most recent first, with where it was in each of them.
+///
+/// It is kept in a file of one line per file: the position's numbers and then the absolute
+/// path, separated by tabs. Lines that can't be read are skipped, so a corrupt session file
+/// just forgets what it can't make sense of.
+pub struct Session {
+    entries: Vec<(Path, Position)>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session { entries: Vec::new() }
+    }
+
+    /// Read the session kept at `path`. A missing or unreadable file gives an empty session.
+    pub fn load(path: &Path) -> Session {
+        let contents = match File::open(path).read_to_string() {
+            Ok(contents) => contents,
+            Err(_)       => return Session::new(),
+        };
+        let mut session = Session::new();
+        for line in contents.lines() {
+            if let Some(entry) = parse_entry(line) {
+                if session.entries.len() < MAX_FILES { session.entries.push(entry) }
+            }
+        }
+        session
+    }
+
+    /// Write the session to `path`, creating the directory it is in if need be.
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        try!(fs::mkdir_recursive(&path.dir_path(), USER_RWX));
+        let mut file = try!(File::create(path));
+        for &(ref file_path, pos) in self.entries.iter() {
+            try!(file.write_line(format!("{}\t{}\t{}\t{}", pos.line, pos.col, pos.top_line,
+                                         file_path.display())[]));
+        }
+        Ok(())
+    }
+
+    /// Where the editor was in the file at `path` when it last had it open.
+    pub fn position(&self, path: &Path) -> Option<Position> {
+        let path = canonical(path);
+        self.entries.iter().find(|&&(ref p, _)| *p == path).map(|&(_, pos)| pos)
+    }
+
+    /// Remember the position in the file at `path`, as the file most recently open.
+    pub fn remember(&mut self, path: &Path, position: Position) {
+        let path = canonical(path);
+        self.entries.retain(|&(ref p, _)| *p != path);
+        self.entries.insert(0, (path, position));
+        self.entries.truncate(MAX_FILES);
+    }
+
+    /// The file most recently open that still exists.
+    pub fn last_file(&self) -> Option<&Path> {
+        self.entries.iter().map(|&(ref p, _)| p).find(|p| p.exists())
+    }
+}
+
+/// `~/.iota/session`, where the session is kept. None if there is no home directory.
+pub fn session_path() -> Option<Path> {
+    os::homedir().map(|home| home.join(".iota").join("session"))
+}
+
+fn parse_entry(line: &str) -> Option<(Path, Position)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 4 || !fields[3].starts_with("/") { return None }
+    match (fields[0].parse(), fields[1].parse(), fields[2].parse()) {
+        (Some(line), Some(col), Some(top_line)) => {
+            Some((Path::new(fields[3]), Position { line: line, col: col, top_line: top_line }))
+        }
+        _ => None,
+    }
+}
+
+// Files are remembered by their absolute path, with every symbolic link on the way to them
+// followed, so that it doesn't matter where the editor was started from or which link a file
+// was opened through. Each directory is resolved before the next part is added to it, so that a
+// `..` in a link is taken from where the link really is.
+fn canonical(path: &Path) -> Path {
+    let absolute = os::make_absolute(path).unwrap_or_else(|_| path.clone());
+    let mut canonical = Path::new("/");
+    for component in absolute.components() {
+        canonical = resolve_links(&canonical.join(component));
+    }
+    canonical
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{fs, File, TempDir, USER_RWX};
+
+    use session::{Session, Position, MAX_FILES};
+
+    fn at(line: uint) -> Position {
+        Position { line: line, col: 2, top_line: 0 }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join(".iota").join("session");
+        let mut session = Session::new();
+        session.remember(&Path::new("/home/user/notes.txt"), at(12));
+        session.remember(&Path::new("/home/user/todo.txt"), Position { line: 3, col: 0, top_line: 1 });
+        session.save(&path).unwrap();
+
+        let loaded = Session::load(&path);
+        assert_eq!(loaded.position(&Path::new("/home/user/notes.txt")), Some(at(12)));
+        assert_eq!(loaded.position(&Path::new("/home/user/todo.txt")),
+                   Some(Position { line: 3, col: 0, top_line: 1 }));
+        assert_eq!(loaded.position(&Path::new("/home/user/other.txt")), None);
+    }
+
+    #[test]
+    fn test_most_recent_first() {
+        let dir = TempDir::new("iota").unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        File::create(&a).write_str("a").unwrap();
+        File::create(&b).write_str("b").unwrap();
+
+        let mut session = Session::new();
+        session.remember(&a, at(1));
+        session.remember(&b, at(2));
+        assert_eq!(session.last_file(), Some(&b));
+        session.remember(&a, at(3));
+        assert_eq!(session.last_file(), Some(&a));
+        assert_eq!(session.position(&a), Some(at(3)));
+        assert_eq!(session.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_files_are_remembered_by_where_links_lead() {
+        let dir = TempDir::new("iota").unwrap();
+        let real = dir.path().join("real");
+        fs::mkdir(&real, USER_RWX).unwrap();
+        File::create(&real.join("notes.txt")).write_str("notes").unwrap();
+        fs::symlink(&real, &dir.path().join("link")).unwrap();
+
+        let mut session = Session::new();
+        session.remember(&dir.path().join("link").join("notes.txt"), at(4));
+        assert_eq!(session.position(&real.join("notes.txt")), Some(at(4)));
+        assert_eq!(session.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_last_file_skips_missing_files() {
+        let dir = TempDir::new("iota").unwrap();
+        let kept = dir.path().join("kept.txt");
+        File::create(&kept).write_str("kept").unwrap();
+
+        let mut session = Session::new();
+        session.remember(&kept, at(1));
+        session.remember(&dir.path().join("deleted.txt"), at(2));
+        assert_eq!(session.last_file(), Some(&kept));
+    }
+
+    #[test]
+    fn test_remembers_a_limited_number_of_files() {
+        let mut session = Session::new();
+        for i in range(0, MAX_FILES + 10) {
+            session.remember(&Path::new(format!("/tmp/file{}", i)), at(i));
+        }
+        assert_eq!(session.entries.len(), MAX_FILES);
+        assert_eq!(session.position(&Path::new("/tmp/file0")), None);
+    }
+
+    #[test]
+    fn test_corrupt_session_is_ignored() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("session");
+        File::create(&path).write(b"\xff\xfe garbage\n").unwrap();
+        assert_eq!(Session::load(&path).entries.len(), 0);
+
+        File::create(&path).write_str("1\t2\t0\t/home/user/ok.txt\n\
+                                       not a line\n\
+                                       x\t2\t0\t/home/user/bad.txt\n\
+                                       1\t2\t0\trelative.txt\n").unwrap();
+        let session = Session::load(&path);
+        assert_eq!(session.entries.len(), 1);
+        assert_eq!(session.position(&Path::new("/home/user/ok.txt")),
+                   Some(Position { line: 1, col: 2, top_line: 0 }));
+    }
+
+    #[test]
+    fn test_missing_session_is_empty() {
+        let dir = TempDir::new("iota").unwrap();
+        assert_eq!(Session::load(&dir.path().join("nothing")).entries.len(), 0);
+    }
+}
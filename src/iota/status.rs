@@ -0,0 +1,178 @@
+/// The format the status line has unless the options give it another.
+pub const DEFAULT_FORMAT: &'static str = "%f%m [%e] %l:%c  %p%%  %L lines  %k%r%i";
+
+/// What the status line can tell about the buffer being edited.
+pub struct Status<'a> {
+    pub name: &'a str,          // The file's path, or what the buffer is if it has none.
+    pub dirty: bool,
+    pub line: uint,             // The cursor's line and column, counted from 0.
+    pub col: uint,
+    pub lines: uint,
+    pub line_ending: &'a str,   // The line ending's name, with whatever else it is worth knowing.
+    pub kind: &'a str,          // "[binary]" or "[hex]" for a buffer that isn't plain text.
+    pub read_only: bool,
+    pub invisibles: bool,       // Whether invisible characters are being shown.
+}
+
+/// A piece of the status line.
+#[derive(Clone, PartialEq, Show)]
+pub enum Field {
+    Text(String),   // Shown as it is.
+    Name,           // %f
+    Modified,       // %m: "*" while there are unsaved changes
+    Line,           // %l
+    Column,         // %c
+    Percent,        // %p: how far through the file the cursor's line is
+    Lines,          // %L
+    LineEnding,     // %e
+    Kind,           // %k
+    ReadOnly,       // %r: "[read-only]" if the buffer is
+    Invisibles,     // %i: "[invisibles]" while they are shown
+}
+
+/// The layout of the status line, parsed from a format string such as `"%f%m  %l:%c  %p%%"`.
+///
+/// Each `%` and the letter after it is replaced by a field of the status, as `Field` lists
+/// them, and `%%` by a `%`. Anything else, including a `%` before a letter that isn't a field,
+/// is shown as it is.
+#[derive(Clone, PartialEq, Show)]
+pub struct StatusLine {
+    fields: Vec<Field>,
+}
+
+impl StatusLine {
+    pub fn parse(format: &str) -> StatusLine {
+        let mut fields = Vec::new();
+        let mut text = String::new();
+        let mut chars = format.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                text.push(ch);
+                continue
+            }
+            let field = match chars.next() {
+                Some('f') => Field::Name,
+                Some('m') => Field::Modified,
+                Some('l') => Field::Line,
+                Some('c') => Field::Column,
+                Some('p') => Field::Percent,
+                Some('L') => Field::Lines,
+                Some('e') => Field::LineEnding,
+                Some('k') => Field::Kind,
+                Some('r') => Field::ReadOnly,
+                Some('i') => Field::Invisibles,
+                Some('%') => { text.push('%'); continue }
+                Some(other) => { text.push('%'); text.push(other); continue }
+                None => { text.push('%'); break }
+            };
+            if !text.is_empty() {
+                fields.push(Field::Text(text));
+                text = String::new();
+            }
+            fields.push(field);
+        }
+        if !text.is_empty() { fields.push(Field::Text(text)) }
+        StatusLine { fields: fields }
+    }
+
+    /// The text of the status line for `status`.
+    pub fn format(&self, status: &Status) -> String {
+        let mut line = String::new();
+        for field in self.fields.iter() {
+            match *field {
+                Field::Text(ref text) => line.push_str(text[]),
+                Field::Name           => line.push_str(status.name),
+                Field::Modified       => if status.dirty { line.push('*') },
+                Field::Line           => line.push_str(format!("{}", status.line + 1)[]),
+                Field::Column         => line.push_str(format!("{}", status.col + 1)[]),
+                Field::Percent        => line.push_str(format!("{}", percent(status))[]),
+                Field::Lines          => line.push_str(format!("{}", status.lines)[]),
+                Field::LineEnding     => line.push_str(status.line_ending),
+                Field::Kind           => line.push_str(status.kind),
+                Field::ReadOnly       => if status.read_only { line.push_str("[read-only]") },
+                Field::Invisibles     => if status.invisibles { line.push_str("[invisibles]") },
+            }
+        }
+        line
+    }
+}
+
+// As far through as the cursor's line goes, so the last line is 100%.
+fn percent(status: &Status) -> uint {
+    if status.lines == 0 { return 100 }
+    (status.line + 1) * 100 / status.lines
+}
+
+#[cfg(test)]
+mod tests {
+
+    use status::{StatusLine, Status, Field, DEFAULT_FORMAT};
+
+    fn status() -> Status<'static> {
+        Status {
+            name: "src/main.rs",
+            dirty: false,
+            line: 9,
+            col: 4,
+            lines: 40,
+            line_ending: "LF",
+            kind: "",
+            read_only: false,
+            invisibles: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_fields_and_text() {
+        let line = StatusLine::parse("%f %m  %l:%c  %p%%");
+        assert_eq!(line.fields, vec![
+            Field::Name, Field::Text(String::from_str(" ")), Field::Modified,
+            Field::Text(String::from_str("  ")), Field::Line, Field::Text(String::from_str(":")),
+            Field::Column, Field::Text(String::from_str("  ")), Field::Percent,
+            Field::Text(String::from_str("%")),
+        ]);
+    }
+
+    #[test]
+    fn test_unknown_fields_are_shown_as_they_are() {
+        let line = StatusLine::parse("%x 100% %");
+        assert_eq!(line.fields, vec![Field::Text(String::from_str("%x 100% %"))]);
+        assert_eq!(line.format(&status()), "%x 100% %");
+    }
+
+    #[test]
+    fn test_format_fills_in_the_fields() {
+        let line = StatusLine::parse("%f%m  %l:%c  %p%% of %L [%e]");
+        assert_eq!(line.format(&status()), "src/main.rs  10:5  25% of 40 [LF]");
+
+        let modified = Status { dirty: true, ..status() };
+        assert_eq!(line.format(&modified), "src/main.rs*  10:5  25% of 40 [LF]");
+    }
+
+    #[test]
+    fn test_fields_can_be_rearranged() {
+        let line = StatusLine::parse("%L lines, at %c on %l of %f");
+        assert_eq!(line.format(&status()), "40 lines, at 5 on 10 of src/main.rs");
+    }
+
+    #[test]
+    fn test_percent_through_the_file() {
+        let line = StatusLine::parse("%p");
+        assert_eq!(line.format(&Status { line: 0, ..status() }), "2");
+        assert_eq!(line.format(&Status { line: 39, ..status() }), "100");
+        assert_eq!(line.format(&Status { line: 0, lines: 1, ..status() }), "100");
+        assert_eq!(line.format(&Status { line: 0, lines: 0, ..status() }), "100");
+    }
+
+    #[test]
+    fn test_default_format() {
+        let line = StatusLine::parse(DEFAULT_FORMAT);
+        assert_eq!(line.format(&status()), "src/main.rs [LF] 10:5  25%  40 lines  ");
+
+        let hex = Status { kind: "[hex]", read_only: true, ..status() };
+        assert_eq!(line.format(&hex), "src/main.rs [LF] 10:5  25%  40 lines  [hex][read-only]");
+
+        let invisibles = Status { invisibles: true, ..status() };
+        assert_eq!(line.format(&invisibles), "src/main.rs [LF] 10:5  25%  40 lines  [invisibles]");
+    }
+}
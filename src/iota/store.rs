@@ -0,0 +1,150 @@
+use libc;
+use gapbuffer::GapBuffer;
+
+use std::mem;
+use std::raw;
+use std::ops::Index;
+use std::ffi::CString;
+use std::iter::Extend;
+use std::os::{MemoryMap, MapOption};
+use std::io::{IoError, IoResult, OtherIoError};
+
+/// The bytes of a text, which everything that reads a buffer's text is written against, so
+/// that it reads a file mapped into memory as it reads one loaded for editing.
+pub trait TextStore: Index<uint, Output=u8> {
+    /// The number of bytes in the text.
+    fn len(&self) -> uint;
+}
+
+impl TextStore for GapBuffer<u8> {
+    fn len(&self) -> uint {
+        self.len()
+    }
+}
+
+/// A file's bytes mapped into memory as they are on disk, which are read in only as they are
+/// looked at, so that a file too large to load can be viewed at once.
+///
+/// Nothing else may shorten the file while it is mapped, as reading past its new end kills the
+/// editor.
+pub struct MappedFile {
+    map: MemoryMap,
+    len: uint,
+}
+
+impl MappedFile {
+    /// Maps the `len` bytes the file at `path` holds. The file need stay open only while it is
+    /// mapped.
+    pub fn open(path: &Path, len: uint) -> IoResult<MappedFile> {
+        let name = CString::from_slice(path.as_vec());
+        let fd = unsafe { libc::open(name.as_ptr(), libc::O_RDONLY, 0) };
+        if fd < 0 { return Err(IoError::last_error()) }
+
+        let map = MemoryMap::new(len, &[MapOption::MapReadable, MapOption::MapFd(fd)]);
+        unsafe { libc::close(fd); }
+        match map {
+            Ok(map) => Ok(MappedFile { map: map, len: len }),
+            Err(e) => Err(IoError {
+                kind: OtherIoError,
+                desc: "couldn't map the file into memory",
+                detail: Some(e.to_string()),
+            }),
+        }
+    }
+
+    /// All the bytes of the file.
+    pub fn as_slice<'a>(&'a self) -> &'a [u8] {
+        unsafe {
+            mem::transmute(raw::Slice { data: self.map.data() as *const u8, len: self.len })
+        }
+    }
+}
+
+impl Index<uint> for MappedFile {
+    type Output = u8;
+
+    fn index<'a>(&'a self, idx: &uint) -> &'a u8 {
+        &self.as_slice()[*idx]
+    }
+}
+
+impl TextStore for MappedFile {
+    fn len(&self) -> uint {
+        self.len
+    }
+}
+
+/// A buffer's text, which is mapped from a file until the first edit reads it into memory.
+pub enum Text {
+    Loaded(GapBuffer<u8>),
+    Mapped(MappedFile),
+}
+
+impl Text {
+    /// An empty text, loaded for editing.
+    pub fn new() -> Text {
+        Text::Loaded(GapBuffer::new())
+    }
+
+    /// Whether the text is still read straight from the file.
+    pub fn is_mapped(&self) -> bool {
+        match *self {
+            Text::Mapped(_) => true,
+            Text::Loaded(_) => false,
+        }
+    }
+
+    /// The text to edit, read into memory first if it was mapped. Its bytes are copied as they
+    /// are, so that every index into the text stays where it was.
+    pub fn edit(&mut self) -> &mut GapBuffer<u8> {
+        let loaded = match *self {
+            Text::Mapped(ref file) => {
+                let mut gap = GapBuffer::with_capacity(file.len());
+                gap.extend(file.as_slice().iter().map(|&byte| byte));
+                Some(gap)
+            }
+            Text::Loaded(_) => None,
+        };
+        if let Some(gap) = loaded { *self = Text::Loaded(gap) }
+        match *self {
+            Text::Loaded(ref mut gap) => gap,
+            Text::Mapped(_) => unreachable!(),
+        }
+    }
+
+    /// All the bytes of the text.
+    pub fn as_slice<'a>(&'a self) -> &'a [u8] {
+        match *self {
+            Text::Loaded(ref gap) => gap[],
+            Text::Mapped(ref file) => file.as_slice(),
+        }
+    }
+
+    /// The bytes of the text from `idx` on.
+    pub fn slice_from<'a>(&'a self, idx: uint) -> &'a [u8] {
+        match *self {
+            Text::Loaded(ref gap) => gap[idx..],
+            Text::Mapped(ref file) => file.as_slice()[idx..],
+        }
+    }
+}
+
+impl Index<uint> for Text {
+    type Output = u8;
+
+    fn index<'a>(&'a self, idx: &uint) -> &'a u8 {
+        match *self {
+            Text::Loaded(ref gap) => &gap[*idx],
+            Text::Mapped(ref file) => &file[*idx],
+        }
+    }
+}
+
+impl TextStore for Text {
+    fn len(&self) -> uint {
+        match *self {
+            Text::Loaded(ref gap) => gap.len(),
+            Text::Mapped(ref file) => file.len,
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Coloring the text by its syntax: a tokenizer for each type of file, and the state of the
+//! constructs that run on from one line to the next.
+
+use std::cmp;
+use std::str;
+
+use buffer::Buffer;
+
+/// A kind of token that is drawn in its own colors. Anything else is drawn plain.
+#[derive(Copy, PartialEq, Show)]
+pub enum Token {
+    Keyword,
+    Comment,
+    String,         // String and character literals.
+    Number,
+}
+
+/// What a line starts inside of, left open by the lines before it.
+#[derive(Copy, PartialEq, Show)]
+pub enum State {
+    Code,
+    Comment(uint),      // A block comment, nested this deep.
+    String,             // A string, run on past the end of a line.
+    RawString(uint),    // A raw string, closed by a quote and this many #s.
+}
+
+/// The rules for tokenizing a type of file. Every one so far has C's comments, strings and
+/// character literals.
+#[derive(PartialEq, Show)]
+pub struct Syntax {
+    keywords: &'static [&'static str],
+    rust: bool,     // Nested block comments, raw and byte strings, lifetimes, strings that run
+                    // on over lines.
+}
+
+pub static RUST: Syntax = Syntax {
+    keywords: &["as", "box", "break", "const", "continue", "crate", "else", "enum", "extern",
+                "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+                "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+                "trait", "true", "type", "unsafe", "use", "where", "while"],
+    rust: true,
+};
+
+/// For anything with C's syntax, picking out the keywords of the common languages like it.
+pub static C_LIKE: Syntax = Syntax {
+    keywords: &["auto", "bool", "break", "case", "catch", "char", "class", "const", "continue",
+                "default", "delete", "do", "double", "else", "enum", "extends", "extern",
+                "false", "final", "float", "for", "func", "function", "goto", "if", "implements",
+                "import", "int", "interface", "long", "namespace", "new", "null", "package",
+                "private", "protected", "public", "register", "return", "short", "signed",
+                "sizeof", "static", "struct", "switch", "template", "this", "throw", "true",
+                "try", "typedef", "typename", "union", "unsigned", "var", "virtual", "void",
+                "volatile", "while"],
+    rust: false,
+};
+
+/// The tokens of `line` that aren't plain, as (start, end, token) byte ranges in order, and
+/// the state the next line starts in. `state` is the state that `line` starts in.
+pub fn tokenize(syntax: &Syntax, line: &[u8], state: State) -> (Vec<(uint, uint, Token)>, State) {
+    let line = match line.last() {
+        Some(&b'\n') => line[..line.len() - 1],
+        _            => line,
+    };
+    let len = line.len();
+    let mut tokens = Vec::new();
+    let mut state = state;
+    let mut start = 0;      // where the token being carried on with began
+    let mut i = 0;
+    loop {
+        // carry on with a token left open, from the line before or just now
+        match state {
+            State::Code => {}
+            State::Comment(depth) => {
+                let (end, depth) = comment_end(syntax, line, i, depth);
+                push(&mut tokens, start, end, Token::Comment);
+                if depth > 0 { return (tokens, State::Comment(depth)) }
+                i = end;
+            }
+            State::String => match quote_end(line, i, b'"') {
+                Some(end) => {
+                    push(&mut tokens, start, end, Token::String);
+                    i = end;
+                }
+                None => {
+                    push(&mut tokens, start, len, Token::String);
+                    // C's strings only go on over a line that ends with a backslash
+                    let on = syntax.rust || line.last() == Some(&b'\\');
+                    return (tokens, if on { State::String } else { State::Code })
+                }
+            },
+            State::RawString(hashes) => match raw_string_end(line, i, hashes) {
+                Some(end) => {
+                    push(&mut tokens, start, end, Token::String);
+                    i = end;
+                }
+                None => {
+                    push(&mut tokens, start, len, Token::String);
+                    return (tokens, State::RawString(hashes))
+                }
+            },
+        }
+        state = State::Code;
+
+        // and find the next token
+        while i < len && !starts_token(line[i]) { i += 1 }
+        if i >= len { return (tokens, State::Code) }
+        start = i;
+        let ch = line[i];
+        if line[i..].starts_with(b"//") {
+            push(&mut tokens, i, len, Token::Comment);
+            return (tokens, State::Code)
+        } else if line[i..].starts_with(b"/*") {
+            state = State::Comment(1);
+            i += 2;
+        } else if ch == b'/' {
+            i += 1;
+        } else if ch == b'"' {
+            state = State::String;
+            i += 1;
+        } else if ch == b'\'' {
+            i = match char_end(syntax, line, i) {
+                Some(end) => {
+                    push(&mut tokens, i, end, Token::String);
+                    end
+                }
+                // a lifetime, or a quote on its own
+                None => word_end(line, i + 1),
+            };
+        } else if is_digit(ch) {
+            i = number_end(line, i);
+            push(&mut tokens, start, i, Token::Number);
+        } else {
+            let end = word_end(line, i);
+            let word = line[i..end];
+            match prefixed_string(syntax, word, line[end..]) {
+                Some(Some(hashes)) => {
+                    state = State::RawString(hashes);
+                    i = end + hashes + 1;
+                }
+                Some(None) if line[end] == b'"' => {
+                    state = State::String;
+                    i = end + 1;
+                }
+                Some(None) => {
+                    i = match char_end(syntax, line, end) {
+                        Some(char_end) => {
+                            push(&mut tokens, start, char_end, Token::String);
+                            char_end
+                        }
+                        None => end,
+                    }
+                }
+                None => {
+                    if syntax.keywords.iter().any(|keyword| keyword.as_bytes() == word) {
+                        push(&mut tokens, start, end, Token::Keyword);
+                    }
+                    i = end;
+                }
+            }
+        }
+    }
+}
+
+/// The states that the lines of a buffer start in, as far down as they have been worked out.
+///
+/// The states are only worked out as far as the screen has needed them, and kept until a line
+/// above them is edited.
+pub struct Highlighter {
+    pub syntax: &'static Syntax,
+    states: Vec<State>,     // the state at the start of each line, from the first
+}
+
+impl Highlighter {
+    pub fn new(syntax: &'static Syntax) -> Highlighter {
+        Highlighter { syntax: syntax, states: vec![State::Code] }
+    }
+
+    /// The state that line `linenum` of `buffer` starts in.
+    pub fn state_at(&mut self, linenum: uint, buffer: &Buffer) -> State {
+        let known = self.states.len() - 1;
+        if linenum > known {
+            let mut state = self.states[known];
+            for line in buffer.lines().skip(known).take(linenum - known) {
+                state = tokenize(self.syntax, line, state).1;
+                self.states.push(state);
+            }
+        }
+        self.states[cmp::min(linenum, self.states.len() - 1)]
+    }
+
+    /// Forget the states of the lines after `linenum`, which has been edited.
+    pub fn invalidate(&mut self, linenum: uint) {
+        self.states.truncate(linenum + 1);
+    }
+}
+
+fn push(tokens: &mut Vec<(uint, uint, Token)>, start: uint, end: uint, token: Token) {
+    if end > start { tokens.push((start, end, token)) }
+}
+
+fn is_digit(ch: u8) -> bool {
+    ch >= b'0' && ch <= b'9'
+}
+
+// Bytes that can be in a word, counting anything past ASCII, so that no word is split.
+fn is_word(ch: u8) -> bool {
+    is_digit(ch) || (ch >= b'a' && ch <= b'z') || (ch >= b'A' && ch <= b'Z') || ch == b'_' ||
+        ch >= 0x80
+}
+
+fn starts_token(ch: u8) -> bool {
+    is_word(ch) || ch == b'/' || ch == b'"' || ch == b'\''
+}
+
+fn word_end(line: &[u8], start: uint) -> uint {
+    let mut i = start;
+    while i < line.len() && is_word(line[i]) { i += 1 }
+    i
+}
+
+// A number goes on over letters, as for suffixes and hex digits, and over a point between
+// digits, but not over the two points of a range.
+fn number_end(line: &[u8], start: uint) -> uint {
+    let mut i = start;
+    while i < line.len() {
+        if is_word(line[i]) {
+            i += 1;
+        } else if line[i] == b'.' && i + 1 < line.len() && is_digit(line[i + 1]) {
+            i += 2;
+        } else {
+            break
+        }
+    }
+    i
+}
+
+// Just past the end of a block comment that the text from `start` is `depth` deep inside, and
+// how deep it is still at the end of the line if it doesn't end on it.
+fn comment_end(syntax: &Syntax, line: &[u8], start: uint, depth: uint) -> (uint, uint) {
+    let mut depth = depth;
+    let mut i = start;
+    while i < line.len() {
+        if line[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 { return (i, 0) }
+        } else if syntax.rust && line[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    (line.len(), depth)
+}
+
+// Just past the `quote` that ends a literal that the text from `start` is inside, going over
+// the characters escaped with backslashes.
+fn quote_end(line: &[u8], start: uint, quote: u8) -> Option<uint> {
+    let mut i = start;
+    while i < line.len() {
+        if line[i] == b'\\' {
+            i += 2;
+        } else if line[i] == quote {
+            return Some(i + 1)
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+fn raw_string_end(line: &[u8], start: uint, hashes: uint) -> Option<uint> {
+    let mut i = start;
+    while i < line.len() {
+        if line[i] == b'"' && line[i + 1..].len() >= hashes &&
+           line[i + 1..i + 1 + hashes].iter().all(|&ch| ch == b'#') {
+            return Some(i + 1 + hashes)
+        }
+        i += 1;
+    }
+    None
+}
+
+// Just past the end of the character literal at the quote at `start`, if it is one. In Rust
+// a quote is as often the start of a lifetime, and is only taken as a character if it closes
+// after one character or an escape.
+fn char_end(syntax: &Syntax, line: &[u8], start: uint) -> Option<uint> {
+    if !syntax.rust { return Some(quote_end(line, start + 1, b'\'').unwrap_or(line.len())) }
+    let next = start + 1;
+    if next >= line.len() { return None }
+    if line[next] == b'\\' { return quote_end(line, next, b'\'') }
+    // the bytes of a UTF-8 character go on while they are continuation bytes
+    let mut close = next + 1;
+    while close < line.len() && line[close] & 0xC0 == 0x80 { close += 1 }
+    if close < line.len() && line[close] == b'\'' && line[next] != b'\'' { Some(close + 1) }
+    else { None }
+}
+
+// Whether `word` is the prefix of a string or character in Rust, as b"", b'', r"", r#""#, br"",
+// going by `rest`, the text after it. Some(Some(hashes)) for a raw string, and Some(None) for a
+// byte string or character.
+fn prefixed_string(syntax: &Syntax, word: &[u8], rest: &[u8]) -> Option<Option<uint>> {
+    if !syntax.rust { return None }
+    let next = match rest.first() {
+        Some(&ch) => ch,
+        None      => return None,
+    };
+    match str::from_utf8(word) {
+        Ok("b") if next == b'"' || next == b'\'' => Some(None),
+        Ok("r") | Ok("br") => {
+            let hashes = rest.iter().take_while(|&&ch| ch == b'#').count();
+            if rest.get(hashes) == Some(&b'"') { Some(Some(hashes)) } else { None }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use syntax::{tokenize, Highlighter, Syntax, State, Token, RUST, C_LIKE};
+    use buffer::Buffer;
+    use std::io::BufReader;
+
+    // The text of each token of `line`, with the state after it.
+    fn tokens(syntax: &Syntax, line: &str, state: State) -> (Vec<(String, Token)>, State) {
+        let (tokens, state) = tokenize(syntax, line.as_bytes(), state);
+        (tokens.into_iter().map(|(start, end, token)| {
+            (String::from_str(line[start..end]), token)
+        }).collect(), state)
+    }
+
+    fn rust(line: &str) -> Vec<(String, Token)> {
+        let (tokens, state) = tokens(&RUST, line, State::Code);
+        assert_eq!(state, State::Code);
+        tokens
+    }
+
+    fn t(text: &str, token: Token) -> (String, Token) {
+        (String::from_str(text), token)
+    }
+
+    #[test]
+    fn test_keywords_and_numbers() {
+        assert_eq!(rust("let x = 42;\n"), vec![t("let", Token::Keyword), t("42", Token::Number)]);
+        assert_eq!(rust("pub fn letter(self)"), vec![t("pub", Token::Keyword),
+                                                     t("fn", Token::Keyword),
+                                                     t("self", Token::Keyword)]);
+        assert_eq!(rust("x1 + 0xff_u8 * 1.5e3"), vec![t("0xff_u8", Token::Number),
+                                                      t("1.5e3", Token::Number)]);
+        assert_eq!(rust("for i in 0..10 {"), vec![t("for", Token::Keyword), t("in", Token::Keyword),
+                                                  t("0", Token::Number), t("10", Token::Number)]);
+        assert_eq!(rust("t.0"), vec![t("0", Token::Number)]);
+    }
+
+    #[test]
+    fn test_words_past_ascii_are_not_split() {
+        assert_eq!(rust("caf\u{e9}if as"), vec![t("as", Token::Keyword)]);
+    }
+
+    #[test]
+    fn test_line_comments() {
+        assert_eq!(rust("let a; // let b\n"), vec![t("let", Token::Keyword),
+                                                   t("// let b", Token::Comment)]);
+        assert_eq!(rust("/// docs"), vec![t("/// docs", Token::Comment)]);
+        assert_eq!(rust("a / b"), vec![]);
+        assert_eq!(rust("\"//\" if"), vec![t("\"//\"", Token::String), t("if", Token::Keyword)]);
+    }
+
+    #[test]
+    fn test_strings_and_escapes() {
+        assert_eq!(rust("f(\"a \\\"quoted\\\" if\", 2)"),
+                   vec![t("\"a \\\"quoted\\\" if\"", Token::String), t("2", Token::Number)]);
+        assert_eq!(rust("\"\\\\\" else"), vec![t("\"\\\\\"", Token::String),
+                                               t("else", Token::Keyword)]);
+    }
+
+    #[test]
+    fn test_characters_and_lifetimes() {
+        assert_eq!(rust("'a' 'static"), vec![t("'a'", Token::String)]);
+        assert_eq!(rust("fn f<'a>(x: &'a str)"), vec![t("fn", Token::Keyword)]);
+        assert_eq!(rust("'\\n' '\\'' '\u{e9}'"), vec![t("'\\n'", Token::String),
+                                                     t("'\\''", Token::String),
+                                                     t("'\u{e9}'", Token::String)]);
+        assert_eq!(rust("b'x' b\"bytes\""), vec![t("b'x'", Token::String),
+                                                t("b\"bytes\"", Token::String)]);
+        assert_eq!(tokens(&C_LIKE, "'ab' x", State::Code).0, vec![t("'ab'", Token::String)]);
+    }
+
+    #[test]
+    fn test_block_comments_run_on() {
+        assert_eq!(tokens(&RUST, "a /* if\n", State::Code),
+                   (vec![t("/* if", Token::Comment)], State::Comment(1)));
+        assert_eq!(tokens(&RUST, "still */ if\n", State::Comment(1)),
+                   (vec![t("still */", Token::Comment), t("if", Token::Keyword)], State::Code));
+        assert_eq!(tokens(&RUST, "/* a */ 1 /* b */", State::Code),
+                   (vec![t("/* a */", Token::Comment), t("1", Token::Number),
+                         t("/* b */", Token::Comment)], State::Code));
+        assert_eq!(tokens(&RUST, "\n", State::Comment(2)), (vec![], State::Comment(2)));
+    }
+
+    #[test]
+    fn test_block_comments_nest_in_rust_only() {
+        assert_eq!(tokens(&RUST, "/* /* */ if", State::Code),
+                   (vec![t("/* /* */ if", Token::Comment)], State::Comment(1)));
+        assert_eq!(tokens(&RUST, "*/ */ if", State::Comment(2)),
+                   (vec![t("*/ */", Token::Comment), t("if", Token::Keyword)], State::Code));
+        assert_eq!(tokens(&C_LIKE, "/* /* */ if", State::Code),
+                   (vec![t("/* /* */", Token::Comment), t("if", Token::Keyword)], State::Code));
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(rust("r\"a\\\" if"), vec![t("r\"a\\\"", Token::String), t("if", Token::Keyword)]);
+        assert_eq!(rust("r#\"say \"hi\"\"# if"), vec![t("r#\"say \"hi\"\"#", Token::String),
+                                                      t("if", Token::Keyword)]);
+        assert_eq!(tokens(&RUST, "br##\"a\"#\n", State::Code),
+                   (vec![t("br##\"a\"#", Token::String)], State::RawString(2)));
+        assert_eq!(tokens(&RUST, "b\"##  if", State::RawString(2)),
+                   (vec![t("b\"##", Token::String), t("if", Token::Keyword)], State::Code));
+        // words that only start like raw strings
+        assert_eq!(rust("r + br#x"), vec![]);
+    }
+
+    #[test]
+    fn test_strings_run_on_over_lines() {
+        assert_eq!(tokens(&RUST, "let s = \"one\n", State::Code),
+                   (vec![t("let", Token::Keyword), t("\"one", Token::String)], State::String));
+        assert_eq!(tokens(&RUST, "two\" if\n", State::String),
+                   (vec![t("two\"", Token::String), t("if", Token::Keyword)], State::Code));
+        // in C only after a backslash
+        assert_eq!(tokens(&C_LIKE, "s = \"one\n", State::Code).1, State::Code);
+        assert_eq!(tokens(&C_LIKE, "s = \"one\\\n", State::Code).1, State::String);
+    }
+
+    #[test]
+    fn test_c_like_keywords() {
+        assert_eq!(tokens(&C_LIKE, "int main(void) { return 0; }", State::Code),
+                   (vec![t("int", Token::Keyword), t("void", Token::Keyword),
+                         t("return", Token::Keyword), t("0", Token::Number)], State::Code));
+        assert_eq!(tokens(&C_LIKE, "let fn", State::Code).0, vec![]);
+    }
+
+    #[test]
+    fn test_highlighter_carries_states_down() {
+        let buffer = Buffer::new_from_reader(BufReader::new(b"a\n/* one\ntwo\n*/ b\nc\n"));
+        let mut highlighter = Highlighter::new(&RUST);
+        assert_eq!(highlighter.state_at(4, &buffer), State::Code);
+        assert_eq!(highlighter.state_at(2, &buffer), State::Comment(1));
+        assert_eq!(highlighter.state_at(0, &buffer), State::Code);
+        // past the last line is as the last line leaves it
+        assert_eq!(highlighter.state_at(20, &buffer), State::Code);
+    }
+
+    #[test]
+    fn test_highlighter_works_out_edited_lines_again() {
+        let mut buffer = Buffer::new_from_reader(BufReader::new(b"a\nb\nc\n"));
+        let mut highlighter = Highlighter::new(&RUST);
+        assert_eq!(highlighter.state_at(2, &buffer), State::Code);
+
+        buffer.replace_range(2, 2, "/*");
+        highlighter.invalidate(buffer.take_edited_line().unwrap());
+        assert_eq!(highlighter.state_at(2, &buffer), State::Comment(1));
+        assert_eq!(buffer.take_edited_line(), None);
+    }
+}
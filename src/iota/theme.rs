@@ -0,0 +1,424 @@
+//! The colors everything on the screen is drawn in.
+
+use std::os;
+use std::num;
+use std::io::File;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+
+use uibuf::{CharColor, CharStyle, Face};
+use syntax::Token;
+use color;
+
+/// The themes that come with the editor, in the order they are cycled through.
+pub const BUILT_IN: [&'static str; 2] = ["dark", "light"];
+
+// The names of the things a theme gives a face to, as a theme file calls them.
+const ELEMENTS: [&'static str; 16] = [
+    "text", "status", "line_numbers", "selection", "search_match", "current_match", "cursor",
+    "message", "error", "keyword", "comment", "string", "number", "invisible",
+    "trailing_whitespace", "current_line",
+];
+
+/// The face of each thing on the screen.
+///
+/// The text is drawn in `text`, and everything drawn over it, from the syntax's tokens to the
+/// selection, keeps the colors under it where its own are the default ones. So a theme for a
+/// terminal with a background of its own can leave the backgrounds default and have them match.
+#[derive(Clone, PartialEq, Show)]
+pub struct Theme {
+    pub name: String,
+    pub text: Face,
+    pub status: Face,
+    pub line_numbers: Face,
+    pub selection: Face,
+    pub search_match: Face,
+    pub current_match: Face,    // The match the cursor is on while searching or replacing.
+    pub cursor: Face,           // The cursors other than the terminal's own.
+    pub message: Face,
+    pub error: Face,
+    pub keyword: Face,
+    pub comment: Face,
+    pub string: Face,
+    pub number: Face,
+    pub invisible: Face,        // The marks of tabs and such, while invisible characters are shown.
+    pub trailing_whitespace: Face,
+    pub current_line: Face,     // The row the cursor is on, while it is highlighted.
+    pub fallback: Option<Box<Theme>>, // What to show instead on a terminal with only the basic
+                                      // colors, for a theme with finer ones.
+}
+
+impl Theme {
+    /// For a terminal with light text on a dark background.
+    pub fn dark() -> Theme {
+        Theme {
+            name: String::from_str("dark"),
+            text: plain(CharColor::Default, CharColor::Default),
+            status: plain(CharColor::Black, CharColor::Blue),
+            line_numbers: plain(CharColor::Grey, CharColor::Default),
+            selection: reverse(),
+            search_match: plain(CharColor::Black, CharColor::Yellow),
+            current_match: reverse(),
+            cursor: reverse(),
+            message: plain(CharColor::Default, CharColor::Default),
+            error: plain(CharColor::Black, CharColor::Red),
+            keyword: plain(CharColor::Yellow, CharColor::Default),
+            comment: plain(CharColor::Blue, CharColor::Default),
+            string: plain(CharColor::Green, CharColor::Default),
+            number: plain(CharColor::Magenta, CharColor::Default),
+            invisible: plain(CharColor::Blue, CharColor::Default),
+            trailing_whitespace: plain(CharColor::Default, CharColor::Red),
+            current_line: plain(CharColor::Default, CharColor::Indexed(236)),
+            fallback: None,
+        }
+    }
+
+    /// For a terminal with dark text on a light background, which yellow and grey are hard to
+    /// read on.
+    pub fn light() -> Theme {
+        Theme {
+            name: String::from_str("light"),
+            status: plain(CharColor::Black, CharColor::Cyan),
+            line_numbers: plain(CharColor::Blue, CharColor::Default),
+            error: plain(CharColor::Red, CharColor::Default),
+            keyword: plain(CharColor::Blue, CharColor::Default),
+            comment: plain(CharColor::Green, CharColor::Default),
+            string: plain(CharColor::Red, CharColor::Default),
+            invisible: plain(CharColor::Grey, CharColor::Default),
+            trailing_whitespace: plain(CharColor::Default, CharColor::Yellow),
+            current_line: plain(CharColor::Default, CharColor::Indexed(254)),
+            ..Theme::dark()
+        }
+    }
+
+    /// The built-in theme called `name`, or the one in the file of that name in the themes
+    /// directory.
+    pub fn named(name: &str) -> Result<Theme, String> {
+        match name {
+            "dark"  => Ok(Theme::dark()),
+            "light" => Ok(Theme::light()),
+            _       => match themes_dir() {
+                Some(dir) => Theme::load(&dir.join(name)),
+                None      => Err(format!("No theme called {}", name)),
+            },
+        }
+    }
+
+    /// Read a theme from the file at `path`, named after the file.
+    pub fn load(path: &Path) -> Result<Theme, String> {
+        let name = path.filename_str().unwrap_or("");
+        match File::open(path).read_to_string() {
+            Ok(contents) => Theme::parse(name, contents[]),
+            Err(e)       => Err(format!("Could not read theme {}: {}", name, e)),
+        }
+    }
+
+    /// A theme from lines of `element = face`, such as `status = black on blue` or
+    /// `selection = reverse`, with `#` starting a comment. `base = light` starts it from a
+    /// built-in theme other than the dark one, and anything the file doesn't give keeps the
+    /// face the base theme has for it.
+    ///
+    /// Besides the basic colors by name, a color can be an entry of the 256-color palette by
+    /// its number or a 24-bit color as `#rrggbb`, followed by `/` and the basic color to show
+    /// on terminals without them, as in `#ff8700/yellow`. Without one it falls back to the
+    /// nearest basic color.
+    pub fn parse(name: &str, contents: &str) -> Result<Theme, String> {
+        let mut theme = Theme { name: String::from_str(name), ..Theme::dark() };
+        let mut basic = theme.clone();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") { continue }
+            let fail = |why: &str| Err(malformed(name, number, why));
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None    => return fail("expected element = face"),
+            };
+            if key == "base" {
+                let base = match value {
+                    "dark"  => Theme::dark(),
+                    "light" => Theme::light(),
+                    _       => return fail("the base must be a built-in theme"),
+                };
+                theme = Theme { name: theme.name, ..base };
+                basic = theme.clone();
+                continue
+            }
+            let (face, fallback) = match parse_face(value) {
+                Some(faces) => faces,
+                None        => return fail(format!("{} isn't a face", value)[]),
+            };
+            match theme.face_mut(key) {
+                Some(element) => *element = face,
+                None          => return fail(format!("no element called {}", key)[]),
+            }
+            if let Some(element) = basic.face_mut(key) { *element = fallback }
+        }
+        theme.fallback = Some(Box::new(basic));
+        Ok(theme)
+    }
+
+    /// The theme as a terminal with `colors` colors can show it: its fallback on one with only
+    /// the basic colors, if it has one, and otherwise with each color brought down to the
+    /// nearest the terminal has.
+    pub fn fit(&self, colors: uint) -> Theme {
+        let mut theme = match self.fallback {
+            Some(ref basic) if colors < color::INDEXED => (**basic).clone(),
+            _                                          => self.clone(),
+        };
+        for key in ELEMENTS.iter() {
+            if let Some(face) = theme.face_mut(*key) {
+                face.fg = color::reduce(face.fg, colors);
+                face.bg = color::reduce(face.bg, colors);
+            }
+        }
+        theme
+    }
+
+    // The face of the element called `key` in a theme file.
+    fn face_mut(&mut self, key: &str) -> Option<&mut Face> {
+        match key {
+            "text"          => Some(&mut self.text),
+            "status"        => Some(&mut self.status),
+            "line_numbers"  => Some(&mut self.line_numbers),
+            "selection"     => Some(&mut self.selection),
+            "search_match"  => Some(&mut self.search_match),
+            "current_match" => Some(&mut self.current_match),
+            "cursor"        => Some(&mut self.cursor),
+            "message"       => Some(&mut self.message),
+            "error"         => Some(&mut self.error),
+            "keyword"       => Some(&mut self.keyword),
+            "comment"       => Some(&mut self.comment),
+            "string"        => Some(&mut self.string),
+            "number"        => Some(&mut self.number),
+            "invisible"     => Some(&mut self.invisible),
+            "trailing_whitespace" => Some(&mut self.trailing_whitespace),
+            "current_line"  => Some(&mut self.current_line),
+            _               => None,
+        }
+    }
+
+    /// The face a token of the syntax is drawn in.
+    pub fn token(&self, token: Token) -> Face {
+        match token {
+            Token::Keyword => self.keyword,
+            Token::Comment => self.comment,
+            Token::String  => self.string,
+            Token::Number  => self.number,
+        }
+    }
+}
+
+/// The names of the themes to cycle through: the built-in ones, then those in the themes
+/// directory in alphabetical order.
+pub fn theme_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILT_IN.iter().map(|name| String::from_str(*name)).collect();
+    let mut files: Vec<String> = themes_dir().and_then(|dir| fs::readdir(&dir).ok())
+                                             .unwrap_or(Vec::new()).iter()
+                                             .filter(|path| path.is_file())
+                                             .filter_map(|path| path.filename_str())
+                                             .map(|name| String::from_str(name))
+                                             .collect();
+    files.sort();
+    let built_in = |name: &String| BUILT_IN.iter().any(|built_in| *built_in == name[]);
+    names.extend(files.into_iter().filter(|name| !built_in(name)));
+    names
+}
+
+/// `~/.iota/themes`, where themes of the user's own are kept. None if there is no home
+/// directory.
+pub fn themes_dir() -> Option<Path> {
+    os::homedir().map(|home| home.join(".iota").join("themes"))
+}
+
+// Why line `number` (from 0) of the theme `name` couldn't be read.
+fn malformed(name: &str, number: uint, why: &str) -> String {
+    format!("Theme {}, line {}: {}", name, number + 1, why)
+}
+
+fn plain(fg: CharColor, bg: CharColor) -> Face {
+    Face::new(fg, bg, CharStyle::Normal)
+}
+
+fn reverse() -> Face {
+    Face::new(CharColor::Default, CharColor::Default, CharStyle::Reverse)
+}
+
+// A face written as `[fg] [on bg] [reverse]`, where colors left out are the default ones, and
+// the face it falls back to on a terminal with only the basic colors.
+fn parse_face(value: &str) -> Option<(Face, Face)> {
+    let mut face = plain(CharColor::Default, CharColor::Default);
+    let mut basic = face;
+    let mut words = value.words().peekable();
+    if let Some((fg, fallback)) = words.peek().and_then(|word| parse_color(*word)) {
+        face.fg = fg;
+        basic.fg = fallback;
+        words.next();
+    }
+    if words.peek() == Some(&"on") {
+        words.next();
+        match words.next().and_then(|word| parse_color(word)) {
+            Some((bg, fallback)) => { face.bg = bg; basic.bg = fallback }
+            None                 => return None,
+        }
+    }
+    if words.peek() == Some(&"reverse") {
+        words.next();
+        face.style = CharStyle::Reverse;
+        basic.style = CharStyle::Reverse;
+    }
+    match words.next() {
+        Some(_) => None,
+        None    => Some((face, basic)),
+    }
+}
+
+// A color, and the basic color it falls back to: the one given after a `/`, or else the nearest.
+fn parse_color(word: &str) -> Option<(CharColor, CharColor)> {
+    let (name, fallback) = match word.find('/') {
+        Some(i) => (word[..i], Some(word[i + 1..])),
+        None    => (word, None),
+    };
+    let color = match extended_color(name).or_else(|| basic_color(name)) {
+        Some(color) => color,
+        None        => return None,
+    };
+    match fallback {
+        Some(fallback) => basic_color(fallback).map(|fallback| (color, fallback)),
+        None           => Some((color, color::reduce(color, color::BASIC))),
+    }
+}
+
+// An entry of the 256-color palette by its number, or a 24-bit color as `#rrggbb`.
+fn extended_color(name: &str) -> Option<CharColor> {
+    if name.starts_with("#") && name.len() == 7 && name.bytes().all(|b| b < 128) {
+        let channel = |i: uint| num::from_str_radix::<u8>(name[i..i + 2], 16);
+        match (channel(1), channel(3), channel(5)) {
+            (Some(r), Some(g), Some(b)) => Some(CharColor::Rgb(r, g, b)),
+            _                           => None,
+        }
+    } else {
+        name.parse().map(|index| CharColor::Indexed(index))
+    }
+}
+
+fn basic_color(name: &str) -> Option<CharColor> {
+    match name {
+        "default" => Some(CharColor::Default),
+        "black"   => Some(CharColor::Black),
+        "red"     => Some(CharColor::Red),
+        "green"   => Some(CharColor::Green),
+        "yellow"  => Some(CharColor::Yellow),
+        "blue"    => Some(CharColor::Blue),
+        "magenta" => Some(CharColor::Magenta),
+        "cyan"    => Some(CharColor::Cyan),
+        "grey"    => Some(CharColor::Grey),
+        _         => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{File, TempDir};
+
+    use theme::{Theme, parse_face};
+    use uibuf::{CharColor, CharStyle, Face};
+    use color;
+
+    fn parse_face_alone(value: &str) -> Option<Face> {
+        parse_face(value).map(|(face, _)| face)
+    }
+
+    #[test]
+    fn test_parse_faces() {
+        assert_eq!(parse_face_alone("black on blue"),
+                   Some(Face::new(CharColor::Black, CharColor::Blue, CharStyle::Normal)));
+        assert_eq!(parse_face_alone("yellow"),
+                   Some(Face::new(CharColor::Yellow, CharColor::Default, CharStyle::Normal)));
+        assert_eq!(parse_face_alone("on red"),
+                   Some(Face::new(CharColor::Default, CharColor::Red, CharStyle::Normal)));
+        assert_eq!(parse_face_alone("reverse"),
+                   Some(Face::new(CharColor::Default, CharColor::Default, CharStyle::Reverse)));
+        assert_eq!(parse_face_alone(""),
+                   Some(Face::new(CharColor::Default, CharColor::Default, CharStyle::Normal)));
+        assert_eq!(parse_face_alone("purple"), None);
+        assert_eq!(parse_face_alone("black on"), None);
+        assert_eq!(parse_face_alone("reverse blue"), None);
+    }
+
+    #[test]
+    fn test_parse_keeps_the_base_for_what_it_leaves_out() {
+        let magenta = Face::new(CharColor::Magenta, CharColor::Default, CharStyle::Normal);
+        let contents = "# mine\nstatus = black on green\n\nkeyword = magenta\n";
+        let theme = Theme::parse("mine", contents).unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.status, Face::new(CharColor::Black, CharColor::Green, CharStyle::Normal));
+        assert_eq!(theme.keyword, magenta);
+        assert_eq!(theme.comment, Theme::dark().comment);
+
+        let theme = Theme::parse("mine", "base = light\nkeyword = magenta").unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.comment, Theme::light().comment);
+        assert_eq!(theme.keyword, magenta);
+    }
+
+    #[test]
+    fn test_parse_finer_colors_with_fallbacks() {
+        let orange = CharColor::Rgb(255, 135, 0);
+        assert_eq!(parse_face("#ff8700/magenta on 236"), Some((
+            Face::new(orange, CharColor::Indexed(236), CharStyle::Normal),
+            Face::new(CharColor::Magenta, CharColor::Black, CharStyle::Normal),
+        )));
+        assert_eq!(parse_face("#ff8700"), Some((
+            Face::new(orange, CharColor::Default, CharStyle::Normal),
+            Face::new(CharColor::Yellow, CharColor::Default, CharStyle::Normal),
+        )));
+        assert_eq!(parse_face("#ff87"), None);
+        assert_eq!(parse_face("#ff870g"), None);
+        assert_eq!(parse_face("256"), None);
+        assert_eq!(parse_face("208/orange"), None);
+    }
+
+    #[test]
+    fn test_fit_to_the_terminal() {
+        let theme = Theme::parse("mine", "keyword = #ff8700/magenta
+string = #ff8700").unwrap();
+        let basic = theme.fit(color::BASIC);
+        assert_eq!(basic.name, "mine");
+        assert_eq!(basic.keyword.fg, CharColor::Magenta);
+        assert_eq!(basic.string.fg, CharColor::Yellow);
+        assert_eq!(basic.comment, Theme::dark().comment);
+
+        let indexed = theme.fit(color::INDEXED);
+        assert_eq!(indexed.keyword.fg, CharColor::Indexed(208));
+        assert_eq!(theme.fit(color::TRUE_COLOR).keyword.fg, CharColor::Rgb(255, 135, 0));
+
+        assert_eq!(Theme::light().fit(color::TRUE_COLOR), Theme::light());
+        assert_eq!(Theme::light().fit(color::BASIC).current_line.bg, CharColor::Grey);
+        assert_eq!(Theme::dark().fit(color::BASIC).keyword, Theme::dark().keyword);
+    }
+
+    #[test]
+    fn test_malformed_themes_say_where() {
+        assert_eq!(Theme::parse("mine", "status black"),
+                   Err(String::from_str("Theme mine, line 1: expected element = face")));
+        assert_eq!(Theme::parse("mine", "\nstatus = black on purple"),
+                   Err(String::from_str("Theme mine, line 2: black on purple isn't a face")));
+        assert_eq!(Theme::parse("mine", "gutter = grey"),
+                   Err(String::from_str("Theme mine, line 1: no element called gutter")));
+        assert_eq!(Theme::parse("mine", "base = solarized"),
+                   Err(String::from_str("Theme mine, line 1: the base must be a built-in theme")));
+    }
+
+    #[test]
+    fn test_load_is_named_after_the_file() {
+        let dir = TempDir::new("iota-theme").unwrap();
+        let path = dir.path().join("paper");
+        File::create(&path).write_str("base = light\n").unwrap();
+        let theme = Theme::load(&path).unwrap();
+        assert_eq!(theme.name, "paper");
+        assert_eq!(theme.status, Theme::light().status);
+
+        assert!(Theme::load(&dir.path().join("missing")).is_err());
+    }
+}
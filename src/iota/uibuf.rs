@@ -6,20 +6,42 @@ pub struct UIBuffer {
     rows: Vec<Vec<Cell>>
 }
 
-#[derive(Copy, PartialEq)]
+#[derive(Copy, PartialEq, Show)]
 pub enum CharStyle {
     Normal,
+    Reverse,
     // TODO: add other styles
     // Bold,
     // Underline,
 }
 
-#[derive(Copy, PartialEq)]
+#[derive(Copy, PartialEq, Show)]
 pub enum CharColor {
     Default,
     Blue,
     Black,
-    // TODO: add other colors
+    Yellow,
+    Grey,
+    Red,
+    Green,
+    Magenta,
+    Cyan,
+    Indexed(u8),            // An entry of the 256-color palette.
+    Rgb(u8, u8, u8),        // A 24-bit color.
+}
+
+/// The colors and style that something on the screen is drawn in.
+#[derive(Copy, PartialEq, Show)]
+pub struct Face {
+    pub fg: CharColor,
+    pub bg: CharColor,
+    pub style: CharStyle,
+}
+
+impl Face {
+    pub fn new(fg: CharColor, bg: CharColor, style: CharStyle) -> Face {
+        Face { fg: fg, bg: bg, style: style }
+    }
 }
 
 impl UIBuffer {
@@ -37,7 +59,12 @@ impl UIBuffer {
         let rows = self.rows.slice_mut(start, stop);
         for row in rows.iter_mut() {
             for cell in row.iter_mut().filter(|cell| cell.dirty) {
-                frontend.draw_char(cell.x, cell.y, cell.ch, cell.fg, cell.bg, CharStyle::Normal);
+                // a cell changed and changed back since it was last drawn is still on the screen
+                let look = (cell.ch, cell.fg, cell.bg, cell.style);
+                if cell.shown != Some(look) {
+                    frontend.draw_char(cell.x, cell.y, cell.ch, cell.fg, cell.bg, cell.style);
+                    cell.shown = Some(look);
+                }
                 cell.dirty = false;
             }
         }
@@ -56,11 +83,17 @@ impl UIBuffer {
         self.height
     }
 
-    /// Set all cells to `ch`.
+    /// Set all cells to `ch`, in the normal style and the default colors.
     pub fn fill(&mut self, ch: char) {
+        self.fill_with_face(ch, Face::new(CharColor::Default, CharColor::Default, CharStyle::Normal));
+    }
+
+    /// Set all cells to `ch`, drawn in `face`.
+    pub fn fill_with_face(&mut self, ch: char, face: Face) {
         for row in range(0, self.height) {
             for col in range(0, self.width) {
-                self.update_cell_content(col, row, ch);
+                self.update_cell(col, row, ch, face.fg, face.bg);
+                self.update_cell_style(col, row, face.style);
             }
         }
     }
@@ -75,6 +108,31 @@ impl UIBuffer {
         self.get_cell_mut(cell_num, row_num).set(ch, fg, bg);
     }
 
+    /// Update the `fg` and `bg` attributes of an individual cell
+    pub fn update_cell_colors(&mut self, cell_num: uint, row_num: uint, fg: CharColor, bg: CharColor) {
+        self.rows[row_num][cell_num].set_colors(fg, bg);
+    }
+
+    /// Draw an individual cell in `face` over what it has already, keeping the cell's own colors
+    /// where `face` has the default ones and its own style where `face` has the normal one.
+    pub fn update_cell_face(&mut self, cell_num: uint, row_num: uint, face: Face) {
+        let cell = &mut self.rows[row_num][cell_num];
+        let fg = if face.fg == CharColor::Default { cell.fg } else { face.fg };
+        let bg = if face.bg == CharColor::Default { cell.bg } else { face.bg };
+        cell.set_colors(fg, bg);
+        if face.style != CharStyle::Normal { cell.set_style(face.style) }
+    }
+
+    /// Update the `style` attribute of an individual cell
+    pub fn update_cell_style(&mut self, cell_num: uint, row_num: uint, style: CharStyle) {
+        self.rows[row_num][cell_num].set_style(style);
+    }
+
+    /// Mark a cell as covered by the wide character to its left, so that it is not drawn over.
+    pub fn cover_cell(&mut self, cell_num: uint, row_num: uint) {
+        self.rows[row_num][cell_num].cover();
+    }
+
     pub fn get_cell_mut(&mut self, cell_num: uint, row_num: uint) -> &mut Cell {
         &mut self.rows[row_num][cell_num]
     }
@@ -84,10 +142,12 @@ impl UIBuffer {
 pub struct Cell {
     pub bg: CharColor,
     pub fg: CharColor,
+    pub style: CharStyle,
     pub ch: char,
     pub x: uint,
     pub y: uint,
-    pub dirty: bool
+    pub dirty: bool,
+    shown: Option<(char, CharColor, CharColor, CharStyle)>, // As the frontend was last given it.
 }
 
 
@@ -96,10 +156,12 @@ impl Cell {
         Cell {
             bg: CharColor::Default,
             fg: CharColor::Default,
+            style: CharStyle::Normal,
             ch: ' ',
             x: 0,
             y: 0,
-            dirty: true
+            dirty: true,
+            shown: None,
         }
     }
 
@@ -110,6 +172,30 @@ impl Cell {
         }
     }
 
+    pub fn set_style(&mut self, style: CharStyle) {
+        if self.style != style {
+            self.dirty = true;
+            self.style = style;
+        }
+    }
+
+    pub fn set_colors(&mut self, fg: CharColor, bg: CharColor) {
+        if self.fg != fg || self.bg != bg {
+            self.dirty = true;
+            self.fg = fg;
+            self.bg = bg;
+        }
+    }
+
+    /// Leave the cell to the wide character drawn before it. Any char set afterwards will make it
+    /// dirty again.
+    pub fn cover(&mut self) {
+        self.ch = '\0';
+        self.dirty = false;
+        // the frontend has the wide character over it now, not what it was given before
+        self.shown = None;
+    }
+
     pub fn set(&mut self, ch: char, fg: CharColor, bg: CharColor) {
         if self.ch != ch || self.fg != fg || self.bg != bg {
             self.dirty = true;
@@ -141,7 +227,7 @@ impl Cell {
 #[cfg(test)]
 mod tests {
     use uibuf::UIBuffer;
-    use uibuf::CharColor;
+    use uibuf::{CharColor, CharStyle, Face};
 
     fn setup_uibuf() -> UIBuffer {
         UIBuffer::new(50, 50)
@@ -166,6 +252,29 @@ mod tests {
         assert_eq!(uibuf.rows[0][30].ch, 'x');
     }
 
+    #[test]
+    fn fill_resets_colors() {
+        let mut uibuf = setup_uibuf();
+        uibuf.update_cell_colors(3, 4, CharColor::Black, CharColor::Yellow);
+        assert!(uibuf.rows[4][3].bg == CharColor::Yellow);
+        uibuf.fill(' ');
+        assert!(uibuf.rows[4][3].fg == CharColor::Default);
+        assert!(uibuf.rows[4][3].bg == CharColor::Default);
+    }
+
+    #[test]
+    fn faces_are_drawn_over_the_cell() {
+        let mut uibuf = setup_uibuf();
+        uibuf.fill_with_face(' ', Face::new(CharColor::Default, CharColor::Grey, CharStyle::Normal));
+        uibuf.update_cell_face(3, 4, Face::new(CharColor::Blue, CharColor::Default, CharStyle::Normal));
+        assert!(uibuf.rows[4][3].fg == CharColor::Blue);
+        assert!(uibuf.rows[4][3].bg == CharColor::Grey);
+
+        uibuf.update_cell_face(3, 4, Face::new(CharColor::Default, CharColor::Default, CharStyle::Reverse));
+        assert!(uibuf.rows[4][3].fg == CharColor::Blue);
+        assert!(uibuf.rows[4][3].style == CharStyle::Reverse);
+    }
+
     #[test]
     fn update_cell_content_updates_a_single_cell() {
         let mut uibuf = setup_uibuf();
@@ -1,8 +1,174 @@
-use buffer::{Buffer, Direction, Mark};
+use super::Response;
+use buffer::{Buffer, Case, Direction, LineEnding, Mark, Matches, SaveMethod, Saved, Progress, NoProgress};
 use input::Input;
-use uibuf::{UIBuffer, CharColor};
+use uibuf::{UIBuffer, Face};
 use frontends::Frontend;
-use overlay::{Overlay, OverlayType};
+use overlay::{Overlay, OverlayType, OverlayEvent};
+use keyboard::Key;
+use options::Options;
+use session::{Session, Position};
+use recent::RecentFiles;
+use history::{History, Histories};
+use replace::QueryReplace;
+use grep::{Grep, parse_result_line};
+use clipboard::Clipboard;
+use killring::{KillRing, Register};
+use status::Status;
+use message::{Messages, Level, truncate};
+use syntax::Highlighter;
+use theme::{Theme, theme_names};
+use layout;
+use color;
+use syntax;
+use completion;
+use filetype;
+
+use std::os;
+use std::cmp;
+use std::str;
+use regex::Regex;
+use std::io::IoResult;
+use std::io::fs::PathExtensions;
+use std::iter::repeat;
+
+/// Which number is shown beside each line.
+#[derive(Copy, PartialEq, Show)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,               // The cursor's line shows its number, the others their distance from it.
+}
+
+impl LineNumbers {
+    /// The number shown beside the (0-based) line `line` while the cursor is on line `cursor`.
+    pub fn label(&self, line: uint, cursor: uint) -> Option<uint> {
+        match *self {
+            LineNumbers::Off                        => None,
+            LineNumbers::Relative if line > cursor  => Some(line - cursor),
+            LineNumbers::Relative if line < cursor  => Some(cursor - line),
+            LineNumbers::Absolute | LineNumbers::Relative => Some(line + 1),
+        }
+    }
+}
+
+// An incremental search in progress.
+struct Search {
+    query: String,
+    forward: bool,
+    regex: bool,            // Whether the query is a regular expression rather than literal text.
+    whole_word: bool,       // Whether only matches that aren't part of a longer word count.
+    case: Case,
+    compiled: Result<Regex, String>, // The query compiled, or why it couldn't be.
+    origin: (uint, uint),   // Where the cursor and the top line were when the search started.
+    found: Option<(uint, uint)>, // Start and end of the match the cursor is on.
+    failing: bool,          // Whether the query has no match.
+    wrapped: bool,          // Whether stepping through the matches has gone round the buffer.
+}
+
+impl Search {
+    fn new(query: &str, forward: bool, case: Case, origin: (uint, uint)) -> Search {
+        Search {
+            query: String::from_str(query),
+            forward: forward,
+            regex: false,
+            whole_word: false,
+            case: case,
+            compiled: Err(String::new()),
+            origin: origin,
+            found: None,
+            failing: false,
+            wrapped: false,
+        }
+    }
+
+    // Compile the query again after it has changed, when it is a regex. The error is kept to
+    // show along with the query rather than ending the search.
+    fn compile(&mut self) {
+        if self.regex {
+            let pattern = if self.case.ignores_case(self.query[]) { format!("(?i){}", self.query) }
+                          else { self.query.clone() };
+            self.compiled = Regex::new(pattern[]).map_err(|e| format!("{}", e));
+        }
+    }
+
+    fn start(&self) -> uint {
+        self.found.map_or(self.origin.0, |(start, _)| start)
+    }
+}
+
+// The match of the search from `from`: the first at or after it going forward, and the last
+// starting before it going backward.
+fn find_in(buffer: &Buffer, search: &Search, from: uint) -> Option<(uint, uint)> {
+    let mut from = from;
+    loop {
+        match find_any_in(buffer, search, from) {
+            Some((start, end)) if search.whole_word && !buffer.is_whole_word(start, end) => {
+                from = if search.forward { start + 1 } else { start };
+            }
+            found => return found,
+        }
+    }
+}
+
+// As `find_in`, whether or not the match is a whole word.
+fn find_any_in(buffer: &Buffer, search: &Search, from: uint) -> Option<(uint, uint)> {
+    if search.query.is_empty() { return None }
+    if search.regex {
+        match search.compiled {
+            Ok(ref re) if search.forward => buffer.find_regex(re, from),
+            Ok(ref re)                   => buffer.rfind_regex(re, from),
+            Err(_)                       => None,
+        }
+    } else if search.forward {
+        buffer.find_case(search.query[], from, search.case)
+    } else {
+        buffer.rfind_case(search.query[], from, search.case)
+    }
+}
+
+// The history of what is entered at a prompt, if it keeps one.
+fn prompt_history(histories: &mut Histories, kind: OverlayType) -> Option<&mut History> {
+    match kind {
+        OverlayType::Prompt      => Some(&mut histories.command),
+        OverlayType::GotoLine    => Some(&mut histories.goto_line),
+        OverlayType::ReplaceFrom |
+        OverlayType::ReplaceAll  |
+        OverlayType::CountMatches |
+        OverlayType::Grep        => Some(&mut histories.search),
+        _                        => None,
+    }
+}
+
+// Every match of the search starting in [from, to), for highlighting and counting. None if the
+// search has no query, or it doesn't compile.
+fn matches_in<'a>(buffer: &'a Buffer, search: &'a Search, from: uint, to: uint) -> Option<Matches<'a>> {
+    if search.query.is_empty() { return None }
+    let matches = if search.regex {
+        match search.compiled {
+            Ok(ref re) => buffer.matches_regex(re, from, to),
+            Err(_)     => return None,
+        }
+    } else {
+        buffer.matches_case(search.query[], from, to, search.case)
+    };
+    Some(if search.whole_word { matches.whole_words() } else { matches })
+}
+
+// The last paste, which a paste of an older kill straight after takes the place of.
+#[derive(Copy)]
+struct Yank {
+    revision: uint,         // Log revision after it, while nothing else has been done since.
+    cursor: uint,           // Where the cursor was before it.
+    selection: Option<(uint, bool)>, // Where the selection it replaced started, and whether it
+                                     // was a block.
+}
+
+// A selection grown a step at a time, which can be shrunk back through the steps.
+struct Expansion {
+    extent: (uint, uint),   // Anchor and cursor of the selection it grew to.
+    previous: Vec<(Option<uint>, uint)>, // Anchor and cursor before each step, the last step
+                                         // last, with no anchor where nothing was selected.
+}
 
 /// A View is an abstract Window (into a Buffer).
 ///
@@ -14,44 +180,358 @@ pub struct View<'v> {
     pub buffer: Buffer,     // Text buffer
     pub uibuf: UIBuffer,    // UIBuffer
     pub overlay: Overlay,
+    pub options: Options,
 
     top_line: Mark,         // First character of the top line to be displayed.
-    left_col: uint,         // Index into the top line to set the left column to.
+    left_col: uint,         // Screen column of the text shown first on each line, when the
+                            // view is scrolled sideways to a cursor on a long line.
     cursor: Mark,           // Cursor displayed by this buffer.
+    extra_cursors: Vec<Mark>, // Secondary cursors, which edits are also applied at.
+    messages: Messages,     // Waiting to be shown in place of the status line.
+    highlighter: Option<Highlighter>, // Colors the text, for a type of file with a syntax.
+    colors: uint,           // How many colors the terminal can show, which the theme is fit to.
+    auto_indent: Option<(uint, uint, uint)>, // Log revision, end and length of the last auto-indent.
+    last_pair: Option<(uint, uint)>, // Log revision and middle of the last auto-inserted pair.
+    pending_save: Option<Path>, // Save As path waiting for confirmation to overwrite its file
+                                // or create its directory.
+    recent: RecentFiles,        // Files opened and saved, for the open recent prompt.
+    histories: Histories,       // What was entered at the prompts, for recalling.
+    search: Option<Search>,     // Incremental search being typed.
+    last_search: String,        // Query of the last search, for searching again.
+    last_whole_word: bool,      // Whether the last search was for whole words.
+    highlight: Option<Search>,  // Ended search whose matches are still highlighted.
+    highlight_revision: uint,   // Log revision when it ended, as an edit clears the highlight.
+    replace: Option<QueryReplace>, // Replacement being asked about, match by match.
+    replace_pattern: String,    // What to replace, waiting for what to replace it with.
+    grep: Option<Grep>,         // Search through files still filling in the results buffer.
+    grep_pattern: String,       // What to search files for, waiting for where to search.
+    results_dir: Option<Path>,  // Directory the results in the buffer are relative to, while
+                                // the buffer holds them.
+    kills: KillRing,            // What was last copied, cut or deleted, for pasting.
+    last_kill: Option<(uint, uint)>, // Log revision and cursor after the last deletion kept in
+                                     // the kill ring, which a deletion straight after adds to.
+    last_yank: Option<Yank>,    // Last paste, while it can still be swapped for an older kill.
+    expansion: Option<Expansion>, // Steps the selection was last grown by.
+    clipboard: Option<Box<Clipboard>>, // System clipboard, which copies go to as well.
+    clipboard_failed: bool,     // Whether it has failed, after which only the kill ring is used.
 }
 
+/// How many files or directories the search through files looks at between keys.
+const GREP_STEP: uint = 20;
+
 impl<'v> View<'v> {
 
     //----- CONSTRUCTORS ---------------------------------------------------------------------------
 
     pub fn new(source: Input, width: uint, height: uint) -> View<'v> {
-        let mut buffer = match source {
+        View::new_with_progress(source, width, height, &mut NoProgress)
+    }
+
+    /// As `new`, telling `progress` how far loading the file has got.
+    pub fn new_with_progress(source: Input, width: uint, height: uint,
+                             progress: &mut Progress) -> View<'v> {
+        let mut messages = Messages::new();
+        let buffer = match source {
             Input::Filename(path) => {
                 match path {
-                    Some(s) => Buffer::new_from_file(Path::new(s)),
+                    Some(s) => match Buffer::new_from_file_with_progress(Path::new(s.as_slice()),
+                                                                         progress) {
+                        Ok(buffer) => buffer,
+                        Err(e)     => {
+                            messages.push(format!("Could not open {}: {}", s, e)[], Level::Error);
+                            Buffer::new()
+                        }
+                    },
                     None    => Buffer::new(),
                 }
             },
             Input::Stdin(reader) => {
-                Buffer::new_from_reader(reader)
+                let mut buffer = Buffer::new_from_reader(reader);
+                buffer.from_stdin = true;
+                buffer
             },
         };
 
-        // NOTE(greg): this may not play well with resizing
         let uibuf = UIBuffer::new(width, height);
 
-        let cursor = Mark::Cursor(0);
-        buffer.set_mark(cursor, 0);
-        let top_line = Mark::DisplayMark(0);
-        buffer.set_mark(top_line, 0);
-
-        View {
+        let mut view = View {
             buffer: buffer,
-            top_line: top_line,
+            top_line: Mark::DisplayMark(0),
             left_col: 0,
-            cursor: cursor,
+            cursor: Mark::Cursor(0),
+            extra_cursors: Vec::new(),
+            messages: messages,
+            highlighter: None,
+            colors: color::BASIC,
+            auto_indent: None,
+            last_pair: None,
+            pending_save: None,
+            recent: RecentFiles::new(),
+            histories: Histories::new(),
+            search: None,
+            last_search: String::new(),
+            last_whole_word: false,
+            highlight: None,
+            highlight_revision: 0,
+            replace: None,
+            replace_pattern: String::new(),
+            grep: None,
+            grep_pattern: String::new(),
+            results_dir: None,
+            kills: KillRing::new(),
+            last_kill: None,
+            last_yank: None,
+            expansion: None,
+            clipboard: None,
+            clipboard_failed: false,
             uibuf: uibuf,
             overlay: Overlay::None,
+            options: Options::new(),
+        };
+        view.start_buffer();
+        view
+    }
+
+    // Put the cursor at the start of a newly loaded buffer, say anything worth knowing about
+    // its file, and ask about it being binary or having a swap file left behind.
+    fn start_buffer(&mut self) {
+        self.buffer.tab_width = self.options.tab_width;
+        self.buffer.set_mark(self.cursor, 0);
+        self.buffer.set_mark(self.top_line, 0);
+        self.choose_syntax();
+
+        if self.buffer.binary {
+            self.show_message("This looks like a binary file");
+        } else if self.buffer.read_only {
+            self.show_message("Not valid UTF-8: opened read-only, with invalid bytes replaced");
+        } else if self.buffer.is_new_file() {
+            self.show_message("New file");
+        }
+
+        if self.buffer.binary {
+            self.set_overlay(OverlayType::ConfirmBinary);
+        } else {
+            self.open_swap();
+        }
+    }
+
+    /// Open the file at the path typed in place of the buffer, which must have no unsaved
+    /// changes. A leading `~` is the home directory, and a relative path is taken from the
+    /// current directory.
+    pub fn open_file(&mut self, name: &str) {
+        if name.is_empty() { return }
+        if self.buffer.is_dirty() {
+            return self.show_message("Save or revert the changes to this buffer first")
+        }
+        let path = completion::expand_path(name);
+        match Buffer::new_from_file(path.clone()) {
+            Ok(buffer) => {
+                self.recent.add(&path);
+                self.switch_buffer(buffer);
+            }
+            Err(e) => self.show_error(format!("Could not open {}: {}", name, e)[]),
+        }
+    }
+
+    // Put another buffer in place of this one, starting afresh in it.
+    fn switch_buffer(&mut self, buffer: Buffer) {
+        self.buffer.remove_swap();
+        self.buffer.remove_autosave();
+        self.buffer = buffer;
+        self.extra_cursors.clear();
+        self.left_col = 0;
+        self.auto_indent = None;
+        self.last_pair = None;
+        self.pending_save = None;
+        self.highlight = None;
+        self.grep = None;
+        self.results_dir = None;
+        self.start_buffer();
+    }
+
+    /// Ask where to search files for `pattern`, once it has been typed.
+    pub fn ask_grep_dir(&mut self, pattern: &str) {
+        if pattern.is_empty() { return self.show_message("Grep cancelled") }
+        self.grep_pattern = String::from_str(pattern);
+        self.set_overlay(OverlayType::GrepIn);
+    }
+
+    /// Start searching the files under the directory typed for the pattern, a regular
+    /// expression, showing the matching lines in a read-only buffer in place of this one. The
+    /// search carries on a few files at a time between keys, in `grep_step`.
+    pub fn start_grep(&mut self, dir: &str) {
+        if self.buffer.is_dirty() {
+            return self.show_message("Save or revert the changes to this buffer first")
+        }
+        let dir = if dir.is_empty() { self.grep_dir() } else { completion::expand_path(dir) };
+        if !dir.is_dir() {
+            return self.show_error(format!("Not a directory: {}", dir.display())[])
+        }
+        let pattern = if self.options.search_case.ignores_case(self.grep_pattern[]) {
+            format!("(?i){}", self.grep_pattern)
+        } else {
+            self.grep_pattern.clone()
+        };
+        let re = match Regex::new(pattern[]) {
+            Ok(re) => re,
+            Err(e) => return self.show_error(format!("Bad pattern: {}", e)[]),
+        };
+        let mut results = Buffer::new();
+        results.read_only = true;
+        self.switch_buffer(results);
+        self.grep = Some(Grep::new(re, dir.clone()));
+        self.results_dir = Some(dir);
+        self.grep_step();
+    }
+
+    pub fn is_grepping(&self) -> bool {
+        self.grep.is_some()
+    }
+
+    /// Carry on the search through files, adding what it finds to the results buffer.
+    pub fn grep_step(&mut self) {
+        let (results, done, (matches, files)) = match self.grep {
+            Some(ref mut grep) => (grep.step(GREP_STEP), grep.is_done(), grep.counts()),
+            None               => return,
+        };
+        for line in results.iter() {
+            self.buffer.append_output(line[]);
+            self.buffer.append_output("\n");
+        }
+        if done {
+            self.grep = None;
+            self.show_message(format!("grep: {} matching lines in {} files", matches, files)[]);
+        } else {
+            self.show_progress(format!("grep: searching... {} matching lines", matches)[]);
+        }
+    }
+
+    pub fn is_showing_results(&self) -> bool {
+        self.results_dir.is_some()
+    }
+
+    /// Open the file of the search result the cursor is on, at its line.
+    pub fn open_result(&mut self) {
+        let line = self.buffer.get_mark_pos(self.cursor).map_or(0, |(line, _)| line);
+        let text = match self.buffer.lines().nth(line) {
+            Some(text) => String::from_utf8_lossy(text).into_owned(),
+            None       => return,
+        };
+        let (path, linenum) = match (parse_result_line(text[]), self.results_dir.as_ref()) {
+            (Some((name, linenum)), Some(dir)) => (dir.join(name), linenum),
+            _                                  => return,
+        };
+        self.open_file(format!("{}", path.display())[]);
+        if self.buffer.file_path.as_ref() == Some(&path) {
+            self.goto_position(linenum, 1);
+        }
+    }
+
+    // Where to search files by default: the directory of the buffer's file, or else the
+    // current directory. From search results, it is the directory they came from.
+    fn grep_dir(&self) -> Path {
+        if let Some(ref dir) = self.results_dir { return dir.clone() }
+        match self.buffer.file_path {
+            Some(ref path) => os::make_absolute(&path.dir_path()).unwrap_or_else(|_| path.dir_path()),
+            None           => os::getcwd().unwrap_or_else(|_| Path::new(".")),
+        }
+    }
+
+    /// Use `recent` as the list of files opened recently, with the buffer's file at the top.
+    pub fn set_recent_files(&mut self, recent: RecentFiles) {
+        self.recent = recent;
+        if let Some(ref path) = self.buffer.file_path {
+            self.recent.add(path);
+        }
+    }
+
+    /// The files opened recently, including the ones opened and saved since `set_recent_files`.
+    pub fn recent_files(&self) -> &RecentFiles {
+        &self.recent
+    }
+
+    /// Recall what was entered at the prompts from `histories`.
+    pub fn set_histories(&mut self, histories: Histories) {
+        self.histories = histories;
+    }
+
+    /// What was entered at the prompts, including this session's entries.
+    pub fn histories(&self) -> &Histories {
+        &self.histories
+    }
+
+    /// Copy to `clipboard` as well as the kill ring, and paste from it on request.
+    pub fn set_clipboard(&mut self, clipboard: Box<Clipboard>) {
+        self.clipboard = Some(clipboard);
+        self.clipboard_failed = false;
+    }
+
+    /// Ask to quit the editor. While the buffer has unsaved changes this asks what to do with
+    /// them instead, and the editor carries on until the answer says to quit.
+    pub fn quit(&mut self) -> Response {
+        if !self.buffer.is_dirty() { return Response::Quit }
+        self.set_overlay(OverlayType::ConfirmQuit);
+        Response::Continue
+    }
+
+    /// Answer the question asked by `quit`: "s" saves and then quits, "q" quits without saving,
+    /// and anything else goes back to editing.
+    pub fn answer_quit(&mut self, answer: &str) -> Response {
+        match answer {
+            "s" => {
+                self.save_buffer();
+                // a save that fails or has more to ask leaves the editor open
+                if self.buffer.is_dirty() { Response::Continue } else { Response::Quit }
+            }
+            "q" => Response::Quit,
+            _   => Response::Continue,
+        }
+    }
+
+    /// Answer the question asked on opening a binary file: "h" shows it as a hex dump, "o"
+    /// opens it as text anyway, and anything else leaves an empty buffer instead.
+    pub fn answer_binary(&mut self, answer: &str) {
+        match answer {
+            "h" => {
+                if let Err(e) = self.buffer.show_as_hex() {
+                    self.show_error(format!("Could not show as hex: {}", e)[]);
+                }
+            }
+            "o" => {}
+            _   => {
+                self.buffer = Buffer::new();
+                self.buffer.tab_width = self.options.tab_width;
+                self.buffer.set_mark(self.cursor, 0);
+                self.buffer.set_mark(self.top_line, 0);
+                self.show_message("Not opened");
+            }
+        }
+        self.choose_syntax();
+    }
+
+    // Color the buffer by the syntax its file's extension gives it, if it is shown as text.
+    fn choose_syntax(&mut self) {
+        let syntax = if self.buffer.binary || self.buffer.hex_view { None } else {
+            filetype::syntax(self.buffer.file_path.as_ref())
+        };
+        self.highlighter = syntax.map(|syntax| Highlighter::new(syntax));
+    }
+
+    // Offer to recover from a swap file left behind by an editor that died while it had the
+    // file open, and start this session's own swap file otherwise.
+    fn open_swap(&mut self) {
+        if !self.options.swap_file { return }
+        let swap = match self.buffer.swap_path() {
+            Some(swap) => swap,
+            None       => return,
+        };
+        if swap.exists() {
+            let age = if self.buffer.swap_is_newer() { "newer" } else { "older" };
+            let message = format!("Found swap file {}, {} than the file", swap.filename_display(), age);
+            self.show_message(message[]);
+            self.set_overlay(OverlayType::ConfirmRecover);
+        } else if let Err(e) = self.buffer.write_swap() {
+            self.show_error(format!("Could not write swap file: {}", e)[]);
         }
     }
 
@@ -64,24 +544,159 @@ impl<'v> View<'v> {
         self.uibuf.get_width()
     }
 
+    /// Fit the view to a window resized to `width` by `height`, scrolling to keep the cursor on
+    /// the screen if it is now off it.
+    pub fn resize(&mut self, width: uint, height: uint) {
+        self.uibuf = UIBuffer::new(width, height);
+        self.move_screen();
+    }
+
+    // The width of the column of line numbers, wide enough for the number of the last line and
+    // a space to keep them off the text. None is shown on a screen too narrow to leave room for
+    // any text beside it.
+    fn gutter_width(&self) -> uint {
+        if self.options.line_numbers == LineNumbers::Off { return 0 }
+        // a relative number is never more than the number of the last line, so this fits both
+        let width = format!("{}", self.buffer.line_count()).len() + 1;
+        if width + 1 < self.get_width() { width } else { 0 }
+    }
+
     //----- DRAWING METHODS ------------------------------------------------------------------------
     /// Clear the buffer
     ///
-    /// Fills every cell in the UIBuffer with the space (' ') char.
-    pub fn clear<T: Frontend>(&mut self, frontend: &mut T) {
-        self.uibuf.fill(' ');
-        self.uibuf.draw_everything(frontend);
+    /// Fills every cell in the UIBuffer with the space (' ') char, in the theme's face for text.
+    /// Nothing is sent to the frontend until the next draw, which only sends the cells that end
+    /// up looking different from what they showed before.
+    pub fn clear(&mut self) {
+        self.uibuf.fill_with_face(' ', self.options.theme.text);
     }
 
     pub fn draw<T: Frontend>(&mut self, frontend: &mut T) {
-        for (index,line) in self.buffer
-                                .lines_from(self.top_line)
-                                .unwrap()
-                                .take(self.get_height())
-                                .enumerate() {
-            draw_line(&mut self.uibuf, line, index, self.left_col);
-            if index == self.get_height() { break; }
+        if !self.is_highlighting() { self.highlight = None }
+        // done here rather than as the cursor moves, as it has to fit the gutter as it is drawn
+        self.scroll_sideways();
+        let current = match self.search {
+            Some(ref search) => search.found,
+            None             => self.replace.as_ref().and_then(|replace| replace.current()),
+        };
+        let selection = match self.buffer.block_ranges(self.options.tab_width) {
+            Some((_, ranges)) => ranges,
+            None              => self.buffer.selection_range().into_iter().collect(),
+        };
+        // only the matches on the screen are looked for
+        let top = self.buffer.get_mark_idx(self.top_line).unwrap_or(0);
+        let matches = match self.search.as_ref().or(self.highlight.as_ref()) {
+            Some(search) => {
+                let bottom = self.buffer.lines_from(self.top_line).unwrap()
+                                        .take(self.get_height())
+                                        .fold(top, |end, line| end + line.len());
+                matches_in(&self.buffer, search, top, bottom).map_or(Vec::new(), |m| m.collect())
+            }
+            None => Vec::new(),
+        };
+
+        let gutter = self.gutter_width();
+        let theme = self.options.theme.clone();
+        let (tab_width, height) = (self.options.tab_width, self.get_height());
+        let wrap_width = if self.options.wrap { Some(self.text_columns()) } else { None };
+        let first_line = self.buffer.get_mark_coords(self.top_line).map_or(0, |(_, y)| y);
+        let cursor_line = self.buffer.get_mark_coords(self.cursor).map_or(0, |(_, y)| y);
+        if let Some(edited) = self.buffer.take_edited_line() {
+            if let Some(ref mut highlighter) = self.highlighter { highlighter.invalidate(edited) }
+        }
+        let mut state = match self.highlighter {
+            Some(ref mut highlighter) => Some(highlighter.state_at(first_line, &self.buffer)),
+            None                      => None,
+        };
+        let cursor_row = if !self.options.highlight_current_line { None } else {
+            self.screen_position(self.cursor).map(|(_, row)| row)
+        };
+        let width = self.get_width();
+        let mut line_start = top;
+        let mut index = 0;      // the screen row to draw on next
+        for (linenum, line) in self.buffer.lines_from(self.top_line).unwrap().enumerate() {
+            if index >= height { break }
+            let rows = wrap_width.map_or(vec![0], |width| layout::wrap(line, width, tab_width));
+            let trailing = trailing_whitespace(line);
+            let tokens = match (self.highlighter.as_ref(), state) {
+                (Some(highlighter), Some(start)) => {
+                    let (tokens, next) = syntax::tokenize(highlighter.syntax, line, start);
+                    state = Some(next);
+                    tokens
+                }
+                _ => Vec::new(),
+            };
+            for (row, &start) in rows.iter().enumerate() {
+                if index >= height { break }
+                let end = if row + 1 < rows.len() { rows[row + 1] } else { line.len() };
+                let text = line[start..end];
+                let (row_start, row_end) = (line_start + start, line_start + end);
+                let invisibles = if !self.options.show_invisibles { None } else {
+                    Some(Invisibles {
+                        trailing_from: if trailing > start { trailing - start } else { 0 },
+                        line_end: self.options.line_end_mark,
+                    })
+                };
+                let shown = draw_line(&mut self.uibuf, text, index, self.left_col, gutter,
+                                      tab_width, invisibles);
+                if gutter > 0 {
+                    // the rows a long line is wrapped onto are marked as going on from the line
+                    let label = if row > 0 { Some(String::from_str("↪")) } else {
+                        self.options.line_numbers.label(first_line + linenum, cursor_line)
+                                                 .map(|number| format!("{}", number))
+                    };
+                    if let Some(label) = label {
+                        draw_gutter(&mut self.uibuf, label[], index, gutter, theme.line_numbers)
+                    }
+                }
+                // under everything else, so that what is drawn over it shows through
+                if cursor_row == Some(index) {
+                    for x in range(0, width) {
+                        self.uibuf.update_cell_face(x, index, theme.current_line);
+                    }
+                }
+                for &(from, to, token) in tokens.iter() {
+                    if from >= end || to <= start { continue }
+                    let span = (cmp::max(from, start) - start, to - start);
+                    for x in span_columns(&self.uibuf, text, self.left_col, gutter, tab_width, span).into_iter() {
+                        self.uibuf.update_cell_face(x, index, theme.token(token));
+                    }
+                }
+                for &(x, invisible) in shown.iter() {
+                    let face = match invisible {
+                        Invisible::Marker   => theme.invisible,
+                        Invisible::Trailing => theme.trailing_whitespace,
+                    };
+                    self.uibuf.update_cell_face(x, index, face);
+                }
+                for &(start, end) in matches.iter() {
+                    if start >= row_end || end <= row_start { continue }
+                    let span = (cmp::max(start, row_start) - row_start, end - row_start);
+                    for x in span_columns(&self.uibuf, text, self.left_col, gutter, tab_width, span).into_iter() {
+                        self.uibuf.update_cell_face(x, index, theme.search_match);
+                    }
+                }
+                for &(start, end) in selection.iter() {
+                    if start < row_end && end > row_start {
+                        let span = (cmp::max(start, row_start) - row_start, end - row_start);
+                        for x in span_columns(&self.uibuf, text, self.left_col, gutter, tab_width, span).into_iter() {
+                            self.uibuf.update_cell_face(x, index, theme.selection);
+                        }
+                    }
+                }
+                if let Some((start, end)) = current {
+                    if start < row_end && end > row_start {
+                        let span = (cmp::max(start, row_start) - row_start, end - row_start);
+                        for x in span_columns(&self.uibuf, text, self.left_col, gutter, tab_width, span).into_iter() {
+                            self.uibuf.update_cell_face(x, index, theme.current_match);
+                        }
+                    }
+                }
+                index += 1;
+            }
+            line_start += line.len();
         }
+        self.draw_extra_cursors();
 
         match self.overlay {
             Overlay::None => self.draw_cursor(frontend),
@@ -94,248 +709,4245 @@ impl<'v> View<'v> {
         self.uibuf.draw_everything(frontend);
     }
 
+    /// Draw the status line across the bottom row, laid out as the options say, or the message
+    /// waiting to be shown in its place.
     pub fn draw_status<T: Frontend>(&mut self, frontend: &mut T) {
-        let buffer_status = self.buffer.status_text();
-        let mut cursor_status = self.buffer.get_mark_coords(self.cursor).unwrap_or((0,0));
-        cursor_status = (cursor_status.0 + 1, cursor_status.1 + 1);
-        let status_text = format!("{} {}", buffer_status, cursor_status).into_bytes();
-        let status_text_len = status_text.len();
         let width = self.get_width();
         let height = self.get_height() - 1;
+        let (text, face) = match self.messages.current() {
+            Some(message) => {
+                let face = match message.level {
+                    Level::Info | Level::Progress => self.options.theme.message,
+                    Level::Error                  => self.options.theme.error,
+                };
+                (truncate(message.text[], width), face)
+            }
+            None => (self.status_text(), self.options.theme.status),
+        };
 
-
+        let mut chars = text.chars();
         for index in range(0, width) {
-            let mut ch: char = ' ';
-            if index < status_text_len {
-                ch = status_text[index] as char;
-            }
-            self.uibuf.update_cell(index, height, ch, CharColor::Black, CharColor::Blue);
+            let ch = chars.next().unwrap_or(' ');
+            self.uibuf.update_cell(index, height, ch, face.fg, face.bg);
+            self.uibuf.update_cell_style(index, height, face.style);
         }
 
         self.uibuf.draw_range(frontend, height, height+1);
     }
 
+    fn status_text(&self) -> String {
+        let (line, col) = self.buffer.get_mark_pos(self.cursor).unwrap_or((0, 0));
+        let name = self.buffer.display_name();
+        let line_ending = self.buffer.ending_text();
+        let status = Status {
+            name: name[],
+            dirty: self.buffer.is_dirty(),
+            line: line,
+            col: col,
+            lines: self.buffer.line_count(),
+            line_ending: line_ending[],
+            kind: self.buffer.kind_text(),
+            read_only: self.buffer.read_only,
+            invisibles: self.options.show_invisibles,
+        };
+        self.options.status_line.format(&status)
+    }
+
     pub fn draw_cursor<T: Frontend>(&mut self, frontend: &mut T) {
-        if let Some(top_line) = self.buffer.get_mark_coords(self.top_line) {
-            if let Some((x, y)) = self.buffer.get_mark_coords(self.cursor) {
-                frontend.draw_cursor(x as int, y as int - top_line.1 as int);
+        let (x, y) = match self.screen_position(self.cursor) {
+            Some((col, row)) => ((self.gutter_width() + col) as int - self.left_col as int, row as int),
+            None             => (-1, -1),
+        };
+        // a cursor scrolled out of view, as by selecting everything, isn't shown
+        if y < 0 || y >= self.get_height() as int || x < 0 || x >= self.get_width() as int {
+            return frontend.draw_cursor(-1, -1)
+        }
+        frontend.draw_cursor(x, y);
+    }
+
+    // Where `mark` is drawn: its column in the text of its screen row, before any scrolling
+    // sideways, and that row counted down from the top of the screen. None if it is above the
+    // top line. Any row further down than the bottom of the screen may be given as just past it.
+    fn screen_position(&self, mark: Mark) -> Option<(uint, uint)> {
+        let tab_width = self.options.tab_width;
+        let (offset, line) = match self.buffer.get_mark_coords(mark) {
+            Some(coords) => coords,
+            None         => return None,
+        };
+        let top = self.top_line_number();
+        if line < top { return None }
+        if !self.options.wrap {
+            return self.buffer.get_mark_screen_offset(mark, tab_width).map(|col| (col, line - top))
+        }
+
+        let (width, height) = (self.text_columns(), self.get_height());
+        let mut lines = match self.buffer.lines_from(self.top_line) {
+            Some(lines) => lines,
+            None        => return None,
+        };
+        let mut row = 0;
+        for _ in range(top, line) {
+            if row > height { return Some((0, row)) }
+            row += lines.next().map_or(1, |text| layout::wrap(text, width, tab_width).len());
+        }
+        match lines.next() {
+            Some(text) => {
+                let rows = layout::wrap(text, width, tab_width);
+                let (line_row, col) = layout::position(text, rows[], offset, tab_width);
+                Some((col, row + line_row))
             }
+            None => Some((0, row)),
         }
     }
 
-    pub fn set_overlay(&mut self, overlay_type: OverlayType) {
-        match overlay_type {
-            OverlayType::Prompt => {
-                self.overlay = Overlay::Prompt {
-                    cursor_x: 1,
-                    prefix: ":",
-                    data: String::new(),
-                };
+    // The frontend only has the one cursor, so the others are drawn by reversing their cell.
+    fn draw_extra_cursors(&mut self) {
+        let gutter = self.gutter_width();
+        for cursor in self.extra_cursors.iter() {
+            if let Some((col, y)) = self.screen_position(*cursor) {
+                if col < self.left_col { continue }
+                let x = gutter + col - self.left_col;
+                if x < self.get_width() && y < self.get_height() {
+                    self.uibuf.update_cell_face(x, y, self.options.theme.cursor);
+                }
             }
         }
     }
 
-    pub fn move_cursor(&mut self, direction: Direction) {
-        self.buffer.shift_mark(self.cursor, direction);
-        self.move_screen();
+    /// Show a message in place of the status line, after any others waiting to be shown, until
+    /// the next key press or it has been up for `message_timeout`.
+    pub fn show_message(&mut self, message: &str) {
+        self.messages.push(message, Level::Info);
     }
 
-    pub fn move_cursor_to_line_end(&mut self) {
-        self.buffer.shift_mark(self.cursor, Direction::LineEnd);
-        self.move_screen();
+    /// As `show_message`, for something that went wrong.
+    pub fn show_error(&mut self, message: &str) {
+        self.messages.push(message, Level::Error);
     }
 
-    pub fn move_cursor_to_line_start(&mut self) {
-        self.buffer.shift_mark(self.cursor, Direction::LineStart);
-        self.move_screen();
+    /// As `show_message`, for how far something has got, which the next message replaces.
+    pub fn show_progress(&mut self, message: &str) {
+        self.messages.push(message, Level::Progress);
     }
 
-    //Update the top_line mark if necessary to keep the cursor on the screen.
-    fn move_screen(&mut self) {
-        if let (Some(cursor), Some(top_line)) = (self.buffer.get_mark_coords(self.cursor),
-                                                 self.buffer.get_mark_coords(self.top_line)) {
-            // FIXME
-            // match cursor.0 as int - self.left_col as int {
-            //     x if x < 0 => { self.left_col -= x as uint; }
-            //     x if x > self.get_width() as int => { self.left_col += x as uint - self.get_width()}
-            //     _ => { }
-            // }
+    /// Take away the message being shown, for the next one waiting, if any.
+    pub fn clear_message(&mut self) {
+        self.messages.dismiss();
+    }
 
-            let cursor_linenum = cursor.1 as int;
-            let cursor_offset = cursor_linenum - top_line.1 as int;
-            let height = self.get_height() as int;
+    pub fn has_message(&self) -> bool {
+        !self.messages.is_empty()
+    }
 
-            if cursor_offset >= (height - 5) {
-                // moving down
-                let times = cursor_offset - (height - 5) + 1;
-                self.buffer.shift_mark(self.top_line, Direction::Down(times as uint));
-            } else if cursor_offset < 5 {
-                // moving up
-                self.buffer.shift_mark(self.top_line, Direction::Up(1));
-            }
+    pub fn set_overlay(&mut self, overlay_type: OverlayType) {
+        let prefix = match overlay_type {
+            OverlayType::Prompt   => ":",
+            OverlayType::GotoLine => "Go to line: ",
+            OverlayType::SaveAs   => "Save as: ",
+            OverlayType::ConfirmOverwrite => "File exists, overwrite? (y/n) ",
+            OverlayType::FileChanged => "File changed on disk: (o)verwrite, (r)eload or (c)ancel? ",
+            OverlayType::ConfirmRevert => "Discard unsaved changes? (y/n) ",
+            OverlayType::ConfirmReadOnly => "Buffer is read-only, save over the file anyway? (y/n) ",
+            OverlayType::ConfirmCreateDir => "Create the directory? (y/n) ",
+            OverlayType::ConfirmRecover => "Recover the text from it? Otherwise it is discarded. (y/n) ",
+            OverlayType::ConfirmBinary => "(h)ex view, (o)pen as text anyway, or (c)ancel? ",
+            OverlayType::ConfirmQuit => "Unsaved changes: (s)ave and quit, (q)uit anyway, or (c)ancel? ",
+            OverlayType::OpenFile => "Open: ",
+            OverlayType::OpenRecent => "Open recent: ",
+            OverlayType::Grep => "Grep: ",
+            OverlayType::CountMatches => "Count matches: ",
+            OverlayType::GrepIn => "Grep in: ",
+            OverlayType::ReplaceFrom => "Replace: ",
+            OverlayType::ReplaceWith => "Replace with: ",
+            OverlayType::ReplaceAll => "Replace all: ",
+            OverlayType::ReplaceAllWith => "Replace all with: ",
+            OverlayType::ConfirmReplace => "Replace? (y)es, (n)o, (a)ll the rest, or (q)uit ",
+        };
+        self.overlay = Overlay::prompt(overlay_type, prefix);
+        if let Some(history) = prompt_history(&mut self.histories, overlay_type) {
+            history.reset();
+        }
+        if let OverlayType::GrepIn = overlay_type {
+            let dir = format!("{}/", self.grep_dir().display());
+            self.overlay.offer(vec![dir]);
+        }
+        if let OverlayType::OpenRecent = overlay_type {
+            let current = self.buffer.file_path.clone();
+            let choices = self.recent.files().iter()
+                                     .filter(|path| Some(*path) != current.as_ref())
+                                     .map(|path| format!("{}", path.display()))
+                                     .collect();
+            self.overlay.offer(choices);
         }
     }
 
-    //----- TEXT EDIT METHODS ----------------------------------------------------------------------
-
-    pub fn delete_char(&mut self, direction: Direction) {
-        match direction {
-            Direction::Left(1) if self.buffer.get_mark_idx(self.cursor) != Some(0) => {
-                self.move_cursor(direction);
-                self.buffer.remove_char(self.cursor);
-            }
-            Direction::Right(1) if self.buffer.get_mark_idx(self.cursor) != Some(self.buffer.len()) => {
-                self.buffer.remove_char(self.cursor);
+    /// Send a key to the overlay. In prompts with a history, Up and Down recall earlier and later
+    /// entries, and what is entered is added to it.
+    pub fn handle_overlay_key(&mut self, key: Key) -> OverlayEvent {
+        if let Some(kind) = self.overlay.overlay_type() {
+            if let Some(history) = prompt_history(&mut self.histories, kind) {
+                if key == Key::Up || key == Key::Down {
+                    let entry = if key == Key::Up { history.previous(self.overlay.input()) }
+                                else { history.next() };
+                    if let Some(entry) = entry { self.overlay.set_input(entry) }
+                    return OverlayEvent::Ok
+                }
+                let event = self.overlay.handle_key_event(key);
+                if let OverlayEvent::Finished(Some(ref input)) = event { history.add(input[]) }
+                return event
             }
-            _ => {}
         }
+        self.overlay.handle_key_event(key)
     }
 
-    pub fn insert_tab(&mut self) {
-        // A tab is just 4 spaces
-        for _ in range(0i, 4) {
-            self.insert_char(' ');
+    pub fn move_cursor(&mut self, direction: Direction) {
+        let wrap = self.options.wrap;
+        for &cursor in self.cursors().iter() {
+            match direction {
+                Direction::Up(n) if wrap   => self.move_by_rows(cursor, n, false),
+                Direction::Down(n) if wrap => self.move_by_rows(cursor, n, true),
+                _                          => self.buffer.shift_mark(cursor, direction),
+            }
         }
+        self.merge_cursors();
+        self.move_screen();
     }
 
-    pub fn insert_char(&mut self, ch: char) {
-        self.buffer.insert_char(self.cursor, ch as u8);
-        self.move_cursor(Direction::Right(1))
-    }
+    // Move `cursor` down, or up, `n` rows of the screen, onto the rows that long lines are
+    // wrapped onto as well as the lines, keeping to its column on the screen where the row it
+    // lands on is long enough. Going past the last or first line goes to the end or the start.
+    fn move_by_rows(&mut self, cursor: Mark, n: uint, down: bool) {
+        let (width, tab_width) = (self.text_columns(), self.options.tab_width);
+        for _ in range(0, n) {
+            let (idx, (offset, linenum)) = match (self.buffer.get_mark_idx(cursor),
+                                                  self.buffer.get_mark_coords(cursor)) {
+                (Some(idx), Some(coords)) => (idx, coords),
+                _                         => return,
+            };
+            let (line_start, line) = (idx - offset, self.line_text(linenum));
+            let rows = layout::wrap(line[], width, tab_width);
+            let (row, col) = layout::position(line[], rows[], offset, tab_width);
 
-    pub fn undo(&mut self) {
-        let point = if let Some(transaction) = self.buffer.undo() { transaction.end_point }
-                    else { return; };
-        self.buffer.set_mark(self.cursor, point);
+            let (start, line, rows, row) = if down && row + 1 < rows.len() {
+                (line_start, line, rows, row + 1)
+            } else if down && line.last() == Some(&b'\n') {
+                let next = self.line_text(linenum + 1);
+                let next_rows = layout::wrap(next[], width, tab_width);
+                (line_start + line.len(), next, next_rows, 0)
+            } else if down {
+                return self.buffer.set_mark(cursor, line_start + line.len())
+            } else if row > 0 {
+                (line_start, line, rows, row - 1)
+            } else if linenum > 0 {
+                let previous = self.line_text(linenum - 1);
+                let previous_rows = layout::wrap(previous[], width, tab_width);
+                let last = previous_rows.len() - 1;
+                (line_start - previous.len(), previous, previous_rows, last)
+            } else {
+                return self.buffer.set_mark(cursor, 0)
+            };
+            self.buffer.set_mark(cursor, start + layout::offset_at(line[], rows[], row, col, tab_width));
+        }
     }
 
-    pub fn redo(&mut self) {
-        let point = if let Some(transaction) = self.buffer.redo() { transaction.end_point }
-                    else { return; };
-        self.buffer.set_mark(self.cursor, point);
+    // The bytes of the (0-based) line `linenum`, newline and all.
+    fn line_text(&self, linenum: uint) -> Vec<u8> {
+        self.buffer.lines_at(linenum).and_then(|mut lines| lines.next())
+                                     .map_or(Vec::new(), |line| line.to_vec())
     }
 
-}
+    /// Add another cursor on the line below the lowest one, at the same column.
+    pub fn add_cursor_below(&mut self) {
+        let lowest = self.cursors().into_iter()
+                                   .filter_map(|c| self.buffer.get_mark_idx(c))
+                                   .max();
 
-pub fn draw_line(buf: &mut UIBuffer, line: &[u8], idx: uint, left: uint) {
-    let width = buf.get_width() - 1;
-    let mut wide_chars = 0;
-    for line_idx in range(left, left + width) {
-        if line_idx < line.len() {
-            match line[line_idx] {
-                b'\t'   => {
-                    let w = 4 - line_idx % 4;
-                    for _ in range(0, w) {
-                        buf.update_cell_content(line_idx + wide_chars - left, idx, ' ');
-                    }
-                }
-                b'\n'   => buf.update_cell_content(line_idx + wide_chars - left, idx, ' '),
-                _       => buf.update_cell_content(line_idx + wide_chars - left, idx,
-                                                   line[line_idx] as char),
-            }
-            wide_chars += (line[line_idx] as char).width(false).unwrap_or(1) - 1;
-        } else { buf.update_cell_content(line_idx + wide_chars - left, idx, ' '); }
-    }
-    if line.len() >= width {
-        buf.update_cell_content(width + wide_chars, idx, '→');
+        if let Some(idx) = lowest {
+            let cursor = self.add_cursor_at(idx);
+            self.buffer.shift_mark(cursor, Direction::Down(1));
+            self.merge_cursors();
+        }
     }
 
-}
-
-#[cfg(test)]
-mod tests {
-
-    use buffer::Direction;
-    use view::View;
-    use input::Input;
+    // Add another cursor at idx, giving its mark.
+    fn add_cursor_at(&mut self, idx: uint) -> Mark {
+        let id = range(1, self.extra_cursors.len() + 2).find(|id| {
+            !self.extra_cursors.contains(&Mark::Cursor(*id))
+        }).unwrap();
+        let cursor = Mark::Cursor(id);
+        self.buffer.set_mark(cursor, idx);
+        self.extra_cursors.push(cursor);
+        cursor
+    }
 
-    fn setup_view<'v>(testcase: &'static str) -> View<'v> {
-        let mut view = View::new(Input::Filename(None), 50, 50);
-        for ch in testcase.chars() {
-            view.insert_char(ch);
+    /// Remove every cursor but the main one, and stop selecting.
+    pub fn collapse_cursors(&mut self) {
+        self.buffer.clear_selection();
+        for cursor in self.extra_cursors.iter() {
+            self.buffer.remove_mark(*cursor);
         }
-        view.buffer.set_mark(view.cursor, 0);
-        view
+        self.extra_cursors.clear();
     }
 
-    #[test]
-    fn test_move_cursor_down() {
-        let mut view = setup_view("test\nsecond");
-        view.move_cursor(Direction::Down(1));
-        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap().1, 1);
-        assert_eq!(view.buffer.lines_from(view.cursor).unwrap().next().unwrap(), b"second"[]);
+    // All the cursors of this view, the main one first.
+    fn cursors(&self) -> Vec<Mark> {
+        let mut cursors = vec![self.cursor];
+        cursors.push_all(self.extra_cursors.as_slice());
+        cursors
     }
 
-    #[test]
-    fn test_move_cursor_up() {
-        let mut view = setup_view("test\nsecond");
-        view.move_cursor(Direction::Down(1));
-        view.move_cursor(Direction::Up(1));
-        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap().1, 0);
-        assert_eq!(view.buffer.lines_from(view.cursor).unwrap().next().unwrap(), b"test\n"[]);
+    // Remove any extra cursors that have ended up on the same spot as another cursor.
+    fn merge_cursors(&mut self) {
+        let mut seen = vec![self.buffer.get_mark_idx(self.cursor)];
+        let mut kept = Vec::new();
+        for &cursor in self.extra_cursors.iter() {
+            let idx = self.buffer.get_mark_idx(cursor);
+            if seen.contains(&idx) {
+                self.buffer.remove_mark(cursor);
+            } else {
+                seen.push(idx);
+                kept.push(cursor);
+            }
+        }
+        self.extra_cursors = kept;
     }
 
-    #[test]
-    fn test_insert_line() {
-        let mut view = setup_view("test\nsecond");
-        view.move_cursor(Direction::Right(1));
-        view.insert_char('\n');
+    /// Start selecting from the cursor, or stop if a selection has already been started. The
+    /// selection runs from there to wherever the cursor is moved, until an edit.
+    pub fn toggle_selection(&mut self) {
+        if self.buffer.is_selecting() { self.buffer.clear_selection() }
+        else { self.buffer.start_selection(self.cursor) }
+    }
 
-        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 1))
+    /// As `toggle_selection`, for a block selection: the rectangle with the cursor at one corner
+    /// and where it started at the other.
+    ///
+    /// Typing into a block puts what is typed on every one of its lines at its left edge, in
+    /// place of what was in the block. Lines too short to reach the left edge are padded with
+    /// spaces up to it, as they are when a block is pasted; copying a block gives them nothing.
+    pub fn toggle_block_selection(&mut self) {
+        if self.buffer.is_selecting() { self.buffer.clear_selection() }
+        else { self.buffer.start_block_selection(self.cursor) }
     }
 
-    #[test]
-    fn test_insert_char() {
-        let mut view = setup_view("test\nsecond");
-        view.insert_char('t');
+    /// Select the whole of the cursor's line, along with its newline.
+    pub fn select_line(&mut self) {
+        if let Some((start, end)) = self.buffer.line_range(self.cursor) {
+            let end = cmp::min(end + 1, self.buffer.len() - 1);
+            self.buffer.select_range(self.cursor, start, end);
+            self.move_screen();
+        }
+    }
 
-        assert_eq!(view.buffer.lines().next().unwrap(), b"ttest\n"[]);
+    /// Select the whole buffer, with the cursor at its end. The view stays where it was unless
+    /// the `select_all_keeps_view` option is off. An empty buffer has nothing to select.
+    pub fn select_all(&mut self) {
+        let end = self.buffer.len() - 1;
+        if end == 0 { return self.buffer.clear_selection() }
+        self.buffer.select_range(self.cursor, 0, end);
+        if !self.options.select_all_keeps_view { self.move_screen() }
     }
 
-    #[test]
-    fn test_delete_char_to_right() {
-        let mut view = setup_view("test\nsecond");
-        view.delete_char(Direction::Right(1));
+    /// Select the text object `object` around the cursor: the word it is on for `w`, or what is
+    /// between the innermost pair of brackets or quotes around it, given by either of the pair, or
+    /// `b` for whichever bracket pair is innermost. `around` takes in the brackets or quotes too,
+    /// or for a word, the spaces after it.
+    pub fn select_object(&mut self, object: char, around: bool) {
+        let idx = match self.buffer.get_mark_idx(self.cursor) {
+            Some(idx) => idx,
+            None      => return,
+        };
+        let delimited = match object {
+            'w' => {
+                let word = if around { self.buffer.word_with_space(self.cursor) }
+                           else { self.buffer.word_at(self.cursor) };
+                match word {
+                    Some((start, end)) => return self.buffer.select_range(self.cursor, start, end),
+                    None               => return self.show_message("Not on a word"),
+                }
+            }
+            '"' | '\'' | '`' => self.buffer.enclosing_quotes(idx, object as u8),
+            'b' => {
+                let pairs = [(b'(', b')'), (b'[', b']'), (b'{', b'}')];
+                pairs.iter().filter_map(|&(open, close)| {
+                    self.buffer.enclosing_brackets(idx, open, close)
+                }).max_by(|&(start, _)| start)
+            }
+            '(' | ')' => self.buffer.enclosing_brackets(idx, b'(', b')'),
+            '[' | ']' => self.buffer.enclosing_brackets(idx, b'[', b']'),
+            '{' | '}' => self.buffer.enclosing_brackets(idx, b'{', b'}'),
+            _         => None,
+        };
+        match delimited {
+            Some((open, close)) if around => self.buffer.select_range(self.cursor, open, close + 1),
+            Some((open, close))           => self.buffer.select_range(self.cursor, open + 1, close),
+            None => {
+                let message = match object {
+                    'b'       => String::from_str("Not inside brackets"),
+                    '(' | ')' => String::from_str("Not inside ()"),
+                    '[' | ']' => String::from_str("Not inside []"),
+                    '{' | '}' => String::from_str("Not inside {}"),
+                    quote     => format!("Not inside {}{}", quote, quote),
+                };
+                self.show_message(message[]);
+            }
+        }
+    }
+
+    /// Grow the selection, or select from the cursor, to the word around it, then its lines, its
+    /// paragraphs and the whole buffer as this is done again.
+    pub fn expand_selection(&mut self) {
+        let cursor = match self.buffer.get_mark_idx(self.cursor) {
+            Some(cursor) => cursor,
+            None         => return,
+        };
+        let anchor = if self.buffer.is_selecting() { self.buffer.get_mark_idx(Mark::Anchor) }
+                     else { None };
+        let (start, end) = self.buffer.selection_range().unwrap_or((cursor, cursor));
+        let (start, end) = match self.buffer.enclosing_range(start, end) {
+            Some(range) => range,
+            None        => return,
+        };
+        let mut previous = self.take_expansion().map_or(Vec::new(), |expansion| expansion.previous);
+        previous.push((anchor, cursor));
+        self.buffer.select_range(self.cursor, start, end);
+        self.expansion = Some(Expansion { extent: (start, end), previous: previous });
+        self.move_screen();
+    }
+
+    /// Undo the last step `expand_selection` grew the selection by, back to exactly what it was
+    /// before, or to no selection at all if there was none.
+    pub fn shrink_selection(&mut self) {
+        let mut expansion = match self.take_expansion() {
+            Some(expansion) => expansion,
+            None            => return,
+        };
+        match expansion.previous.pop() {
+            Some((Some(anchor), cursor)) => {
+                self.buffer.select_range(self.cursor, anchor, cursor);
+                expansion.extent = (anchor, cursor);
+                if !expansion.previous.is_empty() { self.expansion = Some(expansion) }
+            }
+            Some((None, cursor)) => {
+                self.buffer.clear_selection();
+                self.buffer.set_mark(self.cursor, cursor);
+            }
+            None => {}
+        }
+        self.move_screen();
+    }
+
+    // The steps the selection was grown by, if it is still what they grew it to.
+    fn take_expansion(&mut self) -> Option<Expansion> {
+        self.expansion.take().and_then(|expansion| {
+            let (anchor, cursor) = expansion.extent;
+            let expanded = self.buffer.is_selecting() && !self.buffer.is_block_selection()
+                && self.buffer.get_mark_idx(Mark::Anchor) == Some(anchor)
+                && self.buffer.get_mark_idx(self.cursor) == Some(cursor);
+            if expanded { Some(expansion) } else { None }
+        })
+    }
+
+    // Delete the selected text, leaving the cursor where it was, so that what is typed replaces
+    // it. For a block, the cursor is left at its top left corner. None if nothing is selected.
+    fn delete_selection(&mut self) -> Option<String> {
+        if self.buffer.is_block_selection() {
+            let deleted = self.buffer.delete_block(self.options.tab_width, false);
+            if let Some(&(_, edge)) = deleted.first() { self.buffer.set_mark(self.cursor, edge) }
+            let lines: Vec<String> = deleted.into_iter().map(|(text, _)| text).collect();
+            return Some(lines.connect("\n"))
+        }
+        let (start, end) = match self.buffer.selection_range() {
+            Some(range) => range,
+            None        => return None,
+        };
+        let removed = self.buffer.replace_range(start, end, "");
+        self.buffer.set_mark(self.cursor, start);
+        removed
+    }
+
+    // Delete what is in a block selection and put a cursor at its left edge on each of its
+    // lines, so that what is typed goes in on every line. False if there is no block selection.
+    fn cursors_from_block(&mut self) -> bool {
+        let edges: Vec<uint> = self.buffer.delete_block(self.options.tab_width, true)
+                                          .into_iter().map(|(_, edge)| edge).collect();
+        if edges.is_empty() { return false }
+        self.collapse_cursors();
+        self.buffer.set_mark(self.cursor, edges[0]);
+        for &edge in edges.slice_from(1).iter() {
+            self.add_cursor_at(edge);
+        }
+        true
+    }
+
+    /// Keep the selected text for pasting, ending the selection. Without a selection, the whole
+    /// of the cursor's line is kept instead.
+    pub fn copy(&mut self) {
+        let register = match self.buffer.block_ranges(self.options.tab_width) {
+            Some((_, ranges)) => Some(Register::Block(ranges.into_iter().map(|(start, end)| {
+                self.buffer.text_range(start, end)
+            }).collect())),
+            None => match self.buffer.selection_range() {
+                Some((start, end)) => Some(Register::Text(self.buffer.text_range(start, end))),
+                None               => self.buffer.line_range(self.cursor).map(|(start, end)| {
+                    Register::Line(self.buffer.text_range(start, end))
+                }),
+            },
+        };
+        if let Some(register) = register {
+            self.kills.push(register);
+            self.copy_to_clipboard();
+        }
+        self.buffer.clear_selection();
+    }
+
+    /// Copy the selected text, or the cursor's line, and delete it.
+    pub fn cut(&mut self) {
+        if self.refuse_read_only() { return }
+        if self.buffer.is_block_selection() {
+            self.cut_block();
+        } else if let Some(removed) = self.delete_selection() {
+            self.kills.push(Register::Text(removed));
+            self.copy_to_clipboard();
+        } else {
+            self.copy();
+            self.buffer.delete_line(self.cursor);
+        }
+        self.move_screen();
+    }
+
+    // Cut a block selection, keeping its lines for pasting as a block.
+    fn cut_block(&mut self) {
+        let deleted = self.buffer.delete_block(self.options.tab_width, false);
+        if let Some(&(_, edge)) = deleted.first() { self.buffer.set_mark(self.cursor, edge) }
+        self.kills.push(Register::Block(deleted.into_iter().map(|(text, _)| text).collect()));
+        self.copy_to_clipboard();
+    }
+
+    /// Insert the text last copied, cut or deleted at the cursor, in place of any selection. A
+    /// whole line goes on a line of its own below the cursor's line, with the cursor at its
+    /// start, and a block goes down the lines from the cursor's, at the cursor's column.
+    pub fn paste(&mut self) {
+        if self.refuse_read_only() { return }
+        let register = match self.kills.newest() {
+            Some(register) => register.clone(),
+            None           => return,
+        };
+        self.yank(register);
+    }
+
+    /// As `paste`, moving what is pasted to the indentation of the cursor's line, with tabs
+    /// and spaces as that line has them.
+    pub fn paste_reindented(&mut self) {
+        if self.refuse_read_only() { return }
+        let indent: String = match self.buffer.line_range(self.cursor) {
+            Some((start, end)) => {
+                self.buffer.text_range(start, end).chars().take_while(|&c| c == ' ' || c == '\t')
+                                                  .collect()
+            }
+            None => return,
+        };
+        let with_tabs = if indent.is_empty() { self.options.indent_with_tabs }
+                        else { indent.contains_char('\t') };
+        let register = match self.kills.newest() {
+            Some(register) => register.reindented(indent[], with_tabs, self.options.tab_width),
+            None           => return,
+        };
+        self.yank(register);
+    }
+
+    /// Straight after a paste, swap what it put in for the kill before it in the kill ring,
+    /// going round to the most recent again after the oldest.
+    pub fn yank_pop(&mut self) {
+        if self.refuse_read_only() { return }
+        let yank = match self.last_yank {
+            Some(yank) if yank.revision == self.buffer.log.revision() => yank,
+            _ => return self.show_message("The last command was not a paste"),
+        };
+        let register = match self.kills.older() {
+            Some(register) => register.clone(),
+            None           => return,
+        };
+        self.buffer.undo();
+        // put back the selection the paste replaced, for the older kill to replace in turn
+        if let Some((anchor, block)) = yank.selection {
+            self.buffer.set_mark(self.cursor, anchor);
+            if block { self.buffer.start_block_selection(self.cursor) }
+            else { self.buffer.start_selection(self.cursor) }
+        }
+        self.buffer.set_mark(self.cursor, yank.cursor);
+        self.yank(register);
+    }
+
+    // Paste from the kill ring, remembering the paste for `yank_pop`.
+    fn yank(&mut self, register: Register) {
+        let cursor = self.buffer.get_mark_idx(self.cursor).unwrap_or(0);
+        let selection = self.buffer.get_mark_idx(Mark::Anchor).map(|anchor| {
+            (anchor, self.buffer.is_block_selection())
+        });
+        self.insert_pasted(register);
+        self.last_yank = Some(Yank {
+            revision: self.buffer.log.revision(),
+            cursor: cursor,
+            selection: selection,
+        });
+    }
+
+    /// Insert text pasted into the terminal at every cursor, in place of any selection, just as
+    /// it is: nothing is auto-indented or paired, and it is undone in one step.
+    pub fn paste_text(&mut self, text: &str) {
+        if self.refuse_read_only() { return }
+        let revision = self.buffer.log.revision();
+        self.delete_selection();
+        for &cursor in self.cursors().iter() {
+            self.buffer.insert_str(cursor, text);
+        }
+        self.buffer.log.group_since(revision);
+        self.merge_cursors();
+        self.move_screen();
+    }
+
+    /// Insert the text on the system clipboard at the cursor, in place of any selection. Without
+    /// a system clipboard to paste from, the newest kill is pasted instead.
+    pub fn paste_from_clipboard(&mut self) {
+        if self.refuse_read_only() { return }
+        let pasted = match self.clipboard {
+            Some(ref mut clipboard) if !self.clipboard_failed => clipboard.paste(),
+            _                                                 => return self.paste(),
+        };
+        match pasted {
+            Ok(text) => self.insert_pasted(Register::Text(text)),
+            Err(e)   => {
+                self.clipboard_failed = true;
+                self.paste();
+                self.show_error(format!("System clipboard unavailable, using the editor's own: {}", e)[]);
+            }
+        }
+    }
+
+    fn insert_pasted(&mut self, register: Register) {
+        let revision = self.buffer.log.revision();
+        self.delete_selection();
+        match register {
+            Register::Text(ref text) => self.buffer.insert_str(self.cursor, text[]),
+            Register::Line(ref text) => {
+                if let Some((_, end)) = self.buffer.line_range(self.cursor) {
+                    self.buffer.set_mark(self.cursor, end);
+                    self.buffer.insert_str(self.cursor, format!("\n{}", text)[]);
+                    self.buffer.set_mark(self.cursor, end + 1);
+                }
+            }
+            Register::Block(ref lines) => {
+                self.buffer.insert_block(self.cursor, lines[], self.options.tab_width);
+            }
+        }
+        self.buffer.log.group_since(revision);
+        self.move_screen();
+    }
+
+    // Put the newest kill on the system clipboard too. The first time that fails, say why, and
+    // leave copying to the kill ring alone from then on.
+    fn copy_to_clipboard(&mut self) {
+        let text = match self.kills.newest() {
+            Some(register) => register.text(),
+            None           => return,
+        };
+        let copied = match self.clipboard {
+            Some(ref mut clipboard) if !self.clipboard_failed => clipboard.copy(text[]),
+            _                                                 => return,
+        };
+        if let Err(e) = copied {
+            self.clipboard_failed = true;
+            self.show_error(format!("System clipboard unavailable, using the editor's own: {}", e)[]);
+        }
+    }
+
+    pub fn move_cursor_to_line_end(&mut self) {
+        self.buffer.shift_mark(self.cursor, Direction::LineEnd);
+        self.move_screen();
+    }
+
+    pub fn set_mark(&mut self, name: char) {
+        if let Some(idx) = self.buffer.get_mark_idx(self.cursor) {
+            self.buffer.set_mark(Mark::Named(name), idx);
+        }
+    }
+
+    pub fn goto_mark(&mut self, name: char) {
+        if let Some(idx) = self.buffer.get_mark_idx(Mark::Named(name)) {
+            self.buffer.set_mark(self.cursor, idx);
+            self.move_screen();
+        }
+    }
+
+    /// Move every cursor to the start of its line.
+    ///
+    /// With the `smart_home` option on, that is the first non-whitespace character of the line,
+    /// unless the cursor is already there, in which case it is column 0. Repeating the move
+    /// toggles between the two.
+    pub fn move_cursor_to_line_start(&mut self) {
+        for &cursor in self.cursors().iter() {
+            let target = match (self.buffer.get_mark_idx(cursor), self.buffer.get_mark_pos(cursor)) {
+                (Some(idx), Some((line, _))) if self.options.smart_home => {
+                    self.buffer.first_non_whitespace(line).and_then(|first| {
+                        if first != idx { Some(first) } else { None }
+                    })
+                }
+                _ => None,
+            };
+            match target {
+                Some(idx) => self.buffer.set_mark(cursor, idx),
+                None      => self.buffer.shift_mark(cursor, Direction::LineStart),
+            }
+        }
+        self.merge_cursors();
+        self.move_screen();
+    }
+
+    /// Move the cursor to the bracket matching the one under it, or the next one on its line.
+    pub fn goto_matching_bracket(&mut self) {
+        let target = self.buffer.get_mark_idx(self.cursor)
+                                .and_then(|idx| self.buffer.find_matching_bracket(idx));
+        match target {
+            Some(idx) => {
+                self.buffer.set_mark(self.cursor, idx);
+                self.move_screen();
+            }
+            None => self.show_message("No matching bracket"),
+        }
+    }
+
+    /// Start an incremental search from the cursor, forward or backward. Until it ends, the
+    /// editor sends keys to the search methods below rather than to the mode.
+    pub fn start_search(&mut self, forward: bool) {
+        let cursor = self.buffer.get_mark_idx(self.cursor).unwrap_or(0);
+        let top_line = self.buffer.get_mark_idx(self.top_line).unwrap_or(0);
+        self.highlight = None;
+        self.histories.search.reset();
+        self.search = Some(Search::new("", forward, self.options.search_case, (cursor, top_line)));
+        self.show_search();
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Add a character to the query, moving to its first match from where the cursor is.
+    pub fn extend_search(&mut self, c: char) {
+        let from = match self.search {
+            Some(ref mut search) => {
+                search.query.push(c);
+                // the match the cursor is on may still match, so it is searched from too
+                search.compile();
+                if search.forward { search.start() }
+                else { search.found.map_or(search.origin.0, |(start, _)| start + 1) }
+            }
+            None => return,
+        };
+        self.search_from(from, false);
+    }
+
+    /// Take the last character off the query, going back to its first match from the start.
+    pub fn shorten_search(&mut self) {
+        let from = match self.search {
+            Some(ref mut search) => {
+                search.query.pop();
+                search.compile();
+                search.wrapped = false;
+                search.origin.0
+            }
+            None => return,
+        };
+        self.search_from(from, false);
+    }
+
+    /// Replace the query with an earlier one from the search history, or a later one, and
+    /// search for it from where the search started.
+    pub fn recall_search(&mut self, earlier: bool) {
+        let from = match self.search {
+            Some(ref mut search) => {
+                let entry = if earlier { self.histories.search.previous(search.query[]) }
+                            else { self.histories.search.next() };
+                match entry {
+                    Some(entry) => search.query = String::from_str(entry),
+                    None        => return,
+                }
+                search.compile();
+                search.wrapped = false;
+                search.origin.0
+            }
+            None => return,
+        };
+        self.search_from(from, false);
+    }
+
+    /// Move on to the next match in the given direction, which the search then carries on in,
+    /// going round to the other end of the buffer after the last one. With nothing typed yet,
+    /// search for the last query again.
+    pub fn search_again(&mut self, forward: bool) {
+        let from = match self.search {
+            Some(ref mut search) => {
+                search.forward = forward;
+                if search.query.is_empty() {
+                    search.query = self.last_search.clone();
+                    search.whole_word = self.last_whole_word;
+                    search.compile();
+                    // from past the cursor, which is usually on the last search's match
+                    if forward { search.origin.0 + 1 } else { search.origin.0 }
+                } else if forward && search.found.is_some() {
+                    search.start() + 1
+                } else {
+                    search.start()
+                }
+            }
+            None => return,
+        };
+        self.search_from(from, true);
+    }
+
+    /// Switch between searching for the query as literal text and as a regular expression.
+    pub fn toggle_search_regex(&mut self) {
+        let from = match self.search {
+            Some(ref mut search) => {
+                search.regex = !search.regex;
+                search.compile();
+                search.wrapped = false;
+                search.origin.0
+            }
+            None => return,
+        };
+        self.search_from(from, false);
+    }
+
+    /// Move on to the next way of matching case: smart case, then ignoring case, then matching
+    /// it exactly.
+    pub fn cycle_search_case(&mut self) {
+        let from = match self.search {
+            Some(ref mut search) => {
+                search.case = match search.case {
+                    Case::Smart  => Case::Ignore,
+                    Case::Ignore => Case::Exact,
+                    Case::Exact  => Case::Smart,
+                };
+                search.compile();
+                search.wrapped = false;
+                search.origin.0
+            }
+            None => return,
+        };
+        self.search_from(from, false);
+    }
+
+    /// End the search, leaving the cursor on the match. Its matches stay highlighted until the
+    /// buffer is edited, another search starts, or `clear_highlight` is called.
+    pub fn end_search(&mut self) {
+        if let Some(mut search) = self.search.take() {
+            if !search.query.is_empty() {
+                self.last_search = search.query.clone();
+                self.last_whole_word = search.whole_word;
+                self.histories.search.add(search.query[]);
+                search.found = None;
+                self.highlight = Some(search);
+                self.highlight_revision = self.buffer.log.revision();
+            }
+        }
+    }
+
+    /// Search for the word the cursor is on as a whole word, moving to its next occurrence, or
+    /// its previous one going backward. The search ends straight away, leaving its matches
+    /// highlighted, and searching again with no query repeats it.
+    pub fn search_word(&mut self, forward: bool) {
+        let (start, end) = match self.buffer.word_at(self.cursor) {
+            Some(word) => word,
+            None       => return self.show_message("no word under cursor"),
+        };
+        self.start_search(forward);
+        if let Some(ref mut search) = self.search {
+            search.query = self.buffer.text_range(start, end);
+            search.whole_word = true;
+            search.case = Case::Exact;
+            search.found = Some((start, end));
+        }
+        self.search_from(if forward { end } else { start }, true);
+        self.end_search();
+    }
+
+    /// Say how many times the search being typed, or the one still highlighted, matches in the
+    /// selection, or the whole buffer without one. With neither search, ask what to count,
+    /// starting from the last search's query.
+    pub fn count_matches(&mut self) {
+        if !self.is_highlighting() { self.highlight = None }
+        let count = match self.search.as_ref().or(self.highlight.as_ref()) {
+            Some(search) => Some(self.count_selected(search)),
+            None         => None,
+        };
+        match count {
+            Some(count) => self.report_matches(count),
+            None        => {
+                self.set_overlay(OverlayType::CountMatches);
+                let last = self.last_search.clone();
+                self.overlay.set_input(last[]);
+            }
+        }
+    }
+
+    /// Say how many times `query` occurs in the selection or the buffer, ignoring case as a
+    /// search would.
+    pub fn count_matches_of(&mut self, query: &str) {
+        if query.is_empty() { return }
+        let search = Search::new(query, true, self.options.search_case, (0, 0));
+        let count = self.count_selected(&search);
+        self.report_matches(count);
+    }
+
+    // How many matches of the search lie wholly within the selection, or the whole buffer.
+    fn count_selected(&self, search: &Search) -> uint {
+        let (from, to) = self.buffer.selection_range().unwrap_or((0, self.buffer.len()));
+        matches_in(&self.buffer, search, from, to).map_or(0, |m| m.filter(|&(_, end)| end <= to).count())
+    }
+
+    fn report_matches(&mut self, count: uint) {
+        if count == 1 { self.show_message("1 match") }
+        else { self.show_message(format!("{} matches", count)[]) }
+    }
+
+    /// Stop highlighting the matches of the last search, leaving the cursor where it is.
+    pub fn clear_highlight(&mut self) {
+        self.highlight = None;
+    }
+
+    pub fn is_highlighting(&self) -> bool {
+        self.highlight.is_some() && self.buffer.log.revision() == self.highlight_revision
+    }
+
+    /// End the search, putting the cursor and the screen back where they were before it.
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            let (cursor, top_line) = search.origin;
+            self.buffer.set_mark(self.cursor, cursor);
+            self.buffer.set_mark(self.top_line, top_line);
+        }
+    }
+
+    // Move the cursor to the match of the query from `from` in the search's direction, only
+    // wrapping around to the other end of the buffer when asked to.
+    fn search_from(&mut self, from: uint, wrap: bool) {
+        let found = match self.search {
+            Some(ref mut search) => {
+                let mut found = find_in(&self.buffer, search, from);
+                if found.is_none() && wrap && !search.query.is_empty() {
+                    let restart = if search.forward { 0 } else { self.buffer.len() };
+                    found = find_in(&self.buffer, search, restart);
+                    search.wrapped = found.is_some();
+                }
+                search.failing = found.is_none() && !search.query.is_empty();
+                if found.is_some() { search.found = found }
+                found
+            }
+            None => return,
+        };
+        if let Some((idx, _)) = found {
+            self.buffer.set_mark(self.cursor, idx);
+            self.move_screen();
+        }
+        self.show_search();
+    }
+
+    fn show_search(&mut self) {
+        let message = match self.search {
+            Some(ref search) => {
+                let error = match search.compiled {
+                    Err(ref e) if search.regex && !search.query.is_empty() => format!(" [{}]", e),
+                    _ => String::new(),
+                };
+                let case = match search.case {
+                    Case::Exact  => "exact case",
+                    Case::Ignore => "ignoring case",
+                    Case::Smart  => "smart case",
+                };
+                format!("{}{}{}{} ({}): {}{}{}",
+                        if search.failing { "Failing search" } else { "Search" },
+                        if search.forward { "" } else { " backward" },
+                        if search.regex { " regex" } else { "" },
+                        if search.whole_word { " word" } else { "" },
+                        case,
+                        search.query,
+                        if search.wrapped { " (wrapped)" } else { "" },
+                        error)
+            }
+            None => return,
+        };
+        self.show_progress(message[]);
+    }
+
+    /// Ask what to replace `pattern` with, once it has been typed.
+    pub fn ask_replacement(&mut self, pattern: &str) {
+        if pattern.is_empty() { return self.show_message("Replace cancelled") }
+        self.replace_pattern = String::from_str(pattern);
+        self.set_overlay(OverlayType::ReplaceWith);
+    }
+
+    /// Replace the pattern typed before with `replacement`, in the matches in the selection, or
+    /// from the cursor to the end of the buffer without one, asking about each one in turn.
+    pub fn start_replace(&mut self, replacement: &str) {
+        if self.refuse_read_only() { return }
+        let from = self.buffer.get_mark_idx(self.cursor).unwrap_or(0);
+        let pattern = self.replace_pattern[];
+        self.replace = Some(match self.buffer.selection_range() {
+            Some((start, end)) => QueryReplace::within(&self.buffer, pattern, replacement, start, end),
+            None               => QueryReplace::new(&self.buffer, pattern, replacement, from),
+        });
+        // the cursor goes from match to match, which would otherwise move the selection about
+        self.buffer.clear_selection();
+        self.next_replace();
+    }
+
+    /// Answer the question asked by `start_replace` about the match at the cursor: "y" replaces
+    /// it, "n" skips it, "a" replaces it and all the rest without asking, and anything else
+    /// stops replacing.
+    pub fn answer_replace(&mut self, answer: &str) {
+        match self.replace {
+            Some(ref mut replace) => match answer {
+                "y" => replace.replace(&mut self.buffer),
+                "n" => replace.skip(),
+                "a" => replace.replace_remaining(&mut self.buffer),
+                _   => {}
+            },
+            None => return,
+        }
+        match answer {
+            "y" | "n" => self.next_replace(),
+            _         => self.finish_replace(),
+        }
+    }
+
+    /// Tidy up after an overlay is closed without an answer.
+    pub fn cancel_overlay(&mut self, overlay: OverlayType) {
+        if let OverlayType::ConfirmReplace = overlay {
+            self.finish_replace();
+        }
+    }
+
+    // Move to the next match to ask about, or finish once there are none left.
+    fn next_replace(&mut self) {
+        let found = match self.replace {
+            Some(ref mut replace) => replace.next_match(&self.buffer),
+            None                  => return,
+        };
+        match found {
+            Some((start, _)) => {
+                self.buffer.set_mark(self.cursor, start);
+                self.move_screen();
+                self.set_overlay(OverlayType::ConfirmReplace);
+            }
+            None => self.finish_replace(),
+        }
+    }
+
+    // Stop replacing, with the cursor at the end of the last replacement.
+    fn finish_replace(&mut self) {
+        if let Some(replace) = self.replace.take() {
+            let (count, last) = replace.finish(&mut self.buffer);
+            if let Some(idx) = last {
+                self.buffer.set_mark(self.cursor, idx);
+                self.move_screen();
+            }
+            self.report_replaced(count);
+        }
+    }
+
+    /// Ask what to replace every occurrence of `pattern` with, once it has been typed.
+    pub fn ask_replace_all_with(&mut self, pattern: &str) {
+        if pattern.is_empty() { return self.show_message("Replace cancelled") }
+        self.replace_pattern = String::from_str(pattern);
+        self.set_overlay(OverlayType::ReplaceAllWith);
+    }
+
+    /// Replace every occurrence of the pattern typed before in the selection, or the whole buffer
+    /// without one, with `replacement`.
+    pub fn replace_all(&mut self, replacement: &str) {
+        if self.refuse_read_only() { return }
+        let (start, end) = self.buffer.selection_range().unwrap_or((0, self.buffer.len()));
+        let count = self.buffer.replace_between(self.replace_pattern[], replacement, start, end);
+        self.move_screen();
+        self.report_replaced(count);
+    }
+
+    fn report_replaced(&mut self, count: uint) {
+        self.show_message(format!("replaced {} occurrence{}", count,
+                                  if count == 1 { "" } else { "s" })[]);
+    }
+
+    /// Move the cursor to the start of the given (1-based) line, scrolling so that the line ends up
+    /// roughly in the middle of the screen.
+    pub fn goto_line(&mut self, linenum: uint) {
+        self.buffer.goto_line(self.cursor, linenum);
+
+        let line = self.buffer.get_mark_coords(self.cursor).map(|(_, line)| line).unwrap_or(0);
+        let half = self.get_height() / 2;
+        let top = if line > half { line - half } else { 0 };
+        self.buffer.goto_line(self.top_line, top + 1);
+    }
+
+    /// Move the cursor to a (1-based) character column of a (1-based) line, as `goto_line` does,
+    /// clamping the column to the end of the line.
+    pub fn goto_position(&mut self, linenum: uint, col: uint) {
+        self.goto_line(linenum);
+        let line_len = match self.buffer.lines_from(self.cursor).and_then(|mut lines| lines.next()) {
+            Some(line) => str::from_utf8(line).map(|s| s.trim_right_matches('\n').chars().count())
+                                              .unwrap_or(0),
+            None       => 0,
+        };
+        if col > 1 {
+            self.move_cursor(Direction::Right(cmp::min(col - 1, line_len)));
+        }
+    }
+
+    /// Put the cursor and the top of the screen back where they were when the file was last
+    /// open. A position the file no longer reaches, as it has changed since, is ignored.
+    pub fn restore_position(&mut self, session: &Session) {
+        let position = match self.buffer.file_path {
+            Some(ref path) => session.position(path),
+            None           => None,
+        };
+        let pos = match position {
+            Some(pos) if pos.line < self.buffer.line_count() && pos.top_line <= pos.line => pos,
+            _ => return,
+        };
+        self.buffer.goto_line(self.cursor, pos.line + 1);
+        let col = match self.buffer.lines_from(self.cursor).and_then(|mut lines| lines.next()) {
+            Some(line) => {
+                let len = if line.ends_with(b"\n") { line.len() - 1 } else { line.len() };
+                // a column in the middle of a character is from some other version of the line
+                if pos.col <= len && (pos.col == len || (line[pos.col] & 0xC0) != 0x80) { pos.col }
+                else { 0 }
+            }
+            None => 0,
+        };
+        let start = self.buffer.get_mark_idx(self.cursor).unwrap_or(0);
+        self.buffer.set_mark(self.cursor, start + col);
+        self.buffer.goto_line(self.top_line, pos.top_line + 1);
+        self.move_screen();
+    }
+
+    /// Record where the cursor and the top of the screen are in the file.
+    pub fn remember_position(&self, session: &mut Session) {
+        if let (Some(path), Some((line, col))) = (self.buffer.file_path.as_ref(),
+                                                  self.buffer.get_mark_pos(self.cursor)) {
+            session.remember(path, Position { line: line, col: col, top_line: self.top_line_number() });
+        }
+    }
+
+    /// Scroll forward by a screenful, less a couple of lines of overlap, taking the cursor along.
+    ///
+    /// The screen stops once the last line is at the bottom of it, and the cursor stops on the last
+    /// line.
+    pub fn page_down(&mut self) {
+        let (page, top) = (self.page_size(), self.top_line_number());
+        let (lines, height) = (self.buffer.line_count(), self.get_height());
+        let last_top = if lines > height { lines - height } else { 0 };
+        let new_top = cmp::max(cmp::min(top + page, last_top), top);
+
+        if new_top > top {
+            self.buffer.shift_mark(self.top_line, Direction::Down(new_top - top));
+        }
+        self.buffer.shift_mark(self.cursor, Direction::Down(page));
+    }
+
+    /// Scroll back by a screenful, less a couple of lines of overlap, taking the cursor along.
+    pub fn page_up(&mut self) {
+        let (page, top) = (self.page_size(), self.top_line_number());
+        let new_top = if top > page { top - page } else { 0 };
+
+        if new_top < top {
+            self.buffer.shift_mark(self.top_line, Direction::Up(top - new_top));
+        }
+        self.buffer.shift_mark(self.cursor, Direction::Up(page));
+    }
+
+    fn page_size(&self) -> uint {
+        let height = self.get_height();
+        if height > 3 { height - 2 } else { 1 }
+    }
+
+    fn top_line_number(&self) -> uint {
+        self.buffer.get_mark_coords(self.top_line).map(|(_, line)| line).unwrap_or(0)
+    }
+
+    //Update the top_line mark if necessary to keep the cursor on the screen.
+    fn move_screen(&mut self) {
+        if self.options.wrap { return self.move_wrapped_screen() }
+        if let (Some(cursor), Some(top_line)) = (self.buffer.get_mark_coords(self.cursor),
+                                                 self.buffer.get_mark_coords(self.top_line)) {
+            let cursor_linenum = cursor.1 as int;
+            let cursor_offset = cursor_linenum - top_line.1 as int;
+            let height = cmp::max(self.get_height() as int, 1);
+            // the lines kept between the cursor and the edges, fewer in a window too short for
+            // them to leave it room
+            let margin = cmp::min(5, (height - 1) / 2);
+
+            if cursor_offset >= (height - margin) {
+                // moving down
+                let times = cursor_offset - (height - margin) + 1;
+                self.buffer.shift_mark(self.top_line, Direction::Down(times as uint));
+            } else if cursor_offset < margin {
+                // moving up, which stops at the first line
+                let times = margin - cursor_offset;
+                self.buffer.shift_mark(self.top_line, Direction::Up(times as uint));
+            }
+        }
+    }
+
+    // As move_screen, counting the rows that long lines are wrapped onto rather than lines.
+    fn move_wrapped_screen(&mut self) {
+        let line = match self.buffer.get_mark_coords(self.cursor) {
+            Some((_, line)) => line,
+            None            => return,
+        };
+        let height = cmp::max(self.get_height(), 1);
+        let margin = cmp::min(5, (height - 1) / 2);
+        // a cursor far off the screen is jumped to, rather than scrolled to a line at a time
+        let top = self.top_line_number();
+        if line < top || line >= top + height {
+            self.buffer.goto_line(self.top_line, line + 1);
+        }
+
+        loop {
+            match self.screen_position(self.cursor) {
+                Some((_, row)) if row >= height - margin && self.top_line_number() < line => {
+                    self.buffer.shift_mark(self.top_line, Direction::Down(1));
+                }
+                _ => break,
+            }
+        }
+        // going back up stops short of a line so long it would push the cursor down too far
+        loop {
+            match self.screen_position(self.cursor) {
+                Some((_, row)) if row < margin && self.top_line_number() > 0 => {
+                    self.buffer.shift_mark(self.top_line, Direction::Up(1));
+                    match self.screen_position(self.cursor) {
+                        Some((_, row)) if row < height - margin => {}
+                        _ => {
+                            self.buffer.shift_mark(self.top_line, Direction::Down(1));
+                            break
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // Scroll sideways by half the width of the text at a time, as far as it takes to bring the
+    // cursor back between the edges. Once scrolled, the leftmost column shows that there is text
+    // hidden to its left, so the cursor is kept to the right of it. Nothing is hidden while long
+    // lines are wrapped.
+    fn scroll_sideways(&mut self) {
+        let col = match self.buffer.get_mark_screen_offset(self.cursor, self.options.tab_width) {
+            Some(col) => col,
+            None      => return,
+        };
+        let width = self.text_columns();
+        if width < 2 || self.options.wrap {
+            self.left_col = 0;
+            return
+        }
+        let step = width / 2;
+
+        if col >= self.left_col + width {
+            self.left_col += ((col - self.left_col - width) / step + 1) * step;
+        } else if self.left_col > 0 && col <= self.left_col {
+            let left = col / step * step;
+            self.left_col = if left == col && left > 0 { left - step } else { left };
+        }
+    }
+
+    // The number of columns of text the screen has room for, between the line numbers and the
+    // column that shows when a line goes on past the right edge.
+    fn text_columns(&self) -> uint {
+        let width = self.get_width();
+        if width > self.gutter_width() { width - 1 - self.gutter_width() } else { 0 }
+    }
+
+    //----- FILE METHODS ---------------------------------------------------------------------------
+
+    /// Save the buffer to its file, asking for a file name if it doesn't have one yet, or what to
+    /// do if something else changed the file.
+    pub fn save_buffer(&mut self) {
+        if self.buffer.file_path.is_none() {
+            self.set_overlay(OverlayType::SaveAs);
+            return
+        }
+        if self.buffer.read_only {
+            self.set_overlay(OverlayType::ConfirmReadOnly);
+            return
+        }
+        if self.buffer.changed_on_disk() {
+            self.set_overlay(OverlayType::FileChanged);
+            return
+        }
+        let dir = self.buffer.file_path.as_ref().map(|path| path.dir_path());
+        if let Some(dir) = dir {
+            if !dir.exists() {
+                self.ask_to_create_dir(&dir);
+                return
+            }
+        }
+        let trimmed = self.trim_before_save();
+        let result = self.buffer.save(&self.options.backup, self.options.final_newline);
+        self.report_save(result, trimmed);
+    }
+
+    /// Save the buffer under a new name, asking first if that would overwrite another file.
+    pub fn save_buffer_as(&mut self, name: &str) {
+        if name.is_empty() {
+            self.show_message("Save cancelled");
+            return
+        }
+        let path = Path::new(name);
+        if path.exists() && self.buffer.file_path.as_ref() != Some(&path) {
+            self.pending_save = Some(path);
+            self.set_overlay(OverlayType::ConfirmOverwrite);
+        } else if !path.dir_path().exists() {
+            self.ask_to_create_dir(&path.dir_path());
+            self.pending_save = Some(path);
+        } else {
+            self.write_buffer_as(path);
+        }
+    }
+
+    fn ask_to_create_dir(&mut self, dir: &Path) {
+        self.pending_save = None;
+        self.show_message(format!("No directory {}", dir.display())[]);
+        self.set_overlay(OverlayType::ConfirmCreateDir);
+    }
+
+    /// Answer the question asked by `save_buffer` or `save_buffer_as` about creating the missing
+    /// directory to save in.
+    pub fn confirm_create_dir(&mut self, confirmed: bool) {
+        let pending = self.pending_save.take();
+        if !confirmed {
+            return self.show_message("Save cancelled")
+        }
+        let trimmed = self.trim_before_save();
+        let result = match pending {
+            Some(path) => self.buffer.save_as_creating_dirs(path, &self.options.backup,
+                                                            self.options.final_newline),
+            None       => self.buffer.save_creating_dirs(&self.options.backup,
+                                                         self.options.final_newline),
+        };
+        self.report_save(result, trimmed);
+    }
+
+    /// Answer the question asked by `save_buffer_as` about overwriting a file.
+    pub fn confirm_overwrite(&mut self, confirmed: bool) {
+        match self.pending_save.take() {
+            Some(path) => {
+                if confirmed { self.write_buffer_as(path) }
+                else { self.show_message("Save cancelled") }
+            }
+            None => {}
+        }
+    }
+
+    /// Answer the question asked by `save_buffer` about a file changed on disk.
+    pub fn answer_file_changed(&mut self, answer: &str) {
+        match answer {
+            "o" => {
+                self.buffer.refresh_disk_stat();
+                self.save_buffer();
+            }
+            "r" => self.confirm_revert(true),
+            _   => self.show_message("Save cancelled"),
+        }
+    }
+
+    /// Answer the question asked by `save_buffer` about a read-only buffer.
+    pub fn confirm_read_only(&mut self, confirmed: bool) {
+        if confirmed {
+            self.buffer.read_only = false;
+            self.save_buffer();
+        } else {
+            self.show_message("Save cancelled");
+        }
+    }
+
+    /// Throw away unsaved changes by reading the file again, asking first if there are any.
+    pub fn revert_buffer(&mut self) {
+        if self.buffer.is_dirty() {
+            self.set_overlay(OverlayType::ConfirmRevert);
+        } else {
+            self.confirm_revert(true);
+        }
+    }
+
+    /// Answer the question asked by `revert_buffer`.
+    pub fn confirm_revert(&mut self, confirmed: bool) {
+        if !confirmed {
+            return self.show_message("Revert cancelled")
+        }
+        match self.buffer.revert() {
+            Ok(())  => self.show_message("Reverted to the file on disk"),
+            Err(e)  => self.show_error(format!("Could not revert: {}", e)[]),
+        }
+        self.move_screen();
+    }
+
+    /// Answer the question asked on opening a file about recovering from its swap file. Unless
+    /// it couldn't be read, the swap file then holds this session's text.
+    pub fn confirm_recover(&mut self, confirmed: bool) {
+        if confirmed {
+            match self.buffer.recover_from_swap() {
+                Ok(()) => self.show_message("Recovered from the swap file, not yet saved"),
+                Err(e) => return self.show_error(format!("Could not recover: {}", e)[]),
+            }
+        }
+        if let Err(e) = self.buffer.write_swap() {
+            self.show_error(format!("Could not write swap file: {}", e)[]);
+        }
+    }
+
+    /// Write the swap file once enough edits have been made since it was last written.
+    pub fn update_swap(&mut self) {
+        if !self.options.swap_file { return }
+        if let Some(Err(e)) = self.buffer.update_swap(self.options.swap_edits) {
+            self.show_error(format!("Could not write swap file: {}", e)[]);
+        }
+    }
+
+    /// Write the buffer to its autosave file, if it has changes that aren't saved.
+    pub fn autosave(&mut self) {
+        if !self.buffer.is_dirty() { return }
+        match self.buffer.autosave() {
+            Some(Ok(_))  => self.show_message("autosaved"),
+            Some(Err(e)) => self.show_error(format!("Could not autosave: {}", e)[]),
+            None         => {}
+        }
+    }
+
+    /// Warn while the file has been changed by something else since it was read, unless there
+    /// is already a message to show.
+    pub fn check_file_changed(&mut self) {
+        if self.messages.is_empty() && self.buffer.changed_on_disk() {
+            self.show_error("File changed on disk");
+        }
+    }
+
+    /// Change the line ending the whole buffer is saved with.
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        if self.refuse_read_only() { return }
+        self.buffer.set_line_ending(ending);
+    }
+
+    /// Switch whether the buffer is saved with a UTF-8 byte order mark.
+    pub fn toggle_bom(&mut self) {
+        if self.refuse_read_only() { return }
+        let bom = !self.buffer.bom;
+        self.buffer.set_bom(bom);
+        if bom { self.show_message("File will be saved with a byte order mark") }
+        else { self.show_message("File will be saved without a byte order mark") }
+    }
+
+    fn write_buffer_as(&mut self, path: Path) {
+        let trimmed = self.trim_before_save();
+        let result = self.buffer.save_as(path, &self.options.backup,
+                                         self.options.final_newline);
+        self.report_save(result, trimmed);
+    }
+
+    // Trim trailing whitespace if the option is on, returning the number of lines trimmed.
+    fn trim_before_save(&mut self) -> uint {
+        if self.options.trim_trailing_whitespace {
+            self.buffer.trim_trailing_whitespace()
+        } else { 0 }
+    }
+
+    fn report_save(&mut self, result: IoResult<Saved>, trimmed: uint) {
+        let saved = match result {
+            Ok(saved) => saved,
+            Err(e)    => return self.show_error(format!("Could not save: {}", e)[]),
+        };
+        if let Some(ref path) = self.buffer.file_path {
+            self.recent.add(path);
+        }
+        // saved as a new name, it may be a new type of file
+        self.choose_syntax();
+        let mut message = format!("wrote {} bytes", saved.bytes);
+        if saved.method == SaveMethod::InPlace {
+            message.push_str(" in place");
+        }
+        if saved.added_newline {
+            message.push_str(", added final newline");
+        }
+        match trimmed {
+            0 => {}
+            1 => message.push_str(", trimmed 1 line"),
+            n => message.push_str(format!(", trimmed {} lines", n)[]),
+        }
+        match saved.backup {
+            Some(Ok(ref path)) => {
+                message.push_str(format!(", backup written to {}", path.display())[])
+            }
+            Some(Err(ref e)) => {
+                message.push_str(format!(", but no backup was made: {}", e)[]);
+                return self.show_error(message[])
+            }
+            None => {}
+        }
+        self.show_message(message[]);
+    }
+
+    //----- TEXT EDIT METHODS ----------------------------------------------------------------------
+
+    // Whether the buffer is read-only, in which case the edit about to be made is refused with a
+    // message saying so.
+    fn refuse_read_only(&mut self) -> bool {
+        if self.buffer.read_only {
+            self.show_error("Buffer is read-only");
+        }
+        self.buffer.read_only
+    }
+
+    /// Go on to the next way of numbering the lines: absolute after none, then relative, then
+    /// none again.
+    pub fn cycle_line_numbers(&mut self) {
+        self.options.line_numbers = match self.options.line_numbers {
+            LineNumbers::Off      => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        };
+        match self.options.line_numbers {
+            LineNumbers::Off      => self.show_message("Line numbers off"),
+            LineNumbers::Absolute => self.show_message("Line numbers on"),
+            LineNumbers::Relative => self.show_message("Line numbers relative to the cursor"),
+        }
+    }
+
+    /// Fit the theme to a terminal that can show `colors` colors, as its frontend says.
+    pub fn set_colors(&mut self, colors: uint) {
+        self.colors = colors;
+        self.options.theme = self.options.theme.fit(colors);
+    }
+
+    /// Go on to the next theme, after the built-in ones going through those in the themes
+    /// directory. One that can't be read is shown as the default theme, with why.
+    pub fn cycle_theme(&mut self) {
+        let names = theme_names();
+        let next = names.iter().position(|name| *name == self.options.theme.name)
+                               .map_or(0, |i| (i + 1) % names.len());
+        let name = names[next][];
+        match Theme::named(name) {
+            Ok(theme) => {
+                self.options.theme = theme.fit(self.colors);
+                self.show_message(format!("Theme: {}", name)[]);
+            }
+            Err(e) => {
+                // keeping its name, so that the next cycle carries on past it
+                self.options.theme = Theme { name: String::from_str(name), ..Theme::dark() };
+                self.show_error(e[]);
+            }
+        }
+    }
+
+    /// Draw the row the cursor is on in the theme's face for it, or stop.
+    pub fn toggle_current_line(&mut self) {
+        self.options.highlight_current_line = !self.options.highlight_current_line;
+        if self.options.highlight_current_line { self.show_message("Highlighting the cursor's line") }
+        else { self.show_message("Not highlighting the cursor's line") }
+    }
+
+    /// Show tabs, non-breaking spaces and the whitespace at the ends of lines, or go back to
+    /// leaving them blank.
+    pub fn toggle_invisibles(&mut self) {
+        self.options.show_invisibles = !self.options.show_invisibles;
+        if self.options.show_invisibles { self.show_message("Showing invisible characters") }
+        else { self.show_message("Hiding invisible characters") }
+    }
+
+    /// Wrap lines too long for the screen onto the rows below, or go back to scrolling sideways
+    /// to them.
+    pub fn toggle_wrap(&mut self) {
+        self.options.wrap = !self.options.wrap;
+        self.left_col = 0;
+        self.move_screen();
+        if self.options.wrap { self.show_message("Long lines wrapped") }
+        else { self.show_message("Long lines scroll sideways") }
+    }
+
+    /// Switch the buffer between read-only and writable.
+    pub fn toggle_read_only(&mut self) {
+        if self.buffer.hex_view {
+            return self.show_error("A hex view can't be made writable")
+        }
+        self.buffer.read_only = !self.buffer.read_only;
+        if self.buffer.read_only { self.show_message("Buffer is now read-only") }
+        else { self.show_message("Buffer is now writable") }
+    }
+
+    /// Delete a character next to every cursor, returning the one deleted at the main cursor.
+    ///
+    /// Nothing is deleted to the left of a cursor at the start of the buffer, or to the right of
+    /// one at the end.
+    pub fn delete_char(&mut self, direction: Direction) -> Option<String> {
+        if self.refuse_read_only() { return None }
+        if let Some(selected) = self.delete_selection() {
+            self.move_screen();
+            return Some(selected)
+        }
+        if let Direction::Left(1) = direction {
+            if let Some(indent) = self.remove_auto_indent() { return Some(indent) }
+            if let Some(pair) = self.remove_auto_pair() { return Some(pair) }
+        }
+
+        // deleting at every cursor is one step to undo
+        let revision = self.buffer.log.revision();
+        let mut deleted = None;
+        for &cursor in self.cursors().iter() {
+            let ch = match direction {
+                Direction::Left(1) if self.buffer.get_mark_idx(cursor) != Some(0) => {
+                    self.buffer.shift_mark(cursor, direction);
+                    self.buffer.remove_char(cursor)
+                }
+                Direction::Right(1) => self.buffer.remove_char(cursor),
+                _                   => None,
+            };
+            if cursor == self.cursor { deleted = ch; }
+        }
+        self.buffer.log.group_since(revision);
+        self.merge_cursors();
+        self.move_screen();
+        deleted
+    }
+
+    /// Delete the word next to the cursor, keeping it in the kill ring. Words deleted one after
+    /// another are kept together, so that they paste back as they were.
+    pub fn delete_word(&mut self, direction: Direction) {
+        if self.refuse_read_only() { return }
+        let (follows, backward) = (self.follows_kill(), match direction {
+            Direction::Left(_) => true,
+            _                  => false,
+        });
+        let removed = self.buffer.delete_word(self.cursor, direction);
+        self.kill(removed[], backward, follows);
+        self.move_screen();
+    }
+
+    /// Delete the cursor's line, keeping it in the kill ring along with any deleted just before.
+    pub fn delete_line(&mut self) {
+        if self.refuse_read_only() { return }
+        let follows = self.follows_kill();
+        let removed = self.buffer.delete_line(self.cursor);
+        self.kill(removed[], false, follows);
+        self.move_screen();
+    }
+
+    // Whether nothing has been edited and the cursor hasn't moved since the last deletion kept
+    // in the kill ring, so that another deletion adds to it.
+    fn follows_kill(&self) -> bool {
+        match self.last_kill {
+            Some((revision, cursor)) => {
+                revision == self.buffer.log.revision()
+                    && self.buffer.get_mark_idx(self.cursor) == Some(cursor)
+            }
+            None => false,
+        }
+    }
+
+    // Keep text just deleted in the kill ring, added to the newest kill if it `follows` that.
+    fn kill(&mut self, removed: &str, backward: bool, follows: bool) {
+        if removed.is_empty() { return }
+        if follows { self.kills.add_to_newest(removed, backward) }
+        else { self.kills.push(Register::Text(String::from_str(removed))) }
+        self.last_kill = self.buffer.get_mark_idx(self.cursor).map(|cursor| {
+            (self.buffer.log.revision(), cursor)
+        });
+        self.copy_to_clipboard();
+    }
+
+    pub fn duplicate_line(&mut self) {
+        if self.refuse_read_only() { return }
+        self.buffer.duplicate_line(self.cursor);
+        self.move_screen();
+    }
+
+    pub fn join_lines(&mut self) {
+        if self.refuse_read_only() { return }
+        self.buffer.join_lines(self.cursor);
+        self.move_screen();
+    }
+
+    pub fn transpose_chars(&mut self) {
+        if self.refuse_read_only() { return }
+        self.buffer.transpose_chars(self.cursor);
+        self.move_screen();
+    }
+
+    /// Convert the selection to upper case, or the word after the cursor if nothing is selected.
+    pub fn upcase_word(&mut self) {
+        self.change_case(true);
+    }
+
+    /// Convert the selection to lower case, or the word after the cursor if nothing is selected.
+    pub fn downcase_word(&mut self) {
+        self.change_case(false);
+    }
+
+    // Convert the selection, or the part of each of its lines in a block, to upper or lower
+    // case, as one step to undo. Without a selection the word after the cursor is converted.
+    fn change_case(&mut self, upper: bool) {
+        if self.refuse_read_only() { return }
+        let ranges = match self.buffer.block_ranges(self.options.tab_width) {
+            Some((_, ranges)) => ranges,
+            None              => self.buffer.selection_range().into_iter().collect(),
+        };
+        if ranges.is_empty() {
+            if upper { self.buffer.upcase_word(self.cursor) }
+            else { self.buffer.downcase_word(self.cursor) }
+        } else {
+            let revision = self.buffer.log.revision();
+            // from the bottom up, so that each range is still where it was found
+            for &(start, end) in ranges.iter().rev() {
+                if upper { self.buffer.upcase_region(start, end) }
+                else { self.buffer.downcase_region(start, end) }
+            }
+            self.buffer.log.group_since(revision);
+        }
+        self.move_screen();
+    }
+
+    pub fn sort_lines(&mut self, reverse: bool) {
+        if self.refuse_read_only() { return }
+        self.buffer.sort_all_lines(self.cursor, reverse);
+        self.move_screen();
+    }
+
+    /// Indent the line of every cursor, and every selected line, by one level.
+    pub fn indent_line(&mut self) {
+        if self.refuse_read_only() { return }
+        let indent = if self.options.indent_with_tabs { String::from_str("\t") }
+                     else { repeat(' ').take(self.options.tab_width).collect() };
+        let revision = self.buffer.log.revision();
+        for (start, end) in self.line_spans().into_iter() {
+            self.buffer.indent_lines(start, end, indent.as_slice());
+        }
+        self.buffer.log.group_since(revision);
+        self.move_screen();
+    }
+
+    /// Remove a level of indentation from the line of every cursor, and every selected line.
+    pub fn dedent_line(&mut self) {
+        if self.refuse_read_only() { return }
+        let width = self.options.tab_width;
+        let revision = self.buffer.log.revision();
+        for (start, end) in self.line_spans().into_iter() {
+            self.buffer.dedent_lines(start, end, width);
+        }
+        self.buffer.log.group_since(revision);
+        self.move_screen();
+    }
+
+    /// Comment out the line of every cursor, or uncomment it if it already is, using the line
+    /// comment leader for the buffer's filetype. The selected lines are commented out together,
+    /// or uncommented if every one of them already is.
+    pub fn toggle_comment(&mut self) {
+        if self.refuse_read_only() { return }
+        let leader = filetype::comment_leader(self.buffer.file_path.as_ref());
+        let revision = self.buffer.log.revision();
+        for (start, end) in self.line_spans().into_iter() {
+            self.buffer.toggle_comment(start, end, leader);
+        }
+        self.buffer.log.group_since(revision);
+        self.move_screen();
+    }
+
+    /// Swap the cursor's line with the one above or below it.
+    pub fn move_line(&mut self, direction: Direction) {
+        if self.refuse_read_only() { return }
+        self.buffer.move_line(self.cursor, direction);
+        self.move_screen();
+    }
+
+    /// Re-break the paragraph around the cursor to the `text_width` option.
+    pub fn fill_paragraph(&mut self) {
+        if self.refuse_read_only() { return }
+        let (width, tab_width) = (self.options.text_width, self.options.tab_width);
+        self.buffer.fill_paragraph(self.cursor, width, tab_width);
+        self.move_screen();
+    }
+
+    /// Convert tabs in the indentation (or the whole line) of every line into spaces.
+    pub fn expand_tabs(&mut self, whole_line: bool) {
+        if self.refuse_read_only() { return }
+        self.buffer.expand_tabs(self.options.tab_width, whole_line);
+        self.move_screen();
+    }
+
+    /// Convert spaces in the indentation (or the whole line) of every line into tabs.
+    pub fn unexpand_tabs(&mut self, whole_line: bool) {
+        if self.refuse_read_only() { return }
+        self.buffer.unexpand_tabs(self.options.tab_width, whole_line);
+        self.move_screen();
+    }
+
+    // The distinct line numbers that the cursors are on.
+    fn cursor_lines(&self, cursors: &[Mark]) -> Vec<uint> {
+        let mut lines: Vec<uint> = cursors.iter()
+                                       .filter_map(|c| self.buffer.get_mark_coords(*c))
+                                       .map(|(_, line)| line)
+                                       .collect();
+        lines.sort();
+        lines.dedup();
+        lines
+    }
+
+    // The cursors' lines and the selected lines, as [start, end) runs of line numbers. Cursors
+    // each get a line of their own, while the selection's lines are taken together, standing for
+    // the line of the cursor at its end.
+    fn line_spans(&self) -> Vec<(uint, uint)> {
+        let selected = self.buffer.selected_lines();
+        let cursors = if selected.is_some() { self.extra_cursors.clone() } else { self.cursors() };
+        let lines = self.cursor_lines(cursors[]).into_iter().filter(|&line| {
+            selected.map_or(true, |(start, end)| line < start || line >= end)
+        });
+        let mut spans: Vec<(uint, uint)> = lines.map(|line| (line, line + 1)).collect();
+        spans.extend(selected.into_iter());
+        spans.sort();
+        spans
+    }
+
+    /// Indent the selected lines when there is a selection, or the line of every cursor with the
+    /// `tab_indents_line` option on. Otherwise insert a tab at every cursor with the
+    /// `indent_with_tabs` option on, or the spaces that take each cursor to the next tab stop.
+    pub fn insert_tab(&mut self) {
+        if self.refuse_read_only() { return }
+        if self.buffer.selection_range().is_some() || self.options.tab_indents_line {
+            return self.indent_line()
+        }
+        if self.options.indent_with_tabs { return self.insert_char('\t') }
+
+        let revision = self.buffer.log.revision();
+        if !self.cursors_from_block() { self.delete_selection(); }
+        let tab_width = self.options.tab_width;
+        for &cursor in self.cursors().iter() {
+            let col = self.buffer.get_mark_screen_offset(cursor, tab_width).unwrap_or(0);
+            let spaces: String = repeat(' ').take(tab_width - col % tab_width).collect();
+            self.buffer.insert_str(cursor, spaces.as_slice());
+        }
+        self.buffer.log.group_since(revision);
+        self.merge_cursors();
+        self.move_screen();
+    }
+
+    /// Start a new line at every cursor, indented like the line it was split from when the
+    /// `auto_indent` option is on.
+    pub fn insert_newline(&mut self) {
+        if self.refuse_read_only() { return }
+        // replacing any selection and breaking the line at every cursor is one step to undo
+        let revision = self.buffer.log.revision();
+        self.delete_selection();
+        for &cursor in self.cursors().iter() {
+            let indent = if self.options.auto_indent { self.buffer.leading_whitespace(cursor) }
+                         else { String::new() };
+            self.buffer.insert_str(cursor, format!("\n{}", indent).as_slice());
+
+            if cursor == self.cursor && !indent.is_empty() {
+                if let Some(idx) = self.buffer.get_mark_idx(cursor) {
+                    self.auto_indent = Some((self.buffer.log.revision(), idx, indent.len()));
+                }
+            }
+        }
+        self.buffer.log.group_since(revision);
+        self.merge_cursors();
+        self.move_screen();
+    }
+
+    // Backspacing straight after an auto-indent takes the whole indent away again.
+    fn remove_auto_indent(&mut self) -> Option<String> {
+        let (revision, end, len) = match self.auto_indent.take() {
+            Some(indent) => indent,
+            None         => return None,
+        };
+        if !self.extra_cursors.is_empty() || revision != self.buffer.log.revision()
+                                          || self.buffer.get_mark_idx(self.cursor) != Some(end) {
+            return None
+        }
+
+        let removed = self.buffer.replace_range(end - len, end, "");
+        self.move_screen();
+        removed
+    }
+
+    // Backspacing straight after an auto-pair was inserted takes both halves away again.
+    fn remove_auto_pair(&mut self) -> Option<String> {
+        let (revision, idx) = match self.last_pair.take() {
+            Some(pair) => pair,
+            None       => return None,
+        };
+        if !self.extra_cursors.is_empty() || revision != self.buffer.log.revision()
+                                          || self.buffer.get_mark_idx(self.cursor) != Some(idx) {
+            return None
+        }
+
+        let removed = self.buffer.replace_range(idx - 1, idx + 1, "");
+        self.move_screen();
+        removed
+    }
+
+    /// Insert a character at every cursor.
+    ///
+    /// With the `auto_pair` option on, opening brackets and quotes get their closing half
+    /// inserted after the cursor, and typing a closing half that is already next to the cursor
+    /// steps over it. Quotes straight after a word aren't paired, as they're likely apostrophes.
+    pub fn insert_char(&mut self, ch: char) {
+        if self.refuse_read_only() { return }
+        // typing over a selection replaces it, and typing at every cursor is one step to undo
+        let revision = self.buffer.log.revision();
+        if !self.cursors_from_block() { self.delete_selection(); }
+        let encoded = ch.to_string();
+        let auto_pair = self.options.auto_pair;
+        for &cursor in self.cursors().iter() {
+            if auto_pair && is_closing(ch) && self.buffer.char_after(cursor) == Some(ch as u8) {
+                self.buffer.shift_mark(cursor, Direction::Right(1));
+                continue
+            }
+            let after_word = self.buffer.decoded_char_before(cursor)
+                                        .map_or(false, |c| c == '_' || c.is_alphanumeric());
+
+            if encoded.len() == 1 {
+                self.buffer.insert_char(cursor, encoded.as_bytes()[0]);
+                self.buffer.shift_mark(cursor, Direction::Right(1));
+            } else {
+                self.buffer.insert_str(cursor, encoded.as_slice());
+            }
+
+            match closing_pair(ch) {
+                Some(closer) if auto_pair && !(closer == ch && after_word) => {
+                    self.buffer.insert_char(cursor, closer as u8);
+                    if cursor == self.cursor {
+                        if let Some(idx) = self.buffer.get_mark_idx(cursor) {
+                            self.last_pair = Some((self.buffer.log.revision(), idx));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.buffer.log.group_since(revision);
+        self.merge_cursors();
+        self.move_screen();
+    }
+
+    pub fn undo(&mut self) {
+        if self.refuse_read_only() { return }
+        let point = if let Some(transaction) = self.buffer.undo() { transaction.end_point }
+                    else { return; };
+        self.buffer.set_mark(self.cursor, point);
+        self.move_screen();
+    }
+
+    pub fn redo(&mut self) {
+        if self.refuse_read_only() { return }
+        let point = if let Some(transaction) = self.buffer.redo() { transaction.end_point }
+                    else { return; };
+        self.buffer.set_mark(self.cursor, point);
+        self.move_screen();
+    }
+
+}
+
+// The character that closes a pair opened by ch, if it opens one.
+fn closing_pair(ch: char) -> Option<char> {
+    match ch {
+        '('  => Some(')'),
+        '['  => Some(']'),
+        '{'  => Some('}'),
+        '"'  => Some('"'),
+        '\'' => Some('\''),
+        _    => None,
+    }
+}
+
+fn is_closing(ch: char) -> bool {
+    match ch {
+        ')' | ']' | '}' | '"' | '\'' => true,
+        _                           => false,
+    }
+}
+
+/// How `draw_line` shows what is otherwise invisible on a row.
+#[derive(Copy)]
+pub struct Invisibles {
+    pub trailing_from: uint,    // Where the whitespace at the end of the line starts in the row.
+    pub line_end: Option<char>, // Drawn after the end of the line, if the row has it.
+}
+
+/// What a cell that `draw_line` drew shows that isn't text.
+#[derive(Copy, PartialEq, Show)]
+pub enum Invisible {
+    Marker,     // The mark of a tab, a non-breaking space or the end of the line.
+    Trailing,   // Whitespace at the end of the line.
+}
+
+// Draw `line` on row `idx` from column `left` of the text on, in the cells after the first
+// `gutter`, which are left for the line number. Arrows at the edges show where the line goes on
+// past them.
+//
+// With `invisibles`, a tab starts with a `»`, a non-breaking space is a `·`, and a mark is drawn
+// after the end of the line. The cells that show them, and those of the whitespace at the end of
+// the line, are given back for their faces to be drawn in.
+pub fn draw_line(buf: &mut UIBuffer, line: &[u8], idx: uint, left: uint, gutter: uint,
+                 tab_width: uint, invisibles: Option<Invisibles>) -> Vec<(uint, Invisible)> {
+    let width = buf.get_width() - 1 - gutter;
+    let text = String::from_utf8_lossy(line);
+    let mut shown = Vec::new();
+    let mut col = 0;        // screen column of the next character, counted from the line start
+    let mut truncated = false;
+    let mut hidden = false; // whether any of the line is left of the screen
+    for (i, ch) in text.char_indices() {
+        if ch == '\n' {
+            if let Some(mark) = invisibles.and_then(|invisibles| invisibles.line_end) {
+                if col >= left && col < left + width {
+                    buf.update_cell_content(gutter + col - left, idx, mark);
+                    shown.push((gutter + col - left, Invisible::Marker));
+                    col += 1;
+                }
+            }
+            break;
+        }
+        if col < left { hidden = true }
+        let w = layout::char_width(ch, tab_width, col);
+        if col + w > left + width {
+            truncated = true;
+            break;
+        }
+        if col >= left {
+            let offset = gutter + col - left;
+            match ch {
+                '\t' if invisibles.is_some() => {
+                    buf.update_cell_content(offset, idx, '»');
+                    for i in range(1, w) { buf.update_cell_content(offset + i, idx, ' '); }
+                    shown.push((offset, Invisible::Marker));
+                }
+                '\t' => for i in range(0, w) { buf.update_cell_content(offset + i, idx, ' '); },
+                '\u{A0}' if invisibles.is_some() => {
+                    buf.update_cell_content(offset, idx, '·');
+                    shown.push((offset, Invisible::Marker));
+                }
+                _    if w == 0 => {}
+                _    => {
+                    buf.update_cell_content(offset, idx, ch);
+                    for i in range(1, w) { buf.cover_cell(offset + i, idx); }
+                }
+            }
+            if invisibles.map_or(false, |invisibles| i >= invisibles.trailing_from) {
+                shown.extend(range(offset, offset + w).map(|x| (x, Invisible::Trailing)));
+            }
+        }
+        col += w;
+    }
+    for offset in range(cmp::max(col, left) - left, width) {
+        buf.update_cell_content(gutter + offset, idx, ' ');
+    }
+    if truncated {
+        buf.update_cell_content(gutter + width, idx, '→');
+    }
+    if hidden && width > 0 {
+        buf.update_cell_content(gutter, idx, '←');
+    }
+    shown
+}
+
+// Where the whitespace at the end of `line` starts.
+fn trailing_whitespace(line: &[u8]) -> uint {
+    let content = if line.ends_with(b"\n") { line.len() - 1 } else { line.len() };
+    line[..content].iter().rposition(|&b| b != b' ' && b != b'\t').map_or(0, |i| i + 1)
+}
+
+// The columns of the cells draw_line put the bytes of the line in [span.0, span.1) in. A newline
+// in the span gets the cell after the end of the line, so that a span running on to the next
+// line shows it. Lines that aren't valid UTF-8 have none, as their bytes don't line up with what
+// is drawn.
+fn span_columns(buf: &UIBuffer, line: &[u8], left: uint, gutter: uint, tab_width: uint,
+                span: (uint, uint)) -> Vec<uint> {
+    let width = buf.get_width() - 1 - gutter;
+    let mut columns = Vec::new();
+    let text = match str::from_utf8(line) {
+        Ok(text) => text,
+        Err(_)   => return columns,
+    };
+    let mut col = 0;
+    for (i, ch) in text.char_indices() {
+        if i >= span.1 { break; }
+        if ch == '\n' {
+            if i >= span.0 && col >= left && col < left + width { columns.push(gutter + col - left); }
+            break;
+        }
+        let w = layout::char_width(ch, tab_width, col);
+        if col + w > left + width { break; }
+        if i >= span.0 && col >= left {
+            columns.extend(range(gutter + col - left, gutter + col - left + w));
+        }
+        col += w;
+    }
+    columns
+}
+
+// Draw `label` in `face` and flush against the space at the end of a gutter `gutter` wide, on
+// row `idx`.
+fn draw_gutter(buf: &mut UIBuffer, label: &str, idx: uint, gutter: uint, face: Face) {
+    let cells = repeat(' ').take(gutter - 1 - label.chars().count()).chain(label.chars())
+                           .chain(Some(' ').into_iter());
+    for (x, ch) in cells.enumerate() {
+        buf.update_cell(x, idx, ch, face.fg, face.bg);
+        buf.update_cell_style(x, idx, face.style);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use buffer::Direction;
+    use view::{View, LineNumbers, Invisibles, Invisible, draw_line, span_columns};
+    use status::StatusLine;
+    use syntax::{Highlighter, RUST};
+    use color;
+    use input::Input;
+    use overlay::Overlay;
+    use uibuf::UIBuffer;
+
+    use std::iter::repeat;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::io::{fs, File, TempDir, USER_RWX};
+    use utils::data_from_str;
+    use clipboard::Clipboard;
+    use frontends::{Frontend, EditorEvent, CharColor, CharStyle};
+    use std::time::Duration;
+
+    fn setup_view<'v>(testcase: &str) -> View<'v> {
+        let mut view = View::new(Input::Filename(None), 50, 50);
+        view.options.auto_pair = false;
+        for ch in testcase.chars() {
+            view.insert_char(ch);
+        }
+        view.options.auto_pair = true;
+        view.buffer.set_mark(view.cursor, 0);
+        view
+    }
+
+    #[test]
+    fn test_insert_at_every_cursor() {
+        let mut view = setup_view("test\nsecond\nthird");
+        view.move_cursor(Direction::Right(2));
+        view.add_cursor_below();
+        view.add_cursor_below();
+        view.insert_char('x');
+
+        let lines: Vec<&[u8]> = view.buffer.lines().collect();
+        assert_eq!(lines[0], b"texst\n"[]);
+        assert_eq!(lines[1], b"sexcond\n"[]);
+        assert_eq!(lines[2], b"thxird"[]);
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_edits_at_every_cursor_undo_together() {
+        let mut view = setup_view("test\nsecond\nthird");
+        view.move_cursor(Direction::Right(2));
+        view.add_cursor_below();
+        view.add_cursor_below();
+        view.insert_char('x');
+        view.insert_char('y');
+        view.delete_char(Direction::Left(1));
+        view.insert_newline();
+
+        view.undo();
+        assert_eq!(view.buffer.lines().nth(1).unwrap(), b"sexcond\n"[]);
+        view.undo();
+        assert_eq!(view.buffer.lines().nth(1).unwrap(), b"sexycond\n"[]);
+        view.undo();
+        assert_eq!(view.buffer.lines().nth(1).unwrap(), b"sexcond\n"[]);
+        view.undo();
+        let lines: Vec<&[u8]> = view.buffer.lines().collect();
+        assert_eq!(lines, vec![b"test\n"[], b"second\n"[], b"third"[]]);
+    }
+
+    #[test]
+    fn test_overlapping_cursors_merge() {
+        let mut view = setup_view("ab\ncd");
+        view.add_cursor_below();
+        assert_eq!(view.extra_cursors.len(), 1);
+
+        // both cursors end up clamped to the end of the buffer
+        view.move_cursor(Direction::Right(10));
+        assert_eq!(view.extra_cursors.len(), 0);
+    }
+
+    #[test]
+    fn test_collapse_cursors() {
+        let mut view = setup_view("test\nsecond");
+        view.add_cursor_below();
+        view.collapse_cursors();
+        view.insert_char('x');
+
+        assert_eq!(view.buffer.lines().nth(1).unwrap(), b"second"[]);
+    }
+
+    #[test]
+    fn test_move_cursor_down() {
+        let mut view = setup_view("test\nsecond");
+        view.move_cursor(Direction::Down(1));
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap().1, 1);
+        assert_eq!(view.buffer.lines_from(view.cursor).unwrap().next().unwrap(), b"second"[]);
+    }
+
+    #[test]
+    fn test_move_cursor_up() {
+        let mut view = setup_view("test\nsecond");
+        view.move_cursor(Direction::Down(1));
+        view.move_cursor(Direction::Up(1));
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap().1, 0);
+        assert_eq!(view.buffer.lines_from(view.cursor).unwrap().next().unwrap(), b"test\n"[]);
+    }
+
+    #[test]
+    fn test_insert_line() {
+        let mut view = setup_view("test\nsecond");
+        view.move_cursor(Direction::Right(1));
+        view.insert_char('\n');
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 1))
+    }
+
+    #[test]
+    fn test_insert_char() {
+        let mut view = setup_view("test\nsecond");
+        view.insert_char('t');
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"ttest\n"[]);
+    }
+
+    #[test]
+    fn test_delete_char_to_right() {
+        let mut view = setup_view("test\nsecond");
+        view.delete_char(Direction::Right(1));
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"est\n"[]);
+    }
+
+    #[test]
+    fn test_delete_char_to_left() {
+        let mut view = setup_view("test\nsecond");
+        view.move_cursor(Direction::Right(1));
+        view.delete_char(Direction::Left(1));
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"est\n"[]);
+    }
+
+
+    #[test]
+    fn test_delete_char_at_start_of_line() {
+        let mut view = setup_view("test\nsecond");
+        view.move_cursor(Direction::Down(1));
+        view.delete_char(Direction::Left(1));
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"testsecond"[]);
+    }
+
+    #[test]
+    fn test_delete_char_at_end_of_line() {
+        let mut view = setup_view("test\nsecond");
+        view.move_cursor(Direction::Right(4));
+        view.delete_char(Direction::Right(1));
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"testsecond"[]);
+    }
+
+    #[test]
+    fn test_delete_char_returns_deleted_char() {
+        let mut view = setup_view("test");
+        assert_eq!(view.delete_char(Direction::Right(1)), Some(data_from_str("t")));
+
+        view.move_cursor(Direction::Right(1));
+        assert_eq!(view.delete_char(Direction::Left(1)), Some(data_from_str("e")));
+    }
+
+    #[test]
+    fn deleting_in_empty_buffer_does_nothing() {
+        let mut view = setup_view("");
+
+        assert_eq!(view.delete_char(Direction::Left(1)), None);
+        assert_eq!(view.delete_char(Direction::Right(1)), None);
+        assert_eq!(view.buffer.len(), 1);
+    }
+
+    #[test]
+    fn deleting_forward_at_end_of_buffer_does_nothing() {
+        let mut view = setup_view("test");
+        view.move_cursor(Direction::Right(4));
+
+        assert_eq!(view.delete_char(Direction::Right(1)), None);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"test");
+    }
+
+    #[test]
+    fn wide_characters_cover_two_cells() {
+        let mut uibuf = UIBuffer::new(10, 1);
+        draw_line(&mut uibuf, "a\u{4E2D}b".as_bytes(), 0, 0, 0, 4, None);
+
+        assert_eq!(uibuf.get_cell_mut(0, 0).ch, 'a');
+        assert_eq!(uibuf.get_cell_mut(1, 0).ch, '\u{4E2D}');
+        assert!(!uibuf.get_cell_mut(2, 0).dirty);
+        assert_eq!(uibuf.get_cell_mut(3, 0).ch, 'b');
+    }
+
+    #[test]
+    fn combining_characters_take_no_cell() {
+        let mut uibuf = UIBuffer::new(10, 1);
+        draw_line(&mut uibuf, "e\u{301}x".as_bytes(), 0, 0, 0, 4, None);
+
+        assert_eq!(uibuf.get_cell_mut(0, 0).ch, 'e');
+        assert_eq!(uibuf.get_cell_mut(1, 0).ch, 'x');
+    }
+
+    #[test]
+    fn invisible_characters_are_marked() {
+        let mut uibuf = UIBuffer::new(20, 1);
+        let invisibles = Invisibles { trailing_from: 5, line_end: Some('¶') };
+        let shown = draw_line(&mut uibuf, "\ta\u{A0}b  \n".as_bytes(), 0, 0, 0, 4, Some(invisibles));
+
+        let drawn: String = range(0, 10).map(|x| uibuf.get_cell_mut(x, 0).ch).collect();
+        assert_eq!(drawn, "»   a·b  ¶");
+        assert_eq!(shown, vec![(0, Invisible::Marker), (5, Invisible::Marker),
+                               (7, Invisible::Trailing), (8, Invisible::Trailing),
+                               (9, Invisible::Marker)]);
+    }
+
+    #[test]
+    fn cursor_sits_on_glyph_after_wide_characters() {
+        let mut view = setup_view("\u{4E2D}\u{6587}a");
+        let offsets: Vec<uint> = range(0, 3).map(|_| {
+            let offset = view.buffer.get_mark_screen_offset(view.cursor, 4).unwrap();
+            view.move_cursor(Direction::Right(1));
+            offset
+        }).collect();
+
+        assert_eq!(offsets, vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn cursor_sits_on_glyph_after_control_character() {
+        let mut uibuf = UIBuffer::new(10, 1);
+        draw_line(&mut uibuf, "a\u{1b}b".as_bytes(), 0, 0, 0, 4, None);
+        assert_eq!(uibuf.get_cell_mut(2, 0).ch, 'b');
+
+        let mut view = setup_view("a\u{1b}b");
+        view.move_cursor(Direction::Right(2));
+        assert_eq!(view.buffer.get_mark_screen_offset(view.cursor, 4).unwrap(), 2);
+    }
+
+    #[test]
+    fn tabs_expand_to_next_tab_stop() {
+        let mut uibuf = UIBuffer::new(20, 1);
+        draw_line(&mut uibuf, "\tab\tc\t\td".as_bytes(), 0, 0, 0, 4, None);
+
+        assert_eq!(uibuf.get_cell_mut(3, 0).ch, ' ');
+        assert_eq!(uibuf.get_cell_mut(4, 0).ch, 'a');
+        assert_eq!(uibuf.get_cell_mut(5, 0).ch, 'b');
+        assert_eq!(uibuf.get_cell_mut(7, 0).ch, ' ');
+        assert_eq!(uibuf.get_cell_mut(8, 0).ch, 'c');
+        assert_eq!(uibuf.get_cell_mut(15, 0).ch, ' ');
+        assert_eq!(uibuf.get_cell_mut(16, 0).ch, 'd');
+    }
+
+    #[test]
+    fn cursor_jumps_over_tab_in_one_move() {
+        let mut view = setup_view("ab\tc\td");
+        view.options.tab_width = 8;
+        let offsets: Vec<uint> = range(0, 6).map(|_| {
+            let offset = view.buffer.get_mark_screen_offset(view.cursor,
+                                                            view.options.tab_width).unwrap();
+            view.move_cursor(Direction::Right(1));
+            offset
+        }).collect();
+
+        assert_eq!(offsets, vec![0, 1, 2, 8, 9, 16]);
+    }
+
+    #[test]
+    fn inserting_multibyte_character_encodes_it() {
+        let mut view = setup_view("");
+        view.insert_char('\u{4E2D}');
+        view.insert_char('x');
+
+        assert_eq!(view.buffer.lines().next().unwrap(), "\u{4E2D}x".as_bytes());
+    }
+
+    #[test]
+    fn jumping_to_buffer_end_and_start_scrolls_the_screen() {
+        let mut view = setup_view(repeat("line\n").take(100).collect::<String>().as_slice());
+        view.move_cursor(Direction::BufferEnd);
+        let (_, cursor_line) = view.buffer.get_mark_coords(view.cursor).unwrap();
+        let (_, top_line) = view.buffer.get_mark_coords(view.top_line).unwrap();
+        assert_eq!(cursor_line, 100);
+        assert!(top_line > 0 && cursor_line - top_line < view.get_height());
+
+        view.move_cursor(Direction::BufferStart);
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 0));
+    }
+
+    fn setup_paging_view<'v>() -> View<'v> {
+        let mut view = View::new(Input::Filename(None), 50, 10);
+        let text = repeat("line\n").take(100).collect::<String>();
+        view.buffer.insert_str(view.cursor, text.as_slice());
+        view.buffer.set_mark(view.cursor, 0);
+        view.buffer.set_mark(view.top_line, 0);
+        view
+    }
+
+    #[test]
+    fn page_down_keeps_the_cursor_column() {
+        let mut view = setup_paging_view();
+        view.buffer.set_mark(view.cursor, 2);
+        view.page_down();
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (2, 7));
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 7));
+    }
+
+    #[test]
+    fn repeated_page_down_parks_on_the_last_line() {
+        let mut view = setup_paging_view();
+        for _ in range(0, 20) { view.page_down(); }
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap().1, 100);
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 92));
+    }
+
+    #[test]
+    fn repeated_page_up_parks_on_the_first_line() {
+        let mut view = setup_paging_view();
+        for _ in range(0, 3) { view.page_down(); }
+        for _ in range(0, 5) { view.page_up(); }
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 0));
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn goto_line_centers_the_target_line() {
+        let mut view = setup_view(repeat("line\n").take(100).collect::<String>().as_slice());
+        view.goto_line(60);
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 59));
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 35));
+    }
+
+    #[test]
+    fn goto_line_near_the_top_does_not_scroll_past_it() {
+        let mut view = setup_view(repeat("line\n").take(100).collect::<String>().as_slice());
+        view.goto_line(3);
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 2));
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn read_only_buffer_refuses_edits() {
+        let mut view = setup_view("test\nsecond");
+        view.buffer.read_only = true;
+        view.insert_char('x');
+        view.delete_line();
+        assert_eq!(view.delete_char(Direction::Right(1)), None);
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"test\n"[]);
+        assert_eq!(message(&view), Some(data_from_str("Buffer is read-only")));
+        assert!(!view.buffer.is_dirty());
+
+        // moving around still works
+        view.move_cursor(Direction::Down(1));
+        assert_eq!(view.buffer.get_mark_pos(view.cursor).unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn reverting_a_read_only_buffer_still_refuses_edits() {
+        let dir = TempDir::new("iota").unwrap();
+        let path = dir.path().join("test.txt");
+        File::create(&path).write_str("test").unwrap();
+
+        let mut view = setup_view("");
+        view.buffer.file_path = Some(path);
+        // as opening with -R does
+        view.buffer.read_only = true;
+        view.confirm_revert(true);
+        view.answer_file_changed("r");
+        view.insert_char('x');
+
+        assert_eq!(text(&view), "test");
+        assert_eq!(message(&view), Some(data_from_str("Buffer is read-only")));
+    }
+
+    #[test]
+    fn toggling_read_only_allows_edits() {
+        let mut view = setup_view("test");
+        view.buffer.read_only = true;
+        view.toggle_read_only();
+        view.insert_char('x');
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"xtest"[]);
+    }
+
+    #[test]
+    fn goto_position_clamps_line_and_column() {
+        let mut view = setup_view("a\nh\u{E9}llo\nx");
+        view.goto_position(2, 3);
+        assert_eq!(view.buffer.get_mark_pos(view.cursor).unwrap(), (1, 3));
+
+        view.goto_position(2, 99);
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 8);
+
+        view.goto_position(50, 1);
+        assert_eq!(view.buffer.get_mark_pos(view.cursor).unwrap(), (2, 0));
+    }
+
+    #[test]
+    fn goto_matching_bracket_moves_the_cursor() {
+        let mut view = setup_view("if (a) {\n}");
+        view.move_cursor(Direction::Right(6));
+        view.goto_matching_bracket();
+
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 1));
+        assert!(message(&view).is_none());
+    }
+
+    #[test]
+    fn unmatched_bracket_shows_a_message() {
+        let mut view = setup_view("(a");
+        view.goto_matching_bracket();
+
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 0);
+        assert!(message(&view).is_some());
+    }
+
+    fn newline_after<'v>(testcase: &str, col: uint) -> View<'v> {
+        let mut view = setup_view(testcase);
+        view.move_cursor(Direction::Right(col));
+        view.insert_newline();
+        view
+    }
+
+    #[test]
+    fn newline_copies_tab_indent() {
+        let view = newline_after("\t\tfoo", 5);
+        let mut lines = view.buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"\t\tfoo\n"[]);
+        assert_eq!(lines.next().unwrap(), b"\t\t"[]);
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn newline_copies_space_indent() {
+        let view = newline_after("    foo", 7);
+        let mut lines = view.buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"    foo\n"[]);
+        assert_eq!(lines.next().unwrap(), b"    "[]);
+    }
+
+    #[test]
+    fn newline_copies_mixed_indent() {
+        let view = newline_after("\t  foo bar", 6);
+        let mut lines = view.buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"\t  foo\n"[]);
+        assert_eq!(lines.next().unwrap(), b"\t   bar"[]);
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (3, 1));
+    }
+
+    #[test]
+    fn newline_inside_indent_copies_only_what_is_before_the_cursor() {
+        let view = newline_after("    foo", 2);
+        let mut lines = view.buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"  \n"[]);
+        assert_eq!(lines.next().unwrap(), b"    foo"[]);
+    }
+
+    #[test]
+    fn newline_without_auto_indent() {
+        let mut view = setup_view("    foo");
+        view.options.auto_indent = false;
+        view.move_cursor(Direction::Right(7));
+        view.insert_newline();
+        let mut lines = view.buffer.lines();
+        assert_eq!(lines.next().unwrap(), b"    foo\n"[]);
+        assert_eq!(lines.next().unwrap(), b""[]);
+    }
+
+    #[test]
+    fn backspace_removes_whole_auto_indent() {
+        let mut view = newline_after("\t  foo", 6);
+        assert_eq!(view.delete_char(Direction::Left(1)), Some(data_from_str("\t  ")));
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 1));
+
+        view.delete_char(Direction::Left(1));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"\t  foo"[]);
+    }
+
+    #[test]
+    fn backspace_after_typing_on_indented_line_removes_one_char() {
+        let mut view = newline_after("    foo", 7);
+        view.insert_char('x');
+        view.delete_char(Direction::Left(1));
+        view.delete_char(Direction::Left(1));
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (3, 1));
+    }
+
+    #[test]
+    fn indent_line_at_every_cursor() {
+        let mut view = setup_view("a\nb\nc");
+        view.add_cursor_below();
+        view.indent_line();
+        let mut lines = view.buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"    a\n"[]);
+        assert_eq!(lines.next().unwrap(), b"    b\n"[]);
+        assert_eq!(lines.next().unwrap(), b"c"[]);
+    }
+
+    #[test]
+    fn tab_inserts_spaces_to_the_next_tab_stop() {
+        let mut view = setup_view("ab\n\tx");
+        view.move_cursor(Direction::Right(1));
+        // after the tab, which already took the cursor to a tab stop
+        view.add_cursor_at(4);
+        view.insert_tab();
+        let mut lines = view.buffer.lines();
+
+        assert_eq!(lines.next().unwrap(), b"a   b\n"[]);
+        assert_eq!(lines.next().unwrap(), b"\t    x"[]);
+
+        view.undo();
+        assert_eq!(text(&view), "ab\n\tx");
+    }
+
+    #[test]
+    fn tab_inserts_a_tab_when_indenting_with_tabs() {
+        let mut view = setup_view("ab");
+        view.options.indent_with_tabs = true;
+        view.move_cursor(Direction::Right(1));
+        view.insert_tab();
+
+        assert_eq!(text(&view), "a\tb");
+    }
+
+    #[test]
+    fn indent_line_with_tabs() {
+        let mut view = setup_view("a");
+        view.options.indent_with_tabs = true;
+        view.indent_line();
+        assert_eq!(view.buffer.lines().next().unwrap(), b"\ta"[]);
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn smart_home_toggles_between_text_and_column_zero() {
+        let mut view = setup_view("    foo");
+        view.move_cursor(Direction::Right(6));
+        let mut cols = Vec::new();
+        for _ in range(0, 3) {
+            view.move_cursor_to_line_start();
+            cols.push(view.buffer.get_mark_coords(view.cursor).unwrap().0);
+        }
+        assert_eq!(cols, vec![4, 0, 4]);
+    }
+
+    #[test]
+    fn smart_home_on_blank_line_goes_to_column_zero() {
+        let mut view = setup_view("    ");
+        view.move_cursor(Direction::Right(2));
+        view.move_cursor_to_line_start();
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn plain_home_goes_to_column_zero() {
+        let mut view = setup_view("    foo");
+        view.options.smart_home = false;
+        view.move_cursor(Direction::Right(6));
+        view.move_cursor_to_line_start();
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn toggle_comment_keeps_cursor_on_its_character() {
+        let mut view = setup_view("  foo");
+        view.move_cursor(Direction::Right(3));
+        view.toggle_comment();
+
+        assert_eq!(view.buffer.lines().next().unwrap(), b"  # foo"[]);
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 5);
+    }
+
+    #[test]
+    fn dedent_line_at_column_zero_does_nothing() {
+        let mut view = setup_view("a\n    b");
+        view.dedent_line();
+        assert_eq!(view.buffer.lines().next().unwrap(), b"a\n"[]);
+        assert_eq!(view.buffer.lines().nth(1).unwrap(), b"    b"[]);
+    }
+
+    #[test]
+    fn opening_bracket_inserts_its_pair() {
+        let mut view = setup_view("");
+        view.insert_char('(');
+        assert_eq!(view.buffer.lines().next().unwrap(), b"()"[]);
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 1);
+    }
+
+    #[test]
+    fn typing_the_closer_steps_over_it() {
+        let mut view = setup_view("");
+        for ch in "f(a[0])".chars() { view.insert_char(ch); }
+        assert_eq!(view.buffer.lines().next().unwrap(), b"f(a[0])"[]);
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 7);
+    }
+
+    #[test]
+    fn pairing_at_end_of_buffer() {
+        let mut view = setup_view("x = ");
+        view.move_cursor(Direction::BufferEnd);
+        view.insert_char('"');
+        assert_eq!(view.buffer.lines().next().unwrap(), b"x = \"\""[]);
+        assert_eq!(view.buffer.get_mark_idx(view.cursor).unwrap(), 5);
+    }
+
+    #[test]
+    fn quote_after_word_is_not_paired() {
+        let mut view = setup_view("don");
+        view.move_cursor(Direction::BufferEnd);
+        view.insert_char('\'');
+        assert_eq!(view.buffer.lines().next().unwrap(), b"don'"[]);
+
+        let mut view = setup_view("caf\u{e9}");
+        view.move_cursor(Direction::BufferEnd);
+        view.insert_char('\'');
+        assert_eq!(view.buffer.lines().next().unwrap(), "caf\u{e9}'".as_bytes());
+    }
+
+    #[test]
+    fn backspace_in_fresh_pair_removes_both() {
+        let mut view = setup_view("ab");
+        view.move_cursor(Direction::Right(1));
+        view.insert_char('[');
+        assert_eq!(view.delete_char(Direction::Left(1)), Some(data_from_str("[]")));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"ab"[]);
+    }
+
+    #[test]
+    fn backspace_after_typing_in_pair_removes_one_char() {
+        let mut view = setup_view("");
+        view.insert_char('{');
+        view.insert_char('x');
+        view.delete_char(Direction::Left(1));
+        view.delete_char(Direction::Left(1));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"}"[]);
+    }
+
+    #[test]
+    fn pairing_can_be_turned_off() {
+        let mut view = setup_view("");
+        view.options.auto_pair = false;
+        view.insert_char('(');
+        view.insert_char(')');
+        view.insert_char(')');
+        assert_eq!(view.buffer.lines().next().unwrap(), b"())"[]);
+    }
+
+    #[test]
+    fn backspace_removes_whole_grapheme() {
+        let mut view = setup_view("e\u{301}x");
+        view.move_cursor(Direction::Right(1));
+
+        assert_eq!(view.delete_char(Direction::Left(1)), Some(data_from_str("e\u{301}")));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"x"[]);
+    }
+
+    #[test]
+    fn deleting_backward_at_start_of_first_line_does_nothing() {
+        let mut view = setup_view("test\nsecond");
+        view.delete_char(Direction::Left(1));
+
+        let lines: Vec<&[u8]> = view.buffer.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(view.buffer.lines().next().unwrap(), b"test\n");
+    }
+
+    fn cursor_idx(view: &View) -> uint {
+        view.buffer.get_mark_idx(view.cursor).unwrap()
+    }
+
+    fn search_for(view: &mut View, query: &str) {
+        view.start_search(true);
+        for c in query.chars() {
+            view.extend_search(c);
+        }
+    }
+
+    #[test]
+    fn search_moves_to_each_match_as_it_is_typed() {
+        let mut view = setup_view("cat cot\ncoat");
+        view.start_search(true);
+        view.extend_search('c');
+        assert_eq!(cursor_idx(&view), 0);
+        view.extend_search('o');
+        assert_eq!(cursor_idx(&view), 4);
+        view.extend_search('a');
+        assert_eq!(cursor_idx(&view), 8);
+        assert_eq!(message(&view), Some(String::from_str("Search (smart case): coa")));
+
+        view.shorten_search();
+        assert_eq!(cursor_idx(&view), 4);
+        view.end_search();
+        assert!(!view.is_searching());
+        assert_eq!(cursor_idx(&view), 4);
+    }
+
+    #[test]
+    fn search_next_wraps_around() {
+        let mut view = setup_view("ab ab ab");
+        view.move_cursor(Direction::Right(1));
+        search_for(&mut view, "ab");
+        assert_eq!(cursor_idx(&view), 3);
+        view.search_again(true);
+        assert_eq!(cursor_idx(&view), 6);
+        view.search_again(true);
+        assert_eq!(cursor_idx(&view), 0);
+        assert_eq!(message(&view), Some(String::from_str("Search (smart case): ab (wrapped)")));
+    }
+
+    #[test]
+    fn failing_search_stays_put() {
+        let mut view = setup_view("abc");
+        search_for(&mut view, "bx");
+        assert_eq!(cursor_idx(&view), 1);
+        assert_eq!(message(&view), Some(String::from_str("Failing search (smart case): bx")));
+    }
+
+    #[test]
+    fn cancelling_search_goes_back() {
+        let mut view = setup_view("one\ntwo\nthree");
+        view.move_cursor(Direction::Right(1));
+        search_for(&mut view, "three");
+        assert_eq!(cursor_idx(&view), 8);
+        view.cancel_search();
+        assert!(!view.is_searching());
+        assert_eq!(cursor_idx(&view), 1);
+    }
+
+    #[test]
+    fn search_next_with_no_query_repeats_the_last_search() {
+        let mut view = setup_view("x y x y");
+        search_for(&mut view, "y");
+        view.end_search();
+        view.start_search(true);
+        view.search_again(true);
+        assert_eq!(cursor_idx(&view), 6);
+    }
+
+    #[test]
+    fn backward_search_finds_earlier_matches() {
+        let mut view = setup_view("ab ab ab");
+        view.move_cursor(Direction::Right(5));
+        view.start_search(false);
+        view.extend_search('a');
+        assert_eq!(cursor_idx(&view), 3);
+        view.extend_search('b');
+        assert_eq!(cursor_idx(&view), 3);
+        assert_eq!(message(&view), Some(String::from_str("Search backward (smart case): ab")));
+
+        view.search_again(false);
+        assert_eq!(cursor_idx(&view), 0);
+        view.search_again(false);
+        assert_eq!(cursor_idx(&view), 6);
+        assert_eq!(message(&view), Some(String::from_str("Search backward (smart case): ab (wrapped)")));
+    }
+
+    #[test]
+    fn switching_direction_keeps_the_query() {
+        let mut view = setup_view("x1 x2 x3");
+        view.move_cursor(Direction::Right(4));
+        view.start_search(false);
+        view.extend_search('x');
+        assert_eq!(cursor_idx(&view), 3);
+        view.search_again(false);
+        assert_eq!(cursor_idx(&view), 0);
+        view.search_again(true);
+        assert_eq!(cursor_idx(&view), 3);
+        assert_eq!(message(&view), Some(String::from_str("Search (smart case): x")));
+    }
+
+    #[test]
+    fn regex_search_matches_patterns() {
+        let mut view = setup_view("id = 42;\nname = 7;");
+        search_for(&mut view, "[0-9]");
+        assert!(message(&view).unwrap().starts_with("Failing"));
+        view.toggle_search_regex();
+        assert_eq!(cursor_idx(&view), 5);
+        assert_eq!(message(&view), Some(String::from_str("Search regex (smart case): [0-9]")));
+
+        view.extend_search('+');
+        view.extend_search(';');
+        view.search_again(true);
+        assert_eq!(cursor_idx(&view), 16);
+    }
+
+    #[test]
+    fn invalid_regex_keeps_searching() {
+        let mut view = setup_view("f(x)");
+        view.start_search(true);
+        view.toggle_search_regex();
+        view.extend_search('(');
+        assert!(view.is_searching());
+        let shown = message(&view).unwrap();
+        assert!(shown.starts_with("Failing search regex (smart case): ( ["));
+
+        view.extend_search('x');
+        view.extend_search(')');
+        assert_eq!(cursor_idx(&view), 2);
+        assert_eq!(message(&view), Some(String::from_str("Search regex (smart case): (x)")));
+    }
+
+    #[test]
+    fn replace_all_reports_how_many() {
+        let mut view = setup_view("one two one");
+        view.move_cursor(Direction::Right(9));
+        view.ask_replace_all_with("one");
+        view.replace_all("1");
+        assert_eq!(view.buffer.lines().next().unwrap(), b"1 two 1"[]);
+        assert_eq!(cursor_idx(&view), 7);
+        assert_eq!(message(&view), Some(String::from_str("replaced 2 occurrences")));
+    }
+
+    #[test]
+    fn search_case_can_be_changed() {
+        let mut view = setup_view("Abc abc");
+        search_for(&mut view, "abc");
+        assert_eq!(cursor_idx(&view), 0);
+
+        view.cycle_search_case();
+        assert_eq!(message(&view), Some(String::from_str("Search (ignoring case): abc")));
+        assert_eq!(cursor_idx(&view), 0);
+
+        view.cycle_search_case();
+        assert_eq!(message(&view), Some(String::from_str("Search (exact case): abc")));
+        assert_eq!(cursor_idx(&view), 4);
+    }
+
+    #[test]
+    fn regex_search_follows_the_case_setting() {
+        let mut view = setup_view("ABC abc");
+        view.start_search(true);
+        view.toggle_search_regex();
+        for c in "a.c".chars() { view.extend_search(c) }
+        assert_eq!(cursor_idx(&view), 0);
+        view.cycle_search_case();
+        view.cycle_search_case();
+        assert_eq!(cursor_idx(&view), 4);
+    }
+
+    #[test]
+    fn ended_search_stays_highlighted_until_an_edit() {
+        let mut view = setup_view("one two one");
+        search_for(&mut view, "one");
+        assert!(!view.is_highlighting());
+        view.search_again(true);
+        view.end_search();
+        assert!(view.is_highlighting());
+        assert_eq!(cursor_idx(&view), 8);
+
+        view.insert_char('x');
+        assert!(!view.is_highlighting());
+    }
+
+    #[test]
+    fn highlight_can_be_cleared_without_moving() {
+        let mut view = setup_view("one two one");
+        search_for(&mut view, "two");
+        view.end_search();
+        view.clear_highlight();
+        assert!(!view.is_highlighting());
+        assert_eq!(cursor_idx(&view), 4);
+
+        search_for(&mut view, "one");
+        view.cancel_search();
+        assert!(!view.is_highlighting());
+    }
+
+    #[test]
+    fn word_search_skips_longer_words() {
+        let mut view = setup_view("foo foobar afoo foo_ foo");
+        view.search_word(true);
+        assert_eq!(cursor_idx(&view), 21);
+        assert!(!view.is_searching());
+        assert!(view.is_highlighting());
+
+        view.start_search(true);
+        view.search_again(true);
+        assert_eq!(cursor_idx(&view), 0);
+        assert_eq!(message(&view), Some(String::from_str("Search word (smart case): foo (wrapped)")));
+    }
+
+    #[test]
+    fn word_search_backward() {
+        let mut view = setup_view("bar x bar barx bar");
+        view.move_cursor(Direction::Right(15));
+        view.search_word(false);
+        assert_eq!(cursor_idx(&view), 6);
+        view.search_word(false);
+        assert_eq!(cursor_idx(&view), 0);
+    }
+
+    #[test]
+    fn word_search_needs_a_word() {
+        let mut view = setup_view("a + b");
+        view.move_cursor(Direction::Right(2));
+        view.search_word(true);
+        assert!(!view.is_highlighting());
+        assert_eq!(message(&view), Some(String::from_str("no word under cursor")));
+        assert_eq!(cursor_idx(&view), 2);
+    }
+
+    #[test]
+    fn search_recalls_earlier_queries() {
+        let mut view = setup_view("one two three");
+        search_for(&mut view, "two");
+        view.end_search();
+        search_for(&mut view, "thr");
+        view.end_search();
+
+        view.move_cursor(Direction::Left(100));
+        view.start_search(true);
+        view.extend_search('o');
+        view.recall_search(true);
+        assert_eq!(cursor_idx(&view), 8);
+        view.recall_search(true);
+        assert_eq!(cursor_idx(&view), 4);
+        view.recall_search(false);
+        view.recall_search(false);
+        assert_eq!(message(&view), Some(String::from_str("Search (smart case): o")));
+    }
+
+    #[test]
+    fn grep_fills_a_results_buffer_to_open_from() {
+        let dir = TempDir::new("iota").unwrap();
+        fs::mkdir(&dir.path().join("src"), USER_RWX).unwrap();
+        File::create(&dir.path().join("src/lib.rs")).write_str("mod a;\nmod needle;\n").unwrap();
+        File::create(&dir.path().join("notes")).write_str("no match\n").unwrap();
+
+        let mut view = setup_view("");
+        view.ask_grep_dir("needle");
+        view.overlay = Overlay::None;
+        view.start_grep(format!("{}", dir.path().display())[]);
+        while view.is_grepping() { view.grep_step() }
+        assert!(view.buffer.read_only);
+        assert!(view.is_showing_results());
+        assert_eq!(view.buffer.lines().next().unwrap(), b"src/lib.rs:2: mod needle;\n"[]);
+        assert_eq!(message(&view), Some(String::from_str("grep: 1 matching lines in 1 files")));
+
+        view.open_result();
+        assert!(!view.is_showing_results());
+        assert_eq!(view.buffer.file_path, Some(dir.path().join("src/lib.rs")));
+        assert_eq!(view.buffer.get_mark_pos(view.cursor), Some((1, 0)));
+    }
+
+    #[test]
+    fn saving_says_how_many_lines_were_trimmed() {
+        let dir = TempDir::new("iota").unwrap();
+        let mut view = setup_view("a 
+b	
+c
+");
+        view.options.trim_trailing_whitespace = true;
+        view.save_buffer_as(format!("{}", dir.path().join("trimmed").display())[]);
+        assert_eq!(message(&view), Some(String::from_str("wrote 6 bytes, trimmed 2 lines")));
+    }
+
+    #[test]
+    fn count_matches_of_the_search() {
+        let mut view = setup_view("cat Cat\ncat concat");
+        search_for(&mut view, "cat");
+        view.count_matches();
+        assert_eq!(message(&view), Some(String::from_str("4 matches")));
+
+        view.toggle_search_regex();
+        view.extend_search('$');
+        view.count_matches();
+        assert_eq!(message(&view), Some(String::from_str("2 matches")));
+
+        view.start_search(true);
+        for c in "^cat c".chars() { view.extend_search(c) }
+        view.toggle_search_regex();
+        view.end_search();
+        view.count_matches();
+        assert_eq!(message(&view), Some(String::from_str("1 match")));
+    }
+
+    #[test]
+    fn count_matches_asks_when_there_is_no_search() {
+        let mut view = setup_view("a b a");
+        view.count_matches();
+        assert!(view.overlay.overlay_type().is_some());
+        view.overlay = Overlay::None;
+
+        view.count_matches_of("a");
+        assert_eq!(message(&view), Some(String::from_str("2 matches")));
+        view.count_matches_of("x");
+        assert_eq!(message(&view), Some(String::from_str("0 matches")));
+    }
+
+    #[test]
+    fn typing_over_a_selection_replaces_it() {
+        let mut view = setup_view("one two three");
+        view.move_cursor(Direction::Right(4));
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(3));
+        assert_eq!(view.buffer.selection_range(), Some((4, 7)));
+
+        view.insert_char('2');
+        assert_eq!(view.buffer.lines().next().unwrap(), b"one 2 three");
+        assert!(!view.buffer.is_selecting());
+        view.undo();
+        assert_eq!(view.buffer.lines().next().unwrap(), b"one two three");
+    }
+
+    #[test]
+    fn deleting_with_a_selection_removes_just_the_selection() {
+        let mut view = setup_view("abc\ndef");
+        view.move_cursor(Direction::Right(2));
+        view.toggle_selection();
+        view.move_cursor(Direction::Down(1));
+        assert_eq!(view.delete_char(Direction::Left(1)), Some(String::from_str("c\nde")));
+        assert_eq!(view.buffer.lines().next().unwrap(), b"abf");
+        assert_eq!(cursor_idx(&view), 2);
+    }
+
+    #[test]
+    fn selection_can_be_dropped_without_editing() {
+        let mut view = setup_view("abc");
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(2));
+        view.collapse_cursors();
+        assert_eq!(view.buffer.selection_range(), None);
+
+        view.toggle_selection();
+        view.toggle_selection();
+        view.move_cursor(Direction::Left(1));
+        assert_eq!(view.buffer.selection_range(), None);
+        view.insert_char('x');
+        assert_eq!(view.buffer.lines().next().unwrap(), b"axbc");
+    }
+
+    #[test]
+    fn selected_newline_takes_the_cell_after_the_line() {
+        let buf = UIBuffer::new(10, 2);
+        assert_eq!(span_columns(&buf, b"ab\n", 0, 0, 4, (1, 3)), vec![1, 2]);
+        assert_eq!(span_columns(&buf, b"\n", 0, 0, 4, (0, 1)), vec![0]);
+        assert_eq!(span_columns(&buf, b"ab\n", 0, 0, 4, (0, 2)), vec![0, 1]);
+    }
+
+    #[test]
+    fn replacing_and_counting_keep_to_the_selection() {
+        let mut view = setup_view("a a a a");
+        view.move_cursor(Direction::Right(2));
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(3));
+        view.count_matches_of("a");
+        assert_eq!(message(&view), Some(String::from_str("2 matches")));
+
+        view.ask_replace_all_with("a");
+        view.replace_all("b");
+        assert_eq!(view.buffer.lines().next().unwrap(), b"a b b a");
+        assert_eq!(message(&view), Some(String::from_str("replaced 2 occurrences")));
+    }
+
+    fn text(view: &View) -> String {
+        view.buffer.text_range(0, view.buffer.len())
+    }
+
+    // The message given last, whether or not it is the one shown yet.
+    fn message(view: &View) -> Option<String> {
+        view.messages.newest().map(|message| message.text.clone())
+    }
+
+    #[test]
+    fn copy_and_paste_a_selection() {
+        let mut view = setup_view("one two");
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(4));
+        view.copy();
+        assert!(!view.buffer.is_selecting());
+        assert_eq!(text(&view), "one two");
+
+        view.move_cursor(Direction::LineEnd);
+        view.paste();
+        assert_eq!(text(&view), "one twoone ");
+        assert_eq!(cursor_idx(&view), 11);
+        view.undo();
+        assert_eq!(text(&view), "one two");
+    }
+
+    #[test]
+    fn cut_a_selection() {
+        let mut view = setup_view("one two");
+        view.move_cursor(Direction::Right(3));
+        view.toggle_selection();
+        view.move_cursor(Direction::LineEnd);
+        view.cut();
+        assert_eq!(text(&view), "one");
+        view.move_cursor(Direction::LineStart);
+        view.paste();
+        assert_eq!(text(&view), " twoone");
+    }
+
+    #[test]
+    fn without_a_selection_whole_lines_are_copied() {
+        let mut view = setup_view("one\ntwo");
+        view.move_cursor(Direction::Right(1));
+        view.copy();
+        view.paste();
+        assert_eq!(text(&view), "one\none\ntwo");
+        assert_eq!(cursor_idx(&view), 4);
+        view.undo();
+        assert_eq!(text(&view), "one\ntwo");
+    }
+
+    #[test]
+    fn whole_lines_cut_and_pasted_at_the_end() {
+        let mut view = setup_view("one\ntwo");
+        view.cut();
+        assert_eq!(text(&view), "two");
+        view.move_cursor(Direction::LineEnd);
+        view.paste();
+        assert_eq!(text(&view), "two\none");
+        assert_eq!(cursor_idx(&view), 4);
+
+        view.undo();
+        view.undo();
+        assert_eq!(text(&view), "one\ntwo");
+    }
+
+    #[test]
+    fn pasting_over_a_selection_replaces_it() {
+        let mut view = setup_view("ab");
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(1));
+        view.copy();
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(1));
+        view.paste();
+        assert_eq!(text(&view), "aa");
+        view.undo();
+        assert_eq!(text(&view), "ab");
+    }
+
+    struct FakeClipboard {
+        copied: Rc<RefCell<Vec<String>>>,
+        pasted: Result<String, String>,
+    }
+
+    impl Clipboard for FakeClipboard {
+        fn copy(&mut self, text: &str) -> Result<(), String> {
+            match self.pasted {
+                Ok(_)    => Ok(self.copied.borrow_mut().push(String::from_str(text))),
+                Err(ref e) => Err(e.clone()),
+            }
+        }
+
+        fn paste(&mut self) -> Result<String, String> {
+            self.pasted.clone()
+        }
+    }
+
+    #[test]
+    fn copies_go_to_the_system_clipboard_too() {
+        let copied = Rc::new(RefCell::new(Vec::new()));
+        let mut view = setup_view("one\ntwo");
+        view.set_clipboard(box FakeClipboard { copied: copied.clone(),
+                                               pasted: Ok(String::from_str("other")) });
+        view.copy();
+        view.move_cursor(Direction::Down(1));
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(2));
+        view.cut();
+        assert_eq!(*copied.borrow(), vec![String::from_str("one"), String::from_str("tw")]);
+
+        view.paste_from_clipboard();
+        assert_eq!(text(&view), "one\nothero");
+        view.paste();
+        assert_eq!(text(&view), "one\nothertwo");
+    }
+
+    #[test]
+    fn failing_system_clipboard_falls_back_to_the_register() {
+        let copied = Rc::new(RefCell::new(Vec::new()));
+        let mut view = setup_view("ab");
+        view.set_clipboard(box FakeClipboard { copied: copied.clone(),
+                                               pasted: Err(String::from_str("no display")) });
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(1));
+        view.copy();
+        let unavailable = "System clipboard unavailable, using the editor's own: no display";
+        assert_eq!(message(&view), Some(String::from_str(unavailable)));
+
+        view.clear_message();
+        view.paste_from_clipboard();
+        assert_eq!(message(&view), None);
+        assert_eq!(text(&view), "aab");
+        assert_eq!(copied.borrow().len(), 0);
+    }
+
+    #[test]
+    fn changing_case_converts_the_selection() {
+        let mut view = setup_view("one two three");
+        view.move_cursor(Direction::Right(4));
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(5));
+        view.upcase_word();
+        assert_eq!(text(&view), "one TWO Three");
+
+        view.downcase_word();
+        assert_eq!(text(&view), "one two three");
+        view.undo();
+        assert_eq!(text(&view), "one TWO Three");
+    }
 
-        assert_eq!(view.buffer.lines().next().unwrap(), b"est\n"[]);
+    #[test]
+    fn changing_case_converts_each_line_of_a_block() {
+        let mut view = setup_view("abcd
+efgh");
+        view.move_cursor(Direction::Right(1));
+        view.toggle_block_selection();
+        view.move_cursor(Direction::Down(1));
+        view.move_cursor(Direction::Right(2));
+        view.upcase_word();
+        assert_eq!(text(&view), "aBCd
+eFGh");
+
+        view.undo();
+        assert_eq!(text(&view), "abcd
+efgh");
     }
 
     #[test]
-    fn test_delete_char_to_left() {
-        let mut view = setup_view("test\nsecond");
+    fn typing_into_a_block_types_on_every_line() {
+        let mut view = setup_view("a1\n\nc3");
         view.move_cursor(Direction::Right(1));
-        view.delete_char(Direction::Left(1));
+        view.toggle_block_selection();
+        view.move_cursor(Direction::Down(2));
+        view.insert_char('-');
+        assert_eq!(text(&view), "a-1\n -\nc-3");
+        view.undo();
+        assert_eq!(text(&view), "a1\n\nc3");
 
-        assert_eq!(view.buffer.lines().next().unwrap(), b"est\n"[]);
+        view.collapse_cursors();
+        view.buffer.set_mark(view.cursor, 1);
+        view.toggle_block_selection();
+        view.move_cursor(Direction::Down(2));
+        view.insert_char('-');
+        view.insert_char('>');
+        assert_eq!(text(&view), "a->1\n ->\nc->3");
+        view.collapse_cursors();
+        assert_eq!(cursor_idx(&view), 3);
     }
 
+    #[test]
+    fn typing_over_a_block_replaces_it() {
+        let mut view = setup_view("abcd\nefgh");
+        view.move_cursor(Direction::Right(1));
+        view.toggle_block_selection();
+        view.move_cursor(Direction::Down(1));
+        view.move_cursor(Direction::Right(2));
+        view.insert_char('x');
+        assert_eq!(text(&view), "axd\nexh");
+    }
 
     #[test]
-    fn test_delete_char_at_start_of_line() {
-        let mut view = setup_view("test\nsecond");
+    fn block_is_copied_and_pasted_by_column() {
+        let mut view = setup_view("abcd\nefgh\nij");
+        view.move_cursor(Direction::Right(1));
+        view.toggle_block_selection();
         view.move_cursor(Direction::Down(1));
-        view.delete_char(Direction::Left(1));
+        view.move_cursor(Direction::Right(2));
+        view.cut();
+        assert_eq!(text(&view), "ad\neh\nij");
+        assert_eq!(cursor_idx(&view), 1);
 
-        assert_eq!(view.buffer.lines().next().unwrap(), b"testsecond"[]);
+        view.move_cursor(Direction::Down(1));
+        view.move_cursor(Direction::LineEnd);
+        view.paste();
+        assert_eq!(text(&view), "ad\nehbc\nijfg");
+        view.undo();
+        assert_eq!(text(&view), "ad\neh\nij");
     }
 
     #[test]
-    fn test_delete_char_at_end_of_line() {
-        let mut view = setup_view("test\nsecond");
-        view.move_cursor(Direction::Right(4));
-        view.delete_char(Direction::Right(1));
+    fn words_deleted_in_a_row_paste_back_together() {
+        let mut view = setup_view("one two three");
+        view.move_cursor(Direction::LineEnd);
+        view.delete_word(Direction::Left(1));
+        view.delete_word(Direction::Left(1));
+        view.delete_word(Direction::Left(1));
+        assert_eq!(text(&view), "");
+        view.paste();
+        assert_eq!(text(&view), "one two three");
 
-        assert_eq!(view.buffer.lines().next().unwrap(), b"testsecond"[]);
+        let mut view = setup_view("one two\nthree");
+        view.delete_word(Direction::Right(1));
+        view.move_cursor(Direction::LineEnd);
+        view.delete_word(Direction::Left(1));
+        view.paste();
+        assert_eq!(text(&view), " two\nthree");
     }
 
     #[test]
-    fn deleting_backward_at_start_of_first_line_does_nothing() {
-        let mut view = setup_view("test\nsecond");
+    fn paste_then_yank_pop_cycles_through_older_kills() {
+        let mut view = setup_view("one two");
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(3));
+        view.copy();
+        view.move_cursor(Direction::Right(1));
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(3));
+        view.copy();
+
+        view.paste();
+        assert_eq!(text(&view), "one twotwo");
+        view.yank_pop();
+        assert_eq!(text(&view), "one twoone");
+        view.yank_pop();
+        assert_eq!(text(&view), "one twotwo");
+        assert_eq!(cursor_idx(&view), 10);
+        view.undo();
+        assert_eq!(text(&view), "one two");
+    }
+
+    #[test]
+    fn yank_pop_over_a_selection() {
+        let mut view = setup_view("abc");
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(1));
+        view.copy();
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(1));
+        view.copy();
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(1));
+        view.paste();
+        assert_eq!(text(&view), "abb");
+        view.yank_pop();
+        assert_eq!(text(&view), "aba");
+        view.undo();
+        assert_eq!(text(&view), "abc");
+    }
+
+    #[test]
+    fn yank_pop_only_follows_a_paste() {
+        let mut view = setup_view("ab");
+        view.copy();
+        view.yank_pop();
+        assert_eq!(message(&view), Some(String::from_str("The last command was not a paste")));
+        assert_eq!(text(&view), "ab");
+
+        view.paste();
+        view.insert_char('x');
+        view.clear_message();
+        view.yank_pop();
+        assert_eq!(message(&view), Some(String::from_str("The last command was not a paste")));
+        assert_eq!(text(&view), "ab\nxab");
+    }
+
+    #[test]
+    fn select_a_line_and_cut_it() {
+        let mut view = setup_view("one\ntwo\nthree");
+        view.move_cursor(Direction::Down(1));
+        view.select_line();
+        assert_eq!(view.buffer.selection_range(), Some((4, 8)));
+        view.cut();
+        assert_eq!(text(&view), "one\nthree");
+
+        view.move_cursor(Direction::BufferEnd);
+        view.select_line();
+        assert_eq!(view.buffer.selection_range(), Some((4, 9)));
+    }
+
+    #[test]
+    fn expand_the_selection_and_shrink_it_back() {
+        let mut view = setup_view("one two\nthree\n\nfour");
+        view.move_cursor(Direction::Right(5));
+        let mut extents = Vec::new();
+        for _ in range(0u, 5) {
+            view.expand_selection();
+            extents.push(view.buffer.selection_range());
+        }
+        assert_eq!(extents, vec![Some((4, 7)), Some((0, 8)), Some((0, 14)), Some((0, 19)),
+                                 Some((0, 19))]);
+
+        view.shrink_selection();
+        assert_eq!(view.buffer.selection_range(), Some((0, 14)));
+        view.shrink_selection();
+        view.shrink_selection();
+        assert_eq!(view.buffer.selection_range(), Some((4, 7)));
+        view.shrink_selection();
+        assert!(!view.buffer.is_selecting());
+        assert_eq!(cursor_idx(&view), 5);
+    }
+
+    #[test]
+    fn shrinking_goes_back_to_a_selection_made_by_hand() {
+        let mut view = setup_view("one two");
+        view.move_cursor(Direction::Right(6));
+        view.toggle_selection();
+        view.move_cursor(Direction::Left(1));
+        view.expand_selection();
+        assert_eq!(view.buffer.selection_range(), Some((4, 7)));
+        view.shrink_selection();
+        assert_eq!(view.buffer.selection_range(), Some((5, 6)));
+        assert_eq!(cursor_idx(&view), 5);
+
+        // moving the cursor leaves nothing to shrink back to
+        view.expand_selection();
+        view.move_cursor(Direction::Left(1));
+        view.shrink_selection();
+        assert_eq!(view.buffer.selection_range(), Some((4, 6)));
+    }
+
+    #[test]
+    fn indenting_and_commenting_take_the_selected_lines() {
+        let mut view = setup_view("a\nb\nc");
+        view.select_line();
+        view.expand_selection();
+        view.shrink_selection();
+        view.move_cursor(Direction::Down(1));
+        view.toggle_comment();
+        assert_eq!(text(&view), "# a\n# b\nc");
+        view.toggle_comment();
+        assert_eq!(text(&view), "a\nb\nc");
+
+        view.indent_line();
+        assert_eq!(text(&view), "    a\n    b\nc");
+    }
+
+    #[test]
+    fn paste_a_nested_block_deeper() {
+        let mut view = setup_view("if a {\n    b();\n}\nfn f() {\n        \n}");
+        view.toggle_selection();
+        view.move_cursor(Direction::Down(2));
+        view.move_cursor(Direction::LineEnd);
+        view.copy();
+
+        view.buffer.set_mark(view.cursor, 35);
+        view.paste_reindented();
+        let lines: Vec<&[u8]> = view.buffer.lines().skip(3).collect();
+        assert_eq!(lines, vec![b"fn f() {\n"[], b"        if a {\n"[], b"            b();\n"[],
+                               b"        }\n"[], b"}"[]]);
+    }
+
+    #[test]
+    fn paste_a_nested_block_shallower() {
+        let mut view = setup_view("\t\tif a {\n\t\t\tb();\n\t\t}\n  ");
+        view.toggle_selection();
+        view.move_cursor(Direction::Down(2));
+        view.move_cursor(Direction::LineEnd);
+        view.copy();
+
+        view.move_cursor(Direction::BufferEnd);
+        view.paste_reindented();
+        assert_eq!(text(&view), "\t\tif a {\n\t\t\tb();\n\t\t}\n  if a {\n      b();\n  }");
+
+        // plain pasting leaves the indentation alone
+        view.undo();
+        view.move_cursor(Direction::BufferEnd);
+        view.paste();
+        assert_eq!(text(&view), "\t\tif a {\n\t\t\tb();\n\t\t}\n  \t\tif a {\n\t\t\tb();\n\t\t}");
+    }
+
+    #[test]
+    fn pasted_text_goes_in_as_it_is() {
+        let mut view = setup_view("x");
+        view.options.auto_indent = true;
+        view.options.auto_pair = true;
+        view.paste_text("fn f() {\n    (a\n}\n");
+        assert_eq!(text(&view), "fn f() {\n    (a\n}\nx");
+        assert_eq!(cursor_idx(&view), 18);
+        view.undo();
+        assert_eq!(text(&view), "x");
+    }
+
+    #[test]
+    fn pasted_text_replaces_the_selection() {
+        let mut view = setup_view("abc");
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(2));
+        view.paste_text("x\ny");
+        assert_eq!(text(&view), "x\nyc");
+        view.undo();
+        assert_eq!(text(&view), "abc");
+    }
+
+    #[test]
+    fn select_all_then_copy_and_delete() {
+        let mut view = setup_view("one\ntwo");
+        view.select_all();
+        assert_eq!(view.buffer.selection_range(), Some((0, 7)));
+        assert_eq!(cursor_idx(&view), 7);
+        view.copy();
+        view.paste();
+        assert_eq!(text(&view), "one\ntwoone\ntwo");
+
+        view.undo();
+        view.select_all();
         view.delete_char(Direction::Left(1));
+        assert_eq!(text(&view), "");
+        view.undo();
+        assert_eq!(text(&view), "one\ntwo");
+    }
 
-        let lines: Vec<&[u8]> = view.buffer.lines().collect();
+    #[test]
+    fn select_all_then_replace_in_it() {
+        let mut view = setup_view("a b\na");
+        view.select_all();
+        view.ask_replace_all_with("a");
+        view.replace_all("c");
+        assert_eq!(text(&view), "c b\nc");
+    }
 
-        assert_eq!(lines.len(), 2);
-        assert_eq!(view.buffer.lines().next().unwrap(), b"test\n");
+    #[test]
+    fn select_all_leaves_the_view_alone() {
+        let lines: String = range(0u, 100).map(|_| "x\n").collect();
+        let mut view = setup_view(lines[]);
+        view.select_all();
+        assert_eq!(view.buffer.get_mark_coords(view.top_line), Some((0, 0)));
+
+        view.collapse_cursors();
+        view.buffer.set_mark(view.cursor, 0);
+        view.options.select_all_keeps_view = false;
+        view.select_all();
+        assert!(view.buffer.get_mark_coords(view.top_line).unwrap().1 > 50);
+    }
+
+    #[test]
+    fn select_all_of_an_empty_buffer_selects_nothing() {
+        let mut view = setup_view("");
+        view.select_all();
+        assert!(!view.buffer.is_selecting());
+        assert_eq!(view.buffer.selection_range(), None);
+    }
+
+    #[test]
+    fn select_inside_and_around_brackets() {
+        let mut view = setup_view("f(a, [b, (c)], d)");
+        view.buffer.set_mark(view.cursor, 7);
+        view.select_object('b', false);
+        assert_eq!(view.buffer.selection_range(), Some((6, 12)));
+        view.select_object('(', false);
+        assert_eq!(view.buffer.selection_range(), Some((2, 16)));
+        view.cut();
+        assert_eq!(text(&view), "f()");
+
+        view.undo();
+        view.buffer.set_mark(view.cursor, 10);
+        view.select_object(')', true);
+        view.cut();
+        assert_eq!(text(&view), "f(a, [b, ], d)");
+    }
+
+    #[test]
+    fn select_inside_nothing_says_so() {
+        let mut view = setup_view("a (b");
+        view.buffer.set_mark(view.cursor, 3);
+        view.select_object('(', false);
+        assert!(!view.buffer.is_selecting());
+        assert_eq!(message(&view), Some(String::from_str("Not inside ()")));
+        view.select_object('"', true);
+        assert_eq!(message(&view), Some(String::from_str("Not inside \"\"")));
+        view.buffer.set_mark(view.cursor, 1);
+        view.select_object('w', false);
+        assert_eq!(message(&view), Some(String::from_str("Not on a word")));
+    }
+
+    #[test]
+    fn select_inside_quotes_passes_escaped_quotes() {
+        let mut view = setup_view(r#"say("a \"b\" c", 'd')"#);
+        view.buffer.set_mark(view.cursor, 9);
+        view.select_object('"', false);
+        view.copy();
+        view.buffer.set_mark(view.cursor, 18);
+        view.select_object('\'', true);
+        view.paste();
+        assert_eq!(text(&view), r#"say("a \"b\" c", a \"b\" c)"#);
+    }
+
+    #[test]
+    fn select_a_word_with_its_space() {
+        let mut view = setup_view("one two three");
+        view.buffer.set_mark(view.cursor, 5);
+        view.select_object('w', false);
+        assert_eq!(view.buffer.selection_range(), Some((4, 7)));
+        view.buffer.set_mark(view.cursor, 5);
+        view.select_object('w', true);
+        view.cut();
+        assert_eq!(text(&view), "one three");
+    }
+
+    // Keeps what is drawn on it, as a terminal would show it.
+    struct FakeFrontend {
+        cells: Vec<Vec<(char, CharColor, CharStyle)>>,
+        cursor: (int, int),
+        drawn: uint,        // How many cells have been drawn.
+    }
+
+    impl FakeFrontend {
+        fn new(width: uint, height: uint) -> FakeFrontend {
+            let cells = range(0, height).map(|_| {
+                range(0, width).map(|_| (' ', CharColor::Default, CharStyle::Normal)).collect()
+            }).collect();
+            FakeFrontend { cells: cells, cursor: (0, 0), drawn: 0 }
+        }
+
+        // The text on row `y`, without the blank cells at the end.
+        fn row(&self, y: uint) -> String {
+            let text: String = self.cells[y].iter().map(|&(ch, _, _)| ch).collect();
+            String::from_str(text.trim_right())
+        }
+    }
+
+    impl Frontend for FakeFrontend {
+        fn poll_event(&self) -> EditorEvent { EditorEvent::NoEvent }
+        fn peek_event(&self, _: Duration) -> EditorEvent { EditorEvent::NoEvent }
+        fn present(&self) {}
+        fn get_window_height(&self) -> uint { self.cells.len() }
+        fn get_window_width(&self) -> uint { self.cells[0].len() }
+        fn colors(&self) -> uint { color::TRUE_COLOR }
+        fn draw_cursor(&mut self, offset: int, linenum: int) { self.cursor = (offset, linenum) }
+        fn draw_char(&mut self, offset: uint, linenum: uint, ch: char, fg: CharColor, _: CharColor,
+                     style: CharStyle) {
+            self.cells[linenum][offset] = (ch, fg, style);
+            self.drawn += 1;
+        }
+    }
+
+    fn draw_view(view: &mut View, frontend: &mut FakeFrontend) {
+        view.clear();
+        view.draw(&mut *frontend);
+    }
+
+    #[test]
+    fn line_numbers_go_beside_the_text() {
+        let mut view = setup_view("first\n\tx\n\nlast");
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(frontend.row(0), "1 first");
+        assert_eq!(frontend.row(1), "2     x");
+        assert_eq!(frontend.row(2), "3");
+        assert_eq!(frontend.row(3), "4 last");
+        assert_eq!(frontend.row(4), "");
+        assert!(frontend.cells[0][0].1 == CharColor::Grey);
+        assert!(frontend.cells[0][2].1 == CharColor::Default);
+        assert_eq!(frontend.cursor, (2, 0));
+
+        view.move_cursor(Direction::Down(1));
+        view.move_cursor_to_line_end();
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.cursor, (7, 1));
+    }
+
+    #[test]
+    fn line_numbers_are_as_wide_as_the_last() {
+        let text = repeat("a\n").take(10).collect::<Vec<&str>>().connect("");
+        let mut view = setup_view(text[]);
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(frontend.row(0), " 1 a");
+        assert_eq!(frontend.row(9), "10 a");
+        assert_eq!(frontend.row(10), "11");
+        assert_eq!(frontend.row(11), "");
+
+        view.move_cursor(Direction::BufferEnd);
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.cursor, (3, 10));
+    }
+
+    #[test]
+    fn selection_and_cursors_move_over_with_the_text() {
+        let mut view = setup_view("first\nsecond");
+        view.move_cursor(Direction::Right(1));
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(2));
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+
+        let reversed: Vec<uint> = range(0, 10).filter(|&x| {
+            frontend.cells[0][x].2 == CharStyle::Reverse
+        }).collect();
+        assert_eq!(reversed, vec![3, 4]);
+        assert_eq!(frontend.cursor, (5, 0));
+
+        view.toggle_selection();
+        view.add_cursor_below();
+        draw_view(&mut view, &mut frontend);
+        assert!(frontend.cells[0][3].2 == CharStyle::Normal);
+        assert!(frontend.cells[1][5].2 == CharStyle::Reverse);
+    }
+
+    #[test]
+    fn line_numbers_can_be_turned_off() {
+        let mut view = setup_view("first\n\tx");
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.options.line_numbers = LineNumbers::Off;
+        view.move_cursor(Direction::Down(1));
+        view.move_cursor_to_line_end();
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(frontend.row(0), "first");
+        assert_eq!(frontend.row(1), "    x");
+        assert!(frontend.cells[0][0].1 == CharColor::Default);
+        assert_eq!(frontend.cursor, (5, 1));
+
+        view.cycle_line_numbers();
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(1), "2     x");
+        assert_eq!(frontend.cursor, (7, 1));
+    }
+
+    #[test]
+    fn relative_line_numbers_follow_the_cursor() {
+        let text = repeat("a\n").take(11).collect::<Vec<&str>>().connect("");
+        let mut view = setup_view(text[]);
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.options.line_numbers = LineNumbers::Relative;
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(frontend.row(0), " 1 a");
+        assert_eq!(frontend.row(1), " 1 a");
+        assert_eq!(frontend.row(11), "11");
+
+        view.move_cursor(Direction::Down(2));
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), " 2 a");
+        assert_eq!(frontend.row(1), " 1 a");
+        assert_eq!(frontend.row(2), " 3 a");
+        assert_eq!(frontend.row(3), " 1 a");
+        assert_eq!(frontend.row(11), " 9");
+        assert_eq!(frontend.cursor, (3, 2));
+    }
+
+    #[test]
+    fn line_numbers_cycle_off_absolute_relative() {
+        let mut view = setup_view("a");
+        let modes: Vec<LineNumbers> = range(0, 3).map(|_| {
+            view.cycle_line_numbers();
+            view.options.line_numbers
+        }).collect();
+        assert_eq!(modes, vec![LineNumbers::Relative, LineNumbers::Off, LineNumbers::Absolute]);
+    }
+
+    #[test]
+    fn drawn_lines_start_after_the_gutter() {
+        let mut uibuf = UIBuffer::new(8, 1);
+        draw_line(&mut uibuf, b"abcdefgh", 0, 0, 3, 4, None);
+
+        assert_eq!(uibuf.get_cell_mut(3, 0).ch, 'a');
+        assert_eq!(uibuf.get_cell_mut(6, 0).ch, 'd');
+        assert_eq!(uibuf.get_cell_mut(7, 0).ch, '→');
+        assert_eq!(span_columns(&uibuf, b"abcdefgh", 0, 3, 4, (1, 8)), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn the_cursor_is_drawn_on_its_row_of_the_screen() {
+        let mut view = setup_view(repeat("line\n").take(100).collect::<String>().as_slice());
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.goto_line(80);
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 55));
+        assert_eq!(frontend.row(0), " 56 line");
+        assert_eq!(frontend.cursor, (4, 24));
+    }
+
+    #[test]
+    fn undoing_a_paste_scrolled_to_keeps_the_screen_on_the_text() {
+        let mut view = setup_view("a\nb\n");
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.move_cursor(Direction::BufferEnd);
+        view.paste_text(repeat("line\n").take(200).collect::<String>().as_slice());
+        draw_view(&mut view, &mut frontend);
+        assert!(view.buffer.get_mark_coords(view.top_line).unwrap().1 > 100);
+
+        view.undo();
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 0));
+        assert_eq!(frontend.row(0), "1 a");
+
+        view.redo();
+        draw_view(&mut view, &mut frontend);
+        assert!(view.buffer.get_mark_coords(view.top_line).unwrap().1 > 100);
+    }
+
+    #[test]
+    fn a_short_window_still_keeps_the_cursor_on_the_screen() {
+        let mut view = View::new(Input::Filename(None), 50, 4);
+        let text = repeat("line\n").take(100).collect::<String>();
+        view.buffer.insert_str(view.cursor, text.as_slice());
+        view.buffer.set_mark(view.cursor, 0);
+        view.buffer.set_mark(view.top_line, 0);
+
+        fn on_screen(view: &View) -> bool {
+            let (_, cursor) = view.buffer.get_mark_coords(view.cursor).unwrap();
+            let (_, top) = view.buffer.get_mark_coords(view.top_line).unwrap();
+            top <= cursor && cursor < top + view.get_height()
+        }
+        for _ in range(0, 10) {
+            view.move_cursor(Direction::Down(1));
+            assert!(on_screen(&view));
+        }
+        assert!(view.buffer.get_mark_coords(view.top_line).unwrap().1 > 0);
+        for _ in range(0, 10) {
+            view.move_cursor(Direction::Up(1));
+            assert!(on_screen(&view));
+        }
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn resizing_scrolls_to_keep_the_cursor_on_the_screen() {
+        let mut view = setup_view(repeat("line\n").take(100).collect::<String>().as_slice());
+        view.goto_line(40);
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 15));
+
+        view.resize(50, 10);
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 35));
+        let mut frontend = FakeFrontend::new(50, 10);
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.cursor, (4, 4));
+
+        // back to full height, the cursor is kept off the top edge
+        view.resize(50, 50);
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 34));
+    }
+
+    fn setup_narrow_view<'v>(text: &str) -> View<'v> {
+        let mut view = View::new(Input::Filename(None), 20, 5);
+        view.buffer.insert_str(view.cursor, text);
+        view.buffer.set_mark(view.cursor, 0);
+        view.buffer.set_mark(view.top_line, 0);
+        view
+    }
+
+    #[test]
+    fn long_lines_scroll_sideways_to_the_cursor() {
+        let mut view = setup_narrow_view("abcdefghijklmnopqrstuvwxyz0123\nshort");
+        view.options.line_numbers = LineNumbers::Off;
+        let mut frontend = FakeFrontend::new(20, 5);
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "abcdefghijklmnopqrs→");
+
+        view.move_cursor(Direction::Right(30));
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "←tuvwxyz0123");
+        assert_eq!(frontend.row(1), "←");
+        assert_eq!(frontend.cursor, (12, 0));
+
+        view.move_cursor(Direction::Left(12));
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "←klmnopqrstuvwxyz01→");
+        assert_eq!(frontend.cursor, (9, 0));
+
+        view.move_cursor(Direction::Down(1));
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "abcdefghijklmnopqrs→");
+        assert_eq!(frontend.row(1), "short");
+        assert_eq!(frontend.cursor, (5, 1));
+    }
+
+    #[test]
+    fn sideways_scrolling_counts_tabs_and_the_gutter() {
+        let mut view = setup_narrow_view("\t\t\t\t\t\tend");
+        let mut frontend = FakeFrontend::new(20, 5);
+        view.move_cursor(Direction::Right(9));
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(frontend.row(0), "1 ←       end");
+        assert_eq!(frontend.cursor, (13, 0));
+    }
+
+    #[test]
+    fn wrapped_lines_go_on_the_rows_below() {
+        let mut view = setup_narrow_view("one two three four five six\nend");
+        let mut frontend = FakeFrontend::new(20, 5);
+        view.toggle_wrap();
+        view.move_cursor(Direction::Right(16));
+        draw_view(&mut view, &mut frontend);
+
+        assert_eq!(frontend.row(0), "1 one two three");
+        assert_eq!(frontend.row(1), "↪ four five six");
+        assert_eq!(frontend.row(2), "2 end");
+        assert!(frontend.cells[1][0].1 == CharColor::Grey);
+        assert_eq!(frontend.cursor, (4, 1));
+
+        view.toggle_wrap();
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "1 one two three fou→");
+        assert_eq!(frontend.row(1), "2 end");
+        assert_eq!(frontend.cursor, (18, 0));
+    }
+
+    #[test]
+    fn moving_up_and_down_goes_a_row_at_a_time_while_wrapped() {
+        let mut view = setup_narrow_view("one two three four five six\nend");
+        let mut frontend = FakeFrontend::new(20, 5);
+        view.toggle_wrap();
+        view.move_cursor(Direction::Right(2));
+
+        view.move_cursor(Direction::Down(1));
+        assert_eq!(cursor_idx(&view), 16);
+        view.move_cursor(Direction::Down(1));
+        assert_eq!(cursor_idx(&view), 30);
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.cursor, (4, 2));
+
+        view.move_cursor(Direction::Up(1));
+        assert_eq!(cursor_idx(&view), 16);
+        view.move_cursor(Direction::Up(2));
+        assert_eq!(cursor_idx(&view), 0);
+    }
+
+    #[test]
+    fn scrolling_counts_wrapped_rows() {
+        let text = repeat("aaaa bbbb cccc dddd eeee\n").take(4).collect::<String>();
+        let mut view = setup_narrow_view(text[]);
+        let mut frontend = FakeFrontend::new(20, 5);
+        view.toggle_wrap();
+
+        for _ in range(0, 8) {
+            view.move_cursor(Direction::Down(1));
+            draw_view(&mut view, &mut frontend);
+            assert!(frontend.cursor.1 >= 0 && frontend.cursor.1 < view.get_height() as int);
+        }
+        assert_eq!(view.buffer.get_mark_coords(view.cursor).unwrap(), (0, 4));
+        assert!(view.buffer.get_mark_coords(view.top_line).unwrap().1 > 1);
+        for _ in range(0, 8) {
+            view.move_cursor(Direction::Up(1));
+            draw_view(&mut view, &mut frontend);
+            assert!(frontend.cursor.1 >= 0 && frontend.cursor.1 < view.get_height() as int);
+        }
+        assert_eq!(view.buffer.get_mark_coords(view.top_line).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn the_status_line_goes_across_the_bottom_row() {
+        let mut view = setup_view("one\ntwo\nthree");
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.move_cursor(Direction::Down(1));
+        view.draw_status(&mut frontend);
+
+        let row = view.get_height() - 1;
+        assert_eq!(frontend.row(row), "untitled* [LF] 2:1  66%  3 lines");
+        assert!(frontend.cells[row].iter().all(|&(_, fg, _)| fg == CharColor::Black));
+
+        view.buffer.read_only = true;
+        view.draw_status(&mut frontend);
+        assert_eq!(frontend.row(row), "untitled* [LF] 2:1  66%  3 lines  [read-only]");
+    }
+
+    #[test]
+    fn the_status_line_has_the_format_in_the_options() {
+        let mut view = setup_view("one\ntwo\nthree");
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.options.status_line = StatusLine::parse("%l/%L %f%m");
+        view.draw_status(&mut frontend);
+        assert_eq!(frontend.row(view.get_height() - 1), "1/3 untitled*");
+    }
+
+    #[test]
+    fn messages_take_the_place_of_the_status_line() {
+        let mut view = setup_view("text");
+        let mut frontend = FakeFrontend::new(50, 50);
+        let row = view.get_height() - 1;
+        view.show_message("wrote 5 bytes");
+        view.show_error("Could not write swap file: permission denied");
+        view.draw_status(&mut frontend);
+        assert_eq!(frontend.row(row), "wrote 5 bytes");
+        assert!(frontend.cells[row][0].1 == CharColor::Default);
+
+        // the next key shows the next message, in the colors for an error
+        view.clear_message();
+        view.draw_status(&mut frontend);
+        assert_eq!(frontend.row(row), "Could not write swap file: permission denied");
+        assert!(frontend.cells[row][0].1 == CharColor::Black);
+
+        view.clear_message();
+        view.draw_status(&mut frontend);
+        assert_eq!(frontend.row(row), "untitled* [LF] 1:1  100%  1 lines");
+    }
+
+    #[test]
+    fn long_messages_are_cut_short_with_an_ellipsis() {
+        let mut view = setup_view("text");
+        let mut frontend = FakeFrontend::new(50, 50);
+        let long = repeat("word ").take(20).collect::<Vec<&str>>().connect("");
+        view.show_message(long[]);
+        view.draw_status(&mut frontend);
+
+        let row = frontend.row(view.get_height() - 1);
+        assert_eq!(row.chars().count(), 50);
+        assert!(row.ends_with("word word\u{2026}"));
+    }
+
+    #[test]
+    fn searching_replaces_its_own_messages() {
+        let mut view = setup_view("abc");
+        view.show_message("autosaved");
+        view.start_search(true);
+        view.extend_search('a');
+        view.extend_search('b');
+        view.end_search();
+        assert_eq!(view.messages.current().unwrap().text, "autosaved");
+        view.clear_message();
+        assert_eq!(view.messages.current().unwrap().text, "Search (smart case): ab");
+        view.clear_message();
+        assert!(!view.has_message());
+    }
+
+    #[test]
+    fn text_is_colored_by_its_syntax() {
+        let mut view = setup_view("fn main() {\n    /* open\n    */ let x = 10;\n}");
+        view.highlighter = Some(Highlighter::new(&RUST));
+        view.options.line_numbers = LineNumbers::Off;
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+
+        assert!(frontend.cells[0][0].1 == CharColor::Yellow);
+        assert!(frontend.cells[0][3].1 == CharColor::Default);
+        assert!(frontend.cells[1][6].1 == CharColor::Blue);
+        // the comment goes on from the line before
+        assert!(frontend.cells[2][4].1 == CharColor::Blue);
+        assert!(frontend.cells[2][7].1 == CharColor::Yellow);
+        assert!(frontend.cells[2][15].1 == CharColor::Magenta);
+    }
+
+    #[test]
+    fn editing_above_the_screen_recolors_it() {
+        let mut view = setup_view("fn main() {\n    /* open\n    */ let x = 10;\n}");
+        view.highlighter = Some(Highlighter::new(&RUST));
+        view.options.line_numbers = LineNumbers::Off;
+        let mut frontend = FakeFrontend::new(50, 50);
+        view.buffer.set_mark(view.cursor, 24);
+        view.buffer.set_mark(view.top_line, 24);
+        draw_view(&mut view, &mut frontend);
+        assert!(frontend.cells[0][4].1 == CharColor::Blue);
+
+        // commenting out the line that opens the block comment closes it
+        view.buffer.set_mark(view.cursor, 12);
+        view.insert_char('/');
+        view.insert_char('/');
+        view.buffer.set_mark(view.cursor, 26);
+        view.buffer.set_mark(view.top_line, 26);
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "    */ let x = 10;");
+        assert!(frontend.cells[0][4].1 == CharColor::Default);
+        assert!(frontend.cells[0][7].1 == CharColor::Yellow);
+    }
+
+    #[test]
+    fn everything_is_drawn_through_the_theme() {
+        let mut view = setup_view("fn main() {}");
+        view.highlighter = Some(Highlighter::new(&RUST));
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+        assert!(frontend.cells[0][2].1 == CharColor::Yellow);
+
+        view.cycle_theme();
+        assert_eq!(view.options.theme.name, "light");
+        assert_eq!(message(&view), Some(String::from_str("Theme: light")));
+        draw_view(&mut view, &mut frontend);
+        // the line number, then the keyword
+        assert!(frontend.cells[0][0].1 == CharColor::Blue);
+        assert!(frontend.cells[0][2].1 == CharColor::Blue);
+    }
+
+    #[test]
+    fn invisible_characters_are_shown_when_asked() {
+        let mut view = setup_view("\tx  \ny");
+        view.options.line_numbers = LineNumbers::Off;
+        view.options.line_end_mark = Some('$');
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "    x");
+
+        view.toggle_invisibles();
+        assert_eq!(message(&view), Some(String::from_str("Showing invisible characters")));
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.row(0), "»   x  $");
+        assert_eq!(frontend.row(1), "y");
+        assert!(frontend.cells[0][0].1 == CharColor::Blue);
+        assert!(frontend.cells[0][4].1 == CharColor::Default);
+        assert!(frontend.cells[0][7].1 == CharColor::Blue);
+        assert!(view.uibuf.get_cell_mut(5, 0).bg == CharColor::Red);
+        assert!(view.uibuf.get_cell_mut(4, 0).bg == CharColor::Default);
+        assert!(view.status_text()[].ends_with("[invisibles]"));
+    }
+
+    #[test]
+    fn the_cursors_line_is_highlighted_across_the_window() {
+        let mut view = setup_view("first\nsecond line\nthird");
+        view.options.highlight_current_line = true;
+        view.move_cursor(Direction::Down(1));
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+
+        let current_line = view.options.theme.current_line.bg;
+        assert!(range(0, 50).all(|x| view.uibuf.get_cell_mut(x, 1).bg == current_line));
+        assert!(view.uibuf.get_cell_mut(0, 0).bg == CharColor::Default);
+        assert!(view.uibuf.get_cell_mut(0, 2).bg == CharColor::Default);
+
+        // only the rows it moves between are drawn again
+        frontend.drawn = 0;
+        view.move_cursor(Direction::Down(1));
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.drawn, 2 * 50);
+        assert!(view.uibuf.get_cell_mut(10, 1).bg == CharColor::Default);
+        assert!(view.uibuf.get_cell_mut(10, 2).bg == current_line);
+
+        frontend.drawn = 0;
+        draw_view(&mut view, &mut frontend);
+        assert_eq!(frontend.drawn, 0);
+
+        view.toggle_current_line();
+        draw_view(&mut view, &mut frontend);
+        assert!(view.uibuf.get_cell_mut(10, 2).bg == CharColor::Default);
+    }
+
+    #[test]
+    fn matches_and_selections_show_over_the_cursors_line() {
+        let mut view = setup_view("one two one");
+        view.options.highlight_current_line = true;
+        view.options.line_numbers = LineNumbers::Off;
+        view.search_word(true);
+        let mut frontend = FakeFrontend::new(50, 50);
+        draw_view(&mut view, &mut frontend);
+
+        assert!(view.uibuf.get_cell_mut(0, 0).bg == view.options.theme.search_match.bg);
+        assert!(view.uibuf.get_cell_mut(8, 0).bg == view.options.theme.search_match.bg);
+        assert!(view.uibuf.get_cell_mut(4, 0).bg == view.options.theme.current_line.bg);
+
+        view.clear_highlight();
+        view.buffer.set_mark(view.cursor, 0);
+        view.toggle_selection();
+        view.move_cursor(Direction::Right(3));
+        draw_view(&mut view, &mut frontend);
+        assert!(view.uibuf.get_cell_mut(1, 0).style == CharStyle::Reverse);
+        assert!(view.uibuf.get_cell_mut(1, 0).bg == view.options.theme.current_line.bg);
+        assert!(view.uibuf.get_cell_mut(8, 0).style == CharStyle::Normal);
     }
 }
@@ -5,10 +5,12 @@ extern crate rustbox;
 extern crate docopt;
 extern crate iota;
 
+#[cfg(not(test))] use std::os;
 #[cfg(not(test))] use std::io::stdio;
 #[cfg(not(test))] use docopt::Docopt;
 #[cfg(not(test))] use iota::{
-    Editor, Input,
+    Editor, Input, Session, RecentFiles, Histories, SystemClipboard,
+    parse_file_position, parse_line_arg, session_path, recent_path, history_path,
     StandardMode, NormalMode,
     RustboxFrontend, Mode
 };
@@ -17,9 +19,15 @@ extern crate iota;
 Usage: iota [<filename>] [options]
        iota --help
 
+Open the file at a line with `iota +<line> <filename>`, or with a filename
+ending in :<line> or :<line>:<column>.
+
 Options:
-    --vi           Start Iota with vi-like modes
-    -h, --help     Show this message.
+    --vi             Start Iota with vi-like modes
+    -R, --read-only  Open the file read-only
+    -c, --continue   Open the file that was open last time
+    --recent         Open the file opened or saved most recently
+    -h, --help       Show this message.
 ";
 
 
@@ -27,20 +35,54 @@ Options:
 struct Args {
     arg_filename: Option<String>,
     flag_vi: bool,
+    flag_read_only: bool,
+    flag_continue: bool,
+    flag_recent: bool,
     flag_help: bool,
 }
 
 #[cfg(not(test))]
 fn main() {
+    // pull out a +<line> argument, which docopt can't describe
+    let mut argv = os::args();
+    let plus_arg = argv.iter().position(|arg| parse_line_arg(arg.as_slice()).is_some());
+    let plus_line = plus_arg.and_then(|i| parse_line_arg(argv.remove(i).as_slice()));
+    let argv: Vec<&str> = argv.iter().map(|arg| arg.as_slice()).collect();
+
     let args: Args = Docopt::new(USAGE)
-                            .and_then(|d| d.decode())
+                            .and_then(|d| d.argv(argv.as_slice()).decode())
                             .unwrap_or_else(|e| e.exit());
 
-    // editor source - either a filename or stdin
-    let source = if stdio::stdin_raw().isatty() {
-        Input::Filename(args.arg_filename)
-    } else {
+    // the session remembers where the editor was in each file, and which was open last
+    let session_file = session_path();
+    let mut session = session_file.as_ref().map_or(Session::new(), |path| Session::load(path));
+    // and the recent files list which ones were opened or saved most recently
+    let recent_file = recent_path();
+    let mut recent = recent_file.as_ref().map_or(RecentFiles::new(), |path| RecentFiles::load(path));
+    let mut filename = args.arg_filename;
+    if filename.is_none() && args.flag_continue {
+        filename = session.last_file().and_then(|path| path.as_str()).map(String::from_str);
+    }
+    if filename.is_none() && args.flag_recent {
+        filename = recent.most_recent().and_then(|path| path.as_str()).map(String::from_str);
+    }
+
+    // editor source - either a filename or stdin, when it is "-" or there is no filename and
+    // something is piped in. Keyboard input then still comes from the terminal, as termbox
+    // reads it from /dev/tty.
+    let read_stdin = match filename {
+        Some(ref name) => name.as_slice() == "-",
+        None           => !stdio::stdin_raw().isatty(),
+    };
+    let mut position = plus_line.map(|line| (line, None));
+    let source = if read_stdin {
         Input::Stdin(stdio::stdin())
+    } else {
+        Input::Filename(filename.map(|arg| {
+            let (name, line, col) = parse_file_position(arg.as_slice());
+            if let Some(line) = line { position = Some((line, col)) }
+            name
+        }))
     };
 
     // RustBox options
@@ -61,5 +103,28 @@ fn main() {
 
     // start the editor
     let mut editor = Editor::new(source, mode, frontend);
+    if args.flag_read_only {
+        editor.set_read_only();
+    }
+    match position {
+        Some((line, col)) => editor.goto_position(line, col),
+        None              => editor.restore_position(&session),
+    }
+    editor.set_recent_files(recent);
+    // the prompts remember what was entered at them in earlier sessions
+    let history_file = history_path();
+    editor.set_histories(history_file.as_ref().map_or(Histories::new(), |path| Histories::load(path)));
+    editor.set_clipboard(box SystemClipboard::new());
     editor.start();
+
+    editor.remember_position(&mut session);
+    if let Some(ref path) = session_file {
+        let _ = session.save(path);
+    }
+    if let Some(ref path) = recent_file {
+        let _ = editor.recent_files().save(path);
+    }
+    if let Some(ref path) = history_file {
+        let _ = editor.histories().save(path);
+    }
 }